@@ -32,7 +32,7 @@ impl ChatModel for ScriptedModel {
     ) -> Result<ModelCompletion, ProviderError> {
         let mut guard = self.responses.lock().expect("lock poisoned");
         guard.pop_front().unwrap_or_else(|| {
-            Err(ProviderError::Response(
+            Err(ProviderError::response(
                 "scripted model exhausted responses".to_string(),
             ))
         })
@@ -73,23 +73,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let model = ScriptedModel::new(vec![
         Ok(ModelCompletion {
             text: None,
-            thinking: None,
+            thinking: Vec::new(),
+            thinking_offsets: Vec::new(),
             tool_calls: vec![ModelToolCall {
                 id: "call_1".to_string(),
                 name: "read_dep".to_string(),
                 arguments: json!({}),
             }],
             usage: None,
+            raw: None,
         }),
         Ok(ModelCompletion {
             text: None,
-            thinking: None,
+            thinking: Vec::new(),
+            thinking_offsets: Vec::new(),
             tool_calls: vec![ModelToolCall {
                 id: "call_2".to_string(),
                 name: "done".to_string(),
                 arguments: json!({"message": "dependency override applied"}),
             }],
             usage: None,
+            raw: None,
         }),
     ]);
 
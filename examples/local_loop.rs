@@ -33,7 +33,7 @@ impl ChatModel for ScriptedModel {
     ) -> Result<ModelCompletion, ProviderError> {
         let mut guard = self.responses.lock().expect("lock poisoned");
         guard.pop_front().unwrap_or_else(|| {
-            Err(ProviderError::Response(
+            Err(ProviderError::response(
                 "scripted model exhausted responses".to_string(),
             ))
         })
@@ -99,23 +99,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut agent = build_agent(vec![
         Ok(ModelCompletion {
             text: Some("Working on it".to_string()),
-            thinking: Some("Need arithmetic".to_string()),
+            thinking: vec!["Need arithmetic".to_string()],
+            thinking_offsets: Vec::new(),
             tool_calls: vec![ModelToolCall {
                 id: "call_1".to_string(),
                 name: "add".to_string(),
                 arguments: json!({"a": 2, "b": 3}),
             }],
             usage: None,
+            raw: None,
         }),
         Ok(ModelCompletion {
             text: None,
-            thinking: None,
+            thinking: Vec::new(),
+            thinking_offsets: Vec::new(),
             tool_calls: vec![ModelToolCall {
                 id: "call_2".to_string(),
                 name: "done".to_string(),
                 arguments: json!({"message": "2 + 3 = 5"}),
             }],
             usage: None,
+            raw: None,
         }),
     ]);
 
@@ -125,23 +129,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut streaming_agent = build_agent(vec![
         Ok(ModelCompletion {
             text: Some("Streaming run".to_string()),
-            thinking: Some("Will call add and done".to_string()),
+            thinking: vec!["Will call add and done".to_string()],
+            thinking_offsets: Vec::new(),
             tool_calls: vec![ModelToolCall {
                 id: "call_3".to_string(),
                 name: "add".to_string(),
                 arguments: json!({"a": 10, "b": 7}),
             }],
             usage: None,
+            raw: None,
         }),
         Ok(ModelCompletion {
             text: None,
-            thinking: None,
+            thinking: Vec::new(),
+            thinking_offsets: Vec::new(),
             tool_calls: vec![ModelToolCall {
                 id: "call_4".to_string(),
                 name: "done".to_string(),
                 arguments: json!({"message": "10 + 7 = 17"}),
             }],
             usage: None,
+            raw: None,
         }),
     ]);
 
@@ -155,8 +163,19 @@ async fn main() -> Result<(), Box<dyn Error>> {
             AgentEvent::MessageComplete {
                 message_id,
                 content,
-            } => println!("message complete [{message_id}]: {content}"),
+                is_final,
+            } => println!(
+                "message complete [{message_id}]{}: {content}",
+                if is_final { " (final)" } else { "" }
+            ),
             AgentEvent::HiddenUserMessage { content } => println!("hidden: {content}"),
+            AgentEvent::IterationStart {
+                iteration,
+                max_iterations,
+            } => println!("iteration start {iteration}/{max_iterations}"),
+            AgentEvent::IterationComplete { iteration } => {
+                println!("iteration complete {iteration}")
+            }
             AgentEvent::StepStart {
                 step_id,
                 title,
@@ -169,6 +188,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
             } => println!("step complete [{step_id}] {status:?} ({duration_ms} ms)"),
             AgentEvent::Thinking { content } => println!("thinking: {content}"),
             AgentEvent::Text { content } => println!("text: {content}"),
+            AgentEvent::ToolCallDelta {
+                tool_call_id,
+                partial_json,
+            } => println!("tool call delta [{tool_call_id}]: {partial_json}"),
             AgentEvent::ToolCall {
                 tool,
                 args_json,
@@ -180,7 +203,30 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 tool_call_id,
                 is_error,
             } => println!("tool result [{tool_call_id}] {tool}: {result_text} (error={is_error})"),
-            AgentEvent::FinalResponse { content } => println!("stream final: {content}"),
+            AgentEvent::Usage {
+                cumulative_input,
+                cumulative_output,
+                ..
+            } => println!("usage: {cumulative_input} in / {cumulative_output} out"),
+            AgentEvent::FinalResponse { content, .. } => println!("stream final: {content}"),
+            AgentEvent::Retry {
+                attempt, delay_ms, ..
+            } => println!("stream retry #{attempt} in {delay_ms}ms"),
+            AgentEvent::InputRequested { prompt, .. } => {
+                println!("stream input requested: {prompt}")
+            }
+            AgentEvent::Cancelled { reason } => println!("stream cancelled: {reason}"),
+            AgentEvent::HistoryCompacted { removed, .. } => {
+                println!("stream compacted {removed} messages")
+            }
+            AgentEvent::HistoryTruncated { dropped } => {
+                println!("stream truncated {dropped} messages")
+            }
+            AgentEvent::NudgeSkipped { reason } => println!("stream nudge skipped: {reason}"),
+            AgentEvent::RawCompletion { value } => println!("stream raw completion: {value}"),
+            AgentEvent::UnmatchedToolResult { tool_call_id } => {
+                println!("stream unmatched tool result: {tool_call_id}")
+            }
         }
     }
 
@@ -0,0 +1,31 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+/// Time source for retry backoff, request pacing, run deadlines, and step durations.
+///
+/// Tool timeouts still use the tokio timer. Swap in a manual clock (such as
+/// `testing::TestClock`) to make time-dependent runs deterministic.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    /// Current instant.
+    fn now(&self) -> Instant;
+
+    /// Waits for `duration`.
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Default [`Clock`] backed by the system clock and the tokio timer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+#[async_trait]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+}
@@ -1,21 +1,36 @@
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::ops::Range;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 
 use async_stream::try_stream;
+use futures_util::future::join_all;
 use futures_util::{Stream, StreamExt};
-use tokio::time::{Duration, sleep};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, timeout};
+pub use tokio_util::sync::CancellationToken;
 
 use crate::error::{AgentError, ProviderError, ToolError};
 use crate::llm::{
-    ChatModel, ModelCompletion, ModelMessage, ModelToolCall, ModelToolChoice, ModelToolDefinition,
+    ChatModel, ImageData, ModelCallOptions, ModelCompletion, ModelIo, ModelIoHook, ModelMessage,
+    ModelStream, ModelStreamAccumulator, ModelStreamChunk, ModelToolCall, ModelToolChoice,
+    ModelToolDefinition, ModelUsage, ObservedModel, ResponseFormat, estimate_tokens,
 };
-use crate::tools::{DependencyMap, ToolOutcome, ToolSpec};
+use crate::tools::{DependencyMap, ToolOutcome, ToolSpec, validate_value};
+use telemetry::Span;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+pub use clock::{Clock, TokioClock};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 /// Tool-calling policy sent to the underlying model.
 pub enum AgentToolChoice {
     /// Model decides whether to call tools.
+    #[default]
     Auto,
     /// Model must call at least one tool.
     Required,
@@ -23,12 +38,10 @@ pub enum AgentToolChoice {
     None,
     /// Model must call the named tool.
     Tool(String),
-}
-
-impl Default for AgentToolChoice {
-    fn default() -> Self {
-        Self::Auto
-    }
+    /// Force the named tool on the first iteration of each query, then fall back to `Auto`.
+    ///
+    /// Also falls back to `Auto` when the tool is not offered for that iteration.
+    PreferTool(String),
 }
 
 #[derive(Debug, Clone)]
@@ -40,6 +53,8 @@ pub struct AgentConfig {
     pub max_iterations: u32,
     /// Optional system prompt injected at the start of empty history.
     pub system_prompt: Option<String>,
+    /// Few-shot example turns placed after the system prompt before the first user message.
+    pub example_turns: Vec<ModelMessage>,
     /// Tool-choice policy passed to the model adapter.
     pub tool_choice: AgentToolChoice,
     /// Maximum number of retries for request-level provider failures.
@@ -48,8 +63,57 @@ pub struct AgentConfig {
     pub llm_retry_base_delay_ms: u64,
     /// Maximum retry delay in milliseconds.
     pub llm_retry_max_delay_ms: u64,
-    /// Optional hidden follow-up user message injected once before finishing.
+    /// Pick each retry delay uniformly from `[0, backoff]` instead of the full backoff.
+    pub retry_jitter: bool,
+    /// Optional seed for retry jitter; a per-agent random seed is used when unset.
+    pub retry_jitter_seed: Option<u64>,
+    /// Optional hidden follow-up user message injected when the model finishes without tool
+    /// calls, up to [`AgentConfig::max_hidden_prompt_injections`] times per conversation.
     pub hidden_user_message_prompt: Option<String>,
+    /// How many times per conversation the hidden user prompt may be injected. Defaults to 1.
+    ///
+    /// The count carries over between queries on the same [`Agent`] and is reset by
+    /// [`Agent::reset_nudge`] or by replacing the conversation, for example with
+    /// [`Agent::clear_history`].
+    pub max_hidden_prompt_injections: u32,
+    /// Execute multiple tool calls from one assistant turn concurrently.
+    pub parallel_tool_calls: bool,
+    /// Optional wall-clock limit for one query, checked between steps.
+    pub run_timeout: Option<Duration>,
+    /// Optional per-call limit for tool handlers.
+    pub tool_timeout: Option<Duration>,
+    /// Optional cap on tool result text stored in history and emitted in events, applied to
+    /// every result including denials and errors raised before a handler runs.
+    pub max_tool_result_chars: Option<usize>,
+    /// Optional policy for shrinking history before model invocations.
+    pub compaction: Option<CompactionPolicy>,
+    /// Optional hard cap on stored history messages, enforced before each model invocation by
+    /// dropping the oldest user, assistant, and tool-result messages after the system prompt
+    /// and example turns, including whole tool turns within the current run.
+    pub max_history_messages: Option<usize>,
+    /// Optional cap on the estimated input tokens of one model invocation.
+    pub max_input_tokens: Option<usize>,
+    /// Optional minimum spacing between consecutive model invocations within one query.
+    pub min_request_interval: Option<Duration>,
+    /// Optional cap on back-to-back failed tool results before the run is aborted.
+    pub max_consecutive_tool_errors: Option<u32>,
+    /// End the run with [`AgentError::Tool`] on the first failed tool result instead of
+    /// letting the model react to it; off by default.
+    pub abort_on_tool_error: bool,
+    /// In done-tool mode, abort after this many completions in a row with no text, thinking,
+    /// or tool calls.
+    pub max_empty_completions: Option<u32>,
+    /// Optional cap on tool calls executed across one run, independent of iterations.
+    pub max_tool_calls: Option<u32>,
+    /// Optional text every assistant turn should start with; see
+    /// [`ModelCallOptions::assistant_prefill`] for per-provider behavior.
+    pub assistant_prefill: Option<String>,
+    /// Emit [`AgentEvent::RawCompletion`] with each provider response body; off by default.
+    pub capture_raw_responses: bool,
+    /// Answer a repeat of an already executed tool call (same name and arguments) in the same
+    /// run with the earlier result instead of running the tool again, as long as no other call
+    /// executed in between; off by default.
+    pub dedupe_tool_calls: bool,
 }
 
 impl Default for AgentConfig {
@@ -58,16 +122,74 @@ impl Default for AgentConfig {
             require_done_tool: false,
             max_iterations: 24,
             system_prompt: None,
+            example_turns: Vec::new(),
             tool_choice: AgentToolChoice::Auto,
             llm_max_retries: 5,
             llm_retry_base_delay_ms: 1_000,
             llm_retry_max_delay_ms: 60_000,
+            retry_jitter: true,
+            retry_jitter_seed: None,
             hidden_user_message_prompt: None,
+            max_hidden_prompt_injections: 1,
+            parallel_tool_calls: false,
+            run_timeout: None,
+            tool_timeout: None,
+            max_tool_result_chars: None,
+            compaction: None,
+            max_history_messages: None,
+            max_input_tokens: None,
+            min_request_interval: None,
+            max_consecutive_tool_errors: None,
+            abort_on_tool_error: false,
+            max_empty_completions: Some(3),
+            max_tool_calls: None,
+            assistant_prefill: None,
+            capture_raw_responses: false,
+            dedupe_tool_calls: false,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// When and how the agent shrinks its history before a model invocation.
+///
+/// The system prompt and the `keep_recent` newest messages are always kept verbatim.
+pub struct CompactionPolicy {
+    /// Condition that starts a compaction.
+    pub trigger: CompactionTrigger,
+    /// How older messages are reduced.
+    pub strategy: CompactionStrategy,
+    /// Number of most recent messages left untouched.
+    pub keep_recent: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Threshold for [`CompactionPolicy`].
+pub enum CompactionTrigger {
+    /// Compact once history holds more than this many messages.
+    MessageCount(usize),
+    /// Compact once the estimated history size exceeds this many tokens.
+    EstimatedTokens(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Reduction applied by [`CompactionPolicy`].
+pub enum CompactionStrategy {
+    /// Ask the model to summarize older messages and replace them with one summary note.
+    Summarize,
+    /// Replace the content of older tool results with a short placeholder.
+    DropToolResults,
+}
+
+const COMPACTION_PROMPT: &str = "Summarize the conversation below so the assistant can continue the task. Keep decisions, facts learned from tools, file names, and open work. Be concise.";
+const COMPACTED_TOOL_RESULT: &str = "[tool result removed during history compaction]";
+const TOOL_ERROR_ABORT_NOTE: &str = "Tool call skipped: run aborted after a tool error.";
+const STOP_CONDITION_NOTE: &str = "Tool call skipped: run ended by a stop condition.";
+/// Retries allowed by [`QueryOptions::json_object`] and [`QueryOptions::json_schema`].
+const DEFAULT_RESPONSE_FORMAT_RETRIES: u32 = 2;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// Message role used in emitted stream events.
 pub enum AgentRole {
     /// End-user message.
@@ -77,6 +199,7 @@ pub enum AgentRole {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// Final status for a tool execution step.
 pub enum StepStatus {
     /// Tool step succeeded.
@@ -86,7 +209,12 @@ pub enum StepStatus {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "type"))]
 /// Streamed events emitted by [`Agent::query_stream`].
+///
+/// With the `serde` feature, events serialize with a `type` tag naming the variant
+/// (`{"type":"ToolCall",...}`), one compact object per line for NDJSON logs, so a recorded
+/// stream can be replayed with [`Agent::from_event_log`].
 pub enum AgentEvent {
     /// A new message started.
     MessageStart {
@@ -101,12 +229,32 @@ pub enum AgentEvent {
         message_id: String,
         /// Rendered message content.
         content: String,
+        /// True when this assistant message is the answer returned in
+        /// [`AgentEvent::FinalResponse`].
+        #[cfg_attr(feature = "serde", serde(default))]
+        is_final: bool,
     },
     /// Hidden user prompt injected by config.
     HiddenUserMessage {
         /// Hidden prompt content.
         content: String,
     },
+    /// An agent loop iteration started.
+    ///
+    /// Each pass covers one model call plus the tool steps it requested.
+    IterationStart {
+        /// 1-based iteration number within this run.
+        iteration: u32,
+        /// Configured iteration limit.
+        max_iterations: u32,
+    },
+    /// An agent loop iteration finished.
+    ///
+    /// Not emitted when the pass ends in cancellation, an input request, or an error.
+    IterationComplete {
+        /// 1-based iteration number within this run.
+        iteration: u32,
+    },
     /// A tool execution step started.
     StepStart {
         /// Tool-call id.
@@ -123,18 +271,35 @@ pub enum AgentEvent {
         /// Step completion status.
         status: StepStatus,
         /// Execution duration in milliseconds.
+        #[cfg_attr(feature = "serde", serde(with = "duration_ms_serde"))]
         duration_ms: u128,
     },
     /// Model returned reasoning/thinking text.
+    ///
+    /// Streaming adapters emit one event per delta; buffered adapters emit the full text once.
     Thinking {
         /// Thinking content.
         content: String,
     },
     /// Model returned regular text content.
+    ///
+    /// Streaming adapters emit one event per delta as it arrives; buffered adapters emit the
+    /// full text once. Whether the text was the final answer is reported on the message's
+    /// [`AgentEvent::MessageComplete`].
     Text {
         /// Text content.
         content: String,
     },
+    /// A fragment of a tool call's arguments arrived from a streaming model.
+    ///
+    /// Fragments are not valid JSON on their own; the assembled arguments follow in
+    /// [`AgentEvent::ToolCall`] once the model turn ends.
+    ToolCallDelta {
+        /// Provider/tool-call id.
+        tool_call_id: String,
+        /// Raw argument text in arrival order.
+        partial_json: String,
+    },
     /// Model requested a tool call.
     ToolCall {
         /// Tool name.
@@ -155,17 +320,259 @@ pub enum AgentEvent {
         /// Whether this tool result represents an error.
         is_error: bool,
     },
+    /// Token usage reported for the latest model invocation.
+    Usage {
+        /// Input tokens for this invocation.
+        input_tokens: u32,
+        /// Output tokens for this invocation.
+        output_tokens: u32,
+        /// Input tokens accumulated across the current run.
+        cumulative_input: u32,
+        /// Output tokens accumulated across the current run.
+        cumulative_output: u32,
+    },
     /// Final response for the query.
     FinalResponse {
         /// Final assistant output.
         content: String,
+        /// Structured result from [`ToolOutcome::DoneWithData`], if the run ended that way.
+        data: Option<serde_json::Value>,
+    },
+    /// A request-level model failure is about to be retried after a backoff delay.
+    Retry {
+        /// 1-based attempt number that failed.
+        attempt: u32,
+        /// Maximum number of attempts for this invocation.
+        max_attempts: u32,
+        /// Backoff delay before the next attempt.
+        delay_ms: u64,
+        /// Rendered provider error.
+        error: String,
+    },
+    /// A tool paused the run to ask the human operator for input.
+    ///
+    /// The stream ends after this event. Answer with [`Agent::provide_input`] and call
+    /// [`Agent::query_stream`] with an empty message to resume.
+    InputRequested {
+        /// Question for the operator.
+        prompt: String,
+        /// Tool-call id awaiting the answer.
+        tool_call_id: String,
+    },
+    /// The run was stopped through its [`CancellationToken`].
+    Cancelled {
+        /// Point in the loop where cancellation was observed.
+        reason: String,
+    },
+    /// Older history was compacted according to [`AgentConfig::compaction`].
+    HistoryCompacted {
+        /// Messages removed (summarize) or tool results cleared (drop).
+        removed: usize,
+        /// Length of the inserted summary; `0` when no summary was written.
+        summary_len: usize,
+    },
+    /// The oldest messages were evicted to honor [`AgentConfig::max_history_messages`].
+    HistoryTruncated {
+        /// Messages removed from history.
+        dropped: usize,
+    },
+    /// The model finished without tool calls while a hidden user prompt is configured, but the
+    /// prompt was not injected.
+    NudgeSkipped {
+        /// Why the prompt was not injected.
+        reason: String,
+    },
+    /// Provider response body behind the current assistant message.
+    ///
+    /// Only emitted with [`AgentConfig::capture_raw_responses`] and when the adapter captured it.
+    RawCompletion {
+        /// Response JSON as returned by the provider.
+        value: serde_json::Value,
+    },
+    /// A tool result in history references no earlier tool call.
+    ///
+    /// Checked before each model invocation and reported once per result. The result is still
+    /// sent, but providers that correlate results by id may reject the request.
+    UnmatchedToolResult {
+        /// Id the orphaned result refers to.
+        tool_call_id: String,
+    },
+}
+
+/// Serializes `u128` millisecond counts as `u64`; tagged enums cannot buffer `u128` values.
+#[cfg(feature = "serde")]
+mod duration_ms_serde {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(*value).unwrap_or(u64::MAX))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<u128, D::Error> {
+        u64::deserialize(deserializer).map(u128::from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Serializable checkpoint of an [`Agent`]'s conversation state.
+///
+/// Created by [`Agent::snapshot`] and applied with [`Agent::restore`]. Tools, model, and config
+/// are not captured; rebuild the agent with the same builder before restoring.
+pub struct AgentSnapshot {
+    /// Full message history, including the system prompt if one was injected.
+    pub messages: Vec<ModelMessage>,
+    /// Counter used to generate the next message id.
+    pub next_message_id: u64,
+    /// Token usage accumulated during the most recent run.
+    pub last_run_usage: ModelUsage,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Aggregated tool activity for one run, returned by [`Agent::tool_metrics`].
+pub struct ToolMetrics {
+    /// Tool calls that produced a result, including denied and failed ones.
+    pub calls: u32,
+    /// Calls whose result was an error.
+    pub errors: u32,
+    /// Summed execution time, at millisecond resolution.
+    pub total_duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// One registered tool, returned by [`Agent::tool_catalog`].
+pub struct ToolInfo {
+    /// Tool name the model calls.
+    pub name: String,
+    /// Tool description sent to the model.
+    pub description: String,
+    /// JSON schema for the tool's arguments.
+    pub schema: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Everything a run produced, returned by [`Agent::query_with_trace`].
+pub struct QueryResult {
+    /// Final response text, if the run finished.
+    pub final_response: Option<String>,
+    /// Structured data from [`ToolOutcome::DoneWithData`], if any.
+    pub final_data: Option<serde_json::Value>,
+    /// Tool results in the order they were recorded.
+    pub tool_results: Vec<ToolResultRecord>,
+    /// Token usage accumulated across the run.
+    pub usage: ModelUsage,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Per-query model overrides for [`Agent::query_with_options`].
+///
+/// Unset fields keep the model adapter's configured values.
+pub struct QueryOptions {
+    /// Max output tokens for each model invocation in the query.
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature for each model invocation in the query.
+    pub temperature: Option<f32>,
+    /// Sequences that stop generation.
+    pub stop_sequences: Vec<String>,
+    /// Requires the final response to be JSON; it is parsed into
+    /// [`AgentEvent::FinalResponse`] `data`.
+    ///
+    /// Adapters without a native JSON mode ignore the constraint, so a non-JSON reply is
+    /// answered with a correction request instead, up to `response_format_retries` times.
+    pub response_format: Option<ResponseFormat>,
+    /// Correction requests sent when the final response does not match `response_format`.
+    pub response_format_retries: u32,
+}
+
+impl QueryOptions {
+    /// Options requiring the final response to be a JSON object.
+    pub fn json_object() -> Self {
+        Self {
+            response_format: Some(ResponseFormat::JsonObject),
+            response_format_retries: DEFAULT_RESPONSE_FORMAT_RETRIES,
+            ..Self::default()
+        }
+    }
+
+    /// Options requiring the final response to be JSON matching `schema`.
+    pub fn json_schema(schema: serde_json::Value) -> Self {
+        Self {
+            response_format: Some(ResponseFormat::JsonSchema(schema)),
+            response_format_retries: DEFAULT_RESPONSE_FORMAT_RETRIES,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<QueryOptions> for ModelCallOptions {
+    fn from(options: QueryOptions) -> Self {
+        Self {
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            stop_sequences: options.stop_sequences,
+            assistant_prefill: None,
+            capture_raw: false,
+            response_format: options.response_format,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Provider request built by [`Agent::preview_request`].
+pub struct PreviewRequest {
+    /// Messages that would be sent, including the system prompt and the new user message.
+    pub messages: Vec<ModelMessage>,
+    /// Tool definitions that would be advertised.
+    pub tools: Vec<ModelToolDefinition>,
+    /// Tool choice after applying the agent's policy.
+    pub tool_choice: ModelToolChoice,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// One tool result captured in a [`QueryResult`].
+pub struct ToolResultRecord {
+    /// Tool call ID.
+    pub tool_call_id: String,
+    /// Tool name.
+    pub tool: String,
+    /// Tool output text sent back to the model.
+    pub result_text: String,
+    /// Whether the tool result represents an error.
+    pub is_error: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Verdict returned by an [`AgentBuilder::on_tool_call`] hook.
+pub enum ToolCallDecision {
+    /// Execute the call as requested.
+    Allow,
+    /// Skip execution and report the reason to the model as an error result.
+    Deny {
+        /// Explanation surfaced in the tool result.
+        reason: String,
     },
+    /// Execute the call with replacement arguments.
+    Rewrite(serde_json::Value),
 }
 
+type ToolCallHook = dyn Fn(&ModelToolCall) -> ToolCallDecision + Send + Sync;
+type ToolResultMapper = dyn Fn(&str, String, bool) -> String + Send + Sync;
+type TokenEstimator = dyn Fn(&[ModelMessage]) -> usize + Send + Sync;
+type ToolFilter = dyn Fn(&[ToolSpec], &[ModelMessage]) -> Vec<String> + Send + Sync;
+type StopCondition = dyn Fn(&AgentEvent) -> Option<String> + Send + Sync;
+
 /// Builder for [`Agent`].
 pub struct AgentBuilder {
     model: Option<Arc<dyn ChatModel>>,
     tools: Vec<ToolSpec>,
+    tool_call_hook: Option<Arc<ToolCallHook>>,
+    tool_result_mapper: Option<Arc<ToolResultMapper>>,
+    tool_filter: Option<Arc<ToolFilter>>,
+    stop_condition: Option<Arc<StopCondition>>,
+    model_io_hook: Option<Arc<ModelIoHook>>,
+    token_estimator: Arc<TokenEstimator>,
+    clock: Arc<dyn Clock>,
     config: AgentConfig,
     dependencies: DependencyMap,
     dependency_overrides: DependencyMap,
@@ -176,6 +583,13 @@ impl Default for AgentBuilder {
         Self {
             model: None,
             tools: Vec::new(),
+            tool_call_hook: None,
+            tool_result_mapper: None,
+            tool_filter: None,
+            stop_condition: None,
+            model_io_hook: None,
+            token_estimator: Arc::new(estimate_tokens),
+            clock: Arc::new(TokioClock),
             config: AgentConfig::default(),
             dependencies: DependencyMap::new(),
             dependency_overrides: DependencyMap::new(),
@@ -205,6 +619,17 @@ impl AgentBuilder {
         self
     }
 
+    /// Adds tools renamed to `{prefix}__{name}`, so tool sets that reuse names can coexist.
+    ///
+    /// The model sees and calls the prefixed names.
+    pub fn tools_namespaced(mut self, prefix: &str, tools: Vec<ToolSpec>) -> Self {
+        self.tools.extend(tools.into_iter().map(|tool| {
+            let name = format!("{prefix}__{}", tool.name());
+            tool.with_name(name)
+        }));
+        self
+    }
+
     /// Replaces the full agent config.
     pub fn config(mut self, config: AgentConfig) -> Self {
         self.config = config;
@@ -217,6 +642,17 @@ impl AgentBuilder {
         self
     }
 
+    /// Primes the conversation with example user, assistant, and tool turns.
+    ///
+    /// The examples are inserted after the system prompt when the first query starts and stay
+    /// at the head of history. They are sent to the model like any other message, but are not
+    /// counted by [`Agent::messages_len`], [`Agent::tool_call_counts`], or
+    /// [`Agent::tool_results`], and history compaction leaves them alone.
+    pub fn example_turns(mut self, examples: Vec<ModelMessage>) -> Self {
+        self.config.example_turns = examples;
+        self
+    }
+
     /// Enables or disables explicit `done` completion mode.
     pub fn require_done_tool(mut self, require_done_tool: bool) -> Self {
         self.config.require_done_tool = require_done_tool;
@@ -230,6 +666,9 @@ impl AgentBuilder {
     }
 
     /// Sets tool-choice policy for model invocations.
+    ///
+    /// [`AgentBuilder::build`] rejects `Required` and `Tool` without registered tools, and
+    /// `Tool` or `PreferTool` naming a tool that is not registered.
     pub fn tool_choice(mut self, tool_choice: AgentToolChoice) -> Self {
         self.config.tool_choice = tool_choice;
         self
@@ -248,12 +687,253 @@ impl AgentBuilder {
         self
     }
 
-    /// Sets a hidden user prompt injected once if model returns no tool calls.
+    /// Enables or disables full jitter on retry delays (enabled by default).
+    pub fn retry_jitter(mut self, enabled: bool) -> Self {
+        self.config.retry_jitter = enabled;
+        self
+    }
+
+    /// Seeds the retry jitter generator so delays are reproducible.
+    pub fn retry_jitter_seed(mut self, seed: u64) -> Self {
+        self.config.retry_jitter_seed = Some(seed);
+        self
+    }
+
+    /// Sets a hidden user prompt injected once per conversation if model returns no tool calls.
+    ///
+    /// Later queries on the same agent reuse the count, so a chat loop is nudged once rather
+    /// than on every message; call [`Agent::reset_nudge`] to allow it again. Raise the limit
+    /// with [`AgentBuilder::max_hidden_prompt_injections`]. It is never
+    /// injected with [`AgentBuilder::require_done_tool`]; [`AgentEvent::NudgeSkipped`] reports
+    /// each skipped nudge.
     pub fn hidden_user_message_prompt(mut self, prompt: impl Into<String>) -> Self {
         self.config.hidden_user_message_prompt = Some(prompt.into());
         self
     }
 
+    /// Lets the hidden user prompt be injected up to `max` times per conversation instead of once.
+    pub fn max_hidden_prompt_injections(mut self, max: u32) -> Self {
+        self.config.max_hidden_prompt_injections = max;
+        self
+    }
+
+    /// Runs tool calls from the same assistant turn concurrently.
+    ///
+    /// Events and history entries are still emitted in the order the model requested the calls.
+    pub fn parallel_tool_calls(mut self, parallel_tool_calls: bool) -> Self {
+        self.config.parallel_tool_calls = parallel_tool_calls;
+        self
+    }
+
+    /// Sets a wall-clock deadline for each query.
+    ///
+    /// The deadline is checked at iteration and tool-call boundaries; a running tool or model
+    /// call is never interrupted.
+    pub fn run_timeout(mut self, run_timeout: Duration) -> Self {
+        self.config.run_timeout = Some(run_timeout);
+        self
+    }
+
+    /// Sets a per-call timeout for tool handlers.
+    ///
+    /// A timed-out call is recorded as an error result and the loop continues.
+    pub fn tool_timeout(mut self, tool_timeout: Duration) -> Self {
+        self.config.tool_timeout = Some(tool_timeout);
+        self
+    }
+
+    /// Compacts older history before model invocations according to `policy`.
+    pub fn compaction(mut self, policy: CompactionPolicy) -> Self {
+        self.config.compaction = Some(policy);
+        self
+    }
+
+    /// Keeps at most `max` messages in history by evicting the oldest ones before each model
+    /// invocation.
+    ///
+    /// The system prompt, example turns, system notes from [`Agent::push_system_note`], and the
+    /// latest user message are never evicted, so history can stay above `max` when they alone
+    /// exceed it. Eviction continues past the cap until the oldest kept message is a user
+    /// message, so providers always see the conversation open with a user turn and tool calls
+    /// leave together with their results. Inside a long tool loop, the oldest assistant turns
+    /// after the latest user message are evicted together with their results; the newest turn
+    /// is always kept. Runs after [`AgentBuilder::compaction`] and reports
+    /// each eviction with [`AgentEvent::HistoryTruncated`].
+    pub fn max_history_messages(mut self, max: usize) -> Self {
+        self.config.max_history_messages = Some(max);
+        self
+    }
+
+    /// Truncates tool result text longer than `max_chars` characters.
+    ///
+    /// The cap also applies to error results such as unknown-tool lists and timeout messages, after
+    /// any [`AgentBuilder::map_tool_result`] hook. Completion messages from `ToolOutcome::Done` are
+    /// never truncated.
+    pub fn max_tool_result_chars(mut self, max_chars: usize) -> Self {
+        self.config.max_tool_result_chars = Some(max_chars);
+        self
+    }
+
+    /// Spaces consecutive model invocations in one query at least `interval` apart.
+    ///
+    /// Applies to every attempt, on top of any retry backoff.
+    pub fn min_request_interval(mut self, interval: Duration) -> Self {
+        self.config.min_request_interval = Some(interval);
+        self
+    }
+
+    /// Aborts with [`AgentError::ToolErrorLoop`] after `max_errors` failed tool results in a row.
+    ///
+    /// Any successful tool result resets the count.
+    pub fn max_consecutive_tool_errors(mut self, max_errors: u32) -> Self {
+        self.config.max_consecutive_tool_errors = Some(max_errors);
+        self
+    }
+
+    /// Ends the run with [`AgentError::Tool`] on the first failed tool result, for scripted
+    /// runs where a tool error should not be worked around.
+    ///
+    /// The failing call's `ToolResult` and `StepComplete` events are emitted first, and calls
+    /// after it in the same turn are recorded as skipped.
+    pub fn abort_on_tool_error(mut self, enabled: bool) -> Self {
+        self.config.abort_on_tool_error = enabled;
+        self
+    }
+
+    /// Aborts with [`AgentError::EmptyCompletionLoop`] after `max_empty` fully empty completions
+    /// in a row while [`AgentBuilder::require_done_tool`] is set. Defaults to 3.
+    pub fn max_empty_completions(mut self, max_empty: u32) -> Self {
+        self.config.max_empty_completions = Some(max_empty);
+        self
+    }
+
+    /// Aborts with [`AgentError::MaxToolCallsReached`] instead of executing more than
+    /// `max_tool_calls` tool calls in one run.
+    ///
+    /// Calls that would exceed the cap are recorded as skipped. With parallel tool calls the
+    /// whole batch is skipped when it does not fit in the remaining budget.
+    pub fn max_tool_calls(mut self, max_tool_calls: u32) -> Self {
+        self.config.max_tool_calls = Some(max_tool_calls);
+        self
+    }
+
+    /// Starts every assistant turn with `prefill`, for example `{` to force a JSON reply.
+    ///
+    /// Anthropic continues from a real prefill and the returned text includes it. Providers
+    /// without prefill support receive a system instruction to begin with the text instead.
+    pub fn assistant_prefill(mut self, prefill: impl Into<String>) -> Self {
+        self.config.assistant_prefill = Some(prefill.into());
+        self
+    }
+
+    /// Emits [`AgentEvent::RawCompletion`] with each provider response, for debugging what
+    /// normalization dropped.
+    pub fn capture_raw_responses(mut self, enabled: bool) -> Self {
+        self.config.capture_raw_responses = enabled;
+        self
+    }
+
+    /// Reuses the result of an identical earlier tool call in the same run instead of
+    /// executing it again.
+    ///
+    /// Arguments are compared after sorting object keys. Only successful results are reused,
+    /// and any call whose handler actually runs clears the cache, since it may have changed what
+    /// earlier calls observed (`read`, `edit`, `read` runs both reads). Denied, unknown,
+    /// malformed, and timed-out calls leave the cache alone.
+    pub fn dedupe_tool_calls(mut self, enabled: bool) -> Self {
+        self.config.dedupe_tool_calls = enabled;
+        self
+    }
+
+    /// Registers a hook that observes every model invocation and its result.
+    ///
+    /// The hook sees the full request and response; filter or redact inside it as needed.
+    pub fn on_model_io<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ModelIo<'_>) + Send + Sync + 'static,
+    {
+        self.model_io_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Rejects a model invocation whose estimated input exceeds `max_tokens`.
+    ///
+    /// The run fails with [`AgentError::InputTooLarge`] before the provider is called.
+    pub fn max_input_tokens(mut self, max_tokens: usize) -> Self {
+        self.config.max_input_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Replaces the default chars/4 token estimator used for input limits and compaction.
+    pub fn token_estimator<F>(mut self, estimator: F) -> Self
+    where
+        F: Fn(&[ModelMessage]) -> usize + Send + Sync + 'static,
+    {
+        self.token_estimator = Arc::new(estimator);
+        self
+    }
+
+    /// Replaces the [`TokioClock`] used for retry backoff, request pacing, and run deadlines.
+    pub fn clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Registers a hook that reviews every tool call before it runs.
+    ///
+    /// Denied calls are recorded as error results; rewritten arguments are used for execution and
+    /// reported in the emitted [`AgentEvent::ToolCall`].
+    pub fn on_tool_call<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&ModelToolCall) -> ToolCallDecision + Send + Sync + 'static,
+    {
+        self.tool_call_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Registers a transform applied to every tool result before it is stored in history.
+    ///
+    /// The hook receives the tool name, the result text, and whether it is an error, and returns
+    /// the text to keep; use it to strip paths, redact secrets, or shorten long output. It sees
+    /// every result, including denied calls, cached results, and errors such as unknown tools or
+    /// timeouts. The mapped text is also what [`AgentEvent::ToolResult`] reports.
+    pub fn map_tool_result<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&str, String, bool) -> String + Send + Sync + 'static,
+    {
+        self.tool_result_mapper = Some(Arc::new(hook));
+        self
+    }
+
+    /// Chooses which registered tools are offered on each model invocation.
+    ///
+    /// The filter receives all tools and the current history, and returns the names to offer.
+    /// Unknown names are ignored; registration order is kept.
+    pub fn tool_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&[ToolSpec], &[ModelMessage]) -> Vec<String> + Send + Sync + 'static,
+    {
+        self.tool_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Registers a custom stop condition checked after each event a run emits.
+    ///
+    /// Returning `Some(content)` ends the run right after that event with
+    /// [`AgentEvent::FinalResponse`] carrying `content`; tool calls of the current turn that
+    /// have no result yet are recorded as skipped. The condition is not checked on the run's own
+    /// [`AgentEvent::FinalResponse`], so `done` and other completions take precedence.
+    pub fn stop_when<F>(mut self, condition: F) -> Self
+    where
+        F: Fn(&AgentEvent) -> Option<String> + Send + Sync + 'static,
+    {
+        self.stop_condition = Some(Arc::new(condition));
+        self
+    }
+
     /// Inserts a typed runtime dependency.
     pub fn dependency<T>(self, value: T) -> Self
     where
@@ -292,11 +972,14 @@ impl AgentBuilder {
 
     /// Builds an [`Agent`] and validates required config.
     pub fn build(self) -> Result<Agent, AgentError> {
-        let Some(model) = self.model else {
+        let Some(mut model) = self.model else {
             return Err(AgentError::Config(
                 "agent model must be configured via AgentBuilder::model(...)".to_string(),
             ));
         };
+        if let Some(hook) = self.model_io_hook {
+            model = Arc::new(ObservedModel::new(model, hook));
+        }
 
         let mut tool_map = HashMap::new();
         for tool in &self.tools {
@@ -310,16 +993,37 @@ impl AgentBuilder {
                 )));
             }
         }
+        validate_tool_choice(&self.config.tool_choice, &tool_map)?;
 
+        let retry_rng = JitterRng::new(self.config.retry_jitter_seed);
         Ok(Agent {
             model,
             tools: self.tools,
             tool_map,
+            tool_call_hook: self.tool_call_hook,
+            tool_result_mapper: self.tool_result_mapper,
+            tool_filter: self.tool_filter,
+            stop_condition: self.stop_condition,
+            token_estimator: self.token_estimator,
+            clock: self.clock,
             config: self.config,
             dependencies: self.dependencies,
             dependency_overrides: self.dependency_overrides,
             history: Vec::new(),
             next_message_id: 0,
+            run_usage: ModelUsage::default(),
+            pending_input: None,
+            resume_after_input: false,
+            last_request_at: None,
+            tool_error_streak: 0,
+            last_tool_error: None,
+            hidden_prompts_injected: 0,
+            executed_tool_calls: HashMap::new(),
+            reported_unmatched_tool_results: HashSet::new(),
+            priming: 0..0,
+            tool_metrics: HashMap::new(),
+            malformed_tool_calls: HashMap::new(),
+            retry_rng,
         })
     }
 }
@@ -329,11 +1033,36 @@ pub struct Agent {
     model: Arc<dyn ChatModel>,
     tools: Vec<ToolSpec>,
     tool_map: HashMap<String, ToolSpec>,
+    tool_call_hook: Option<Arc<ToolCallHook>>,
+    tool_result_mapper: Option<Arc<ToolResultMapper>>,
+    tool_filter: Option<Arc<ToolFilter>>,
+    stop_condition: Option<Arc<StopCondition>>,
+    token_estimator: Arc<TokenEstimator>,
+    clock: Arc<dyn Clock>,
     config: AgentConfig,
     dependencies: DependencyMap,
     dependency_overrides: DependencyMap,
     history: Vec<ModelMessage>,
     next_message_id: u64,
+    run_usage: ModelUsage,
+    pending_input: Option<PendingInput>,
+    resume_after_input: bool,
+    last_request_at: Option<Instant>,
+    tool_error_streak: u32,
+    last_tool_error: Option<String>,
+    /// Hidden user prompts injected since the conversation started; see [`Agent::reset_nudge`].
+    hidden_prompts_injected: u32,
+    /// Successful results of tool calls since the last executed call, keyed by
+    /// [`tool_call_key`].
+    executed_tool_calls: HashMap<String, String>,
+    /// Ids already reported with [`AgentEvent::UnmatchedToolResult`].
+    reported_unmatched_tool_results: HashSet<String>,
+    /// Position of the inserted [`AgentConfig::example_turns`] in `history`.
+    priming: Range<usize>,
+    tool_metrics: HashMap<String, ToolMetrics>,
+    /// Parse errors for the current turn's streamed tool calls, keyed by tool-call id.
+    malformed_tool_calls: HashMap<String, String>,
+    retry_rng: JitterRng,
 }
 
 impl Agent {
@@ -345,92 +1074,633 @@ impl Agent {
     /// Clears conversation history and resets message-id counter.
     pub fn clear_history(&mut self) {
         self.history.clear();
+        self.priming = 0..0;
         self.next_message_id = 0;
+        self.hidden_prompts_injected = 0;
+        self.reported_unmatched_tool_results.clear();
+        self.clear_pending_input();
+    }
+
+    /// Lets the hidden user prompt be injected again, as if the conversation had just started.
+    ///
+    /// Injections of [`AgentConfig::hidden_user_message_prompt`] are counted across queries;
+    /// clearing, loading, or restoring history resets the count too.
+    pub fn reset_nudge(&mut self) {
+        self.hidden_prompts_injected = 0;
     }
 
     /// Replaces history with a preloaded message sequence.
     pub fn load_history(&mut self, messages: Vec<ModelMessage>) {
         self.next_message_id = messages.len() as u64;
         self.history = messages;
+        self.priming = 0..0;
+        self.hidden_prompts_injected = 0;
+        self.reported_unmatched_tool_results.clear();
+        self.clear_pending_input();
+    }
+
+    /// Updates the system prompt and applies it to the head of the current history.
+    ///
+    /// An existing leading system message is replaced; otherwise one is inserted.
+    pub fn set_system_prompt(&mut self, prompt: impl Into<String>) {
+        let prompt = prompt.into();
+        self.config.system_prompt = Some(prompt.clone());
+
+        match self.history.first_mut() {
+            Some(ModelMessage::System(existing)) => *existing = prompt,
+            _ => {
+                self.history.insert(0, ModelMessage::System(prompt));
+                if !self.priming.is_empty() {
+                    self.priming = self.priming.start + 1..self.priming.end + 1;
+                }
+            }
+        }
+    }
+
+    /// Appends a system-level note to the conversation, effective from the next model call.
+    ///
+    /// Use this to shift policy mid-session (for example "you are now in review mode") without
+    /// replacing the system prompt. Providers handle the note differently:
+    /// - Anthropic, Google and Bedrock fold it into the system instruction block.
+    /// - OpenAI, Azure OpenAI and Grok send it inline as a `system` role message.
+    pub fn push_system_note(&mut self, text: impl Into<String>) {
+        if self.history.is_empty()
+            && let Some(system_prompt) = &self.config.system_prompt
+        {
+            self.history
+                .push(ModelMessage::System(system_prompt.clone()));
+        }
+        self.history.push(ModelMessage::System(text.into()));
     }
 
-    /// Returns number of history messages.
+    /// Returns number of history messages, not counting example turns.
     pub fn messages_len(&self) -> usize {
-        self.history.len()
+        self.history.len() - self.priming.len()
     }
 
-    /// Returns current history slice.
+    /// Returns current history slice, including any example turns.
     pub fn messages(&self) -> &[ModelMessage] {
         &self.history
     }
 
+    /// History without the example turns.
+    fn conversation(&self) -> impl Iterator<Item = &ModelMessage> {
+        self.history
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.priming.contains(index))
+            .map(|(_, message)| message)
+    }
+
+    /// Where [`AgentConfig::example_turns`] go, if they are due: after the leading system
+    /// messages of a history that has no user message yet.
+    fn example_turns_position(&self, history: &[ModelMessage]) -> Option<usize> {
+        if self.config.example_turns.is_empty()
+            || !self.priming.is_empty()
+            || history
+                .iter()
+                .any(|message| matches!(message, ModelMessage::User(_)))
+        {
+            return None;
+        }
+        Some(
+            history
+                .iter()
+                .take_while(|message| matches!(message, ModelMessage::System(_)))
+                .count(),
+        )
+    }
+
+    /// Counts tool calls requested by the model in the current history, keyed by tool name.
+    pub fn tool_call_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for message in self.conversation() {
+            if let ModelMessage::Assistant { tool_calls, .. } = message {
+                for tool_call in tool_calls {
+                    *counts.entry(tool_call.name.clone()).or_default() += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// Lists recorded tool results in history order as `(tool name, is_error)`.
+    pub fn tool_results(&self) -> Vec<(String, bool)> {
+        self.conversation()
+            .filter_map(|message| match message {
+                ModelMessage::ToolResult {
+                    tool_name,
+                    is_error,
+                    ..
+                } => Some((tool_name.clone(), *is_error)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Builds a tool-less agent whose history is rebuilt from a recorded event stream.
+    ///
+    /// See [`Agent::load_event_log`] for how events map onto history. Use the builder plus
+    /// `load_event_log` when the resumed agent needs tools.
+    pub fn from_event_log<M>(
+        events: &[AgentEvent],
+        model: M,
+        config: AgentConfig,
+    ) -> Result<Agent, AgentError>
+    where
+        M: ChatModel + 'static,
+    {
+        let mut agent = Agent::builder().model(model).config(config).build()?;
+        agent.load_event_log(events)?;
+        Ok(agent)
+    }
+
+    /// Replaces history with messages replayed from a recorded event stream.
+    ///
+    /// `MessageComplete`, `HiddenUserMessage`, `ToolCall`, and `ToolResult` events become
+    /// history messages and the message-id counter continues after the last recorded id. The
+    /// configured system prompt is placed first. Logs that cannot be replayed exactly, such as
+    /// ones with unmatched tool calls, compaction, truncation, or operator input, return
+    /// [`AgentError::InvalidEventLog`] and leave history unchanged. Tool-result images are not
+    /// part of the event stream and are not restored.
+    pub fn load_event_log(&mut self, events: &[AgentEvent]) -> Result<(), AgentError> {
+        let (mut history, next_message_id) = replay::history_from_events(events)?;
+        if let Some(system_prompt) = &self.config.system_prompt {
+            history.insert(0, ModelMessage::System(system_prompt.clone()));
+        }
+        self.history = history;
+        self.priming = 0..0;
+        self.hidden_prompts_injected = 0;
+        self.reported_unmatched_tool_results.clear();
+        self.next_message_id = next_message_id;
+        self.clear_pending_input();
+        Ok(())
+    }
+
+    /// Captures history and run state so the conversation can be resumed later.
+    pub fn snapshot(&self) -> AgentSnapshot {
+        AgentSnapshot {
+            messages: self.history.clone(),
+            next_message_id: self.next_message_id,
+            last_run_usage: self.run_usage.clone(),
+        }
+    }
+
+    /// Replaces history and run state with a previously captured snapshot.
+    pub fn restore(&mut self, snapshot: AgentSnapshot) {
+        self.history = snapshot.messages;
+        self.priming = 0..0;
+        self.hidden_prompts_injected = 0;
+        self.reported_unmatched_tool_results.clear();
+        self.next_message_id = snapshot.next_message_id;
+        self.run_usage = snapshot.last_run_usage;
+        self.clear_pending_input();
+    }
+
+    /// Answers a pending [`AgentEvent::InputRequested`] by recording `answer` as the tool result.
+    ///
+    /// A following [`Agent::query_stream`] call with an empty message resumes the loop.
+    pub fn provide_input(
+        &mut self,
+        tool_call_id: &str,
+        answer: impl Into<String>,
+    ) -> Result<(), AgentError> {
+        let Some(pending) = self
+            .pending_input
+            .take_if(|pending| pending.tool_call.id == tool_call_id)
+        else {
+            return Err(AgentError::Config(format!(
+                "no pending input request for tool call '{tool_call_id}'"
+            )));
+        };
+
+        self.history.push(ModelMessage::ToolResult {
+            tool_call_id: pending.tool_call.id,
+            tool_name: pending.tool_call.name,
+            content: answer.into(),
+            is_error: false,
+            images: Vec::new(),
+        });
+        self.record_skipped_tool_calls(
+            &pending.skipped,
+            "Tool call skipped: an earlier call in this turn requested user input.",
+        );
+        self.resume_after_input = true;
+        Ok(())
+    }
+
+    /// Returns the tool-call id currently waiting on [`Agent::provide_input`], if any.
+    pub fn pending_input(&self) -> Option<&str> {
+        self.pending_input
+            .as_ref()
+            .map(|pending| pending.tool_call.id.as_str())
+    }
+
+    /// Returns token usage accumulated during the most recent run.
+    pub fn last_run_usage(&self) -> ModelUsage {
+        self.run_usage.clone()
+    }
+
+    /// Returns per-tool call counts, error counts, and durations for the most recent run.
+    pub fn tool_metrics(&self) -> HashMap<String, ToolMetrics> {
+        self.tool_metrics.clone()
+    }
+
+    /// Returns the name, description, and argument schema of every registered tool.
+    ///
+    /// Lists all tools in registration order, regardless of any [`AgentBuilder::tool_filter`].
+    pub fn tool_catalog(&self) -> Vec<ToolInfo> {
+        self.tools
+            .iter()
+            .map(|tool| ToolInfo {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                schema: tool.json_schema().clone(),
+            })
+            .collect()
+    }
+
+    /// Renders [`Agent::tool_catalog`] as a Markdown list, one tool per item with its arguments.
+    pub fn tools_markdown(&self) -> String {
+        let mut rendered = String::new();
+        for tool in self.tool_catalog() {
+            rendered.push_str(&format!("- `{}`: {}\n", tool.name, tool.description));
+            let required = tool
+                .schema
+                .get("required")
+                .and_then(serde_json::Value::as_array)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let Some(properties) = tool
+                .schema
+                .get("properties")
+                .and_then(serde_json::Value::as_object)
+            else {
+                continue;
+            };
+            for (name, property) in properties {
+                let kind = property
+                    .get("type")
+                    .and_then(serde_json::Value::as_str)
+                    .unwrap_or("any");
+                let requirement = if required.iter().any(|field| field == name) {
+                    "required"
+                } else {
+                    "optional"
+                };
+                rendered.push_str(&format!("  - `{name}` ({kind}, {requirement})"));
+                if let Some(description) = property
+                    .get("description")
+                    .and_then(serde_json::Value::as_str)
+                {
+                    rendered.push_str(&format!(": {description}"));
+                }
+                rendered.push('\n');
+            }
+        }
+        rendered
+    }
+
+    /// Shows the request the next query would send, without calling the model or changing state.
+    pub fn preview_request(&self, user_message: impl Into<String>) -> PreviewRequest {
+        let mut messages = self.history.clone();
+        if !matches!(messages.first(), Some(ModelMessage::System(_)))
+            && let Some(system_prompt) = &self.config.system_prompt
+        {
+            messages.insert(0, ModelMessage::System(system_prompt.clone()));
+        }
+        if let Some(position) = self.example_turns_position(&messages) {
+            messages.splice(
+                position..position,
+                self.config.example_turns.iter().cloned(),
+            );
+        }
+        messages.push(ModelMessage::User(user_message.into()));
+
+        let tools = self.tool_definitions(&messages);
+        let tool_choice = self.resolve_tool_choice(&tools, 1);
+        PreviewRequest {
+            messages,
+            tools,
+            tool_choice,
+        }
+    }
+
     /// Runs one user query and returns the final response text.
     pub async fn query(&mut self, user_message: impl Into<String>) -> Result<String, AgentError> {
-        let stream = self.query_stream(user_message);
+        self.query_with_options(user_message, QueryOptions::default())
+            .await
+    }
+
+    /// Like [`Agent::query`], with model overrides applied to every invocation in this query.
+    ///
+    /// History compaction summaries are not affected.
+    pub async fn query_with_options(
+        &mut self,
+        user_message: impl Into<String>,
+        options: QueryOptions,
+    ) -> Result<String, AgentError> {
+        let stream = self.run_stream(user_message.into(), CancellationToken::new(), options);
         futures_util::pin_mut!(stream);
 
         let mut final_response: Option<String> = None;
 
         while let Some(event) = stream.next().await {
             match event? {
-                AgentEvent::FinalResponse { content } => final_response = Some(content),
+                AgentEvent::FinalResponse { content, .. } => final_response = Some(content),
                 AgentEvent::MessageStart { .. }
                 | AgentEvent::MessageComplete { .. }
                 | AgentEvent::HiddenUserMessage { .. }
+                | AgentEvent::IterationStart { .. }
+                | AgentEvent::IterationComplete { .. }
                 | AgentEvent::StepStart { .. }
                 | AgentEvent::StepComplete { .. }
                 | AgentEvent::Thinking { .. }
                 | AgentEvent::Text { .. }
+                | AgentEvent::ToolCallDelta { .. }
                 | AgentEvent::ToolCall { .. }
-                | AgentEvent::ToolResult { .. } => {}
+                | AgentEvent::ToolResult { .. }
+                | AgentEvent::Usage { .. }
+                | AgentEvent::Retry { .. }
+                | AgentEvent::InputRequested { .. }
+                | AgentEvent::Cancelled { .. }
+                | AgentEvent::HistoryCompacted { .. }
+                | AgentEvent::HistoryTruncated { .. }
+                | AgentEvent::NudgeSkipped { .. }
+                | AgentEvent::RawCompletion { .. }
+                | AgentEvent::UnmatchedToolResult { .. } => {}
             }
         }
 
         final_response.ok_or(AgentError::MissingFinalResponse)
     }
 
-    /// Runs one user query and streams intermediate events.
-    pub fn query_stream(
+    /// Runs one user query and returns its final response together with the tool results and
+    /// usage collected along the way.
+    ///
+    /// If the iteration limit is hit, the partial trace is returned inside
+    /// [`AgentError::MaxIterationsWithTrace`] instead of being discarded.
+    pub async fn query_with_trace(
         &mut self,
         user_message: impl Into<String>,
-    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
-        let user_message = user_message.into();
+    ) -> Result<QueryResult, AgentError> {
+        let stream = self.query_stream(user_message);
+        futures_util::pin_mut!(stream);
 
-        try_stream! {
-            if self.history.is_empty() {
-                if let Some(system_prompt) = &self.config.system_prompt {
-                    self.history.push(ModelMessage::System(system_prompt.clone()));
-                }
-            }
+        let mut trace = QueryResult::default();
 
-            let user_message_id = self.next_message_id(AgentRole::User);
-            yield AgentEvent::MessageStart {
-                message_id: user_message_id.clone(),
-                role: AgentRole::User,
-            };
-            self.history.push(ModelMessage::User(user_message.clone()));
-            yield AgentEvent::MessageComplete {
-                message_id: user_message_id,
-                content: user_message,
+        while let Some(event) = stream.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(AgentError::MaxIterationsReached { max_iterations }) => {
+                    return Err(AgentError::MaxIterationsWithTrace {
+                        max_iterations,
+                        trace: Box::new(trace),
+                    });
+                }
+                Err(err) => return Err(err),
             };
 
-            let tool_definitions = self
-                .tools
-                .iter()
-                .map(|tool| ModelToolDefinition {
-                    name: tool.name().to_string(),
-                    description: tool.description().to_string(),
-                    parameters: tool.json_schema().clone(),
-                })
-                .collect::<Vec<_>>();
+            match event {
+                AgentEvent::FinalResponse { content, data } => {
+                    trace.final_response = Some(content);
+                    trace.final_data = data;
+                }
+                AgentEvent::ToolResult {
+                    tool,
+                    result_text,
+                    tool_call_id,
+                    is_error,
+                } => trace.tool_results.push(ToolResultRecord {
+                    tool_call_id,
+                    tool,
+                    result_text,
+                    is_error,
+                }),
+                AgentEvent::Usage {
+                    cumulative_input,
+                    cumulative_output,
+                    ..
+                } => {
+                    trace.usage = ModelUsage {
+                        input_tokens: cumulative_input,
+                        output_tokens: cumulative_output,
+                    };
+                }
+                AgentEvent::MessageStart { .. }
+                | AgentEvent::MessageComplete { .. }
+                | AgentEvent::HiddenUserMessage { .. }
+                | AgentEvent::IterationStart { .. }
+                | AgentEvent::IterationComplete { .. }
+                | AgentEvent::StepStart { .. }
+                | AgentEvent::StepComplete { .. }
+                | AgentEvent::Thinking { .. }
+                | AgentEvent::Text { .. }
+                | AgentEvent::ToolCallDelta { .. }
+                | AgentEvent::ToolCall { .. }
+                | AgentEvent::Retry { .. }
+                | AgentEvent::InputRequested { .. }
+                | AgentEvent::Cancelled { .. }
+                | AgentEvent::HistoryCompacted { .. }
+                | AgentEvent::HistoryTruncated { .. }
+                | AgentEvent::NudgeSkipped { .. }
+                | AgentEvent::RawCompletion { .. }
+                | AgentEvent::UnmatchedToolResult { .. } => {}
+            }
+        }
 
-            let tool_choice = self.resolve_tool_choice(!tool_definitions.is_empty());
-            let mut hidden_prompt_injected = false;
+        Ok(trace)
+    }
 
-            for _ in 0..self.config.max_iterations {
-                let completion = self
-                    .invoke_with_retry(&tool_definitions, tool_choice.clone())
-                    .await?;
+    /// Runs one user query and streams intermediate events.
+    pub fn query_stream(
+        &mut self,
+        user_message: impl Into<String>,
+    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+        self.query_stream_with_cancel(user_message, CancellationToken::new())
+    }
+
+    /// Like [`Agent::query_stream`], but stops early once `cancel` is triggered.
+    ///
+    /// The token is checked before each model invocation and before each tool execution.
+    /// On cancellation the stream yields [`AgentEvent::Cancelled`] and ends without error.
+    /// Tool calls that were requested but not executed get an error result in history, so a
+    /// follow-up query can resume from a valid conversation.
+    pub fn query_stream_with_cancel(
+        &mut self,
+        user_message: impl Into<String>,
+        cancel: CancellationToken,
+    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+        self.run_stream(user_message.into(), cancel, QueryOptions::default())
+    }
+
+    /// Runs one user query on a spawned task and sends its events through a bounded channel.
+    ///
+    /// At most `buffer` events (minimum 1) wait in the channel; when it is full the run pauses
+    /// until the receiver catches up, so a slow consumer slows the agent instead of queueing
+    /// events without limit. Dropping the receiver cancels the run as
+    /// [`Agent::query_stream_with_cancel`] would. The task hands the agent back once the run
+    /// ends so it can take further queries.
+    pub fn query_channel(
+        mut self,
+        user_message: impl Into<String>,
+        buffer: usize,
+    ) -> (
+        mpsc::Receiver<Result<AgentEvent, AgentError>>,
+        JoinHandle<Agent>,
+    ) {
+        let user_message = user_message.into();
+        let (sender, receiver) = mpsc::channel(buffer.max(1));
+        let handle = tokio::spawn(async move {
+            let cancel = CancellationToken::new();
+            {
+                let events = self.query_stream_with_cancel(user_message, cancel.clone());
+                futures_util::pin_mut!(events);
+                while let Some(event) = events.next().await {
+                    if !cancel.is_cancelled() && sender.send(event).await.is_err() {
+                        cancel.cancel();
+                    }
+                }
+            }
+            self
+        });
+        (receiver, handle)
+    }
+
+    fn run_stream(
+        &mut self,
+        user_message: String,
+        cancel: CancellationToken,
+        options: QueryOptions,
+    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+        let stop_condition = self.stop_condition.clone();
+        try_stream! {
+            let mut stopped = None;
+            {
+                let events = self.run_turns(user_message, cancel, options);
+                futures_util::pin_mut!(events);
+                while let Some(event) = events.next().await {
+                    let event = event?;
+                    let stop = match (&stop_condition, &event) {
+                        (_, AgentEvent::FinalResponse { .. }) | (None, _) => None,
+                        (Some(condition), event) => condition(event),
+                    };
+                    yield event;
+                    if stop.is_some() {
+                        stopped = stop;
+                        break;
+                    }
+                }
+            }
+
+            if let Some(content) = stopped {
+                self.clear_pending_input();
+                let unanswered = unanswered_tool_calls(&self.history);
+                self.record_skipped_tool_calls(&unanswered, STOP_CONDITION_NOTE);
+                yield AgentEvent::FinalResponse {
+                    content,
+                    data: None,
+                };
+            }
+        }
+    }
+
+    fn run_turns(
+        &mut self,
+        user_message: String,
+        cancel: CancellationToken,
+        options: QueryOptions,
+    ) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+        let format_retries = options.response_format_retries;
+        let mut call_options = ModelCallOptions::from(options);
+        if call_options.assistant_prefill.is_none() {
+            call_options
+                .assistant_prefill
+                .clone_from(&self.config.assistant_prefill);
+        }
+        call_options.capture_raw |= self.config.capture_raw_responses;
+        try_stream! {
+            if let Some(pending) = &self.pending_input {
+                Err::<(), AgentError>(AgentError::Config(format!(
+                    "tool call '{}' is waiting for input; call Agent::provide_input first",
+                    pending.tool_call.id
+                )))?;
+            }
+
+            self.run_usage = ModelUsage::default();
+            self.last_request_at = None;
+            self.tool_error_streak = 0;
+            self.last_tool_error = None;
+            self.executed_tool_calls.clear();
+            self.tool_metrics.clear();
+            let resuming = std::mem::take(&mut self.resume_after_input);
+
+            if !matches!(self.history.first(), Some(ModelMessage::System(_)))
+                && let Some(system_prompt) = &self.config.system_prompt
+            {
+                self.history
+                    .insert(0, ModelMessage::System(system_prompt.clone()));
+            }
+            if let Some(position) = self.example_turns_position(&self.history) {
+                let examples = self.config.example_turns.clone();
+                self.priming = position..position + examples.len();
+                self.history.splice(position..position, examples);
+            }
+
+            if !(resuming && user_message.is_empty()) {
+                let user_message_id = self.next_message_id(AgentRole::User);
+                yield AgentEvent::MessageStart {
+                    message_id: user_message_id.clone(),
+                    role: AgentRole::User,
+                };
+                self.history.push(ModelMessage::User(user_message.clone()));
+                yield AgentEvent::MessageComplete {
+                    message_id: user_message_id,
+                    content: user_message,
+                    is_final: false,
+                };
+            }
+
+            let mut format_retries_used = 0_u32;
+            let mut empty_completions = 0_u32;
+            let mut tool_calls_executed = 0_u32;
+            let run_started = self.clock.now();
+            let run_span = Span::run(self.model.model_id(), self.config.max_iterations);
+
+            for iteration in 1..=self.config.max_iterations {
+                if cancel.is_cancelled() {
+                    yield AgentEvent::Cancelled {
+                        reason: "cancelled before model invocation".to_string(),
+                    };
+                    return;
+                }
+                self.check_run_deadline(run_started)?;
+                self.check_tool_error_streak()?;
+                yield AgentEvent::IterationStart {
+                    iteration,
+                    max_iterations: self.config.max_iterations,
+                };
+                let iteration_span = run_span.iteration(iteration);
+                // Loaded history, answers from `provide_input`, and tool results may all pair a
+                // result with an unknown id; report those but still send them.
+                for tool_call_id in self.unreported_unmatched_tool_results() {
+                    run_span.unmatched_tool_result(&tool_call_id);
+                    yield AgentEvent::UnmatchedToolResult { tool_call_id };
+                }
+
+                if let Some((removed, summary_len)) = self.compact_history().await? {
+                    yield AgentEvent::HistoryCompacted {
+                        removed,
+                        summary_len,
+                    };
+                }
+                if let Some(dropped) = self.truncate_history() {
+                    yield AgentEvent::HistoryTruncated { dropped };
+                }
+                self.check_input_budget()?;
+
+                let tool_definitions = self.tool_definitions(&self.history);
+                let tool_choice = self.resolve_tool_choice(&tool_definitions, iteration);
 
                 let assistant_message_id = self.next_message_id(AgentRole::Assistant);
                 yield AgentEvent::MessageStart {
@@ -438,50 +1708,338 @@ impl Agent {
                     role: AgentRole::Assistant,
                 };
 
-                if let Some(thinking) = completion.thinking.clone() {
-                    yield AgentEvent::Thinking { content: thinking };
+                let mut accumulator = ModelStreamAccumulator::default();
+                let mut streamed_call_ids = HashMap::new();
+                {
+                    let max_attempts = self.config.llm_max_retries.max(1);
+                    let mut attempt = 0;
+                    let mut model_stream = loop {
+                        attempt += 1;
+                        self.pace_requests().await;
+                        match iteration_span
+                            .instrument(self.open_model_stream(
+                                &tool_definitions,
+                                tool_choice.clone(),
+                                &call_options,
+                            ))
+                            .await
+                        {
+                            Ok(model_stream) => break model_stream,
+                            Err(err)
+                                if !is_retryable_provider_error(&err) || attempt >= max_attempts =>
+                            {
+                                iteration_span.provider_error(&err);
+                                Err::<(), AgentError>(AgentError::Provider(err))?;
+                            }
+                            Err(err) => {
+                                let delay_ms = retry_delay_ms(
+                                    attempt - 1,
+                                    self.config.llm_retry_base_delay_ms,
+                                    self.config.llm_retry_max_delay_ms,
+                                    self.config.retry_jitter.then_some(&self.retry_rng),
+                                );
+                                iteration_span.retry(attempt, max_attempts, delay_ms, &err);
+                                yield AgentEvent::Retry {
+                                    attempt,
+                                    max_attempts,
+                                    delay_ms,
+                                    error: err.to_string(),
+                                };
+                                self.clock.sleep(Duration::from_millis(delay_ms)).await;
+                            }
+                        }
+                    };
+                    while let Some(chunk) = model_stream.next().await {
+                        let chunk = chunk
+                            .inspect_err(|err| iteration_span.provider_error(err))
+                            .map_err(AgentError::Provider)?;
+                        match &chunk {
+                            ModelStreamChunk::ToolCallStart { index, id, .. } => {
+                                streamed_call_ids.insert(*index, id.clone());
+                            }
+                            ModelStreamChunk::ToolCallInputDelta {
+                                index,
+                                partial_json,
+                            } if !partial_json.is_empty() => {
+                                if let Some(tool_call_id) = streamed_call_ids.get(index) {
+                                    yield AgentEvent::ToolCallDelta {
+                                        tool_call_id: tool_call_id.clone(),
+                                        partial_json: partial_json.clone(),
+                                    };
+                                }
+                            }
+                            _ => {}
+                        }
+                        for event in stream_chunk_events(&chunk) {
+                            yield event;
+                        }
+                        accumulator.push(chunk);
+                    }
+                }
+                let (mut completion, malformed_tool_calls) = accumulator.finish();
+                self.malformed_tool_calls = malformed_tool_calls;
+                if let Some(value) = completion.raw.take() {
+                    yield AgentEvent::RawCompletion { value };
                 }
 
+                let format_check = call_options
+                    .response_format
+                    .as_ref()
+                    .filter(|_| completion.tool_calls.is_empty() && !self.config.require_done_tool)
+                    .map(|format| {
+                        check_response_format(format, completion.text.as_deref().unwrap_or_default())
+                    });
+                let retry_format =
+                    matches!(format_check, Some(Err(_))) && format_retries_used < format_retries;
+                let ends_run = completion.tool_calls.is_empty()
+                    && !self.config.require_done_tool
+                    && !self.can_inject_hidden_prompt()
+                    && !retry_format;
                 self.append_assistant_message(&completion);
 
-                if let Some(text) = completion.text.clone() {
-                    if !text.is_empty() {
-                        yield AgentEvent::Text {
-                            content: text.clone(),
-                        };
-                    }
-                }
-
                 let assistant_content = completion.text.clone().unwrap_or_default();
                 yield AgentEvent::MessageComplete {
                     message_id: assistant_message_id,
                     content: assistant_content.clone(),
+                    is_final: ends_run,
+                };
+
+                let usage = completion.usage.clone().unwrap_or_default();
+                self.run_usage.input_tokens =
+                    self.run_usage.input_tokens.saturating_add(usage.input_tokens);
+                self.run_usage.output_tokens =
+                    self.run_usage.output_tokens.saturating_add(usage.output_tokens);
+                yield AgentEvent::Usage {
+                    input_tokens: usage.input_tokens,
+                    output_tokens: usage.output_tokens,
+                    cumulative_input: self.run_usage.input_tokens,
+                    cumulative_output: self.run_usage.output_tokens,
                 };
 
                 if completion.tool_calls.is_empty() {
                     if !self.config.require_done_tool {
-                        if !hidden_prompt_injected {
-                            if let Some(hidden_prompt) = self.config.hidden_user_message_prompt.clone() {
-                                hidden_prompt_injected = true;
-                                self.history.push(ModelMessage::User(hidden_prompt.clone()));
+                        if self.can_inject_hidden_prompt()
+                            && let Some(hidden_prompt) =
+                                self.config.hidden_user_message_prompt.clone()
+                        {
+                            self.hidden_prompts_injected += 1;
+                            self.history.push(ModelMessage::User(hidden_prompt.clone()));
+                            yield AgentEvent::HiddenUserMessage {
+                                content: hidden_prompt,
+                            };
+                            yield AgentEvent::IterationComplete { iteration };
+                            continue;
+                        }
+                        let data = match format_check {
+                            Some(Ok(value)) => Some(value),
+                            Some(Err(message)) if retry_format => {
+                                format_retries_used += 1;
+                                let correction = format!(
+                                    "Your reply did not match the requested JSON format: {message}. Reply again with only the JSON."
+                                );
+                                self.history.push(ModelMessage::User(correction.clone()));
                                 yield AgentEvent::HiddenUserMessage {
-                                    content: hidden_prompt,
+                                    content: correction,
                                 };
+                                yield AgentEvent::IterationComplete { iteration };
                                 continue;
                             }
+                            Some(Err(message)) => Err(AgentError::InvalidResponseFormat {
+                                retries: format_retries_used,
+                                message,
+                            })?,
+                            None => None,
+                        };
+                        if self.config.hidden_user_message_prompt.is_some() {
+                            yield AgentEvent::NudgeSkipped {
+                                reason: format!(
+                                    "hidden prompt already injected {} time(s)",
+                                    self.hidden_prompts_injected
+                                ),
+                            };
                         }
 
+                        yield AgentEvent::IterationComplete { iteration };
                         yield AgentEvent::FinalResponse {
                             content: completion.text.unwrap_or_default(),
+                            data,
                         };
                         return;
                     }
+                    if self.config.hidden_user_message_prompt.is_some() {
+                        yield AgentEvent::NudgeSkipped {
+                            reason: "require_done_tool is set; the run continues until done is called"
+                                .to_string(),
+                        };
+                    }
+                    if completion.text.as_deref().is_none_or(str::is_empty)
+                        && completion.thinking.is_empty()
+                    {
+                        empty_completions += 1;
+                        if let Some(max_empty) = self.config.max_empty_completions
+                            && empty_completions >= max_empty
+                        {
+                            Err::<(), AgentError>(AgentError::EmptyCompletionLoop {
+                                count: empty_completions,
+                            })?;
+                        }
+                    } else {
+                        empty_completions = 0;
+                    }
+                    yield AgentEvent::IterationComplete { iteration };
                     continue;
                 }
+                empty_completions = 0;
+
+                if self.config.parallel_tool_calls {
+                    if cancel.is_cancelled() {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls,
+                            "Tool call cancelled before execution.",
+                        );
+                        yield AgentEvent::Cancelled {
+                            reason: "cancelled before tool execution".to_string(),
+                        };
+                        return;
+                    }
+                    if let Err(err) = self.check_run_deadline(run_started) {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls,
+                            "Tool call skipped: run timeout exceeded.",
+                        );
+                        Err::<(), AgentError>(err)?;
+                    }
+                    if let Err(err) =
+                        self.check_tool_call_budget(tool_calls_executed, completion.tool_calls.len())
+                    {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls,
+                            "Tool call skipped: tool call limit reached.",
+                        );
+                        Err::<(), AgentError>(err)?;
+                    }
+                    tool_calls_executed += completion.tool_calls.len() as u32;
+
+                    let reviewed_calls = completion
+                        .tool_calls
+                        .iter()
+                        .map(|tool_call| self.review_tool_call(tool_call))
+                        .collect::<Vec<_>>();
+
+                    for (step_number, (tool_call, _)) in (1_u32..).zip(&reviewed_calls) {
+                        yield AgentEvent::StepStart {
+                            step_id: tool_call.id.clone(),
+                            title: tool_call.name.clone(),
+                            step_number,
+                        };
+
+                        yield AgentEvent::ToolCall {
+                            tool: tool_call.name.clone(),
+                            args_json: tool_call.arguments.clone(),
+                            tool_call_id: tool_call.id.clone(),
+                        };
+                    }
+
+                    let executions = join_all(
+                        reviewed_calls
+                            .iter()
+                            .map(|(tool_call, denial)| {
+                                self.run_reviewed_tool_call(tool_call, denial, &iteration_span)
+                            }),
+                    )
+                    .await;
+
+                    let mut done = None;
+                    let mut input_request = None;
+                    let mut notes = Vec::new();
+                    let mut abort = None;
+                    for ((tool_call, _), (execution, duration_ms)) in
+                        reviewed_calls.iter().zip(executions)
+                    {
+                        if input_request.is_none()
+                            && let Some(prompt) = execution.input_prompt.clone()
+                        {
+                            input_request = Some((tool_call.clone(), prompt));
+                            continue;
+                        }
+                        if done.is_none()
+                            && let Some(message) = execution.done_message.clone()
+                        {
+                            done = Some((message, execution.done_data.clone()));
+                        }
+                        notes.extend(execution.note.clone());
+                        let is_error = execution.is_error;
+                        for event in self.record_tool_result(tool_call, execution, duration_ms) {
+                            yield event;
+                        }
+                        if abort.is_none() {
+                            abort = self.tool_error_abort(tool_call, is_error);
+                        }
+                    }
+
+                    if let Some(err) = abort {
+                        if let Some((tool_call, _)) = input_request.take() {
+                            self.record_skipped_tool_calls(&[tool_call], TOOL_ERROR_ABORT_NOTE);
+                        }
+                        Err::<(), AgentError>(err)?;
+                    }
+
+                    if let Some((tool_call, prompt)) = input_request {
+                        let tool_call_id = tool_call.id.clone();
+                        self.pending_input = Some(PendingInput {
+                            tool_call,
+                            skipped: Vec::new(),
+                        });
+                        yield AgentEvent::InputRequested {
+                            prompt,
+                            tool_call_id,
+                        };
+                        return;
+                    }
+
+                    if let Some((content, data)) = done {
+                        yield AgentEvent::IterationComplete { iteration };
+                        yield AgentEvent::FinalResponse { content, data };
+                        return;
+                    }
+                    for event in self.append_tool_notes(notes) {
+                        yield event;
+                    }
+                    yield AgentEvent::IterationComplete { iteration };
+                    continue;
+                }
+
+                let mut notes = Vec::new();
+
+                for (index, tool_call) in completion.tool_calls.iter().enumerate() {
+                    if cancel.is_cancelled() {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls[index..],
+                            "Tool call cancelled before execution.",
+                        );
+                        yield AgentEvent::Cancelled {
+                            reason: "cancelled before tool execution".to_string(),
+                        };
+                        return;
+                    }
+                    if let Err(err) = self.check_run_deadline(run_started) {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls[index..],
+                            "Tool call skipped: run timeout exceeded.",
+                        );
+                        Err::<(), AgentError>(err)?;
+                    }
+                    if let Err(err) = self.check_tool_call_budget(tool_calls_executed, 1) {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls[index..],
+                            "Tool call skipped: tool call limit reached.",
+                        );
+                        Err::<(), AgentError>(err)?;
+                    }
+                    tool_calls_executed += 1;
 
-                let mut step_number = 0_u32;
-                for tool_call in completion.tool_calls {
-                    step_number += 1;
+                    let step_number = index as u32 + 1;
+                    let (tool_call, denial) = self.review_tool_call(tool_call);
                     yield AgentEvent::StepStart {
                         step_id: tool_call.id.clone(),
                         title: tool_call.name.clone(),
@@ -494,39 +2052,49 @@ impl Agent {
                         tool_call_id: tool_call.id.clone(),
                     };
 
-                    let step_start = Instant::now();
-                    let execution = self.execute_tool_call(&tool_call).await;
-                    self.history.push(ModelMessage::ToolResult {
-                        tool_call_id: tool_call.id.clone(),
-                        tool_name: tool_call.name.clone(),
-                        content: execution.result_text.clone(),
-                        is_error: execution.is_error,
-                    });
-
-                    yield AgentEvent::ToolResult {
-                        tool: tool_call.name.clone(),
-                        result_text: execution.result_text.clone(),
-                        tool_call_id: tool_call.id.clone(),
-                        is_error: execution.is_error,
-                    };
+                    let (execution, duration_ms) =
+                        self.run_reviewed_tool_call(&tool_call, &denial, &iteration_span).await;
+                    if let Some(prompt) = execution.input_prompt {
+                        self.pending_input = Some(PendingInput {
+                            tool_call: tool_call.clone(),
+                            skipped: completion.tool_calls[index + 1..].to_vec(),
+                        });
+                        yield AgentEvent::InputRequested {
+                            prompt,
+                            tool_call_id: tool_call.id.clone(),
+                        };
+                        return;
+                    }
 
-                    yield AgentEvent::StepComplete {
-                        step_id: tool_call.id.clone(),
-                        status: if execution.is_error {
-                            StepStatus::Error
-                        } else {
-                            StepStatus::Completed
-                        },
-                        duration_ms: step_start.elapsed().as_millis(),
-                    };
+                    let done_message = execution.done_message.clone();
+                    let done_data = execution.done_data.clone();
+                    notes.extend(execution.note.clone());
+                    let is_error = execution.is_error;
+                    for event in self.record_tool_result(&tool_call, execution, duration_ms) {
+                        yield event;
+                    }
+                    let abort = self.tool_error_abort(&tool_call, is_error);
+                    if let Some(err) = abort {
+                        self.record_skipped_tool_calls(
+                            &completion.tool_calls[index + 1..],
+                            TOOL_ERROR_ABORT_NOTE,
+                        );
+                        Err::<(), AgentError>(err)?;
+                    }
 
-                    if let Some(done_message) = execution.done_message {
+                    if let Some(done_message) = done_message {
+                        yield AgentEvent::IterationComplete { iteration };
                         yield AgentEvent::FinalResponse {
                             content: done_message,
+                            data: done_data,
                         };
                         return;
                     }
                 }
+                for event in self.append_tool_notes(notes) {
+                    yield event;
+                }
+                yield AgentEvent::IterationComplete { iteration };
             }
 
             Err::<(), AgentError>(AgentError::MaxIterationsReached {
@@ -544,8 +2112,32 @@ impl Agent {
         format!("msg_{}_{}", self.next_message_id, role_label)
     }
 
-    fn resolve_tool_choice(&self, has_tools: bool) -> ModelToolChoice {
-        if !has_tools {
+    fn tool_definitions(&self, messages: &[ModelMessage]) -> Vec<ModelToolDefinition> {
+        let allowed = self
+            .tool_filter
+            .as_ref()
+            .map(|filter| filter(&self.tools, messages));
+        self.tools
+            .iter()
+            .filter(|tool| {
+                allowed
+                    .as_ref()
+                    .is_none_or(|names| names.iter().any(|name| name == tool.name()))
+            })
+            .map(|tool| ModelToolDefinition {
+                name: tool.name().to_string(),
+                description: tool.description().to_string(),
+                parameters: tool.json_schema().clone(),
+            })
+            .collect()
+    }
+
+    fn resolve_tool_choice(
+        &self,
+        tool_definitions: &[ModelToolDefinition],
+        iteration: u32,
+    ) -> ModelToolChoice {
+        if tool_definitions.is_empty() {
             return ModelToolChoice::None;
         }
 
@@ -554,42 +2146,255 @@ impl Agent {
             AgentToolChoice::Required => ModelToolChoice::Required,
             AgentToolChoice::None => ModelToolChoice::None,
             AgentToolChoice::Tool(name) => ModelToolChoice::Tool(name.clone()),
+            AgentToolChoice::PreferTool(name)
+                if iteration == 1 && tool_definitions.iter().any(|tool| &tool.name == name) =>
+            {
+                ModelToolChoice::Tool(name.clone())
+            }
+            AgentToolChoice::PreferTool(_) => ModelToolChoice::Auto,
         }
     }
 
-    async fn invoke_with_retry(
-        &self,
-        tool_definitions: &[ModelToolDefinition],
+    /// Opens a model stream and waits for its first chunk.
+    ///
+    /// Failures before the first chunk are returned so the caller can retry them; errors after
+    /// the first chunk surface through the stream and are not retried because partial output
+    /// was already emitted.
+    async fn open_model_stream<'a>(
+        &'a self,
+        tool_definitions: &'a [ModelToolDefinition],
         tool_choice: ModelToolChoice,
-    ) -> Result<ModelCompletion, AgentError> {
-        let max_retries = self.config.llm_max_retries.max(1);
-        for attempt in 0..max_retries {
-            match self
-                .model
-                .invoke(&self.history, tool_definitions, tool_choice.clone())
-                .await
-            {
-                Ok(completion) => return Ok(completion),
-                Err(err) => {
-                    let should_retry =
-                        is_retryable_provider_error(&err) && (attempt + 1) < max_retries;
-                    if !should_retry {
-                        return Err(AgentError::Provider(err));
+        call_options: &'a ModelCallOptions,
+    ) -> Result<ModelStream<'a>, ProviderError> {
+        let mut model_stream = self.model.invoke_stream_with_options(
+            &self.history,
+            tool_definitions,
+            tool_choice,
+            call_options,
+        );
+
+        match model_stream.next().await {
+            Some(Ok(first_chunk)) => Ok(Box::pin(
+                futures_util::stream::iter([Ok(first_chunk)]).chain(model_stream),
+            )),
+            Some(Err(err)) => Err(err),
+            None => Ok(model_stream),
+        }
+    }
+
+    /// Sleeps until `min_request_interval` has passed since the previous invocation.
+    async fn pace_requests(&mut self) {
+        if let (Some(interval), Some(last_request_at)) =
+            (self.config.min_request_interval, self.last_request_at)
+        {
+            let elapsed = self.clock.now().saturating_duration_since(last_request_at);
+            let remaining = interval.saturating_sub(elapsed);
+            if !remaining.is_zero() {
+                self.clock.sleep(remaining).await;
+            }
+        }
+        self.last_request_at = Some(self.clock.now());
+    }
+
+    fn can_inject_hidden_prompt(&self) -> bool {
+        self.config.hidden_user_message_prompt.is_some()
+            && self.hidden_prompts_injected < self.config.max_hidden_prompt_injections
+    }
+
+    fn check_tool_error_streak(&self) -> Result<(), AgentError> {
+        match self.config.max_consecutive_tool_errors {
+            Some(max_errors) if self.tool_error_streak >= max_errors => {
+                Err(AgentError::ToolErrorLoop {
+                    consecutive_errors: self.tool_error_streak,
+                    last_error: self.last_tool_error.clone().unwrap_or_default(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The error that ends the run for a failed tool result under `abort_on_tool_error`.
+    ///
+    /// Call after `record_tool_result` so the error carries the mapped result text.
+    fn tool_error_abort(&self, tool_call: &ModelToolCall, is_error: bool) -> Option<AgentError> {
+        (self.config.abort_on_tool_error && is_error).then(|| {
+            AgentError::Tool(ToolError::Execution(format!(
+                "{}: {}",
+                tool_call.name,
+                self.last_tool_error.clone().unwrap_or_default()
+            )))
+        })
+    }
+
+    fn check_tool_call_budget(&self, executed: u32, requested: usize) -> Result<(), AgentError> {
+        match self.config.max_tool_calls {
+            Some(max) if executed as usize + requested > max as usize => {
+                Err(AgentError::MaxToolCallsReached { max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_input_budget(&self) -> Result<(), AgentError> {
+        let Some(limit) = self.config.max_input_tokens else {
+            return Ok(());
+        };
+
+        let estimate = (self.token_estimator)(&self.history);
+        if estimate > limit {
+            return Err(AgentError::InputTooLarge { estimate, limit });
+        }
+        Ok(())
+    }
+
+    /// Applies the compaction policy, returning `(removed, summary_len)` when history changed.
+    async fn compact_history(&mut self) -> Result<Option<(usize, usize)>, ProviderError> {
+        let Some(policy) = self.config.compaction.clone() else {
+            return Ok(None);
+        };
+        let Some(range) = self.compaction_range(&policy) else {
+            return Ok(None);
+        };
+
+        match policy.strategy {
+            CompactionStrategy::Summarize => {
+                let request = [
+                    ModelMessage::System(COMPACTION_PROMPT.to_string()),
+                    ModelMessage::User(render_transcript(&self.history[range.clone()])),
+                ];
+                let completion = self
+                    .model
+                    .invoke(&request, &[], ModelToolChoice::None)
+                    .await?;
+                if let Some(usage) = completion.usage {
+                    self.run_usage.input_tokens = self
+                        .run_usage
+                        .input_tokens
+                        .saturating_add(usage.input_tokens);
+                    self.run_usage.output_tokens = self
+                        .run_usage
+                        .output_tokens
+                        .saturating_add(usage.output_tokens);
+                }
+
+                let summary = completion.text.unwrap_or_default();
+                let summary_len = summary.len();
+                let removed = range.len();
+                self.history.splice(
+                    range,
+                    [ModelMessage::User(format!(
+                        "Summary of the earlier conversation:\n{summary}"
+                    ))],
+                );
+                Ok(Some((removed, summary_len)))
+            }
+            CompactionStrategy::DropToolResults => {
+                let mut removed = 0;
+                for message in &mut self.history[range] {
+                    if let ModelMessage::ToolResult { content, .. } = message
+                        && content != COMPACTED_TOOL_RESULT
+                    {
+                        *content = COMPACTED_TOOL_RESULT.to_string();
+                        removed += 1;
                     }
+                }
+                Ok((removed > 0).then_some((removed, 0)))
+            }
+        }
+    }
 
-                    let delay_ms = retry_delay_ms(
-                        attempt,
-                        self.config.llm_retry_base_delay_ms,
-                        self.config.llm_retry_max_delay_ms,
-                    );
-                    sleep(Duration::from_millis(delay_ms)).await;
+    /// Evicts the oldest messages down to [`AgentConfig::max_history_messages`], returning how
+    /// many were dropped.
+    ///
+    /// System notes are skipped, and eviction only stops at a user message, so the kept
+    /// conversation opens with a user turn and no tool call loses its results. When that is not
+    /// enough, whole assistant turns after the latest user message are evicted, oldest first.
+    fn truncate_history(&mut self) -> Option<usize> {
+        let max = self.config.max_history_messages?;
+        let last_user = self
+            .history
+            .iter()
+            .rposition(|message| matches!(message, ModelMessage::User(_)))?;
+        let start = self.compaction_start();
+        let mut evict = vec![false; self.history.len()];
+        let mut dropped = 0;
+        for (message, evicted) in self.history[..last_user].iter().zip(&mut evict).skip(start) {
+            if matches!(message, ModelMessage::System(_)) {
+                continue;
+            }
+            let over_cap = self.history.len() - dropped > max;
+            if !over_cap && (dropped == 0 || matches!(message, ModelMessage::User(_))) {
+                break;
+            }
+            *evicted = true;
+            dropped += 1;
+        }
+
+        // A long tool loop grows history after the latest user message, so evict its oldest
+        // assistant turns whole, each with its tool results, keeping the newest turn.
+        if self.history.len() - dropped > max {
+            let turn_starts = (last_user + 1..self.history.len())
+                .filter(|&index| matches!(self.history[index], ModelMessage::Assistant { .. }))
+                .collect::<Vec<_>>();
+            for turn in turn_starts.windows(2) {
+                if self.history.len() - dropped <= max {
+                    break;
+                }
+                let turn = turn[0]..turn[1];
+                for (message, evicted) in self.history[turn.clone()].iter().zip(&mut evict[turn]) {
+                    if !matches!(message, ModelMessage::System(_)) {
+                        *evicted = true;
+                        dropped += 1;
+                    }
                 }
             }
         }
 
-        Err(AgentError::Config(
-            "retry loop failed unexpectedly".to_string(),
-        ))
+        if dropped == 0 {
+            return None;
+        }
+        let mut evicted = evict.into_iter();
+        self.history.retain(|_| !evicted.next().unwrap_or_default());
+        Some(dropped)
+    }
+
+    /// First history index that compaction or truncation may touch: after the system prompt
+    /// and any example turns.
+    fn compaction_start(&self) -> usize {
+        if self.priming.is_empty() {
+            usize::from(matches!(
+                self.history.first(),
+                Some(ModelMessage::System(_))
+            ))
+        } else {
+            self.priming.end
+        }
+    }
+
+    /// Older messages eligible for compaction, or `None` when the trigger has not fired.
+    ///
+    /// The range never starts at the system prompt and never ends between an assistant
+    /// tool call and its results.
+    fn compaction_range(&self, policy: &CompactionPolicy) -> Option<Range<usize>> {
+        let triggered = match policy.trigger {
+            CompactionTrigger::MessageCount(max) => self.history.len() > max,
+            CompactionTrigger::EstimatedTokens(max) => (self.token_estimator)(&self.history) > max,
+        };
+        if !triggered {
+            return None;
+        }
+
+        let start = self.compaction_start();
+        let mut end = self
+            .history
+            .len()
+            .saturating_sub(policy.keep_recent)
+            .max(start);
+        while end > start && matches!(self.history.get(end), Some(ModelMessage::ToolResult { .. }))
+        {
+            end -= 1;
+        }
+        (end > start).then_some(start..end)
     }
 
     fn append_assistant_message(&mut self, completion: &ModelCompletion) {
@@ -599,63 +2404,577 @@ impl Agent {
         });
     }
 
+    /// Applies the `on_tool_call` hook, returning the call to run and an optional denial reason.
+    fn review_tool_call(&self, tool_call: &ModelToolCall) -> (ModelToolCall, Option<String>) {
+        let Some(hook) = &self.tool_call_hook else {
+            return (tool_call.clone(), None);
+        };
+
+        match hook(tool_call) {
+            ToolCallDecision::Allow => (tool_call.clone(), None),
+            ToolCallDecision::Deny { reason } => (tool_call.clone(), Some(reason)),
+            ToolCallDecision::Rewrite(arguments) => (
+                ModelToolCall {
+                    arguments,
+                    ..tool_call.clone()
+                },
+                None,
+            ),
+        }
+    }
+
+    async fn run_reviewed_tool_call(
+        &self,
+        tool_call: &ModelToolCall,
+        denial: &Option<String>,
+        iteration_span: &Span,
+    ) -> (ToolExecutionResult, u128) {
+        match denial {
+            Some(reason) => (
+                ToolExecutionResult::error(format!("Tool call denied: {reason}")),
+                0,
+            ),
+            None => {
+                if let Some(result) = self.cached_tool_result(tool_call) {
+                    return (
+                        ToolExecutionResult::text(format!("{result}\n(cached: identical call)")),
+                        0,
+                    );
+                }
+                self.execute_tool_call_timed(tool_call, iteration_span)
+                    .await
+            }
+        }
+    }
+
+    /// Returns the earlier result of an identical call when deduplication is enabled.
+    fn cached_tool_result(&self, tool_call: &ModelToolCall) -> Option<&str> {
+        if !self.config.dedupe_tool_calls {
+            return None;
+        }
+        self.executed_tool_calls
+            .get(&tool_call_key(tool_call))
+            .map(String::as_str)
+    }
+
+    async fn execute_tool_call_timed(
+        &self,
+        tool_call: &ModelToolCall,
+        iteration_span: &Span,
+    ) -> (ToolExecutionResult, u128) {
+        let span = iteration_span.tool_call(tool_call);
+        let step_start = self.clock.now();
+        let execution = span.instrument(self.execute_tool_call(tool_call)).await;
+        let duration_ms = self
+            .clock
+            .now()
+            .saturating_duration_since(step_start)
+            .as_millis();
+        span.record_tool_result(duration_ms, execution.is_error);
+        (execution, duration_ms)
+    }
+
+    /// Appends notes from [`ToolOutcome::TextWithNote`] as user messages after the turn's results.
+    fn append_tool_notes(&mut self, notes: Vec<String>) -> Vec<AgentEvent> {
+        notes
+            .into_iter()
+            .map(|note| {
+                self.history.push(ModelMessage::User(note.clone()));
+                AgentEvent::HiddenUserMessage { content: note }
+            })
+            .collect()
+    }
+
+    /// Stores a tool result in history and returns the matching result/step events.
+    ///
+    /// This is the single post-processing point for every result, including denials, cache hits,
+    /// and errors raised before the handler ran: the [`AgentBuilder::map_tool_result`] hook runs
+    /// first, then the `max_tool_result_chars` cap.
+    fn record_tool_result(
+        &mut self,
+        tool_call: &ModelToolCall,
+        mut execution: ToolExecutionResult,
+        duration_ms: u128,
+    ) -> Vec<AgentEvent> {
+        if self.config.dedupe_tool_calls && execution.executed {
+            // The call ran for real and may have side effects, so older results can be stale.
+            self.executed_tool_calls.clear();
+            if !execution.is_error
+                && execution.done_message.is_none()
+                && execution.input_prompt.is_none()
+            {
+                // Cache the raw text; cache hits are mapped and capped again below.
+                self.executed_tool_calls
+                    .insert(tool_call_key(tool_call), execution.result_text.clone());
+            }
+        }
+        if let Some(mapper) = &self.tool_result_mapper {
+            execution.result_text =
+                mapper(&tool_call.name, execution.result_text, execution.is_error);
+        }
+        if execution.done_message.is_none()
+            && let Some(max_chars) = self.config.max_tool_result_chars
+        {
+            execution.result_text = truncate_chars(execution.result_text, max_chars);
+        }
+
+        self.history.push(ModelMessage::ToolResult {
+            tool_call_id: tool_call.id.clone(),
+            tool_name: tool_call.name.clone(),
+            content: execution.result_text.clone(),
+            is_error: execution.is_error,
+            images: execution.images,
+        });
+        let metrics = self.tool_metrics.entry(tool_call.name.clone()).or_default();
+        metrics.calls += 1;
+        metrics.errors += u32::from(execution.is_error);
+        metrics.total_duration += Duration::from_millis(duration_ms as u64);
+        if execution.is_error {
+            self.tool_error_streak += 1;
+            self.last_tool_error = Some(execution.result_text.clone());
+        } else {
+            self.tool_error_streak = 0;
+        }
+
+        vec![
+            AgentEvent::ToolResult {
+                tool: tool_call.name.clone(),
+                result_text: execution.result_text,
+                tool_call_id: tool_call.id.clone(),
+                is_error: execution.is_error,
+            },
+            AgentEvent::StepComplete {
+                step_id: tool_call.id.clone(),
+                status: if execution.is_error {
+                    StepStatus::Error
+                } else {
+                    StepStatus::Completed
+                },
+                duration_ms,
+            },
+        ]
+    }
+
+    /// Closes out tool calls that will not run so every assistant tool call keeps a result.
+    fn record_skipped_tool_calls(&mut self, tool_calls: &[ModelToolCall], content: &str) {
+        for tool_call in tool_calls {
+            self.history.push(ModelMessage::ToolResult {
+                tool_call_id: tool_call.id.clone(),
+                tool_name: tool_call.name.clone(),
+                content: content.to_string(),
+                is_error: true,
+                images: Vec::new(),
+            });
+        }
+    }
+
+    /// Ids of unmatched tool results in history that have not been reported yet.
+    fn unreported_unmatched_tool_results(&mut self) -> Vec<String> {
+        let unreported = unmatched_tool_result_ids(&self.history)
+            .into_iter()
+            .filter(|id| !self.reported_unmatched_tool_results.contains(*id))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        self.reported_unmatched_tool_results
+            .extend(unreported.iter().cloned());
+        unreported
+    }
+
+    fn clear_pending_input(&mut self) {
+        self.pending_input = None;
+        self.resume_after_input = false;
+    }
+
+    fn check_run_deadline(&self, run_started: Instant) -> Result<(), AgentError> {
+        let Some(run_timeout) = self.config.run_timeout else {
+            return Ok(());
+        };
+
+        let elapsed = self.clock.now().saturating_duration_since(run_started);
+        if elapsed > run_timeout {
+            return Err(AgentError::RunTimeout {
+                elapsed_ms: elapsed.as_millis(),
+                timeout_ms: run_timeout.as_millis(),
+            });
+        }
+        Ok(())
+    }
+
     async fn execute_tool_call(&self, tool_call: &ModelToolCall) -> ToolExecutionResult {
         let Some(tool) = self.tool_map.get(&tool_call.name) else {
-            return ToolExecutionResult {
-                result_text: format!("Unknown tool '{}'.", tool_call.name),
-                is_error: true,
-                done_message: None,
-            };
+            return ToolExecutionResult::error(format!(
+                "Unknown tool '{}'. Available tools: {}.",
+                tool_call.name,
+                self.tools
+                    .iter()
+                    .map(ToolSpec::name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
         };
+        if let Some(error) = self.malformed_tool_calls.get(&tool_call.id) {
+            return ToolExecutionResult::error(format!("Error: {error}"));
+        }
 
         let runtime_dependencies = self.dependencies.merged_with(&self.dependency_overrides);
-
-        match tool
-            .execute(tool_call.arguments.clone(), &runtime_dependencies)
-            .await
-        {
-            Ok(ToolOutcome::Text(text)) => ToolExecutionResult {
-                result_text: text,
-                is_error: false,
-                done_message: None,
+        let execution = tool.execute(tool_call.arguments.clone(), &runtime_dependencies);
+
+        let outcome = match self.config.tool_timeout {
+            Some(tool_timeout) => match timeout(tool_timeout, execution).await {
+                Ok(outcome) => outcome,
+                Err(_) => {
+                    return ToolExecutionResult::error(format!(
+                        "tool '{}' timed out after {}ms",
+                        tool_call.name,
+                        tool_timeout.as_millis()
+                    ));
+                }
             },
+            None => execution.await,
+        };
+
+        let execution = match outcome {
+            Ok(ToolOutcome::Text(text)) => ToolExecutionResult::text(text),
             Ok(ToolOutcome::Done(message)) => ToolExecutionResult {
-                result_text: format!("Task completed: {message}"),
-                is_error: false,
-                done_message: Some(message),
+                done_message: Some(message.clone()),
+                ..ToolExecutionResult::text(format!("Task completed: {message}"))
             },
-            Err(err) => ToolExecutionResult {
-                result_text: format_tool_error(err),
-                is_error: true,
-                done_message: None,
+            Ok(ToolOutcome::DoneWithData { message, data }) => ToolExecutionResult {
+                done_message: Some(message.clone()),
+                done_data: Some(data),
+                ..ToolExecutionResult::text(format!("Task completed: {message}"))
+            },
+            Ok(ToolOutcome::TextWithNote { result, note }) => ToolExecutionResult {
+                note: Some(note),
+                ..ToolExecutionResult::text(result)
+            },
+            Ok(ToolOutcome::Multimodal { text, images }) => ToolExecutionResult {
+                images,
+                ..ToolExecutionResult::text(text.unwrap_or_default())
             },
+            Ok(ToolOutcome::NeedsInput { prompt }) => ToolExecutionResult {
+                input_prompt: Some(prompt.clone()),
+                ..ToolExecutionResult::text(format!("Input requested: {prompt}"))
+            },
+            Err(err) => ToolExecutionResult::error(format_tool_error(err)),
+        };
+        ToolExecutionResult {
+            executed: true,
+            ..execution
+        }
+    }
+}
+
+/// Renders messages as plain text for the compaction summarizer.
+fn render_transcript(messages: &[ModelMessage]) -> String {
+    messages
+        .iter()
+        .map(|message| match message {
+            ModelMessage::System(content) => format!("system: {content}"),
+            ModelMessage::User(content) => format!("user: {content}"),
+            ModelMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let mut line = format!("assistant: {}", content.as_deref().unwrap_or_default());
+                for call in tool_calls {
+                    line.push_str(&format!("\n[called {} with {}]", call.name, call.arguments));
+                }
+                line
+            }
+            ModelMessage::ToolResult {
+                tool_name,
+                content,
+                is_error,
+                ..
+            } => {
+                let label = if *is_error {
+                    "tool error"
+                } else {
+                    "tool result"
+                };
+                format!("{label} ({tool_name}): {content}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Rejects forced tool choices that no registered tool can satisfy.
+fn validate_tool_choice(
+    tool_choice: &AgentToolChoice,
+    tool_map: &HashMap<String, ToolSpec>,
+) -> Result<(), AgentError> {
+    match tool_choice {
+        AgentToolChoice::Required | AgentToolChoice::Tool(_) if tool_map.is_empty() => {
+            Err(AgentError::Config(format!(
+                "tool_choice {tool_choice:?} requires at least one registered tool"
+            )))
+        }
+        AgentToolChoice::Tool(name) | AgentToolChoice::PreferTool(name)
+            if !tool_map.contains_key(name) =>
+        {
+            Err(AgentError::Config(format!(
+                "tool_choice names tool '{name}', which is not registered"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Tool calls of the latest assistant message that have no result in history yet.
+fn unanswered_tool_calls(history: &[ModelMessage]) -> Vec<ModelToolCall> {
+    let Some(position) = history
+        .iter()
+        .rposition(|message| matches!(message, ModelMessage::Assistant { .. }))
+    else {
+        return Vec::new();
+    };
+    let ModelMessage::Assistant { tool_calls, .. } = &history[position] else {
+        return Vec::new();
+    };
+    let answered = history[position + 1..]
+        .iter()
+        .filter_map(|message| match message {
+            ModelMessage::ToolResult { tool_call_id, .. } => Some(tool_call_id.as_str()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    tool_calls
+        .iter()
+        .filter(|call| !answered.contains(call.id.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Ids of tool results that do not follow an assistant tool call with the same id.
+fn unmatched_tool_result_ids(history: &[ModelMessage]) -> Vec<&str> {
+    let mut call_ids = HashSet::new();
+    let mut unmatched = Vec::new();
+    for message in history {
+        match message {
+            ModelMessage::Assistant { tool_calls, .. } => {
+                call_ids.extend(tool_calls.iter().map(|call| call.id.as_str()));
+            }
+            ModelMessage::ToolResult { tool_call_id, .. }
+                if !call_ids.contains(tool_call_id.as_str()) =>
+            {
+                unmatched.push(tool_call_id.as_str());
+            }
+            _ => {}
+        }
+    }
+    unmatched
+}
+
+/// Identifies a tool call by name and arguments, with object keys sorted.
+fn tool_call_key(tool_call: &ModelToolCall) -> String {
+    let mut key = tool_call.name.clone();
+    key.push('\0');
+    write_canonical_json(&tool_call.arguments, &mut key);
+    key
+}
+
+fn write_canonical_json(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical_json(value, out);
+            }
+            out.push('}');
         }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_canonical_json(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Parses a final response requested as JSON, tolerating a surrounding Markdown code fence.
+fn check_response_format(format: &ResponseFormat, text: &str) -> Result<serde_json::Value, String> {
+    let trimmed = text.trim();
+    let json = trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|fenced| fenced.trim_start_matches("json").trim())
+        .unwrap_or(trimmed);
+    let value = serde_json::from_str::<serde_json::Value>(json)
+        .map_err(|err| format!("invalid JSON ({err})"))?;
+    match format {
+        ResponseFormat::JsonObject if !value.is_object() => {
+            Err("expected a JSON object".to_string())
+        }
+        ResponseFormat::JsonObject => Ok(value),
+        ResponseFormat::JsonSchema(schema) => validate_value(schema, &value).map(|()| value),
+    }
+}
+
+fn truncate_chars(text: String, max_chars: usize) -> String {
+    let total_chars = text.chars().count();
+    if total_chars <= max_chars {
+        return text;
     }
+
+    let kept = text.chars().take(max_chars).collect::<String>();
+    format!("{kept}… [{} chars truncated]", total_chars - max_chars)
 }
 
 fn is_retryable_provider_error(err: &ProviderError) -> bool {
     match err {
-        ProviderError::Request(_) => true,
-        ProviderError::Response(_) => false,
+        ProviderError::Request { status: None, .. } => true,
+        // 529 is Anthropic's "overloaded" status.
+        ProviderError::Request {
+            status: Some(status),
+            ..
+        } => matches!(status, 408 | 409 | 429 | 500 | 502 | 503 | 504 | 529),
+        ProviderError::Response { .. } => false,
     }
 }
 
-fn retry_delay_ms(attempt: u32, base_delay_ms: u64, max_delay_ms: u64) -> u64 {
+/// Exponential backoff capped at `max_delay_ms`, drawn from `[0, cap]` when `jitter` is given.
+fn retry_delay_ms(
+    attempt: u32,
+    base_delay_ms: u64,
+    max_delay_ms: u64,
+    jitter: Option<&JitterRng>,
+) -> u64 {
     let mut delay = base_delay_ms;
     for _ in 0..attempt {
         delay = delay.saturating_mul(2);
     }
-    delay.min(max_delay_ms)
+    let cap = delay.min(max_delay_ms);
+    match jitter {
+        Some(rng) => rng.next_u64() % cap.saturating_add(1),
+        None => cap,
+    }
+}
+
+/// Small splitmix64 generator for retry jitter; not suitable for anything security related.
+struct JitterRng(AtomicU64);
+
+impl JitterRng {
+    /// Uses `seed` when given; otherwise seeds from std's randomly keyed [`RandomState`].
+    fn new(seed: Option<u64>) -> Self {
+        Self(AtomicU64::new(seed.unwrap_or_else(|| {
+            RandomState::new().hash_one(Instant::now())
+        })))
+    }
+
+    fn next_u64(&self) -> u64 {
+        const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut z = self
+            .0
+            .fetch_add(GAMMA, Ordering::Relaxed)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+fn stream_chunk_events(chunk: &ModelStreamChunk) -> Vec<AgentEvent> {
+    match chunk {
+        ModelStreamChunk::TextDelta(delta) if !delta.is_empty() => vec![AgentEvent::Text {
+            content: delta.clone(),
+        }],
+        ModelStreamChunk::ThinkingDelta { content, .. } if !content.is_empty() => {
+            vec![AgentEvent::Thinking {
+                content: content.clone(),
+            }]
+        }
+        ModelStreamChunk::Completion(completion) => {
+            // Split the text at each thinking block's offset so events follow the model's order.
+            let text = completion.text.as_deref().unwrap_or_default();
+            let mut events = Vec::new();
+            let mut emitted = 0;
+            for (index, block) in completion.thinking.iter().enumerate() {
+                let offset = completion.thinking_offsets.get(index).copied().unwrap_or(0);
+                if offset > emitted
+                    && let Some(segment) = text.get(emitted..offset)
+                {
+                    events.push(AgentEvent::Text {
+                        content: segment.to_string(),
+                    });
+                    emitted = offset;
+                }
+                if !block.is_empty() {
+                    events.push(AgentEvent::Thinking {
+                        content: block.clone(),
+                    });
+                }
+            }
+            if emitted < text.len() {
+                events.push(AgentEvent::Text {
+                    content: text[emitted..].to_string(),
+                });
+            }
+            events
+        }
+        ModelStreamChunk::TextDelta(_)
+        | ModelStreamChunk::ThinkingDelta { .. }
+        | ModelStreamChunk::ToolCallStart { .. }
+        | ModelStreamChunk::ToolCallInputDelta { .. }
+        | ModelStreamChunk::Usage(_) => Vec::new(),
+    }
 }
 
 fn format_tool_error(err: ToolError) -> String {
     err.to_string()
 }
 
+#[derive(Default)]
 struct ToolExecutionResult {
     result_text: String,
     is_error: bool,
     done_message: Option<String>,
+    done_data: Option<serde_json::Value>,
+    input_prompt: Option<String>,
+    images: Vec<ImageData>,
+    /// Follow-up user message to add after this turn's tool results.
+    note: Option<String>,
+    /// True when the tool handler ran to completion; false for denied, cached, unknown,
+    /// malformed, and timed-out calls.
+    executed: bool,
+}
+
+impl ToolExecutionResult {
+    /// Successful result carrying only text.
+    fn text(result_text: impl Into<String>) -> Self {
+        Self {
+            result_text: result_text.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Error result carrying only its message.
+    fn error(result_text: impl Into<String>) -> Self {
+        Self {
+            result_text: result_text.into(),
+            is_error: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Tool call paused on [`ToolOutcome::NeedsInput`], plus later calls from the same turn.
+struct PendingInput {
+    tool_call: ModelToolCall,
+    skipped: Vec<ModelToolCall>,
 }
 
 /// Convenience wrapper around [`Agent::query`].
@@ -674,5 +2993,17 @@ pub fn query_stream(
     agent.query_stream(user_message)
 }
 
+/// Convenience wrapper around [`Agent::query_stream_with_cancel`].
+pub fn query_stream_with_cancel(
+    agent: &mut Agent,
+    user_message: impl Into<String>,
+    cancel: CancellationToken,
+) -> impl Stream<Item = Result<AgentEvent, AgentError>> + '_ {
+    agent.query_stream_with_cancel(user_message, cancel)
+}
+
+mod clock;
+mod replay;
+mod telemetry;
 #[cfg(test)]
 mod tests;
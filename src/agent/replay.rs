@@ -0,0 +1,196 @@
+//! Rebuilds conversation history from a recorded [`AgentEvent`] stream.
+
+use std::collections::HashSet;
+
+use super::{AgentEvent, AgentRole};
+use crate::error::AgentError;
+use crate::llm::{ModelMessage, ModelToolCall};
+
+/// Replays `events` into history messages plus the message-id counter they imply.
+///
+/// Only events that map one-to-one onto history are used; anything that changed history
+/// without a matching event (compaction, operator input) makes the log unreplayable.
+pub(crate) fn history_from_events(
+    events: &[AgentEvent],
+) -> Result<(Vec<ModelMessage>, u64), AgentError> {
+    let mut replay = Replay::default();
+    for (index, event) in events.iter().enumerate() {
+        replay
+            .apply(event)
+            .map_err(|reason| AgentError::InvalidEventLog { index, reason })?;
+    }
+    replay
+        .finish()
+        .map_err(|reason| AgentError::InvalidEventLog {
+            index: events.len(),
+            reason,
+        })
+}
+
+#[derive(Default)]
+struct Replay {
+    history: Vec<ModelMessage>,
+    next_message_id: u64,
+    open_message: Option<(String, AgentRole)>,
+    /// History index of the assistant message that tool calls attach to.
+    assistant_index: Option<usize>,
+    pending_tool_calls: Vec<String>,
+    seen_tool_call_ids: HashSet<String>,
+}
+
+impl Replay {
+    fn apply(&mut self, event: &AgentEvent) -> Result<(), String> {
+        match event {
+            AgentEvent::MessageStart { message_id, role } => {
+                if let Some((open_id, _)) = &self.open_message {
+                    return Err(format!(
+                        "message '{message_id}' started before '{open_id}' completed"
+                    ));
+                }
+                self.expect_no_pending_tool_calls(message_id)?;
+                let sequence = parse_message_id(message_id, *role)?;
+                if sequence <= self.next_message_id {
+                    return Err(format!("message id '{message_id}' is out of order"));
+                }
+                self.next_message_id = sequence;
+                self.open_message = Some((message_id.clone(), *role));
+            }
+            AgentEvent::MessageComplete {
+                message_id,
+                content,
+                ..
+            } => {
+                let Some((open_id, role)) = self.open_message.take() else {
+                    return Err(format!("message '{message_id}' completed without a start"));
+                };
+                if &open_id != message_id {
+                    return Err(format!(
+                        "message '{message_id}' completed while '{open_id}' was open"
+                    ));
+                }
+                match role {
+                    AgentRole::User => {
+                        self.history.push(ModelMessage::User(content.clone()));
+                        self.assistant_index = None;
+                    }
+                    AgentRole::Assistant => {
+                        self.history.push(ModelMessage::Assistant {
+                            content: (!content.is_empty()).then(|| content.clone()),
+                            tool_calls: Vec::new(),
+                        });
+                        self.assistant_index = Some(self.history.len() - 1);
+                    }
+                }
+            }
+            AgentEvent::HiddenUserMessage { content } => {
+                self.expect_no_pending_tool_calls("hidden user message")?;
+                self.history.push(ModelMessage::User(content.clone()));
+                self.assistant_index = None;
+            }
+            AgentEvent::ToolCall {
+                tool,
+                args_json,
+                tool_call_id,
+            } => {
+                let Some(ModelMessage::Assistant { tool_calls, .. }) = self
+                    .assistant_index
+                    .and_then(|index| self.history.get_mut(index))
+                else {
+                    return Err(format!(
+                        "tool call '{tool_call_id}' does not follow an assistant message"
+                    ));
+                };
+                if !self.seen_tool_call_ids.insert(tool_call_id.clone()) {
+                    return Err(format!("duplicate tool call id '{tool_call_id}'"));
+                }
+                tool_calls.push(ModelToolCall {
+                    id: tool_call_id.clone(),
+                    name: tool.clone(),
+                    arguments: args_json.clone(),
+                });
+                self.pending_tool_calls.push(tool_call_id.clone());
+            }
+            AgentEvent::ToolResult {
+                tool,
+                result_text,
+                tool_call_id,
+                is_error,
+            } => {
+                let Some(position) = self
+                    .pending_tool_calls
+                    .iter()
+                    .position(|pending| pending == tool_call_id)
+                else {
+                    return Err(format!(
+                        "tool result '{tool_call_id}' has no matching tool call"
+                    ));
+                };
+                self.pending_tool_calls.remove(position);
+                self.history.push(ModelMessage::ToolResult {
+                    tool_call_id: tool_call_id.clone(),
+                    tool_name: tool.clone(),
+                    content: result_text.clone(),
+                    is_error: *is_error,
+                    images: Vec::new(),
+                });
+            }
+            AgentEvent::InputRequested { tool_call_id, .. } => {
+                return Err(format!(
+                    "tool call '{tool_call_id}' requested operator input, which is not recorded"
+                ));
+            }
+            AgentEvent::HistoryCompacted { .. } => {
+                return Err("history was compacted during the run".to_string());
+            }
+            AgentEvent::HistoryTruncated { .. } => {
+                return Err("history was truncated during the run".to_string());
+            }
+            AgentEvent::IterationStart { .. }
+            | AgentEvent::IterationComplete { .. }
+            | AgentEvent::StepStart { .. }
+            | AgentEvent::StepComplete { .. }
+            | AgentEvent::Thinking { .. }
+            | AgentEvent::Text { .. }
+            | AgentEvent::ToolCallDelta { .. }
+            | AgentEvent::Usage { .. }
+            | AgentEvent::FinalResponse { .. }
+            | AgentEvent::Retry { .. }
+            | AgentEvent::Cancelled { .. }
+            | AgentEvent::NudgeSkipped { .. }
+            | AgentEvent::RawCompletion { .. }
+            | AgentEvent::UnmatchedToolResult { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(Vec<ModelMessage>, u64), String> {
+        if let Some((open_id, _)) = &self.open_message {
+            return Err(format!("message '{open_id}' never completed"));
+        }
+        self.expect_no_pending_tool_calls("end of log")?;
+        Ok((self.history, self.next_message_id))
+    }
+
+    fn expect_no_pending_tool_calls(&self, at: &str) -> Result<(), String> {
+        match self.pending_tool_calls.first() {
+            Some(tool_call_id) => Err(format!(
+                "tool call '{tool_call_id}' has no result before {at}"
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses the counter out of an SDK message id such as `msg_3_assistant`.
+fn parse_message_id(message_id: &str, role: AgentRole) -> Result<u64, String> {
+    let role_label = match role {
+        AgentRole::User => "user",
+        AgentRole::Assistant => "assistant",
+    };
+    message_id
+        .strip_prefix("msg_")
+        .and_then(|rest| rest.strip_suffix(role_label))
+        .and_then(|rest| rest.strip_suffix('_'))
+        .and_then(|sequence| sequence.parse().ok())
+        .ok_or_else(|| format!("message id '{message_id}' is not a {role_label} message id"))
+}
@@ -0,0 +1,134 @@
+//! `tracing` instrumentation for the agent loop.
+//!
+//! Spans are created with explicit parents and never held entered across stream yields. Without
+//! the `tracing` feature every function here compiles to a no-op, so call sites stay uncfg'd.
+
+use crate::error::ProviderError;
+use crate::llm::ModelToolCall;
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use std::future::Future;
+
+    use tracing::Instrument;
+    use tracing::field::Empty;
+
+    use super::{ModelToolCall, ProviderError};
+
+    #[derive(Clone, Debug)]
+    pub(crate) struct Span(tracing::Span);
+
+    impl Span {
+        pub(crate) fn run(model_id: Option<&str>, max_iterations: u32) -> Self {
+            Self(tracing::info_span!(
+                "agent.run",
+                model = model_id.unwrap_or("unknown"),
+                max_iterations
+            ))
+        }
+
+        pub(crate) fn iteration(&self, iteration: u32) -> Self {
+            Self(tracing::debug_span!(parent: &self.0, "agent.iteration", iteration))
+        }
+
+        pub(crate) fn tool_call(&self, tool_call: &ModelToolCall) -> Self {
+            Self(tracing::info_span!(
+                parent: &self.0,
+                "agent.tool_call",
+                tool = %tool_call.name,
+                tool_call_id = %tool_call.id,
+                duration_ms = Empty,
+                is_error = Empty
+            ))
+        }
+
+        pub(crate) fn record_tool_result(&self, duration_ms: u128, is_error: bool) {
+            self.0.record("duration_ms", duration_ms as u64);
+            self.0.record("is_error", is_error);
+        }
+
+        pub(crate) async fn instrument<F: Future>(&self, future: F) -> F::Output {
+            future.instrument(self.0.clone()).await
+        }
+
+        pub(crate) fn retry(
+            &self,
+            attempt: u32,
+            max_attempts: u32,
+            delay_ms: u64,
+            err: &ProviderError,
+        ) {
+            tracing::debug!(
+                parent: &self.0,
+                attempt,
+                max_attempts,
+                delay_ms,
+                status = err.status(),
+                error = %err,
+                "retrying model invocation"
+            );
+        }
+
+        pub(crate) fn provider_error(&self, err: &ProviderError) {
+            tracing::debug!(
+                parent: &self.0,
+                status = err.status(),
+                error = %err,
+                "model invocation failed"
+            );
+        }
+
+        pub(crate) fn unmatched_tool_result(&self, tool_call_id: &str) {
+            tracing::warn!(
+                parent: &self.0,
+                tool_call_id,
+                "tool result does not match any earlier tool call"
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod imp {
+    use std::future::Future;
+
+    use super::{ModelToolCall, ProviderError};
+
+    #[derive(Clone, Debug)]
+    pub(crate) struct Span;
+
+    impl Span {
+        pub(crate) fn run(_model_id: Option<&str>, _max_iterations: u32) -> Self {
+            Self
+        }
+
+        pub(crate) fn iteration(&self, _iteration: u32) -> Self {
+            Self
+        }
+
+        pub(crate) fn tool_call(&self, _tool_call: &ModelToolCall) -> Self {
+            Self
+        }
+
+        pub(crate) fn record_tool_result(&self, _duration_ms: u128, _is_error: bool) {}
+
+        pub(crate) async fn instrument<F: Future>(&self, future: F) -> F::Output {
+            future.await
+        }
+
+        pub(crate) fn retry(
+            &self,
+            _attempt: u32,
+            _max_attempts: u32,
+            _delay_ms: u64,
+            _err: &ProviderError,
+        ) {
+        }
+
+        pub(crate) fn provider_error(&self, _err: &ProviderError) {}
+
+        pub(crate) fn unmatched_tool_result(&self, _tool_call_id: &str) {}
+    }
+}
+
+pub(crate) use imp::Span;
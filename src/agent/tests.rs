@@ -16,6 +16,8 @@ struct MockModel {
     invocations: Arc<AtomicUsize>,
     seen_tool_choices: Arc<Mutex<Vec<ModelToolChoice>>>,
     seen_message_batches: Arc<Mutex<Vec<Vec<ModelMessage>>>>,
+    seen_call_options: Arc<Mutex<Vec<ModelCallOptions>>>,
+    delay: Duration,
 }
 
 impl MockModel {
@@ -25,8 +27,15 @@ impl MockModel {
             invocations: Arc::new(AtomicUsize::new(0)),
             seen_tool_choices: Arc::new(Mutex::new(Vec::new())),
             seen_message_batches: Arc::new(Mutex::new(Vec::new())),
+            seen_call_options: Arc::new(Mutex::new(Vec::new())),
+            delay: Duration::ZERO,
         }
     }
+
+    fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
 }
 
 #[async_trait]
@@ -47,21 +56,82 @@ impl ChatModel for MockModel {
             .expect("message batches lock")
             .push(messages.to_vec());
 
+        if !self.delay.is_zero() {
+            tokio::time::sleep(self.delay).await;
+        }
+
         let mut guard = self.responses.lock().expect("responses lock poisoned");
         guard.pop_front().unwrap_or_else(|| {
-            Err(ProviderError::Response(
+            Err(ProviderError::response(
                 "no more mock model responses".to_string(),
             ))
         })
     }
+
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.seen_call_options
+            .lock()
+            .expect("call options lock")
+            .push(options.clone());
+        self.invoke(messages, tools, tool_choice).await
+    }
+}
+
+struct StreamingModel {
+    turns: Mutex<VecDeque<Vec<ModelStreamChunk>>>,
+}
+
+impl StreamingModel {
+    fn with_turns(turns: Vec<Vec<ModelStreamChunk>>) -> Self {
+        Self {
+            turns: Mutex::new(VecDeque::from(turns)),
+        }
+    }
+}
+
+#[async_trait]
+impl ChatModel for StreamingModel {
+    async fn invoke(
+        &self,
+        _messages: &[ModelMessage],
+        _tools: &[ModelToolDefinition],
+        _tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        Err(ProviderError::response(
+            "streaming model only supports invoke_stream".to_string(),
+        ))
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        _messages: &'a [ModelMessage],
+        _tools: &'a [ModelToolDefinition],
+        _tool_choice: ModelToolChoice,
+    ) -> ModelStream<'a> {
+        let chunks = self
+            .turns
+            .lock()
+            .expect("turns lock poisoned")
+            .pop_front()
+            .unwrap_or_default();
+        Box::pin(futures_util::stream::iter(chunks.into_iter().map(Ok)))
+    }
 }
 
 fn completion(text: Option<&str>, tool_calls: Vec<ModelToolCall>) -> ModelCompletion {
     ModelCompletion {
         text: text.map(ToString::to_string),
-        thinking: None,
+        thinking: Vec::new(),
+        thinking_offsets: Vec::new(),
         tool_calls,
         usage: None,
+        raw: None,
     }
 }
 
@@ -122,6 +192,25 @@ fn fail_tool() -> ToolSpec {
         .with_handler(|_args, _deps| async move { Err(ToolError::Execution("boom".to_string())) })
 }
 
+fn slow_tool(name: &str, delay_ms: u64) -> ToolSpec {
+    let label = name.to_string();
+    ToolSpec::new(name, "sleep then echo")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {},
+            "required": [],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(move |_args, _deps| {
+            let label = label.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                Ok(ToolOutcome::Text(label))
+            }
+        })
+}
+
 fn tool_call(id: &str, name: &str, arguments: serde_json::Value) -> ModelToolCall {
     ModelToolCall {
         id: id.to_string(),
@@ -140,6 +229,86 @@ async fn query_returns_no_tool_response() {
     assert_eq!(response, "hello");
 }
 
+#[tokio::test]
+async fn structured_done_data_reaches_final_response() {
+    let model = MockModel::with_responses(vec![Ok(completion(
+        None,
+        vec![tool_call(
+            "call_1",
+            "done",
+            json!({"message": "counted", "data": {"files": 3, "names": ["a", "b", "c"]}}),
+        )],
+    ))]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(crate::tools::claude_code::done_structured_tool())
+        .require_done_tool(true)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "count files").await;
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "counted".to_string(),
+            data: Some(json!({"files": 3, "names": ["a", "b", "c"]})),
+        })
+    );
+}
+
+#[tokio::test]
+async fn each_thinking_block_becomes_its_own_event() {
+    let mut first = completion(Some("answer"), vec![]);
+    first.thinking = vec!["first thought".to_string(), "second thought".to_string()];
+    let model = MockModel::with_responses(vec![Ok(first)]);
+
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+    let events = collect_events(&mut agent, "think").await;
+
+    let thinking = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::Thinking { content } => Some(content.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(thinking, vec!["first thought", "second thought"]);
+}
+
+#[tokio::test]
+async fn buffered_thinking_events_keep_their_place_around_text() {
+    let mut first = completion(Some("checking\ndone"), vec![]);
+    first.thinking = vec!["plan".to_string(), "verify".to_string()];
+    first.thinking_offsets = vec![0, 8];
+    let model = MockModel::with_responses(vec![Ok(first)]);
+
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+    let events = collect_events(&mut agent, "think").await;
+
+    let content = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::Thinking { content } => Some(format!("thinking:{content}")),
+            AgentEvent::Text { content } => Some(format!("text:{content}")),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        content,
+        vec![
+            "thinking:plan",
+            "text:checking",
+            "thinking:verify",
+            "text:\ndone"
+        ]
+    );
+    assert!(events.contains(&AgentEvent::FinalResponse {
+        content: "checking\ndone".to_string(),
+        data: None,
+    }));
+}
+
 #[tokio::test]
 async fn query_stream_emits_message_and_step_events() {
     let model = MockModel::with_responses(vec![
@@ -187,7 +356,8 @@ async fn query_stream_emits_message_and_step_events() {
     assert_eq!(
         events.last(),
         Some(&AgentEvent::FinalResponse {
-            content: "all done".to_string()
+            content: "all done".to_string(),
+            data: None,
         })
     );
 }
@@ -245,190 +415,3371 @@ async fn max_iterations_error_when_tool_loop_never_finishes() {
     assert!(matches!(err, AgentError::MaxIterationsReached { .. }));
 }
 
+fn long_history() -> Vec<ModelMessage> {
+    vec![
+        ModelMessage::System("be brief".to_string()),
+        ModelMessage::User("add 1 and 2".to_string()),
+        ModelMessage::Assistant {
+            content: None,
+            tool_calls: vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        },
+        ModelMessage::ToolResult {
+            tool_call_id: "call_1".to_string(),
+            tool_name: "add".to_string(),
+            content: "3".to_string(),
+            is_error: false,
+            images: Vec::new(),
+        },
+        ModelMessage::Assistant {
+            content: Some("It is 3".to_string()),
+            tool_calls: Vec::new(),
+        },
+    ]
+}
+
 #[tokio::test]
-async fn tool_error_emits_error_result_and_step_error() {
+async fn summarize_compaction_replaces_old_messages_and_continues_run() {
     let model = MockModel::with_responses(vec![
-        Ok(completion(
-            None,
-            vec![tool_call("call_4", "fail", json!({}))],
-        )),
-        Ok(completion(Some("fallback"), vec![])),
+        Ok(completion(Some("user added 1 and 2, got 3"), vec![])),
+        Ok(completion(Some("6"), vec![])),
     ]);
+    let seen_batches = model.seen_message_batches.clone();
 
     let mut agent = Agent::builder()
         .model(model)
-        .tool(fail_tool())
+        .compaction(CompactionPolicy {
+            trigger: CompactionTrigger::MessageCount(4),
+            strategy: CompactionStrategy::Summarize,
+            keep_recent: 1,
+        })
         .build()
         .expect("agent builds");
+    agent.load_history(long_history());
 
-    let events = agent
-        .query_stream("try")
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .expect("events ok");
-
-    assert!(
-        events
-            .iter()
-            .any(|event| matches!(event, AgentEvent::ToolResult { is_error: true, .. }))
-    );
-
-    assert!(events.iter().any(|event| matches!(
-        event,
-        AgentEvent::StepComplete {
-            status: StepStatus::Error,
-            ..
-        }
-    )));
+    let events = collect_events(&mut agent, "now double it").await;
 
+    assert!(events.contains(&AgentEvent::HistoryCompacted {
+        removed: 4,
+        summary_len: "user added 1 and 2, got 3".len(),
+    }));
     assert_eq!(
         events.last(),
         Some(&AgentEvent::FinalResponse {
-            content: "fallback".to_string()
+            content: "6".to_string(),
+            data: None,
         })
     );
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    assert!(
+        matches!(&batches[0][1], ModelMessage::User(transcript) if transcript.contains("tool result (add): 3"))
+    );
+    assert_eq!(
+        batches[1],
+        vec![
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User(
+                "Summary of the earlier conversation:\nuser added 1 and 2, got 3".to_string()
+            ),
+            ModelMessage::User("now double it".to_string()),
+        ]
+    );
+    assert_eq!(agent.messages_len(), 4);
 }
 
 #[tokio::test]
-async fn dependency_override_is_used_for_tool_execution() {
-    let model = MockModel::with_responses(vec![
-        Ok(completion(
-            None,
-            vec![tool_call("call_5", "read_dep", json!({}))],
-        )),
-        Ok(completion(Some("done"), vec![])),
-    ]);
-
-    let dep_tool = ToolSpec::new("read_dep", "read number")
-        .with_schema(json!({
-            "type": "object",
-            "properties": {},
-            "required": [],
-            "additionalProperties": false
-        }))
-        .expect("valid schema")
-        .with_handler(|_args, deps| {
-            let value = deps
-                .get::<u32>()
-                .ok_or(ToolError::MissingDependency("u32"))
-                .map(|v| *v)
-                .unwrap_or(0);
-            async move { Ok(ToolOutcome::Text(value.to_string())) }
-        });
+async fn drop_tool_results_compaction_keeps_history_shape() {
+    let model = MockModel::with_responses(vec![Ok(completion(Some("6"), vec![]))]);
 
     let mut agent = Agent::builder()
         .model(model)
-        .tool(dep_tool)
-        .dependency(1_u32)
-        .dependency_override(9_u32)
+        .compaction(CompactionPolicy {
+            trigger: CompactionTrigger::EstimatedTokens(1),
+            strategy: CompactionStrategy::DropToolResults,
+            keep_recent: 2,
+        })
         .build()
         .expect("agent builds");
+    agent.load_history(long_history());
 
-    let events = agent
-        .query_stream("dep")
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .expect("events ok");
+    let events = collect_events(&mut agent, "now double it").await;
 
-    assert!(events.iter().any(|event| {
-        matches!(
-            event,
-            AgentEvent::ToolResult {
-                result_text,
-                is_error: false,
-                ..
-            } if result_text == "9"
-        )
+    assert!(events.contains(&AgentEvent::HistoryCompacted {
+        removed: 1,
+        summary_len: 0,
     }));
+    assert_eq!(agent.messages_len(), 7);
+    assert!(matches!(
+        &agent.messages()[3],
+        ModelMessage::ToolResult { content, .. } if content.contains("removed during history compaction")
+    ));
 }
 
 #[tokio::test]
-async fn hidden_user_prompt_is_emitted_once() {
-    let model = MockModel::with_responses(vec![
-        Ok(completion(Some("not complete"), vec![])),
-        Ok(completion(Some("final"), vec![])),
-    ]);
-
+async fn max_history_messages_drops_tool_calls_with_their_results() {
+    let model = MockModel::with_responses(vec![Ok(completion(Some("6"), vec![]))]);
+    let seen_batches = model.seen_message_batches.clone();
     let mut agent = Agent::builder()
         .model(model)
-        .hidden_user_message_prompt("You still have incomplete todos")
+        .max_history_messages(4)
         .build()
         .expect("agent builds");
+    agent.load_history(long_history());
 
-    let events = agent
-        .query_stream("start")
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
-        .expect("events ok");
+    let events = collect_events(&mut agent, "now double it").await;
 
-    let hidden_count = events
-        .iter()
-        .filter(|e| matches!(e, AgentEvent::HiddenUserMessage { .. }))
-        .count();
-    assert_eq!(hidden_count, 1);
+    assert!(events.contains(&AgentEvent::HistoryTruncated { dropped: 4 }));
+    let seen = seen_batches.lock().expect("message batches lock")[0].clone();
+    assert_eq!(
+        seen,
+        vec![
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("now double it".to_string()),
+        ]
+    );
+    assert_eq!(agent.messages_len(), 3);
+}
+
+#[tokio::test]
+async fn max_history_messages_keeps_system_notes_and_opens_with_user_turn() {
+    let model = MockModel::with_responses(vec![Ok(completion(Some("a3"), vec![]))]);
+    let seen_batches = model.seen_message_batches.clone();
+    let mut agent = Agent::builder()
+        .model(model)
+        .max_history_messages(4)
+        .build()
+        .expect("agent builds");
+    agent.load_history(vec![
+        ModelMessage::System("be brief".to_string()),
+        ModelMessage::User("q1".to_string()),
+        ModelMessage::assistant("a1"),
+        ModelMessage::System("review mode".to_string()),
+        ModelMessage::User("q2".to_string()),
+        ModelMessage::assistant("a2"),
+    ]);
+
+    let events = collect_events(&mut agent, "q3").await;
 
+    // Four messages would fit with `a2` kept, but then the conversation would open with it.
+    assert!(events.contains(&AgentEvent::HistoryTruncated { dropped: 4 }));
+    let seen = seen_batches.lock().expect("message batches lock")[0].clone();
     assert_eq!(
-        events.last(),
-        Some(&AgentEvent::FinalResponse {
-            content: "final".to_string()
-        })
+        seen,
+        vec![
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::System("review mode".to_string()),
+            ModelMessage::User("q3".to_string()),
+        ]
     );
 }
 
 #[tokio::test]
-async fn retries_request_errors_then_succeeds() {
+async fn max_history_messages_evicts_tool_turns_within_a_single_query() {
     let model = MockModel::with_responses(vec![
-        Err(ProviderError::Request("timeout".to_string())),
-        Ok(completion(Some("ok"), vec![])),
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 1}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_2", "add", json!({"a": 2, "b": 2}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_3", "add", json!({"a": 3, "b": 3}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
     ]);
+    let seen_batches = model.seen_message_batches.clone();
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .max_history_messages(4)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add three times").await;
+
+    let truncations = events
+        .iter()
+        .filter(|event| matches!(event, AgentEvent::HistoryTruncated { dropped: 2 }))
+        .count();
+    assert_eq!(truncations, 2);
+    let seen = seen_batches.lock().expect("message batches lock")[3].clone();
+    assert_eq!(seen.len(), 3);
+    assert_eq!(seen[0], ModelMessage::User("add three times".to_string()));
+    assert!(matches!(
+        &seen[1],
+        ModelMessage::Assistant { tool_calls, .. } if tool_calls[0].id == "call_3"
+    ));
+    assert!(matches!(
+        &seen[2],
+        ModelMessage::ToolResult { tool_call_id, content, .. }
+            if tool_call_id == "call_3" && content == "6"
+    ));
+}
+
+#[tokio::test]
+async fn max_input_tokens_rejects_oversized_requests_before_calling_model() {
+    let message = "x".repeat(400);
+    assert_eq!(
+        crate::llm::estimate_tokens(&[ModelMessage::User(message.clone())]),
+        100
+    );
+
+    let model = MockModel::with_responses(vec![Ok(completion(Some("fits"), vec![]))]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .max_input_tokens(100)
+        .build()
+        .expect("agent builds");
+    assert_eq!(agent.query(message.clone()).await.expect("fits"), "fits");
 
+    let model = MockModel::with_responses(vec![Ok(completion(Some("unused"), vec![]))]);
     let invocations = model.invocations.clone();
+    let mut agent = Agent::builder()
+        .model(model)
+        .max_input_tokens(99)
+        .build()
+        .expect("agent builds");
+    let err = agent.query(message).await.expect_err("must fail");
+    assert!(matches!(
+        err,
+        AgentError::InputTooLarge {
+            estimate: 100,
+            limit: 99
+        }
+    ));
+    assert_eq!(invocations.load(Ordering::SeqCst), 0);
+}
 
+#[tokio::test]
+async fn custom_token_estimator_drives_input_limit() {
+    let model = MockModel::with_responses(vec![Ok(completion(Some("unused"), vec![]))]);
     let mut agent = Agent::builder()
         .model(model)
-        .llm_retry_config(2, 0, 0)
+        .max_input_tokens(2)
+        .token_estimator(|messages| messages.len() * 10)
         .build()
         .expect("agent builds");
 
-    let response = agent.query("retry").await.expect("query succeeds");
-    assert_eq!(response, "ok");
-    assert_eq!(invocations.load(Ordering::SeqCst), 2);
+    let err = agent.query("hi").await.expect_err("must fail");
+    assert!(matches!(
+        err,
+        AgentError::InputTooLarge {
+            estimate: 10,
+            limit: 2
+        }
+    ));
 }
 
 #[tokio::test]
-async fn load_history_and_tool_choice_are_applied() {
-    let model = MockModel::with_responses(vec![Ok(completion(Some("done"), vec![]))]);
-    let seen_tool_choices = model.seen_tool_choices.clone();
-    let seen_batches = model.seen_message_batches.clone();
+async fn min_request_interval_spaces_model_invocations() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_2", "add", json!({"a": 3, "b": 4}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
 
     let mut agent = Agent::builder()
         .model(model)
         .tool(add_tool())
-        .tool_choice(AgentToolChoice::Required)
+        .min_request_interval(Duration::from_millis(40))
         .build()
         .expect("agent builds");
 
-    agent.load_history(vec![
-        ModelMessage::System("sys".to_string()),
-        ModelMessage::User("old".to_string()),
+    let started = Instant::now();
+    let response = agent.query("add twice").await.expect("query succeeds");
+    assert_eq!(response, "done");
+    assert!(started.elapsed() >= Duration::from_millis(80));
+}
+
+#[tokio::test]
+async fn model_io_hook_sees_each_invocation() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 2, "b": 3}))],
+        )),
+        Err(ProviderError::response("bad payload".to_string())),
+    ]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen_in_hook = seen.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .on_model_io(move |io| {
+            let outcome = match io.result {
+                Ok(completion) => format!("ok:{}", completion.tool_calls.len()),
+                Err(err) => format!("err:{err}"),
+            };
+            seen_in_hook.lock().expect("seen lock").push((
+                io.messages.len(),
+                io.tools
+                    .iter()
+                    .map(|tool| tool.name.clone())
+                    .collect::<Vec<_>>(),
+                io.tool_choice.clone(),
+                outcome,
+            ));
+        })
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query("add").await.expect_err("second call fails");
+    assert!(matches!(
+        err,
+        AgentError::Provider(ProviderError::Response { .. })
+    ));
+
+    let seen = seen.lock().expect("seen lock");
+    assert_eq!(
+        *seen,
+        vec![
+            (
+                1,
+                vec!["add".to_string()],
+                ModelToolChoice::Auto,
+                "ok:1".to_string()
+            ),
+            (
+                3,
+                vec!["add".to_string()],
+                ModelToolChoice::Auto,
+                "err:provider response invalid: bad payload".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn preview_request_shows_next_request_without_mutating_agent() {
+    let model = MockModel::with_responses(Vec::new());
+    let invocations = model.invocations.clone();
+    let agent = Agent::builder()
+        .model(model)
+        .system_prompt("be precise")
+        .tool(add_tool())
+        .tool_choice(AgentToolChoice::Required)
+        .build()
+        .expect("agent builds");
+
+    let preview = agent.preview_request("add 2 and 3");
+
+    assert_eq!(
+        preview.messages,
+        vec![
+            ModelMessage::System("be precise".to_string()),
+            ModelMessage::User("add 2 and 3".to_string()),
+        ]
+    );
+    assert_eq!(preview.tools.len(), 1);
+    assert_eq!(preview.tools[0].name, "add");
+    assert_eq!(preview.tools[0].parameters["required"], json!(["a", "b"]));
+    assert_eq!(preview.tool_choice, ModelToolChoice::Required);
+    assert_eq!(agent.messages_len(), 0);
+    assert_eq!(invocations.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn query_with_trace_keeps_tool_results_when_iterations_run_out() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_2", "add", json!({"a": 3, "b": 4}))],
+        )),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .max_iterations(2)
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query_with_trace("loop").await.expect_err("must fail");
+    let AgentError::MaxIterationsWithTrace {
+        max_iterations,
+        trace,
+    } = err
+    else {
+        panic!("expected partial trace, got {err:?}");
+    };
+    assert_eq!(max_iterations, 2);
+    assert_eq!(trace.final_response, None);
+    assert_eq!(
+        trace
+            .tool_results
+            .iter()
+            .map(|record| (record.tool_call_id.as_str(), record.result_text.as_str()))
+            .collect::<Vec<_>>(),
+        vec![("call_1", "3"), ("call_2", "7")]
+    );
+}
+
+#[tokio::test]
+async fn tool_error_emits_error_result_and_step_error() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_4", "fail", json!({}))],
+        )),
+        Ok(completion(Some("fallback"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(fail_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("try")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::ToolResult { is_error: true, .. }))
+    );
+
+    assert!(events.iter().any(|event| matches!(
+        event,
+        AgentEvent::StepComplete {
+            status: StepStatus::Error,
+            ..
+        }
+    )));
+
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "fallback".to_string(),
+            data: None,
+        })
+    );
+}
+
+#[tokio::test]
+async fn dependency_override_is_used_for_tool_execution() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_5", "read_dep", json!({}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+
+    let dep_tool = ToolSpec::new("read_dep", "read number")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {},
+            "required": [],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(|_args, deps| {
+            let value = deps
+                .get::<u32>()
+                .ok_or(ToolError::MissingDependency("u32"))
+                .map(|v| *v)
+                .unwrap_or(0);
+            async move { Ok(ToolOutcome::Text(value.to_string())) }
+        });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(dep_tool)
+        .dependency(1_u32)
+        .dependency_override(9_u32)
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("dep")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    assert!(events.iter().any(|event| {
+        matches!(
+            event,
+            AgentEvent::ToolResult {
+                result_text,
+                is_error: false,
+                ..
+            } if result_text == "9"
+        )
+    }));
+}
+
+#[tokio::test]
+async fn hidden_user_prompt_limit_allows_repeated_nudges() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("stopping early"), vec![])),
+        Ok(completion(Some("stopping again"), vec![])),
+        Ok(completion(Some("final"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .hidden_user_message_prompt("Keep going")
+        .max_hidden_prompt_injections(2)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "start").await;
+
+    let hidden_count = events
+        .iter()
+        .filter(|e| matches!(e, AgentEvent::HiddenUserMessage { .. }))
+        .count();
+    assert_eq!(hidden_count, 2);
+    assert!(events.iter().any(|e| matches!(
+        e,
+        AgentEvent::NudgeSkipped { reason } if reason.contains("2 time(s)")
+    )));
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::FinalResponse { content, .. }) if content == "final"
+    ));
+}
+
+#[tokio::test]
+async fn hidden_user_prompt_is_injected_once_per_conversation() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("first draft"), vec![])),
+        Ok(completion(Some("first answer"), vec![])),
+        Ok(completion(Some("second answer"), vec![])),
+        Ok(completion(Some("third draft"), vec![])),
+        Ok(completion(Some("third answer"), vec![])),
+    ]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .hidden_user_message_prompt("Keep going")
+        .build()
+        .expect("agent builds");
+    let hidden_count = |events: &[AgentEvent]| {
+        events
+            .iter()
+            .filter(|e| matches!(e, AgentEvent::HiddenUserMessage { .. }))
+            .count()
+    };
+
+    let first = collect_events(&mut agent, "one").await;
+    let second = collect_events(&mut agent, "two").await;
+    agent.reset_nudge();
+    let third = collect_events(&mut agent, "three").await;
+
+    assert_eq!(hidden_count(&first), 1);
+    assert_eq!(hidden_count(&second), 0);
+    assert!(second.iter().any(|e| matches!(
+        e,
+        AgentEvent::NudgeSkipped { reason } if reason.contains("1 time(s)")
+    )));
+    assert!(matches!(
+        second.last(),
+        Some(AgentEvent::FinalResponse { content, .. }) if content == "second answer"
+    ));
+    assert_eq!(hidden_count(&third), 1);
+    assert!(matches!(
+        third.last(),
+        Some(AgentEvent::FinalResponse { content, .. }) if content == "third answer"
+    ));
+}
+
+#[tokio::test]
+async fn hidden_user_prompt_is_emitted_once() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("not complete"), vec![])),
+        Ok(completion(Some("final"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .hidden_user_message_prompt("You still have incomplete todos")
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("start")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    let hidden_count = events
+        .iter()
+        .filter(|e| matches!(e, AgentEvent::HiddenUserMessage { .. }))
+        .count();
+    assert_eq!(hidden_count, 1);
+
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "final".to_string(),
+            data: None,
+        })
+    );
+}
+
+#[tokio::test]
+async fn retries_request_errors_then_succeeds() {
+    let model = MockModel::with_responses(vec![
+        Err(ProviderError::request("timeout")),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .llm_retry_config(2, 0, 0)
+        .build()
+        .expect("agent builds");
+
+    let response = agent.query("retry").await.expect("query succeeds");
+    assert_eq!(response, "ok");
+    assert_eq!(invocations.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn load_history_and_tool_choice_are_applied() {
+    let model = MockModel::with_responses(vec![Ok(completion(Some("done"), vec![]))]);
+    let seen_tool_choices = model.seen_tool_choices.clone();
+    let seen_batches = model.seen_message_batches.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .tool_choice(AgentToolChoice::Required)
+        .build()
+        .expect("agent builds");
+
+    agent.load_history(vec![
+        ModelMessage::System("sys".to_string()),
+        ModelMessage::User("old".to_string()),
+    ]);
+
+    let response = agent.query("new").await.expect("query succeeds");
+    assert_eq!(response, "done");
+
+    assert_eq!(
+        seen_tool_choices.lock().expect("lock").first(),
+        Some(&ModelToolChoice::Required)
+    );
+
+    let first_batch = seen_batches.lock().expect("lock").first().cloned().unwrap();
+    assert!(matches!(first_batch[0], ModelMessage::System(_)));
+    assert!(matches!(first_batch[1], ModelMessage::User(_)));
+    assert!(matches!(first_batch[2], ModelMessage::User(_)));
+}
+
+#[tokio::test]
+async fn prefer_tool_forces_only_the_first_iteration() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(Some("3"), vec![])),
+    ]);
+    let seen_tool_choices = model.seen_tool_choices.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .tool_choice(AgentToolChoice::PreferTool("add".to_string()))
+        .build()
+        .expect("agent builds");
+
+    let response = agent.query("add 1 and 2").await.expect("query succeeds");
+    assert_eq!(response, "3");
+
+    assert_eq!(
+        *seen_tool_choices.lock().expect("lock"),
+        vec![
+            ModelToolChoice::Tool("add".to_string()),
+            ModelToolChoice::Auto
+        ]
+    );
+}
+
+#[test]
+fn build_rejects_forced_tool_choices_without_matching_tool() {
+    let missing = Agent::builder()
+        .model(MockModel::with_responses(vec![]))
+        .tool(add_tool())
+        .tool_choice(AgentToolChoice::Tool("nonexistent".to_string()))
+        .build();
+    match missing {
+        Err(AgentError::Config(message)) => assert!(message.contains("'nonexistent'")),
+        _ => panic!("expected config error for unknown forced tool"),
+    }
+
+    let missing = Agent::builder()
+        .model(MockModel::with_responses(vec![]))
+        .tool(add_tool())
+        .tool_choice(AgentToolChoice::PreferTool("subtract".to_string()))
+        .build();
+    match missing {
+        Err(AgentError::Config(message)) => assert!(message.contains("'subtract'")),
+        _ => panic!("expected config error for unknown preferred tool"),
+    }
+
+    let no_tools = Agent::builder()
+        .model(MockModel::with_responses(vec![]))
+        .tool_choice(AgentToolChoice::Required)
+        .build();
+    match no_tools {
+        Err(AgentError::Config(message)) => {
+            assert!(message.contains("requires at least one registered tool"))
+        }
+        _ => panic!("expected config error for required tool choice without tools"),
+    }
+}
+
+#[tokio::test]
+async fn query_stream_forwards_deltas_and_assembles_tool_calls() {
+    let model = StreamingModel::with_turns(vec![
+        vec![
+            ModelStreamChunk::ThinkingDelta {
+                index: 0,
+                content: "need ".to_string(),
+            },
+            ModelStreamChunk::ThinkingDelta {
+                index: 0,
+                content: "math".to_string(),
+            },
+            ModelStreamChunk::TextDelta("adding".to_string()),
+            ModelStreamChunk::ToolCallStart {
+                index: 0,
+                id: "call_s".to_string(),
+                name: "add".to_string(),
+            },
+            ModelStreamChunk::ToolCallInputDelta {
+                index: 0,
+                partial_json: "{\"a\": 2,".to_string(),
+            },
+            ModelStreamChunk::ToolCallInputDelta {
+                index: 0,
+                partial_json: " \"b\": 5}".to_string(),
+            },
+        ],
+        vec![
+            ModelStreamChunk::TextDelta("the answer ".to_string()),
+            ModelStreamChunk::TextDelta("is 7".to_string()),
+        ],
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("add")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    let texts = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::Text { content, .. } => Some(content.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["adding", "the answer ", "is 7"]);
+
+    let thinking_count = events
+        .iter()
+        .filter(|event| matches!(event, AgentEvent::Thinking { .. }))
+        .count();
+    assert_eq!(thinking_count, 2);
+
+    assert!(events.iter().any(|event| matches!(
+        event,
+        AgentEvent::ToolResult { result_text, is_error: false, .. } if result_text == "7"
+    )));
+
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "the answer is 7".to_string(),
+            data: None,
+        })
+    );
+    assert!(matches!(
+        &agent.messages()[1],
+        ModelMessage::Assistant { tool_calls, .. }
+            if tool_calls[0].arguments == json!({"a": 2, "b": 5})
+    ));
+}
+
+#[tokio::test]
+async fn query_stream_yields_text_and_thinking_deltas_in_arrival_order() {
+    let model = StreamingModel::with_turns(vec![vec![
+        ModelStreamChunk::ThinkingDelta {
+            index: 0,
+            content: "first ".to_string(),
+        },
+        ModelStreamChunk::TextDelta("Hello".to_string()),
+        ModelStreamChunk::ThinkingDelta {
+            index: 0,
+            content: "second".to_string(),
+        },
+        ModelStreamChunk::TextDelta(" world".to_string()),
+    ]]);
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+
+    let events = collect_events(&mut agent, "greet").await;
+
+    let deltas = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::Thinking { content } => Some(format!("thinking:{content}")),
+            AgentEvent::Text { content } => Some(format!("text:{content}")),
+            AgentEvent::MessageComplete { is_final, .. } => Some(format!("complete:{is_final}")),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        deltas,
+        vec![
+            "complete:false",
+            "thinking:first ",
+            "text:Hello",
+            "thinking:second",
+            "text: world",
+            "complete:true",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn query_stream_emits_tool_call_deltas_before_assembled_call() {
+    let fragments = ["{\"a\"", ": 3, \"", "b\": 4", "}"];
+    let mut first_turn = vec![ModelStreamChunk::ToolCallStart {
+        index: 0,
+        id: "call_frag".to_string(),
+        name: "add".to_string(),
+    }];
+    first_turn.extend(
+        fragments
+            .iter()
+            .map(|fragment| ModelStreamChunk::ToolCallInputDelta {
+                index: 0,
+                partial_json: fragment.to_string(),
+            }),
+    );
+    let model = StreamingModel::with_turns(vec![
+        first_turn,
+        vec![ModelStreamChunk::TextDelta("done".to_string())],
+    ]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add").await;
+
+    let deltas = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::ToolCallDelta {
+                tool_call_id,
+                partial_json,
+            } => {
+                assert_eq!(tool_call_id, "call_frag");
+                Some(partial_json.as_str())
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(deltas, fragments);
+
+    let last_delta = events
+        .iter()
+        .rposition(|event| matches!(event, AgentEvent::ToolCallDelta { .. }))
+        .expect("delta event");
+    let call = events
+        .iter()
+        .position(|event| matches!(event, AgentEvent::ToolCall { .. }))
+        .expect("tool call event");
+    assert!(last_delta < call);
+    assert!(matches!(
+        &events[call],
+        AgentEvent::ToolCall { args_json, .. } if *args_json == json!({"a": 3, "b": 4})
+    ));
+    assert_eq!(tool_results(&events), vec![("7".to_string(), false)]);
+}
+
+#[tokio::test]
+async fn query_stream_reports_truncated_tool_arguments_as_tool_error() {
+    let model = StreamingModel::with_turns(vec![
+        vec![
+            ModelStreamChunk::ToolCallStart {
+                index: 0,
+                id: "call_cut".to_string(),
+                name: "add".to_string(),
+            },
+            ModelStreamChunk::ToolCallInputDelta {
+                index: 0,
+                partial_json: "{\"a\": 3, \"b".to_string(),
+            },
+        ],
+        vec![ModelStreamChunk::TextDelta("sorry".to_string())],
+    ]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add").await;
+
+    let results = tool_results(&events);
+    assert_eq!(results.len(), 1);
+    let (text, is_error) = &results[0];
+    assert!(*is_error);
+    assert!(text.contains("not valid JSON"), "{text}");
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::FinalResponse { content, .. }) if content == "sorry"
+    ));
+}
+
+#[tokio::test]
+async fn usage_accumulates_across_iterations_and_resets_per_run() {
+    let mut first = completion(
+        None,
+        vec![tool_call("call_u", "add", json!({"a": 1, "b": 2}))],
+    );
+    first.usage = Some(ModelUsage {
+        input_tokens: 10,
+        output_tokens: 4,
+    });
+    let mut second = completion(Some("3"), vec![]);
+    second.usage = Some(ModelUsage {
+        input_tokens: 15,
+        output_tokens: 2,
+    });
+    let model = MockModel::with_responses(vec![
+        Ok(first),
+        Ok(second),
+        Ok(completion(Some("no usage"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("add")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    let usage_events = events
+        .iter()
+        .filter(|event| matches!(event, AgentEvent::Usage { .. }))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        usage_events,
+        vec![
+            AgentEvent::Usage {
+                input_tokens: 10,
+                output_tokens: 4,
+                cumulative_input: 10,
+                cumulative_output: 4,
+            },
+            AgentEvent::Usage {
+                input_tokens: 15,
+                output_tokens: 2,
+                cumulative_input: 25,
+                cumulative_output: 6,
+            },
+        ]
+    );
+    assert_eq!(
+        agent.last_run_usage(),
+        ModelUsage {
+            input_tokens: 25,
+            output_tokens: 6,
+        }
+    );
+
+    agent.query("again").await.expect("query succeeds");
+    assert_eq!(agent.last_run_usage(), ModelUsage::default());
+}
+
+#[tokio::test]
+async fn parallel_tool_calls_run_concurrently_in_order() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_a", "slow_a", json!({})),
+                tool_call("call_b", "slow_b", json!({})),
+            ],
+        )),
+        Ok(completion(Some("both done"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(slow_tool("slow_a", 200))
+        .tool(slow_tool("slow_b", 200))
+        .parallel_tool_calls(true)
+        .build()
+        .expect("agent builds");
+
+    let started = Instant::now();
+    let events = agent
+        .query_stream("go")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+    let elapsed = started.elapsed();
+
+    assert!(
+        elapsed < Duration::from_millis(350),
+        "expected concurrent execution, took {elapsed:?}"
+    );
+
+    let result_ids = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::ToolResult { tool_call_id, .. } => Some(tool_call_id.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(result_ids, vec!["call_a", "call_b"]);
+
+    let history_ids = agent
+        .messages()
+        .iter()
+        .filter_map(|message| match message {
+            ModelMessage::ToolResult { tool_call_id, .. } => Some(tool_call_id.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(history_ids, vec!["call_a", "call_b"]);
+}
+
+#[tokio::test]
+async fn parallel_tool_calls_finish_on_done_after_all_calls_settle() {
+    let model = MockModel::with_responses(vec![Ok(completion(
+        None,
+        vec![
+            tool_call("call_done", "done", json!({"message": "finished"})),
+            tool_call("call_slow", "slow", json!({})),
+        ],
+    ))]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(done_tool())
+        .tool(slow_tool("slow", 50))
+        .parallel_tool_calls(true)
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("go")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    let result_count = events
+        .iter()
+        .filter(|event| matches!(event, AgentEvent::ToolResult { .. }))
+        .count();
+    assert_eq!(result_count, 2);
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "finished".to_string(),
+            data: None,
+        })
+    );
+}
+
+fn cancel_tool(token: CancellationToken) -> ToolSpec {
+    ToolSpec::new("cancel", "trigger cancellation")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {},
+            "required": [],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(move |_args, _deps| {
+            token.cancel();
+            async move { Ok(ToolOutcome::Text("cancelling".to_string())) }
+        })
+}
+
+#[tokio::test]
+async fn cancellation_between_iterations_stops_loop_and_keeps_history_valid() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "cancel", json!({}))],
+        )),
+        Ok(completion(Some("resumed"), vec![])),
+    ]);
+    let invocations = model.invocations.clone();
+    let token = CancellationToken::new();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(cancel_tool(token.clone()))
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream_with_cancel("go", token)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("cancellation is not an error");
+
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::Cancelled {
+            reason: "cancelled before model invocation".to_string()
+        })
+    );
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::FinalResponse { .. }))
+    );
+    assert!(matches!(
+        agent.messages().last(),
+        Some(ModelMessage::ToolResult { tool_call_id, .. }) if tool_call_id == "call_1"
+    ));
+
+    let response = agent.query("continue").await.expect("follow-up succeeds");
+    assert_eq!(response, "resumed");
+}
+
+#[tokio::test]
+async fn cancellation_closes_out_pending_tool_calls() {
+    let model = MockModel::with_responses(vec![Ok(completion(
+        None,
+        vec![
+            tool_call("call_1", "cancel", json!({})),
+            tool_call("call_2", "add", json!({"a": 1, "b": 2})),
+        ],
+    ))]);
+    let token = CancellationToken::new();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(cancel_tool(token.clone()))
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream_with_cancel("go", token)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("cancellation is not an error");
+
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::Cancelled {
+            reason: "cancelled before tool execution".to_string()
+        })
+    );
+    assert!(!events.iter().any(|event| matches!(
+        event,
+        AgentEvent::ToolCall { tool_call_id, .. } if tool_call_id == "call_2"
+    )));
+
+    let results = agent
+        .messages()
+        .iter()
+        .filter_map(|message| match message {
+            ModelMessage::ToolResult {
+                tool_call_id,
+                is_error,
+                ..
+            } => Some((tool_call_id.as_str(), *is_error)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![("call_1", false), ("call_2", true)]);
+}
+
+fn two_step_model() -> MockModel {
+    MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_2", "add", json!({"a": 3, "b": 4}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ])
+}
+
+#[tokio::test]
+async fn query_channel_delivers_events_in_order_through_small_buffer() {
+    let mut reference = Agent::builder()
+        .model(two_step_model())
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+    let expected = collect_events(&mut reference, "add twice").await;
+
+    let agent = Agent::builder()
+        .model(two_step_model())
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+    let (mut receiver, handle) = agent.query_channel("add twice", 1);
+
+    let mut events = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        events.push(event.expect("event ok"));
+    }
+    assert_eq!(events, expected);
+
+    let agent = handle.await.expect("run task completes");
+    assert_eq!(agent.messages(), reference.messages());
+}
+
+#[tokio::test]
+async fn dropping_query_channel_receiver_cancels_run() {
+    let model = two_step_model();
+    let invocations = model.invocations.clone();
+    let agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+    let (mut receiver, handle) = agent.query_channel("add twice", 1);
+
+    let first = receiver.recv().await.expect("first event");
+    assert!(matches!(first, Ok(AgentEvent::MessageStart { .. })));
+    drop(receiver);
+
+    let agent = tokio::time::timeout(Duration::from_secs(5), handle)
+        .await
+        .expect("run stops after receiver is dropped")
+        .expect("run task completes");
+    assert!(invocations.load(Ordering::SeqCst) <= 1);
+    assert!(!matches!(
+        agent.messages().last(),
+        Some(ModelMessage::Assistant { content: Some(text), .. }) if text == "done"
+    ));
+}
+
+#[tokio::test]
+async fn run_timeout_fires_between_steps() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(Some("too late"), vec![])),
+    ])
+    .with_delay(Duration::from_millis(40));
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .run_timeout(Duration::from_millis(10))
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query("go").await.expect_err("run should time out");
+    match err {
+        AgentError::RunTimeout {
+            elapsed_ms,
+            timeout_ms,
+        } => {
+            assert_eq!(timeout_ms, 10);
+            assert!(elapsed_ms >= 10);
+        }
+        other => panic!("expected RunTimeout, got {other:?}"),
+    }
+
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    assert!(matches!(
+        agent.messages().last(),
+        Some(ModelMessage::ToolResult { tool_call_id, is_error: true, .. }) if tool_call_id == "call_1"
+    ));
+}
+
+#[tokio::test]
+async fn tool_timeout_records_error_and_continues() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "slow", json!({}))],
+        )),
+        Ok(completion(Some("recovered"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(slow_tool("slow", 500))
+        .tool_timeout(Duration::from_millis(20))
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("go")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+
+    assert!(events.contains(&AgentEvent::ToolResult {
+        tool: "slow".to_string(),
+        result_text: "tool 'slow' timed out after 20ms".to_string(),
+        tool_call_id: "call_1".to_string(),
+        is_error: true,
+    }));
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "recovered".to_string(),
+            data: None,
+        })
+    );
+}
+
+#[tokio::test]
+async fn snapshot_round_trips_through_json_and_resumes() {
+    let model = MockModel::with_responses(vec![
+        Ok(ModelCompletion {
+            usage: Some(ModelUsage {
+                input_tokens: 7,
+                output_tokens: 3,
+            }),
+            ..completion(
+                None,
+                vec![tool_call("call_1", "add", json!({"a": 2, "b": 3}))],
+            )
+        }),
+        Ok(completion(Some("5"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .system_prompt("be precise")
+        .build()
+        .expect("agent builds");
+    agent
+        .query("add 2 and 3")
+        .await
+        .expect("first query succeeds");
+
+    let json = serde_json::to_string(&agent.snapshot()).expect("snapshot serializes");
+    let snapshot: AgentSnapshot = serde_json::from_str(&json).expect("snapshot deserializes");
+    assert_eq!(snapshot, agent.snapshot());
+
+    let resumed_model = MockModel::with_responses(vec![Ok(completion(Some("still 5"), vec![]))]);
+    let seen_batches = resumed_model.seen_message_batches.clone();
+    let mut resumed = Agent::builder()
+        .model(resumed_model)
+        .tool(add_tool())
+        .system_prompt("be precise")
+        .build()
+        .expect("agent builds");
+    resumed.restore(snapshot);
+
+    assert_eq!(resumed.messages(), agent.messages());
+    assert_eq!(resumed.last_run_usage().input_tokens, 7);
+
+    let events = resumed
+        .query_stream("and again?")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+    assert!(matches!(
+        events.first(),
+        Some(AgentEvent::MessageStart { message_id, .. }) if message_id == "msg_4_user"
+    ));
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    let seen = &batches[0];
+    assert_eq!(seen.len(), agent.messages().len() + 1);
+    assert_eq!(seen[0], ModelMessage::System("be precise".to_string()));
+    assert_eq!(
+        seen.last(),
+        Some(&ModelMessage::User("and again?".to_string()))
+    );
+}
+
+fn approval_tool() -> ToolSpec {
+    ToolSpec::new("approve", "ask the operator for approval")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {},
+            "required": [],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(|_args, _deps| async move {
+            Ok(ToolOutcome::NeedsInput {
+                prompt: "Deploy to production?".to_string(),
+            })
+        })
+}
+
+#[tokio::test]
+async fn needs_input_pauses_run_until_input_is_provided() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "approve", json!({})),
+                tool_call("call_2", "add", json!({"a": 1, "b": 1})),
+            ],
+        )),
+        Ok(completion(Some("deployed"), vec![])),
+    ]);
+    let seen_batches = model.seen_message_batches.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(approval_tool())
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = agent
+        .query_stream("ship it")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::InputRequested {
+            prompt: "Deploy to production?".to_string(),
+            tool_call_id: "call_1".to_string(),
+        })
+    );
+    assert_eq!(agent.pending_input(), Some("call_1"));
+
+    let err = agent
+        .query("hello?")
+        .await
+        .expect_err("query must wait for input");
+    assert!(matches!(err, AgentError::Config(_)));
+    assert!(agent.provide_input("call_9", "yes").is_err());
+
+    agent
+        .provide_input("call_1", "yes")
+        .expect("input accepted");
+    assert_eq!(agent.pending_input(), None);
+
+    let events = agent
+        .query_stream("")
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok");
+    assert!(!events.iter().any(|event| matches!(
+        event,
+        AgentEvent::MessageStart {
+            role: AgentRole::User,
+            ..
+        }
+    )));
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "deployed".to_string(),
+            data: None,
+        })
+    );
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    let resumed = batches.last().expect("resumed invocation");
+    assert_eq!(
+        &resumed[resumed.len() - 2..],
+        &[
+            ModelMessage::ToolResult {
+                tool_call_id: "call_1".to_string(),
+                tool_name: "approve".to_string(),
+                content: "yes".to_string(),
+                is_error: false,
+                images: Vec::new(),
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_2".to_string(),
+                tool_name: "add".to_string(),
+                content: "Tool call skipped: an earlier call in this turn requested user input."
+                    .to_string(),
+                is_error: true,
+                images: Vec::new(),
+            },
+        ]
+    );
+}
+
+async fn collect_events(agent: &mut Agent, message: &str) -> Vec<AgentEvent> {
+    agent
+        .query_stream(message)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("events ok")
+}
+
+fn tool_results(events: &[AgentEvent]) -> Vec<(String, bool)> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::ToolResult {
+                result_text,
+                is_error,
+                ..
+            } => Some((result_text.clone(), *is_error)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn on_tool_call_allow_runs_tool_unchanged() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 2, "b": 3}))],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+    let reviewed = Arc::new(Mutex::new(Vec::new()));
+    let reviewed_in_hook = reviewed.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .on_tool_call(move |call| {
+            reviewed_in_hook
+                .lock()
+                .expect("reviewed lock")
+                .push(call.id.clone());
+            ToolCallDecision::Allow
+        })
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add").await;
+
+    assert_eq!(tool_results(&events), vec![("5".to_string(), false)]);
+    assert_eq!(*reviewed.lock().expect("reviewed lock"), vec!["call_1"]);
+}
+
+#[tokio::test]
+async fn dedupe_tool_calls_reuses_result_of_identical_call() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call(
+                "call_1",
+                "read",
+                json!({"path": "notes.txt", "range": {"start": 1, "end": 5}}),
+            )],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call(
+                "call_2",
+                "read",
+                json!({"range": {"end": 5, "start": 1}, "path": "notes.txt"}),
+            )],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+    let executions = Arc::new(AtomicUsize::new(0));
+    let executions_in_tool = executions.clone();
+    let read = ToolSpec::new("read", "read a file").with_handler(move |_args, _deps| {
+        let executions = executions_in_tool.clone();
+        async move {
+            executions.fetch_add(1, Ordering::SeqCst);
+            Ok(ToolOutcome::Text("hello".to_string()))
+        }
+    });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(read)
+        .dedupe_tool_calls(true)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "read twice").await;
+
+    assert_eq!(executions.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        tool_results(&events),
+        vec![
+            ("hello".to_string(), false),
+            ("hello\n(cached: identical call)".to_string(), false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn dedupe_tool_calls_reruns_call_after_another_call_executes() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "read", json!({"path": "a.txt"}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call(
+                "call_2",
+                "edit",
+                json!({"path": "a.txt", "text": "new"}),
+            )],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_3", "read", json!({"path": "a.txt"}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+    let file = Arc::new(Mutex::new("old".to_string()));
+    let read_file = file.clone();
+    let read = ToolSpec::new("read", "read a file").with_handler(move |_args, _deps| {
+        let contents = read_file.lock().expect("file lock").clone();
+        async move { Ok(ToolOutcome::Text(contents)) }
+    });
+    let edit = ToolSpec::new("edit", "edit a file").with_handler(move |args, _deps| {
+        *file.lock().expect("file lock") = args["text"].as_str().unwrap_or_default().to_string();
+        async move { Ok(ToolOutcome::Text("edited".to_string())) }
+    });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(read)
+        .tool(edit)
+        .dedupe_tool_calls(true)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "read, edit, read").await;
+
+    assert_eq!(
+        tool_results(&events),
+        vec![
+            ("old".to_string(), false),
+            ("edited".to_string(), false),
+            ("new".to_string(), false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn dedupe_tool_calls_keeps_cache_across_calls_that_never_ran() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 2, "b": 3}))],
+        )),
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_2", "missing", json!({})),
+                tool_call("call_3", "add", json!({"a": 1, "b": 1})),
+            ],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_4", "add", json!({"a": 2, "b": 3}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counted_runs = runs.clone();
+    let add = ToolSpec::new("add", "add numbers").with_handler(move |args, _deps| {
+        counted_runs.fetch_add(1, Ordering::SeqCst);
+        let sum = args["a"].as_i64().unwrap_or_default() + args["b"].as_i64().unwrap_or_default();
+        async move { Ok(ToolOutcome::Text(sum.to_string())) }
+    });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add)
+        .on_tool_call(|call| match call.arguments["a"].as_i64() {
+            Some(1) => ToolCallDecision::Deny {
+                reason: "no ones".to_string(),
+            },
+            _ => ToolCallDecision::Allow,
+        })
+        .dedupe_tool_calls(true)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add twice").await;
+
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        tool_results(&events).last(),
+        Some(&("5\n(cached: identical call)".to_string(), false))
+    );
+}
+
+#[tokio::test]
+async fn example_turns_precede_first_user_message() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("7"), vec![])),
+        Ok(completion(Some("9"), vec![])),
+    ]);
+    let seen_batches = model.seen_message_batches.clone();
+    let examples = vec![
+        ModelMessage::user("what is 1 + 1?"),
+        ModelMessage::assistant_tool_call("example_1", "add", json!({"a": 1, "b": 1})),
+        ModelMessage::tool_result("example_1", "add", "2"),
+        ModelMessage::assistant("2"),
+    ];
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .system_prompt("use the add tool")
+        .example_turns(examples.clone())
+        .build()
+        .expect("agent builds");
+
+    agent.query("what is 3 + 4?").await.expect("query succeeds");
+    agent.query("what is 4 + 5?").await.expect("query succeeds");
+
+    let batches = seen_batches.lock().expect("batches lock");
+    let mut expected = vec![ModelMessage::system("use the add tool")];
+    expected.extend(examples.clone());
+    expected.push(ModelMessage::user("what is 3 + 4?"));
+    assert_eq!(batches[0], expected);
+    assert_eq!(batches[1][1..5], examples[..]);
+    assert_eq!(batches[1].len(), 8);
+    assert_eq!(agent.messages_len(), 5);
+    assert!(agent.tool_call_counts().is_empty());
+    assert!(agent.tool_results().is_empty());
+}
+
+#[test]
+fn unmatched_tool_result_ids_reports_results_without_calls() {
+    let history = vec![
+        ModelMessage::user("add"),
+        ModelMessage::assistant_tool_call("call_1", "add", json!({"a": 1, "b": 2})),
+        ModelMessage::tool_result("call_1", "add", "3"),
+        ModelMessage::tool_result("call_9", "add", "3"),
+    ];
+
+    assert_eq!(unmatched_tool_result_ids(&history), vec!["call_9"]);
+}
+
+#[tokio::test]
+async fn unmatched_tool_results_are_reported_once_and_still_sent() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("first"), vec![])),
+        Ok(completion(Some("second"), vec![])),
+    ]);
+    let seen_batches = model.seen_message_batches.clone();
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+    agent.load_history(vec![
+        ModelMessage::user("add"),
+        ModelMessage::assistant_tool_call("call_1", "add", json!({"a": 1, "b": 2})),
+        ModelMessage::tool_result("call_9", "add", "3"),
+    ]);
+
+    let events = collect_events(&mut agent, "again").await;
+    assert!(events.contains(&AgentEvent::UnmatchedToolResult {
+        tool_call_id: "call_9".to_string()
+    }));
+    assert!(matches!(
+        &seen_batches.lock().expect("message batches lock")[0][2],
+        ModelMessage::ToolResult { tool_call_id, .. } if tool_call_id == "call_9"
+    ));
+
+    let events = collect_events(&mut agent, "once more").await;
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::UnmatchedToolResult { .. }))
+    );
+}
+
+#[tokio::test]
+async fn abort_on_tool_error_ends_run_after_failed_result() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "fail", json!({})),
+                tool_call("call_2", "add", json!({"a": 1, "b": 2})),
+            ],
+        )),
+        Ok(completion(Some("should not be reached"), vec![])),
+    ]);
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(fail_tool())
+        .tool(add_tool())
+        .abort_on_tool_error(true)
+        .build()
+        .expect("agent builds");
+
+    let results = agent.query_stream("try").collect::<Vec<_>>().await;
+
+    let (last, events) = results.split_last().expect("stream yields");
+    assert!(matches!(
+        last,
+        Err(AgentError::Tool(ToolError::Execution(message))) if message.contains("fail: ")
+    ));
+    let events = events
+        .iter()
+        .map(|event| event.as_ref().expect("event ok").clone())
+        .collect::<Vec<_>>();
+    assert_eq!(tool_results(&events).len(), 1);
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::StepComplete {
+            status: StepStatus::Error,
+            ..
+        })
+    ));
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        agent.tool_results(),
+        vec![("fail".to_string(), true), ("add".to_string(), true)]
+    );
+}
+
+#[tokio::test]
+async fn tool_metrics_aggregate_calls_errors_and_durations() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "slow", json!({})),
+                tool_call("call_2", "fail", json!({})),
+            ],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_3", "slow", json!({}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+        Ok(completion(Some("again"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(slow_tool("slow", 5))
+        .tool(fail_tool())
+        .build()
+        .expect("agent builds");
+
+    agent.query("go").await.expect("query succeeds");
+
+    let metrics = agent.tool_metrics();
+    let slow = metrics["slow"];
+    assert_eq!((slow.calls, slow.errors), (2, 0));
+    assert!(slow.total_duration >= Duration::from_millis(10));
+    assert_eq!((metrics["fail"].calls, metrics["fail"].errors), (1, 1));
+
+    agent.query("next").await.expect("query succeeds");
+    assert!(agent.tool_metrics().is_empty());
+}
+
+#[test]
+fn tool_catalog_lists_registered_tools_with_schemas() {
+    let lookup = ToolSpec::new("lookup", "look up a term")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {"query": {"type": "string", "description": "search text"}},
+            "required": ["query"]
+        }))
+        .expect("valid schema")
+        .with_handler(|_args, _deps| async move { Ok(ToolOutcome::Text(String::new())) });
+    let agent = Agent::builder()
+        .model(MockModel::with_responses(Vec::new()))
+        .tool(add_tool())
+        .tool(lookup)
+        .build()
+        .expect("agent builds");
+
+    let catalog = agent.tool_catalog();
+
+    assert_eq!(
+        catalog,
+        vec![
+            ToolInfo {
+                name: "add".to_string(),
+                description: "add two numbers".to_string(),
+                schema: add_tool().json_schema().clone(),
+            },
+            ToolInfo {
+                name: "lookup".to_string(),
+                description: "look up a term".to_string(),
+                schema: json!({
+                    "type": "object",
+                    "properties": {"query": {"type": "string", "description": "search text"}},
+                    "required": ["query"]
+                }),
+            },
+        ]
+    );
+    assert_eq!(
+        agent.tools_markdown(),
+        "- `add`: add two numbers\n  - `a` (integer, required)\n  - `b` (integer, required)\n\
+         - `lookup`: look up a term\n  - `query` (string, required): search text\n"
+    );
+}
+
+#[tokio::test]
+async fn stop_when_ends_run_after_matching_tool_result() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "STOP", json!({})),
+                tool_call("call_2", "add", json!({"a": 1, "b": 2})),
+            ],
+        )),
+        Ok(completion(Some("unreachable"), Vec::new())),
+    ]);
+    let invocations = model.invocations.clone();
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(slow_tool("STOP", 0))
+        .tool(add_tool())
+        .stop_when(|event| match event {
+            AgentEvent::ToolResult { result_text, .. } if result_text.contains("STOP") => {
+                Some("stopped early".to_string())
+            }
+            _ => None,
+        })
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "go").await;
+
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+    assert_eq!(tool_results(&events), vec![("STOP".to_string(), false)]);
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "stopped early".to_string(),
+            data: None,
+        })
+    );
+    assert!(matches!(
+        agent.messages().last(),
+        Some(ModelMessage::ToolResult { tool_call_id, is_error: true, .. }) if tool_call_id == "call_2"
+    ));
+}
+
+#[tokio::test]
+async fn map_tool_result_transforms_history_and_events() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "echo", json!({"text": "secret path"}))],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+    let echo = ToolSpec::new("echo", "echo text").with_handler(|args, _deps| async move {
+        Ok(ToolOutcome::Text(
+            args["text"].as_str().unwrap_or_default().to_string(),
+        ))
+    });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(echo)
+        .map_tool_result(|tool, result, is_error| {
+            format!("{tool}:{is_error}:{}", result.to_uppercase())
+        })
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "echo").await;
+
+    let expected = "echo:false:SECRET PATH".to_string();
+    assert_eq!(tool_results(&events), vec![(expected.clone(), false)]);
+    assert!(agent.messages().iter().any(|message| matches!(
+        message,
+        ModelMessage::ToolResult { content, .. } if *content == expected
+    )));
+}
+
+#[tokio::test]
+async fn map_tool_result_scrubs_error_results() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "missing", json!({})),
+                tool_call("call_2", "add", json!({"a": 2, "b": 3})),
+            ],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .on_tool_call(|call| match call.name.as_str() {
+            "add" => ToolCallDecision::Deny {
+                reason: "secret policy".to_string(),
+            },
+            _ => ToolCallDecision::Allow,
+        })
+        .map_tool_result(|tool, result, is_error| {
+            if is_error {
+                format!("{tool} failed")
+            } else {
+                result
+            }
+        })
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "try").await;
+
+    assert_eq!(
+        tool_results(&events),
+        vec![
+            ("missing failed".to_string(), true),
+            ("add failed".to_string(), true)
+        ]
+    );
+    let stored = agent
+        .messages()
+        .iter()
+        .filter_map(|message| match message {
+            ModelMessage::ToolResult { content, .. } => Some(content.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(stored, vec!["missing failed", "add failed"]);
+}
+
+#[tokio::test]
+async fn on_tool_call_deny_skips_execution() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 2, "b": 3}))],
+        )),
+        Ok(completion(Some("understood"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .on_tool_call(|_call| ToolCallDecision::Deny {
+            reason: "arithmetic is not allowed".to_string(),
+        })
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add").await;
+
+    assert_eq!(
+        tool_results(&events),
+        vec![(
+            "Tool call denied: arithmetic is not allowed".to_string(),
+            true
+        )]
+    );
+    assert!(events.iter().any(|event| matches!(
+        event,
+        AgentEvent::StepComplete {
+            status: StepStatus::Error,
+            ..
+        }
+    )));
+    assert!(agent.messages().iter().any(|message| matches!(
+        message,
+        ModelMessage::ToolResult { is_error: true, content, .. } if content.contains("not allowed")
+    )));
+}
+
+#[tokio::test]
+async fn on_tool_call_rewrite_executes_new_arguments() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 2, "b": 3}))],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .on_tool_call(|call| {
+            let mut arguments = call.arguments.clone();
+            arguments["a"] = json!(10);
+            ToolCallDecision::Rewrite(arguments)
+        })
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add").await;
+
+    assert!(events.contains(&AgentEvent::ToolCall {
+        tool: "add".to_string(),
+        args_json: json!({"a": 10, "b": 3}),
+        tool_call_id: "call_1".to_string(),
+    }));
+    assert_eq!(tool_results(&events), vec![("13".to_string(), false)]);
+}
+
+#[tokio::test]
+async fn system_prompt_is_injected_into_loaded_history_without_one() {
+    let model = MockModel::with_responses(vec![Ok(completion(Some("hi"), vec![]))]);
+    let seen_batches = model.seen_message_batches.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .system_prompt("be brief")
+        .build()
+        .expect("agent builds");
+    agent.load_history(vec![
+        ModelMessage::User("earlier".to_string()),
+        ModelMessage::Assistant {
+            content: Some("reply".to_string()),
+            tool_calls: vec![],
+        },
+    ]);
+
+    agent.query("again").await.expect("query succeeds");
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    assert_eq!(batches[0][0], ModelMessage::System("be brief".to_string()));
+    assert_eq!(batches[0][1], ModelMessage::User("earlier".to_string()));
+    assert_eq!(batches[0].len(), 4);
+}
+
+#[tokio::test]
+async fn set_system_prompt_replaces_head_and_survives_clear_history() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("first"), vec![])),
+        Ok(completion(Some("second"), vec![])),
+    ]);
+    let seen_batches = model.seen_message_batches.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .system_prompt("old prompt")
+        .build()
+        .expect("agent builds");
+    agent.query("one").await.expect("query succeeds");
+
+    agent.set_system_prompt("new prompt");
+    assert_eq!(
+        agent.messages()[0],
+        ModelMessage::System("new prompt".to_string())
+    );
+    assert_eq!(
+        agent
+            .messages()
+            .iter()
+            .filter(|message| matches!(message, ModelMessage::System(_)))
+            .count(),
+        1
+    );
+
+    agent.clear_history();
+    agent.query("two").await.expect("query succeeds");
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    assert_eq!(
+        batches[1],
+        vec![
+            ModelMessage::System("new prompt".to_string()),
+            ModelMessage::User("two".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn system_note_is_sent_with_next_invocation() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("first"), vec![])),
+        Ok(completion(Some("second"), vec![])),
+    ]);
+    let seen_batches = model.seen_message_batches.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .system_prompt("be brief")
+        .build()
+        .expect("agent builds");
+    agent.query("one").await.expect("query succeeds");
+
+    agent.push_system_note("you are now in review mode");
+    agent.query("two").await.expect("query succeeds");
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    assert!(
+        !batches[0]
+            .iter()
+            .any(|message| message == &ModelMessage::System("you are now in review mode".into()))
+    );
+    assert_eq!(
+        batches[1],
+        vec![
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("one".to_string()),
+            ModelMessage::Assistant {
+                content: Some("first".to_string()),
+                tool_calls: vec![],
+            },
+            ModelMessage::System("you are now in review mode".to_string()),
+            ModelMessage::User("two".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn set_system_prompt_inserts_before_loaded_history() {
+    let mut agent = Agent::builder()
+        .model(MockModel::default())
+        .build()
+        .expect("agent builds");
+    agent.load_history(vec![ModelMessage::User("hello".to_string())]);
+
+    agent.set_system_prompt("rules");
+
+    assert_eq!(
+        agent.messages(),
+        &[
+            ModelMessage::System("rules".to_string()),
+            ModelMessage::User("hello".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn retry_emits_event_before_backoff() {
+    let model = MockModel::with_responses(vec![
+        Err(ProviderError::request("connection reset")),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .llm_retry_config(3, 1, 1)
+        .retry_jitter(false)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "retry").await;
+
+    let retries = events
+        .iter()
+        .filter(|event| matches!(event, AgentEvent::Retry { .. }))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        retries,
+        vec![&AgentEvent::Retry {
+            attempt: 1,
+            max_attempts: 3,
+            delay_ms: 1,
+            error: "provider request failed: connection reset".to_string(),
+        }]
+    );
+    assert_eq!(
+        events.last(),
+        Some(&AgentEvent::FinalResponse {
+            content: "ok".to_string(),
+            data: None,
+        })
+    );
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let model = MockModel::with_responses(vec![
+        Err(ProviderError::http(401, "invalid api key")),
+        Ok(completion(Some("unreachable"), vec![])),
+    ]);
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .llm_retry_config(3, 0, 0)
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query("hi").await.expect_err("auth errors fail fast");
+    assert!(matches!(
+        err,
+        AgentError::Provider(ProviderError::Request {
+            status: Some(401),
+            ..
+        })
+    ));
+    assert_eq!(invocations.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn rate_limit_errors_are_retried() {
+    let model = MockModel::with_responses(vec![
+        Err(ProviderError::http(429, "rate limited")),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .llm_retry_config(3, 0, 0)
+        .build()
+        .expect("agent builds");
+
+    let response = agent.query("hi").await.expect("query succeeds after retry");
+    assert_eq!(response, "ok");
+    assert_eq!(invocations.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn max_tool_result_chars_truncates_history_and_events() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "dump", json!({}))],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+    let dump_tool = ToolSpec::new("dump", "return a lot of text")
+        .with_handler(|_args, _deps| async move { Ok(ToolOutcome::Text("x".repeat(1_000))) });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(dump_tool)
+        .max_tool_result_chars(10)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "dump").await;
+
+    let expected = format!("{}… [990 chars truncated]", "x".repeat(10));
+    assert_eq!(tool_results(&events), vec![(expected.clone(), false)]);
+    assert!(agent.messages().iter().any(|message| matches!(
+        message,
+        ModelMessage::ToolResult { content, .. } if *content == expected
+    )));
+}
+
+#[tokio::test]
+async fn max_tool_result_chars_caps_errors_raised_before_the_handler_runs() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "missing", json!({})),
+                tool_call("call_2", "slow", json!({})),
+            ],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(slow_tool("slow", 500))
+        .tool_timeout(Duration::from_millis(20))
+        .max_tool_result_chars(12)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "go").await;
+
+    assert_eq!(
+        tool_results(&events),
+        vec![
+            ("Unknown tool… [34 chars truncated]".to_string(), true),
+            ("tool 'slow' … [20 chars truncated]".to_string(), true),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn multimodal_tool_outcome_keeps_images_in_history() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "screenshot", json!({}))],
+        )),
+        Ok(completion(Some("looks good"), vec![])),
+    ]);
+    let screenshot_tool =
+        ToolSpec::new("screenshot", "capture the screen").with_handler(|_args, _deps| async move {
+            Ok(ToolOutcome::Multimodal {
+                text: Some("captured".to_string()),
+                images: vec![ImageData::new("image/png", "aGVsbG8=")],
+            })
+        });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(screenshot_tool)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "screenshot").await;
+
+    assert_eq!(tool_results(&events), vec![("captured".to_string(), false)]);
+    assert!(agent.messages().iter().any(|message| matches!(
+        message,
+        ModelMessage::ToolResult { content, images, .. }
+            if content == "captured" && *images == vec![ImageData::new("image/png", "aGVsbG8=")]
+    )));
+}
+
+#[tokio::test]
+async fn tool_filter_changes_offered_tools_between_iterations() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_2", "done", json!({"message": "3"}))],
+        )),
+    ]);
+    let offered = Arc::new(Mutex::new(Vec::new()));
+    let offered_in_hook = offered.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .tool(done_tool())
+        .tool_filter(|_tools, messages| {
+            let has_result = messages
+                .iter()
+                .any(|message| matches!(message, ModelMessage::ToolResult { .. }));
+            if has_result {
+                vec!["add".to_string(), "done".to_string()]
+            } else {
+                vec!["add".to_string(), "not_registered".to_string()]
+            }
+        })
+        .on_model_io(move |io| {
+            offered_in_hook.lock().expect("offered lock").push(
+                io.tools
+                    .iter()
+                    .map(|tool| tool.name.clone())
+                    .collect::<Vec<_>>(),
+            );
+        })
+        .build()
+        .expect("agent builds");
+
+    let response = agent.query("add 1 and 2").await.expect("query succeeds");
+
+    assert_eq!(response, "3");
+    assert_eq!(
+        *offered.lock().expect("offered lock"),
+        vec![
+            vec!["add".to_string()],
+            vec!["add".to_string(), "done".to_string()]
+        ]
+    );
+}
+
+#[tokio::test]
+async fn only_terminal_message_is_flagged_final() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            Some("let me add"),
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(Some("it is 3"), vec![])),
+        Ok(completion(Some("the answer is 3"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .hidden_user_message_prompt("double-check")
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add 1 and 2").await;
+
+    let completed = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::MessageComplete {
+                content, is_final, ..
+            } if content != "add 1 and 2" => Some((content.as_str(), *is_final)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        completed,
+        vec![
+            ("let me add", false),
+            ("it is 3", false),
+            ("the answer is 3", true)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn iteration_events_wrap_each_loop_pass() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(Some("3"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .max_iterations(5)
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "add 1 and 2").await;
+
+    let iterations = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::IterationStart {
+                iteration,
+                max_iterations,
+            } => Some(format!("start {iteration}/{max_iterations}")),
+            AgentEvent::IterationComplete { iteration } => Some(format!("complete {iteration}")),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        iterations,
+        vec!["start 1/5", "complete 1", "start 2/5", "complete 2"]
+    );
+
+    let step_start = events
+        .iter()
+        .position(|event| matches!(event, AgentEvent::StepStart { .. }))
+        .expect("step start");
+    let first_complete = events
+        .iter()
+        .position(|event| matches!(event, AgentEvent::IterationComplete { iteration: 1 }))
+        .expect("first iteration completes");
+    assert!(step_start < first_complete);
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::FinalResponse { .. })
+    ));
+}
+
+#[tokio::test]
+async fn tool_note_follows_results_as_hidden_user_message() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "search", json!({})),
+                tool_call("call_2", "add", json!({"a": 1, "b": 2})),
+            ],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+    let seen_batches = model.seen_message_batches.clone();
+    let search = ToolSpec::new("search", "search the docs").with_handler(|_args, _deps| async {
+        Ok(ToolOutcome::TextWithNote {
+            result: "3 hits".to_string(),
+            note: "Only cite sources from 2024.".to_string(),
+        })
+    });
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(search)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "look it up").await;
+    assert!(events.iter().any(|event| matches!(
+        event,
+        AgentEvent::HiddenUserMessage { content } if content == "Only cite sources from 2024."
+    )));
+
+    let batches = seen_batches.lock().expect("message batches lock");
+    let tail = &batches[1][batches[1].len() - 3..];
+    assert!(matches!(
+        &tail[0],
+        ModelMessage::ToolResult { tool_call_id, content, .. }
+            if tool_call_id == "call_1" && content == "3 hits"
+    ));
+    assert!(matches!(
+        &tail[1],
+        ModelMessage::ToolResult { tool_call_id, .. } if tool_call_id == "call_2"
+    ));
+    assert_eq!(
+        tail[2],
+        ModelMessage::User("Only cite sources from 2024.".to_string())
+    );
+}
+
+#[tokio::test]
+async fn unknown_tool_result_lists_available_tools() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "multiply", json!({}))],
+        )),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .tool(done_tool())
+        .build()
+        .expect("agent builds");
+
+    let events = collect_events(&mut agent, "multiply").await;
+
+    assert_eq!(
+        tool_results(&events),
+        vec![(
+            "Unknown tool 'multiply'. Available tools: add, done.".to_string(),
+            true
+        )]
+    );
+}
+
+#[tokio::test]
+async fn consecutive_tool_errors_abort_the_run() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "fail", json!({}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_2", "add", json!({"a": 1, "b": 1}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_3", "fail", json!({}))],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_4", "fail", json!({}))],
+        )),
+        Ok(completion(Some("unreachable"), vec![])),
+    ]);
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .tool(fail_tool())
+        .max_consecutive_tool_errors(2)
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query("fail").await.expect_err("run aborts");
+
+    assert!(matches!(
+        err,
+        AgentError::ToolErrorLoop {
+            consecutive_errors: 2,
+            ref last_error,
+        } if last_error.contains("boom")
+    ));
+    assert_eq!(invocations.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn done_mode_aborts_on_repeated_empty_completions() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("thinking it over"), vec![])),
+        Ok(completion(None, vec![])),
+        Ok(completion(Some(""), vec![])),
+        Ok(completion(None, vec![])),
+        Ok(completion(None, vec![])),
     ]);
+    let invocations = model.invocations.clone();
 
-    let response = agent.query("new").await.expect("query succeeds");
-    assert_eq!(response, "done");
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(done_tool())
+        .require_done_tool(true)
+        .max_iterations(10)
+        .max_empty_completions(3)
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query("finish").await.expect_err("run aborts");
+
+    assert!(matches!(err, AgentError::EmptyCompletionLoop { count: 3 }));
+    assert_eq!(invocations.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn max_tool_calls_aborts_before_exceeding_budget() {
+    let batch = |prefix: &str| {
+        (1..=3)
+            .map(|n| tool_call(&format!("{prefix}_{n}"), "add", json!({"a": n, "b": 1})))
+            .collect::<Vec<_>>()
+    };
+    let model = MockModel::with_responses(vec![
+        Ok(completion(None, batch("first"))),
+        Ok(completion(None, batch("second"))),
+        Ok(completion(Some("unreachable"), vec![])),
+    ]);
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .max_tool_calls(4)
+        .build()
+        .expect("agent builds");
+
+    let err = agent.query("add").await.expect_err("run aborts");
+
+    assert!(matches!(err, AgentError::MaxToolCallsReached { max: 4 }));
+    assert_eq!(invocations.load(Ordering::SeqCst), 2);
+    let results = agent
+        .messages()
+        .iter()
+        .filter_map(|message| match message {
+            ModelMessage::ToolResult {
+                tool_call_id,
+                content,
+                ..
+            } => Some((tool_call_id.as_str(), content.as_str())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(results.len(), 6);
+    assert!(
+        results[..4]
+            .iter()
+            .all(|(_, content)| !content.contains("skipped"))
+    );
+    assert_eq!(results[4].0, "second_2");
+    assert!(results[4].1.contains("tool call limit reached"));
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn event_log_round_trip_rebuilds_history() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            Some("adding"),
+            vec![
+                tool_call("call_1", "add", json!({"a": 1, "b": 2})),
+                tool_call("call_2", "add", json!({"a": "bad"})),
+            ],
+        )),
+        Ok(completion(Some("3"), vec![])),
+        Ok(completion(Some("again 3"), vec![])),
+    ]);
+    let config = AgentConfig {
+        system_prompt: Some("be brief".to_string()),
+        ..AgentConfig::default()
+    };
+    let mut agent = Agent::builder()
+        .model(model)
+        .config(config.clone())
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+
+    let mut events = collect_events(&mut agent, "add 1 and 2").await;
+    events.extend(collect_events(&mut agent, "once more").await);
+
+    let log = events
+        .iter()
+        .map(|event| serde_json::to_string(event).expect("event serializes"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let replayed = log
+        .lines()
+        .map(|line| serde_json::from_str::<AgentEvent>(line).expect("event deserializes"))
+        .collect::<Vec<_>>();
+    assert_eq!(replayed, events);
+
+    let rebuilt = Agent::from_event_log(&replayed, MockModel::with_responses(vec![]), config)
+        .expect("log replays");
 
+    assert_eq!(rebuilt.messages(), agent.messages());
     assert_eq!(
-        seen_tool_choices.lock().expect("lock").first(),
-        Some(&ModelToolChoice::Required)
+        rebuilt.snapshot().next_message_id,
+        agent.snapshot().next_message_id
     );
+}
 
-    let first_batch = seen_batches.lock().expect("lock").first().cloned().unwrap();
-    assert!(matches!(first_batch[0], ModelMessage::System(_)));
-    assert!(matches!(first_batch[1], ModelMessage::User(_)));
-    assert!(matches!(first_batch[2], ModelMessage::User(_)));
+#[cfg(feature = "serde")]
+#[test]
+fn every_event_variant_round_trips_with_its_type_tag() {
+    let events = vec![
+        AgentEvent::MessageStart {
+            message_id: "msg_1".to_string(),
+            role: AgentRole::User,
+        },
+        AgentEvent::MessageComplete {
+            message_id: "msg_1".to_string(),
+            content: "hi".to_string(),
+            is_final: true,
+        },
+        AgentEvent::HiddenUserMessage {
+            content: "keep going".to_string(),
+        },
+        AgentEvent::IterationStart {
+            iteration: 1,
+            max_iterations: 8,
+        },
+        AgentEvent::IterationComplete { iteration: 1 },
+        AgentEvent::StepStart {
+            step_id: "call_1".to_string(),
+            title: "add".to_string(),
+            step_number: 1,
+        },
+        AgentEvent::StepComplete {
+            step_id: "call_1".to_string(),
+            status: StepStatus::Error,
+            duration_ms: 12,
+        },
+        AgentEvent::Thinking {
+            content: "hmm".to_string(),
+        },
+        AgentEvent::Text {
+            content: "3".to_string(),
+        },
+        AgentEvent::ToolCallDelta {
+            tool_call_id: "call_1".to_string(),
+            partial_json: "{\"a\":".to_string(),
+        },
+        AgentEvent::ToolCall {
+            tool: "add".to_string(),
+            args_json: json!({"a": 1, "b": [2.5, null], "nested": {"ok": true}}),
+            tool_call_id: "call_1".to_string(),
+        },
+        AgentEvent::ToolResult {
+            tool: "add".to_string(),
+            result_text: "3".to_string(),
+            tool_call_id: "call_1".to_string(),
+            is_error: false,
+        },
+        AgentEvent::Usage {
+            input_tokens: 10,
+            output_tokens: 2,
+            cumulative_input: 20,
+            cumulative_output: 4,
+        },
+        AgentEvent::FinalResponse {
+            content: "3".to_string(),
+            data: Some(json!({"sum": 3})),
+        },
+        AgentEvent::Retry {
+            attempt: 1,
+            max_attempts: 3,
+            delay_ms: 250,
+            error: "rate limited".to_string(),
+        },
+        AgentEvent::InputRequested {
+            prompt: "Deploy?".to_string(),
+            tool_call_id: "call_2".to_string(),
+        },
+        AgentEvent::Cancelled {
+            reason: "before model call".to_string(),
+        },
+        AgentEvent::HistoryCompacted {
+            removed: 4,
+            summary_len: 32,
+        },
+        AgentEvent::HistoryTruncated { dropped: 3 },
+        AgentEvent::NudgeSkipped {
+            reason: "limit reached".to_string(),
+        },
+        AgentEvent::RawCompletion {
+            value: json!({"id": "resp_1"}),
+        },
+        AgentEvent::UnmatchedToolResult {
+            tool_call_id: "call_9".to_string(),
+        },
+    ];
+
+    for event in events {
+        let line = serde_json::to_string(&event).expect("event serializes");
+        assert!(!line.contains('\n'), "NDJSON lines must not wrap: {line}");
+
+        let value: serde_json::Value = serde_json::from_str(&line).expect("valid json");
+        let debug = format!("{event:?}");
+        let variant = debug.split([' ', '{']).next().expect("variant name");
+        assert_eq!(value["type"], variant);
+
+        let decoded: AgentEvent = serde_json::from_str(&line).expect("event deserializes");
+        assert_eq!(decoded, event);
+    }
+}
+
+#[test]
+fn event_log_with_unmatched_tool_result_is_rejected() {
+    let events = vec![
+        AgentEvent::MessageStart {
+            message_id: "msg_1_user".to_string(),
+            role: AgentRole::User,
+        },
+        AgentEvent::MessageComplete {
+            message_id: "msg_1_user".to_string(),
+            content: "hi".to_string(),
+            is_final: false,
+        },
+        AgentEvent::ToolResult {
+            tool: "add".to_string(),
+            result_text: "3".to_string(),
+            tool_call_id: "call_1".to_string(),
+            is_error: false,
+        },
+    ];
+
+    let err = Agent::from_event_log(
+        &events,
+        MockModel::with_responses(vec![]),
+        AgentConfig::default(),
+    )
+    .err()
+    .expect("log is rejected");
+
+    assert!(matches!(err, AgentError::InvalidEventLog { index: 2, .. }));
+}
+
+#[tokio::test]
+async fn tool_call_counts_and_results_summarize_history() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "add", json!({"a": 1, "b": 2})),
+                tool_call("call_2", "fail", json!({})),
+            ],
+        )),
+        Ok(completion(
+            None,
+            vec![tool_call("call_3", "add", json!({"a": 3, "b": 4}))],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .tool(fail_tool())
+        .build()
+        .expect("agent builds");
+
+    agent.query("go").await.expect("query succeeds");
+
+    let counts = agent.tool_call_counts();
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts.get("add"), Some(&2));
+    assert_eq!(counts.get("fail"), Some(&1));
+    assert_eq!(
+        agent.tool_results(),
+        vec![
+            ("add".to_string(), false),
+            ("fail".to_string(), true),
+            ("add".to_string(), false)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn tools_namespaced_lets_same_named_tools_coexist() {
+    fn search_tool(source: &'static str) -> ToolSpec {
+        ToolSpec::new("search", "search a source").with_handler(move |_args, _deps| async move {
+            Ok(ToolOutcome::Text(format!("{source} results")))
+        })
+    }
+
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![
+                tool_call("call_1", "web__search", json!({})),
+                tool_call("call_2", "docs__search", json!({})),
+            ],
+        )),
+        Ok(completion(Some("done"), vec![])),
+    ]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .tools_namespaced("web", vec![search_tool("web")])
+        .tools_namespaced("docs", vec![search_tool("docs")])
+        .build()
+        .expect("namespaced tools do not collide");
+
+    let events = collect_events(&mut agent, "search").await;
+
+    assert_eq!(
+        tool_results(&events),
+        vec![
+            ("web results".to_string(), false),
+            ("docs results".to_string(), false)
+        ]
+    );
+    let counts = agent.tool_call_counts();
+    assert_eq!(counts.get("web__search"), Some(&1));
+    assert_eq!(counts.get("docs__search"), Some(&1));
+}
+
+#[test]
+fn retry_delay_without_jitter_is_capped_exponential_backoff() {
+    assert_eq!(retry_delay_ms(0, 100, 1_000, None), 100);
+    assert_eq!(retry_delay_ms(2, 100, 1_000, None), 400);
+    assert_eq!(retry_delay_ms(5, 100, 1_000, None), 1_000);
+}
+
+#[test]
+fn retry_delay_with_seeded_jitter_stays_within_backoff() {
+    let rng = JitterRng::new(Some(42));
+    let delays = (0..8)
+        .map(|attempt| retry_delay_ms(attempt, 100, 1_000, Some(&rng)))
+        .collect::<Vec<_>>();
+    for (attempt, delay) in (0..).zip(&delays) {
+        assert!(*delay <= retry_delay_ms(attempt, 100, 1_000, None));
+    }
+
+    let replay = JitterRng::new(Some(42));
+    let replayed = (0..8)
+        .map(|attempt| retry_delay_ms(attempt, 100, 1_000, Some(&replay)))
+        .collect::<Vec<_>>();
+    assert_eq!(delays, replayed);
+}
+
+#[tokio::test]
+async fn assistant_prefill_is_passed_on_every_invocation() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(Some("{\"sum\": 3}"), vec![])),
+    ]);
+    let seen_call_options = model.seen_call_options.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .assistant_prefill("{")
+        .build()
+        .expect("agent builds");
+    agent.query("add 1 and 2").await.expect("query succeeds");
+
+    let seen = seen_call_options.lock().expect("call options lock");
+    assert_eq!(seen.len(), 2);
+    assert!(
+        seen.iter()
+            .all(|options| options.assistant_prefill.as_deref() == Some("{"))
+    );
+}
+
+#[tokio::test]
+async fn capture_raw_responses_requests_and_emits_raw_completion() {
+    let raw = json!({"id": "resp_1", "content": [{"type": "server_tool_use"}]});
+    let model = MockModel::with_responses(vec![Ok(ModelCompletion {
+        raw: Some(raw.clone()),
+        ..completion(Some("hi"), vec![])
+    })]);
+    let seen_call_options = model.seen_call_options.clone();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .capture_raw_responses(true)
+        .build()
+        .expect("agent builds");
+    let events = collect_events(&mut agent, "hello").await;
+
+    assert!(
+        seen_call_options
+            .lock()
+            .expect("call options lock")
+            .iter()
+            .all(|options| options.capture_raw)
+    );
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::RawCompletion { value } if *value == raw))
+    );
+}
+
+#[tokio::test]
+async fn query_with_options_passes_overrides_to_the_model() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("positive"), vec![])),
+        Ok(completion(Some("plain"), vec![])),
+    ]);
+    let seen_call_options = model.seen_call_options.clone();
+    let invocations = model.invocations.clone();
+
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+
+    let options = QueryOptions {
+        max_tokens: Some(8),
+        temperature: Some(0.0),
+        stop_sequences: vec!["\n".to_string()],
+        ..QueryOptions::default()
+    };
+    let response = agent
+        .query_with_options("classify: great product", options.clone())
+        .await
+        .expect("query succeeds");
+    assert_eq!(response, "positive");
+    assert_eq!(
+        *seen_call_options.lock().expect("call options lock"),
+        vec![ModelCallOptions::from(options)]
+    );
+
+    agent.query("again").await.expect("query succeeds");
+    assert_eq!(invocations.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        seen_call_options.lock().expect("call options lock").len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn json_schema_query_retries_until_reply_validates() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("The label is positive."), vec![])),
+        Ok(completion(
+            Some("```json\n{\"label\": \"positive\"}\n```"),
+            vec![],
+        )),
+    ]);
+    let seen_call_options = model.seen_call_options.clone();
+    let invocations = model.invocations.clone();
+    let schema = json!({
+        "type": "object",
+        "properties": {"label": {"type": "string", "enum": ["positive", "negative"]}},
+        "required": ["label"]
+    });
+
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+    let response = agent
+        .query_with_options(
+            "classify: great product",
+            QueryOptions::json_schema(schema.clone()),
+        )
+        .await
+        .expect("query succeeds");
+
+    assert!(response.contains("\"positive\""));
+    assert_eq!(invocations.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        seen_call_options.lock().expect("call options lock")[0].response_format,
+        Some(ResponseFormat::JsonSchema(schema))
+    );
+    assert!(matches!(
+        agent.messages().last(),
+        Some(ModelMessage::Assistant { .. })
+    ));
+    assert!(agent.messages().iter().any(|message| matches!(
+        message,
+        ModelMessage::User(text) if text.starts_with("Your reply did not match the requested JSON format: invalid JSON")
+    )));
+}
+
+#[tokio::test]
+async fn json_object_query_fails_after_retries_are_used() {
+    let model = MockModel::with_responses(vec![
+        Ok(completion(Some("[1, 2]"), vec![])),
+        Ok(completion(Some("still not an object"), vec![])),
+    ]);
+
+    let mut agent = Agent::builder().model(model).build().expect("agent builds");
+    let options = QueryOptions {
+        response_format_retries: 1,
+        ..QueryOptions::json_object()
+    };
+    let err = agent
+        .query_with_options("give me json", options)
+        .await
+        .expect_err("reply never validates");
+
+    assert!(matches!(
+        err,
+        AgentError::InvalidResponseFormat { retries: 1, ref message } if message.starts_with("invalid JSON")
+    ));
+}
+
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct SpanRecorder {
+    next_id: AtomicUsize,
+    spans: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        struct Fields(String);
+        impl tracing::field::Visit for Fields {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.0.push_str(&format!("{}={value:?} ", field.name()));
+            }
+        }
+
+        let mut fields = Fields(String::new());
+        span.record(&mut fields);
+        self.spans
+            .lock()
+            .expect("spans lock")
+            .push((span.metadata().name().to_string(), fields.0));
+        tracing::span::Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) as u64 + 1)
+    }
+
+    fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, _event: &tracing::Event<'_>) {}
+
+    fn enter(&self, _span: &tracing::span::Id) {}
+
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn tracing_records_run_iteration_and_tool_call_spans() {
+    let recorder = SpanRecorder::default();
+    let spans = recorder.spans.clone();
+    let _guard = tracing::subscriber::set_default(recorder);
+
+    let model = MockModel::with_responses(vec![
+        Ok(completion(
+            None,
+            vec![tool_call("call_1", "add", json!({"a": 1, "b": 2}))],
+        )),
+        Ok(completion(Some("3"), vec![])),
+    ]);
+    let mut agent = Agent::builder()
+        .model(model)
+        .tool(add_tool())
+        .build()
+        .expect("agent builds");
+    agent.query("add 1 and 2").await.expect("query succeeds");
+
+    let spans = spans.lock().expect("spans lock");
+    let count = |name: &str| spans.iter().filter(|(span, _)| span == name).count();
+    assert_eq!(count("agent.run"), 1);
+    assert_eq!(count("agent.iteration"), 2);
+    let tool_spans = spans
+        .iter()
+        .filter(|(span, _)| span == "agent.tool_call")
+        .map(|(_, fields)| fields.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(tool_spans.len(), 1);
+    assert!(tool_spans[0].contains("tool=add"));
+    assert!(tool_spans[0].contains("tool_call_id=call_1"));
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn test_clock_records_backoff_sequence_without_waiting() {
+    use crate::testing::TestClock;
+
+    let model = MockModel::with_responses(vec![
+        Err(ProviderError::request("connection reset")),
+        Err(ProviderError::http(503, "unavailable")),
+        Err(ProviderError::http(429, "slow down")),
+        Ok(completion(Some("ok"), vec![])),
+    ]);
+    let clock = TestClock::new();
+
+    let mut agent = Agent::builder()
+        .model(model)
+        .llm_retry_config(4, 1_000, 3_000)
+        .retry_jitter(false)
+        .clock(clock.clone())
+        .build()
+        .expect("agent builds");
+
+    let started = std::time::Instant::now();
+    let response = agent.query("retry").await.expect("query succeeds");
+
+    assert_eq!(response, "ok");
+    assert_eq!(
+        clock.sleeps(),
+        vec![
+            Duration::from_millis(1_000),
+            Duration::from_millis(2_000),
+            Duration::from_millis(3_000)
+        ]
+    );
+    assert_eq!(clock.elapsed(), Duration::from_millis(6_000));
+    assert!(started.elapsed() < Duration::from_secs(1));
 }
@@ -50,17 +50,29 @@ async fn main() -> Result<(), Box<dyn Error>> {
             AgentEvent::MessageComplete {
                 message_id,
                 content,
+                is_final,
             } => {
                 if !content.trim().is_empty() {
-                    println!(
-                        "message-complete [{message_id}] {}",
-                        truncate(&content, 180)
-                    );
+                    let label = if is_final {
+                        "answer"
+                    } else {
+                        "message-complete"
+                    };
+                    println!("{label} [{message_id}] {}", truncate(&content, 180));
                 }
             }
             AgentEvent::HiddenUserMessage { content } => {
                 println!("hidden-user: {}", truncate(&content, 160));
             }
+            AgentEvent::IterationStart {
+                iteration,
+                max_iterations,
+            } => {
+                println!("iteration-start {iteration}/{max_iterations}");
+            }
+            AgentEvent::IterationComplete { iteration } => {
+                println!("iteration-complete {iteration}");
+            }
             AgentEvent::StepStart {
                 step_id,
                 title,
@@ -68,6 +80,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
             } => {
                 println!("step-start #{step_number} [{step_id}] {title}");
             }
+            AgentEvent::ToolCallDelta {
+                tool_call_id,
+                partial_json,
+            } => {
+                println!("tool-call-delta [{tool_call_id}] {partial_json}");
+            }
             AgentEvent::ToolCall {
                 tool,
                 args_json,
@@ -102,8 +120,56 @@ async fn main() -> Result<(), Box<dyn Error>> {
             AgentEvent::Text { content } => {
                 println!("assistant: {}", truncate(&content, 200));
             }
-            AgentEvent::FinalResponse { content } => {
+            AgentEvent::Usage {
+                input_tokens,
+                output_tokens,
+                cumulative_input,
+                cumulative_output,
+            } => {
+                println!(
+                    "usage: +{input_tokens} in / +{output_tokens} out (total {cumulative_input} in / {cumulative_output} out)"
+                );
+            }
+            AgentEvent::FinalResponse { content, data } => {
                 println!("\nfinal:\n{content}");
+                if let Some(data) = data {
+                    println!("data: {data}");
+                }
+            }
+            AgentEvent::Retry {
+                attempt,
+                max_attempts,
+                delay_ms,
+                error,
+            } => {
+                println!("retry {attempt}/{max_attempts} in {delay_ms}ms: {error}");
+            }
+            AgentEvent::InputRequested {
+                prompt,
+                tool_call_id,
+            } => {
+                println!("input requested [{tool_call_id}]: {prompt}");
+            }
+            AgentEvent::Cancelled { reason } => {
+                println!("cancelled: {reason}");
+            }
+            AgentEvent::HistoryCompacted {
+                removed,
+                summary_len,
+            } => {
+                println!("history compacted: {removed} removed, summary {summary_len} chars");
+            }
+            AgentEvent::HistoryTruncated { dropped } => {
+                println!("history truncated: {dropped} dropped");
+            }
+            AgentEvent::NudgeSkipped { reason } => {
+                println!("nudge skipped: {reason}");
+            }
+            AgentEvent::RawCompletion { value } => {
+                println!("raw completion: {value}");
+            }
+            AgentEvent::UnmatchedToolResult { tool_call_id } => {
+                println!("unmatched tool result: {tool_call_id}");
             }
         }
     }
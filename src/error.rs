@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::agent::QueryResult;
+
 #[derive(Debug, Error)]
 pub enum SchemaError {
     #[error("tool schema must be a JSON object")]
@@ -8,6 +10,8 @@ pub enum SchemaError {
     RootTypeMustBeObject,
     #[error("required must be an array of strings")]
     InvalidRequired,
+    #[error("invalid pattern for field '{field}': {message}")]
+    InvalidPattern { field: String, message: String },
 }
 
 #[derive(Debug, Error)]
@@ -26,10 +30,89 @@ pub enum ToolError {
 
 #[derive(Debug, Error)]
 pub enum ProviderError {
-    #[error("provider request failed: {0}")]
-    Request(String),
-    #[error("provider response invalid: {0}")]
-    Response(String),
+    #[error("provider request failed: {message}")]
+    Request {
+        message: String,
+        /// HTTP status when the provider answered; `None` for transport failures.
+        status: Option<u16>,
+        /// The provider's own error message parsed from the response body, when present.
+        provider_message: Option<String>,
+    },
+    #[error("provider response invalid: {message}")]
+    Response {
+        message: String,
+        /// HTTP status of the response that failed to parse; `None` when it is not known,
+        /// e.g. for errors raised while normalizing an already-parsed payload.
+        status: Option<u16>,
+        /// The provider's own error message, e.g. from an in-stream error event.
+        provider_message: Option<String>,
+    },
+}
+
+impl ProviderError {
+    /// Request failure without an HTTP status (transport or client setup).
+    pub fn request(message: impl Into<String>) -> Self {
+        Self::Request {
+            message: message.into(),
+            status: None,
+            provider_message: None,
+        }
+    }
+
+    /// Request failure for a non-success HTTP response.
+    pub fn http(status: u16, message: impl Into<String>) -> Self {
+        Self::Request {
+            message: message.into(),
+            status: Some(status),
+            provider_message: None,
+        }
+    }
+
+    /// Request failure for a non-success HTTP response whose body carried a provider message.
+    pub fn api(status: u16, message: impl Into<String>, provider_message: Option<String>) -> Self {
+        Self::Request {
+            message: message.into(),
+            status: Some(status),
+            provider_message,
+        }
+    }
+
+    /// Invalid response without a known HTTP status or provider message.
+    pub fn response(message: impl Into<String>) -> Self {
+        Self::Response {
+            message: message.into(),
+            status: None,
+            provider_message: None,
+        }
+    }
+
+    /// Error event reported by the provider inside an otherwise successful stream.
+    pub fn stream(message: impl Into<String>, provider_message: impl Into<String>) -> Self {
+        Self::Response {
+            message: message.into(),
+            status: None,
+            provider_message: Some(provider_message.into()),
+        }
+    }
+
+    /// HTTP status attached to this error, if any.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Request { status, .. } | Self::Response { status, .. } => *status,
+        }
+    }
+
+    /// Provider error message parsed from a non-success response body, if any.
+    pub fn provider_message(&self) -> Option<&str> {
+        match self {
+            Self::Request {
+                provider_message, ..
+            }
+            | Self::Response {
+                provider_message, ..
+            } => provider_message.as_deref(),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -40,6 +123,29 @@ pub enum AgentError {
     Provider(#[from] ProviderError),
     #[error("max iterations reached ({max_iterations})")]
     MaxIterationsReached { max_iterations: u32 },
+    /// Returned by `Agent::query_with_trace` so the work done before the limit is not lost.
+    #[error("max iterations reached ({max_iterations}); partial trace attached")]
+    MaxIterationsWithTrace {
+        max_iterations: u32,
+        trace: Box<QueryResult>,
+    },
+    #[error("run timed out after {elapsed_ms}ms (limit {timeout_ms}ms)")]
+    RunTimeout { elapsed_ms: u128, timeout_ms: u128 },
+    #[error("aborted after {consecutive_errors} consecutive tool errors; last: {last_error}")]
+    ToolErrorLoop {
+        consecutive_errors: u32,
+        last_error: String,
+    },
+    #[error("model returned {count} empty completions in a row without calling a tool")]
+    EmptyCompletionLoop { count: u32 },
+    #[error("final response did not match the requested format after {retries} retries: {message}")]
+    InvalidResponseFormat { retries: u32, message: String },
+    #[error("max tool calls reached ({max})")]
+    MaxToolCallsReached { max: u32 },
+    #[error("estimated input of {estimate} tokens exceeds the limit of {limit}")]
+    InputTooLarge { estimate: usize, limit: usize },
+    #[error("invalid event log at event {index}: {reason}")]
+    InvalidEventLog { index: usize, reason: String },
     #[error("agent stream ended without final response")]
     MissingFinalResponse,
     #[error("agent configuration error: {0}")]
@@ -21,7 +21,7 @@
 //! - Tools define capability surface ([`ToolSpec`]).
 //! - The run loop is explicit and inspectable via events ([`AgentEvent`]).
 //! - Completion can be explicit with `done` mode ([`ToolOutcome::Done`]).
-//! - Model adapters stay thin and replaceable ([`ChatModel`], [`AnthropicModel`], [`GoogleModel`], [`GrokModel`]).
+//! - Model adapters stay thin and replaceable ([`ChatModel`], [`AnthropicModel`], [`CohereModel`], [`DeepSeekModel`], [`GoogleModel`], [`GrokModel`], [`MistralModel`], [`OpenAiModel`]).
 //!
 //! ## Quickstart
 //! ```rust,no_run
@@ -51,7 +51,7 @@
 //! while let Some(event) = stream.next().await {
 //!     match event? {
 //!         AgentEvent::ToolCall { tool, .. } => println!("tool: {tool}"),
-//!         AgentEvent::FinalResponse { content } => println!("final: {content}"),
+//!         AgentEvent::FinalResponse { content, .. } => println!("final: {content}"),
 //!         _ => {}
 //!     }
 //! }
@@ -91,21 +91,32 @@ pub mod agent;
 pub mod error;
 /// Provider abstraction and model adapters.
 pub mod llm;
+/// Scripted model and helpers for testing agents and tools (requires the `testing` feature).
+#[cfg(feature = "testing")]
+pub mod testing;
 /// Tool specification, dependency injection, and built-in Claude-code-style tools.
 pub mod tools;
 
 /// Agent runtime API.
 pub use agent::{
-    Agent, AgentBuilder, AgentConfig, AgentEvent, AgentRole, AgentToolChoice, StepStatus, query,
-    query_stream,
+    Agent, AgentBuilder, AgentConfig, AgentEvent, AgentRole, AgentSnapshot, AgentToolChoice,
+    CancellationToken, Clock, CompactionPolicy, CompactionStrategy, CompactionTrigger,
+    PreviewRequest, QueryOptions, QueryResult, StepStatus, TokioClock, ToolCallDecision, ToolInfo,
+    ToolMetrics, ToolResultRecord, query, query_stream, query_stream_with_cancel,
 };
 /// Error values exposed by the SDK.
 pub use error::{AgentError, ProviderError, SchemaError, ToolError};
 /// Model adapters and model-interface types.
 pub use llm::{
-    AnthropicModel, AnthropicModelConfig, ChatModel, GoogleModel, GoogleModelConfig, GrokModel,
-    GrokModelConfig, ModelCompletion, ModelMessage, ModelToolCall, ModelToolChoice,
-    ModelToolDefinition, ModelUsage,
+    AnthropicModel, AnthropicModelConfig, AzureOpenAiModel, AzureOpenAiModelConfig, ChatModel,
+    CohereModel, CohereModelConfig, DeepSeekModel, DeepSeekModelConfig, GoogleModel,
+    GoogleModelConfig, GrokModel, GrokModelConfig, ImageData, MistralModel, MistralModelConfig,
+    ModelCallOptions, ModelCompletion, ModelIo, ModelMessage, ModelStream, ModelStreamChunk,
+    ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage, OpenAiModel,
+    OpenAiModelConfig, RequestDescription, ResponseFormat, estimate_tokens,
 };
+/// AWS Bedrock adapter (requires the `aws` feature).
+#[cfg(feature = "aws")]
+pub use llm::{BedrockModel, BedrockModelConfig};
 /// Tool and dependency primitives.
 pub use tools::{DependencyMap, ToolOutcome, ToolSpec};
@@ -1,21 +1,22 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use anthropic_ai_sdk::client::AnthropicClient;
 use anthropic_ai_sdk::types::message::{
-    ContentBlock, CreateMessageParams, CreateMessageResponse, Message, MessageClient, MessageError,
-    RequiredMessageParams, Role, Thinking, ThinkingType, Tool, ToolChoice,
+    ContentBlock, ContentBlockDelta, CreateMessageParams, CreateMessageResponse, ImageSource,
+    Message, MessageClient, MessageError, RequiredMessageParams, Role, StreamEvent, Thinking,
+    ThinkingType, Tool, ToolChoice,
 };
 use async_trait::async_trait;
+use futures_util::StreamExt;
 
 use crate::error::ProviderError;
 use crate::llm::{
-    ChatModel, ModelCompletion, ModelMessage, ModelToolCall, ModelToolChoice, ModelToolDefinition,
-    ModelUsage,
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelStream, ModelStreamChunk, ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage,
+    RequestDescription, env_var, http_client, joined_len,
 };
 
-#[cfg(test)]
-use anthropic_ai_sdk::types::message::ContentBlockDelta;
-#[cfg(test)]
-use anthropic_ai_sdk::types::message::{MessageStartContent, StopReason, StreamEvent};
-
 #[derive(Debug, Clone)]
 /// Runtime configuration for [`AnthropicModel`].
 pub struct AnthropicModelConfig {
@@ -35,6 +36,12 @@ pub struct AnthropicModelConfig {
     pub top_p: Option<f32>,
     /// Optional budget for extended thinking tokens.
     pub thinking_budget_tokens: Option<usize>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
 }
 
 impl AnthropicModelConfig {
@@ -49,6 +56,8 @@ impl AnthropicModelConfig {
             temperature: None,
             top_p: None,
             thinking_budget_tokens: None,
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
         }
     }
 }
@@ -63,24 +72,87 @@ pub struct AnthropicModel {
 impl AnthropicModel {
     /// Creates a model adapter from explicit config.
     pub fn new(config: AnthropicModelConfig) -> Result<Self, ProviderError> {
+        let http_client = http_client(
+            Some(AnthropicClient::DEFAULT_USER_AGENT),
+            &HashMap::new(),
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
         let mut builder =
-            AnthropicClient::builder(config.api_key.clone(), config.api_version.clone());
+            AnthropicClient::builder(config.api_key.clone(), config.api_version.clone())
+                .with_http_client(http_client);
         if let Some(url) = &config.api_base_url {
             builder = builder.with_api_base_url(url.clone());
         }
 
         let client = builder
             .build::<MessageError>()
-            .map_err(|err| ProviderError::Request(err.to_string()))?;
+            .map_err(|err| ProviderError::request(err.to_string()))?;
 
         Ok(Self { client, config })
     }
 
     /// Creates a model adapter using `ANTHROPIC_API_KEY` from the environment.
     pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
-        let api_key = std::env::var("ANTHROPIC_API_KEY")
-            .map_err(|_| ProviderError::Request("ANTHROPIC_API_KEY is not set".to_string()))?;
-        Self::new(AnthropicModelConfig::new(api_key, model))
+        Self::from_env_with_key("ANTHROPIC_API_KEY", model)
+    }
+
+    /// Creates a model adapter reading the API key from the `var_name` environment variable.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        Self::new(AnthropicModelConfig::new(env_var(var_name)?, model))
+    }
+
+    /// Streams one message through the SDK's server-sent events API.
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> ModelStream<'a> {
+        let request =
+            build_request(messages, tools, tool_choice, &self.config, options).with_stream(true);
+        let prefill = prefill_text(options).map(ToString::to_string);
+        let capture_raw = options.capture_raw;
+
+        Box::pin(async_stream::try_stream! {
+            let events = self
+                .client
+                .create_message_streaming(&request)
+                .await
+                .map_err(map_message_error)?;
+            futures_util::pin_mut!(events);
+
+            // The API continues after the prefill; restore it so callers see the whole turn.
+            if let Some(prefill) = prefill {
+                yield ModelStreamChunk::TextDelta(prefill);
+            }
+
+            let mut mapper = StreamMapper::default();
+            let mut raw_events = Vec::new();
+            while let Some(event) = events.next().await {
+                let event = event.map_err(map_message_error)?;
+                if capture_raw {
+                    raw_events.extend(serde_json::to_value(&event).ok());
+                }
+                let Some(chunk) = normalize_stream_event(&event) else {
+                    continue;
+                };
+                for chunk in mapper.map(chunk)? {
+                    yield chunk;
+                }
+            }
+
+            if capture_raw {
+                yield ModelStreamChunk::Completion(ModelCompletion {
+                    raw: Some(serde_json::Value::Array(raw_events)),
+                    ..ModelCompletion::default()
+                });
+            }
+        })
     }
 }
 
@@ -92,61 +164,187 @@ impl ChatModel for AnthropicModel {
         tools: &[ModelToolDefinition],
         tool_choice: ModelToolChoice,
     ) -> Result<ModelCompletion, ProviderError> {
-        let (history, system) = to_anthropic_messages(messages);
-
-        let required = RequiredMessageParams {
-            model: self.config.model.clone(),
-            messages: history,
-            max_tokens: self.config.max_tokens,
-        };
-
-        let mut request = CreateMessageParams::new(required).with_stream(false);
-
-        if let Some(system_prompt) = system {
-            request = request.with_system(system_prompt);
-        }
-
-        if let Some(temperature) = self.config.temperature {
-            request = request.with_temperature(temperature);
-        }
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
 
-        if let Some(top_p) = self.config.top_p {
-            request = request.with_top_p(top_p);
-        }
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
 
-        if let Some(budget_tokens) = self.config.thinking_budget_tokens {
-            request = request.with_thinking(Thinking {
-                budget_tokens,
-                type_: ThinkingType::Enabled,
-            });
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let request = build_request(messages, &[], ModelToolChoice::Auto, &self.config, options);
+        RequestDescription {
+            system: request.system.into_iter().collect(),
+            message_count: request.messages.len(),
         }
+    }
 
-        if !tools.is_empty() {
-            let anthropic_tools = tools
-                .iter()
-                .map(|tool| Tool {
-                    name: tool.name.clone(),
-                    description: Some(tool.description.clone()),
-                    input_schema: tool.parameters.clone(),
-                })
-                .collect::<Vec<_>>();
-
-            request = request.with_tools(anthropic_tools);
-            request = request.with_tool_choice(match tool_choice {
-                ModelToolChoice::Auto => ToolChoice::Auto,
-                ModelToolChoice::Required => ToolChoice::Any,
-                ModelToolChoice::None => ToolChoice::None,
-                ModelToolChoice::Tool(name) => ToolChoice::Tool { name },
-            });
-        }
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "anthropic.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let request = build_request(messages, tools, tool_choice, &self.config, options);
 
         let response = self
             .client
             .create_message(Some(&request))
             .await
-            .map_err(|err| ProviderError::Request(err.to_string()))?;
+            .map_err(map_message_error)?;
+
+        let mut completion = normalize_response(&response);
+        if options.capture_raw {
+            completion.raw = serde_json::to_value(&response).ok();
+        }
+        // The API continues after the prefill; restore it so callers see the whole turn.
+        if let Some(prefill) = prefill_text(options) {
+            completion.text = Some(format!("{prefill}{}", completion.text.unwrap_or_default()));
+        }
+        Ok(completion)
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> ModelStream<'a> {
+        self.stream_completion(messages, tools, tool_choice, &ModelCallOptions::default())
+    }
+
+    fn invoke_stream_with_options<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &'a ModelCallOptions,
+    ) -> ModelStream<'a> {
+        self.stream_completion(messages, tools, tool_choice, options)
+    }
+}
 
-        Ok(normalize_response(&response))
+fn build_request(
+    messages: &[ModelMessage],
+    tools: &[ModelToolDefinition],
+    tool_choice: ModelToolChoice,
+    config: &AnthropicModelConfig,
+    options: &ModelCallOptions,
+) -> CreateMessageParams {
+    let (mut history, system) = to_anthropic_messages(messages);
+    if let Some(prefill) = prefill_text(options) {
+        history.push(Message::new_text(Role::Assistant, prefill));
+    }
+
+    let required = RequiredMessageParams {
+        model: config.model.clone(),
+        messages: history,
+        max_tokens: options.max_tokens.unwrap_or(config.max_tokens),
+    };
+
+    let mut request = CreateMessageParams::new(required).with_stream(false);
+
+    if let Some(system_prompt) = system {
+        request = request.with_system(system_prompt);
+    }
+
+    if let Some(temperature) = options.temperature.or(config.temperature) {
+        request = request.with_temperature(temperature);
+    }
+
+    if !options.stop_sequences.is_empty() {
+        request = request.with_stop_sequences(options.stop_sequences.clone());
+    }
+
+    if let Some(top_p) = config.top_p {
+        request = request.with_top_p(top_p);
+    }
+
+    if let Some(budget_tokens) = config.thinking_budget_tokens {
+        request = request.with_thinking(Thinking {
+            budget_tokens,
+            type_: ThinkingType::Enabled,
+        });
+    }
+
+    if !tools.is_empty() {
+        let anthropic_tools = tools
+            .iter()
+            .map(|tool| Tool {
+                name: tool.name.clone(),
+                description: Some(tool.description.clone()),
+                input_schema: tool.parameters.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        request = request.with_tools(anthropic_tools);
+        request = request.with_tool_choice(match tool_choice {
+            ModelToolChoice::Auto => ToolChoice::Auto,
+            ModelToolChoice::Required => ToolChoice::Any,
+            ModelToolChoice::None => ToolChoice::None,
+            ModelToolChoice::Tool(name) => ToolChoice::Tool { name },
+        });
+    }
+
+    request
+}
+
+/// Prefill as sent to the API, which rejects a final assistant turn ending in whitespace.
+fn prefill_text(options: &ModelCallOptions) -> Option<&str> {
+    options
+        .assistant_prefill
+        .as_deref()
+        .map(str::trim_end)
+        .filter(|prefill| !prefill.is_empty())
+}
+
+/// Converts SDK errors, recovering the HTTP status from Anthropic's error envelope.
+///
+/// The SDK only forwards the response body, so the status is inferred from `error.type`.
+fn map_message_error(err: MessageError) -> ProviderError {
+    let message = err.to_string();
+    let body = match &err {
+        MessageError::RequestFailed(body) | MessageError::ApiError(body) => body,
+    };
+
+    let parsed = serde_json::from_str::<serde_json::Value>(body).ok();
+    let error_type = parsed
+        .as_ref()
+        .and_then(|value| value["error"]["type"].as_str().map(ToString::to_string));
+    let provider_message = parsed
+        .as_ref()
+        .and_then(|value| value["error"]["message"].as_str().map(ToString::to_string));
+    let status = match error_type.as_deref() {
+        Some("invalid_request_error") => Some(400),
+        Some("authentication_error") => Some(401),
+        Some("permission_error") => Some(403),
+        Some("not_found_error") => Some(404),
+        Some("request_too_large") => Some(413),
+        Some("rate_limit_error") => Some(429),
+        Some("api_error") => Some(500),
+        Some("overloaded_error") => Some(529),
+        _ => None,
+    };
+
+    ProviderError::Request {
+        message,
+        status,
+        provider_message,
     }
 }
 
@@ -165,12 +363,12 @@ fn to_anthropic_messages(messages: &[ModelMessage]) -> (Vec<Message>, Option<Str
                 tool_calls,
             } => {
                 let mut blocks = Vec::new();
-                if let Some(content) = content {
-                    if !content.is_empty() {
-                        blocks.push(ContentBlock::Text {
-                            text: content.clone(),
-                        });
-                    }
+                if let Some(content) = content
+                    && !content.is_empty()
+                {
+                    blocks.push(ContentBlock::Text {
+                        text: content.clone(),
+                    });
                 }
                 for call in tool_calls {
                     blocks.push(ContentBlock::ToolUse {
@@ -188,19 +386,26 @@ fn to_anthropic_messages(messages: &[ModelMessage]) -> (Vec<Message>, Option<Str
                 tool_name: _,
                 content,
                 is_error,
+                images,
             } => {
                 let rendered = if *is_error {
                     format!("Error: {content}")
                 } else {
                     content.clone()
                 };
-                anthropic_messages.push(Message::new_blocks(
-                    Role::User,
-                    vec![ContentBlock::ToolResult {
-                        tool_use_id: tool_call_id.clone(),
-                        content: rendered,
-                    }],
-                ));
+                // The SDK's tool_result only holds text, so images follow it in the same turn.
+                let mut blocks = vec![ContentBlock::ToolResult {
+                    tool_use_id: tool_call_id.clone(),
+                    content: rendered,
+                }];
+                blocks.extend(images.iter().map(|image| ContentBlock::Image {
+                    source: ImageSource {
+                        type_: "base64".to_string(),
+                        media_type: image.media_type.clone(),
+                        data: image.data.clone(),
+                    },
+                }));
+                anthropic_messages.push(Message::new_blocks(Role::User, blocks));
             }
         }
     }
@@ -217,9 +422,16 @@ fn to_anthropic_messages(messages: &[ModelMessage]) -> (Vec<Message>, Option<Str
 fn normalize_response(response: &CreateMessageResponse) -> ModelCompletion {
     let mut text_parts = Vec::new();
     let mut thinking_parts = Vec::new();
+    let mut thinking_offsets = Vec::new();
     let mut tool_calls = Vec::new();
 
     for block in &response.content {
+        if matches!(
+            block,
+            ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }
+        ) {
+            thinking_offsets.push(joined_len(&text_parts, "\n"));
+        }
         match block {
             ContentBlock::Text { text } => text_parts.push(text.clone()),
             ContentBlock::ToolUse { id, name, input } => tool_calls.push(ModelToolCall {
@@ -241,24 +453,22 @@ fn normalize_response(response: &CreateMessageResponse) -> ModelCompletion {
         Some(text_parts.join("\n"))
     };
 
-    let thinking = if thinking_parts.is_empty() {
-        None
-    } else {
-        Some(thinking_parts.join("\n"))
-    };
-
     ModelCompletion {
         text,
-        thinking,
+        thinking: thinking_parts,
+        thinking_offsets,
         tool_calls,
         usage: Some(ModelUsage {
             input_tokens: response.usage.input_tokens,
             output_tokens: response.usage.output_tokens,
         }),
+        raw: None,
     }
 }
 
-#[cfg(test)]
+/// Stream event reduced to the parts that map onto [`ModelStreamChunk`]s.
+///
+/// `index` is the content block index, which keeps parallel tool-use blocks apart.
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum AnthropicStreamChunk {
     Text {
@@ -274,31 +484,29 @@ pub(crate) enum AnthropicStreamChunk {
         partial_json: String,
     },
     ToolCallStart {
+        index: usize,
         id: String,
         name: String,
         input: serde_json::Value,
     },
-    Signature {
-        index: usize,
-        signature: String,
-    },
-    MessageStop {
-        stop_reason: Option<String>,
+    Usage {
+        input_tokens: u32,
+        output_tokens: u32,
     },
     Error {
         message: String,
     },
 }
 
-#[cfg(test)]
 pub(crate) fn normalize_stream_event(event: &StreamEvent) -> Option<AnthropicStreamChunk> {
     match event {
         StreamEvent::ContentBlockStart {
-            index: _,
+            index,
             content_block,
         } => {
             if let ContentBlock::ToolUse { id, name, input } = content_block {
                 Some(AnthropicStreamChunk::ToolCallStart {
+                    index: *index,
                     id: id.clone(),
                     name: name.clone(),
                     input: input.clone(),
@@ -322,47 +530,107 @@ pub(crate) fn normalize_stream_event(event: &StreamEvent) -> Option<AnthropicStr
                     partial_json: partial_json.clone(),
                 })
             }
-            ContentBlockDelta::SignatureDelta { signature } => {
-                Some(AnthropicStreamChunk::Signature {
-                    index: *index,
-                    signature: signature.clone(),
-                })
-            }
+            // Signatures are not kept for buffered responses either.
+            ContentBlockDelta::SignatureDelta { .. } => None,
         },
-        StreamEvent::MessageDelta { delta, usage: _ } => Some(AnthropicStreamChunk::MessageStop {
-            stop_reason: delta.stop_reason.as_ref().map(stop_reason_name),
+        StreamEvent::MessageStart { message } => Some(AnthropicStreamChunk::Usage {
+            input_tokens: message.usage.input_tokens,
+            output_tokens: message.usage.output_tokens,
         }),
-        StreamEvent::MessageStop => Some(AnthropicStreamChunk::MessageStop { stop_reason: None }),
+        StreamEvent::MessageDelta { delta: _, usage } => {
+            usage.as_ref().map(|usage| AnthropicStreamChunk::Usage {
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            })
+        }
         StreamEvent::Error { error } => Some(AnthropicStreamChunk::Error {
             message: error.message.clone(),
         }),
-        StreamEvent::MessageStart {
-            message: MessageStartContent { .. },
-        }
-        | StreamEvent::ContentBlockStop { .. }
-        | StreamEvent::Ping => None,
+        StreamEvent::ContentBlockStop { .. } | StreamEvent::MessageStop | StreamEvent::Ping => None,
     }
 }
 
-#[cfg(test)]
-fn stop_reason_name(stop_reason: &StopReason) -> String {
-    match stop_reason {
-        StopReason::EndTurn => "end_turn",
-        StopReason::MaxTokens => "max_tokens",
-        StopReason::StopSequence => "stop_sequence",
-        StopReason::ToolUse => "tool_use",
-        StopReason::Refusal => "refusal",
-    }
-    .to_string()
+/// Turns [`AnthropicStreamChunk`]s into [`ModelStreamChunk`]s for one streamed message.
+#[derive(Debug, Default)]
+struct StreamMapper {
+    text_block: Option<usize>,
+    input_tokens: u32,
+}
+
+impl StreamMapper {
+    fn map(&mut self, chunk: AnthropicStreamChunk) -> Result<Vec<ModelStreamChunk>, ProviderError> {
+        let chunks = match chunk {
+            AnthropicStreamChunk::Text { index, text } => {
+                vec![ModelStreamChunk::TextDelta(separate_blocks(
+                    &mut self.text_block,
+                    index,
+                    text,
+                ))]
+            }
+            AnthropicStreamChunk::Thinking { index, content } => {
+                vec![ModelStreamChunk::ThinkingDelta { index, content }]
+            }
+            AnthropicStreamChunk::ToolCallStart {
+                index,
+                id,
+                name,
+                input,
+            } => {
+                let mut chunks = vec![ModelStreamChunk::ToolCallStart { index, id, name }];
+                // The API starts tool-use blocks with `{}` and streams the input as JSON deltas.
+                if input.as_object().is_none_or(|object| !object.is_empty()) {
+                    chunks.push(ModelStreamChunk::ToolCallInputDelta {
+                        index,
+                        partial_json: input.to_string(),
+                    });
+                }
+                chunks
+            }
+            AnthropicStreamChunk::ToolInputJson {
+                index,
+                partial_json,
+            } => vec![ModelStreamChunk::ToolCallInputDelta {
+                index,
+                partial_json,
+            }],
+            AnthropicStreamChunk::Usage {
+                input_tokens,
+                output_tokens,
+            } => {
+                // `message_delta` may omit input tokens; keep the count from `message_start`.
+                self.input_tokens = self.input_tokens.max(input_tokens);
+                vec![ModelStreamChunk::Usage(ModelUsage {
+                    input_tokens: self.input_tokens,
+                    output_tokens,
+                })]
+            }
+            AnthropicStreamChunk::Error { message } => {
+                return Err(ProviderError::stream(
+                    format!("anthropic stream error: {message}"),
+                    message,
+                ));
+            }
+        };
+        Ok(chunks)
+    }
+}
+
+/// Prefixes a newline when `index` starts a new block, matching buffered responses, which
+/// join blocks with `\n`.
+fn separate_blocks(current: &mut Option<usize>, index: usize, text: String) -> String {
+    match current.replace(index) {
+        Some(previous) if previous != index => format!("\n{text}"),
+        _ => text,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use anthropic_ai_sdk::types::message::MessageContent;
+    use anthropic_ai_sdk::types::message::{MessageContent, StopReason};
     use serde_json::json;
 
     use super::*;
-    use crate::llm::ModelMessage;
+    use crate::llm::{ImageData, ModelMessage};
 
     #[test]
     fn normalize_response_extracts_tool_calls_and_text() {
@@ -405,6 +673,7 @@ mod tests {
                 tool_name: "search".to_string(),
                 content: "failed".to_string(),
                 is_error: true,
+                images: Vec::new(),
             },
         ];
 
@@ -424,6 +693,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn build_request_appends_trimmed_assistant_prefill() {
+        let config = AnthropicModelConfig::new("key", "claude-sonnet-4-5");
+        let options = ModelCallOptions {
+            assistant_prefill: Some("{\"answer\": ".to_string()),
+            ..ModelCallOptions::default()
+        };
+
+        let request = build_request(
+            &[ModelMessage::User("reply in json".to_string())],
+            &[],
+            ModelToolChoice::Auto,
+            &config,
+            &options,
+        );
+        let value = serde_json::to_value(&request).expect("serializes");
+
+        assert_eq!(value["messages"].as_array().map(Vec::len), Some(2));
+        assert_eq!(value["messages"][1]["role"], "assistant");
+        assert_eq!(value["messages"][1]["content"], "{\"answer\":");
+
+        let plain = build_request(
+            &[ModelMessage::User("hi".to_string())],
+            &[],
+            ModelToolChoice::Auto,
+            &config,
+            &ModelCallOptions::default(),
+        );
+        assert_eq!(plain.messages.len(), 1);
+    }
+
+    #[test]
+    fn to_anthropic_messages_appends_tool_result_images() {
+        let history = vec![ModelMessage::ToolResult {
+            tool_call_id: "call_1".to_string(),
+            tool_name: "screenshot".to_string(),
+            content: "captured".to_string(),
+            is_error: false,
+            images: vec![ImageData::new("image/png", "aGVsbG8=")],
+        }];
+
+        let (messages, _) = to_anthropic_messages(&history);
+        let value = serde_json::to_value(&messages[0]).expect("serializes");
+
+        assert_eq!(value["content"][0]["type"], "tool_result");
+        assert_eq!(value["content"][1]["type"], "image");
+        assert_eq!(value["content"][1]["source"]["type"], "base64");
+        assert_eq!(value["content"][1]["source"]["media_type"], "image/png");
+        assert_eq!(value["content"][1]["source"]["data"], "aGVsbG8=");
+    }
+
     #[test]
     fn normalize_stream_event_maps_deltas() {
         let text_event = StreamEvent::ContentBlockDelta {
@@ -480,8 +800,48 @@ mod tests {
         assert!(completion.text.is_none());
         assert_eq!(
             completion.thinking,
-            Some("I should call a tool".to_string())
+            vec!["I should call a tool".to_string()]
+        );
+    }
+
+    #[test]
+    fn normalize_response_records_thinking_offsets_between_text_blocks() {
+        let response = CreateMessageResponse {
+            content: vec![
+                ContentBlock::Thinking {
+                    thinking: "plan".to_string(),
+                    signature: "sig".to_string(),
+                },
+                ContentBlock::Text {
+                    text: "Checking".to_string(),
+                },
+                ContentBlock::Thinking {
+                    thinking: "verify".to_string(),
+                    signature: "sig".to_string(),
+                },
+                ContentBlock::Text {
+                    text: "Done".to_string(),
+                },
+            ],
+            id: "msg_3".to_string(),
+            model: "claude-test".to_string(),
+            role: Role::Assistant,
+            stop_reason: Some(StopReason::EndTurn),
+            stop_sequence: None,
+            type_: "message".to_string(),
+            usage: anthropic_ai_sdk::types::message::Usage {
+                input_tokens: 1,
+                output_tokens: 1,
+            },
+        };
+
+        let completion = normalize_response(&response);
+        assert_eq!(completion.text.as_deref(), Some("Checking\nDone"));
+        assert_eq!(
+            completion.thinking,
+            vec!["plan".to_string(), "verify".to_string()]
         );
+        assert_eq!(completion.thinking_offsets, vec![0, 8]);
     }
 
     #[test]
@@ -499,10 +859,188 @@ mod tests {
         assert_eq!(
             mapped,
             Some(AnthropicStreamChunk::ToolCallStart {
+                index: 0,
                 id: "tool_1".to_string(),
                 name: "lookup".to_string(),
                 input: json!({"x": 1}),
             })
         );
     }
+
+    #[tokio::test]
+    async fn invoke_stream_assembles_thinking_blocks_and_tool_use_from_deltas() {
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "content": [], "model": "claude-test", "stop_reason": null, "stop_sequence": null, "usage": {"input_tokens": 25, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": "", "signature": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "search "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "first"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig_a"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "thinking", "thinking": "", "signature": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "thinking_delta", "thinking": "then answer"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "content_block_start", "index": 2, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "ping"}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "text_delta", "text": "Looking"}}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "text_delta", "text": " up"}}),
+            json!({"type": "content_block_stop", "index": 2}),
+            json!({"type": "content_block_start", "index": 3, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "lookup", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": ""}}),
+            json!({"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": "{\"query\": "}}),
+            json!({"type": "content_block_delta", "index": 3, "delta": {"type": "input_json_delta", "partial_json": "\"rust\"}"}}),
+            json!({"type": "content_block_stop", "index": 3}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use", "stop_sequence": null}, "usage": {"output_tokens": 9}}),
+            json!({"type": "message_stop"}),
+        ];
+        let body = events
+            .iter()
+            .map(|event| {
+                format!(
+                    "event: {}\ndata: {event}\n\n",
+                    event["type"].as_str().unwrap()
+                )
+            })
+            .collect::<String>();
+        let (url, server) = crate::llm::test_server::serve_once(200, &body);
+        let mut config = AnthropicModelConfig::new("key", "claude-test");
+        config.api_base_url = Some(url);
+        let model = AnthropicModel::new(config).expect("model builds");
+        let messages = [ModelMessage::User("find rust".to_string())];
+
+        let chunks = model
+            .invoke_stream(&messages, &[], ModelToolChoice::Auto)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("stream succeeds");
+        let head = server.join().expect("server thread");
+        assert!(head.starts_with("POST /messages "));
+
+        let mut accumulator = crate::llm::ModelStreamAccumulator::default();
+        for chunk in chunks {
+            accumulator.push(chunk);
+        }
+        let (completion, malformed) = accumulator.finish();
+        assert!(malformed.is_empty());
+        assert_eq!(
+            completion.thinking,
+            vec!["search first".to_string(), "then answer".to_string()]
+        );
+        assert_eq!(completion.text.as_deref(), Some("Looking up"));
+        assert_eq!(
+            completion.tool_calls,
+            vec![ModelToolCall {
+                id: "toolu_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: json!({"query": "rust"}),
+            }]
+        );
+        assert_eq!(
+            completion.usage,
+            Some(ModelUsage {
+                input_tokens: 25,
+                output_tokens: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn stream_mapper_separates_text_blocks_and_reports_errors() {
+        let mut mapper = StreamMapper::default();
+        let first = mapper
+            .map(AnthropicStreamChunk::Text {
+                index: 0,
+                text: "one".to_string(),
+            })
+            .expect("maps");
+        let second = mapper
+            .map(AnthropicStreamChunk::Text {
+                index: 2,
+                text: "two".to_string(),
+            })
+            .expect("maps");
+        assert_eq!(first, vec![ModelStreamChunk::TextDelta("one".to_string())]);
+        assert_eq!(
+            second,
+            vec![ModelStreamChunk::TextDelta("\ntwo".to_string())]
+        );
+
+        let err = mapper
+            .map(AnthropicStreamChunk::Error {
+                message: "Overloaded".to_string(),
+            })
+            .expect_err("stream errors fail the call");
+        assert!(err.to_string().contains("Overloaded"));
+        assert_eq!(err.provider_message(), Some("Overloaded"));
+    }
+
+    #[test]
+    fn message_errors_recover_http_status_from_error_type() {
+        let rate_limited = map_message_error(MessageError::ApiError(
+            json!({"type": "error", "error": {"type": "rate_limit_error", "message": "slow down"}})
+                .to_string(),
+        ));
+        assert_eq!(rate_limited.status(), Some(429));
+        assert_eq!(rate_limited.provider_message(), Some("slow down"));
+
+        let unauthorized = map_message_error(MessageError::ApiError(
+            json!({"type": "error", "error": {"type": "authentication_error", "message": "bad key"}})
+                .to_string(),
+        ));
+        assert_eq!(unauthorized.status(), Some(401));
+
+        let transport = map_message_error(MessageError::ApiError("connection reset".to_string()));
+        assert_eq!(transport.status(), None);
+        assert_eq!(transport.provider_message(), None);
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_ANTHROPIC_KEY", "tenant-key") };
+
+        let model =
+            AnthropicModel::from_env_with_key("AGENT_SDK_TEST_ANTHROPIC_KEY", "claude-sonnet-4-5")
+                .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+        assert_eq!(model.config.model, "claude-sonnet-4-5");
+    }
+
+    #[test]
+    fn from_env_with_key_names_missing_variable() {
+        let err =
+            AnthropicModel::from_env_with_key("AGENT_SDK_TEST_UNSET_KEY", "claude-sonnet-4-5")
+                .expect_err("variable is unset");
+
+        assert!(
+            err.to_string()
+                .contains("AGENT_SDK_TEST_UNSET_KEY is not set")
+        );
+    }
+
+    #[test]
+    fn describe_request_joins_system_messages_and_counts_prefill() {
+        let model = AnthropicModel::new(AnthropicModelConfig::new("key", "claude-test"))
+            .expect("model builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+        let options = ModelCallOptions {
+            assistant_prefill: Some("Bonjour ".to_string()),
+            ..ModelCallOptions::default()
+        };
+
+        let description = model.describe_request(&messages, &options);
+
+        assert_eq!(
+            description.system_text().as_deref(),
+            Some("be brief\n\nanswer in French")
+        );
+        assert_eq!(description.system.len(), 1);
+        assert_eq!(description.message_count, 2);
+    }
 }
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::error::ProviderError;
+use crate::llm::openai::{
+    OpenAiChatCompletionResponse, OpenAiModelConfig, apply_call_options, build_request,
+    describe_chat_request, extract_api_error, normalize_response,
+};
+use crate::llm::{
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolChoice, ModelToolDefinition, RequestDescription, env_var, http_client, parse_response,
+    with_prefill_instruction,
+};
+
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+
+#[derive(Debug, Clone)]
+/// Runtime configuration for [`AzureOpenAiModel`].
+pub struct AzureOpenAiModelConfig {
+    /// Azure OpenAI API key, sent as the `api-key` header.
+    pub api_key: String,
+    /// Resource endpoint (for example `https://my-resource.openai.azure.com`).
+    pub endpoint: String,
+    /// Deployment name the model is served under.
+    pub deployment: String,
+    /// `api-version` query parameter.
+    pub api_version: String,
+    /// Optional sampling temperature.
+    pub temperature: Option<f32>,
+    /// Optional nucleus sampling parameter.
+    pub top_p: Option<f32>,
+    /// Optional max output tokens.
+    pub max_tokens: Option<u32>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
+}
+
+impl AzureOpenAiModelConfig {
+    /// Creates a config with sensible defaults.
+    pub fn new(
+        api_key: impl Into<String>,
+        endpoint: impl Into<String>,
+        deployment: impl Into<String>,
+    ) -> Self {
+        Self {
+            api_key: api_key.into(),
+            endpoint: endpoint.into(),
+            deployment: deployment.into(),
+            api_version: DEFAULT_API_VERSION.to_string(),
+            temperature: None,
+            top_p: None,
+            max_tokens: Some(4096),
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Azure OpenAI chat completions adapter implementing [`ChatModel`].
+///
+/// Shares request and response mapping with [`crate::OpenAiModel`]; only the URL layout and
+/// auth header differ.
+pub struct AzureOpenAiModel {
+    client: Client,
+    config: AzureOpenAiModelConfig,
+}
+
+impl AzureOpenAiModel {
+    /// Creates a model adapter from explicit config.
+    pub fn new(config: AzureOpenAiModelConfig) -> Result<Self, ProviderError> {
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Creates a model adapter using `AZURE_OPENAI_ENDPOINT`, `AZURE_OPENAI_API_KEY`, and
+    /// `AZURE_OPENAI_DEPLOYMENT`, plus optional `AZURE_OPENAI_API_VERSION`.
+    pub fn from_env() -> Result<Self, ProviderError> {
+        let deployment = env_var("AZURE_OPENAI_DEPLOYMENT")?;
+        Self::from_env_with_key("AZURE_OPENAI_API_KEY", deployment)
+    }
+
+    /// Like [`AzureOpenAiModel::from_env`], reading the API key from the `var_name`
+    /// environment variable and using `deployment` instead of `AZURE_OPENAI_DEPLOYMENT`.
+    pub fn from_env_with_key(
+        var_name: &str,
+        deployment: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        let mut config = AzureOpenAiModelConfig::new(
+            env_var(var_name)?,
+            env_var("AZURE_OPENAI_ENDPOINT")?,
+            deployment,
+        );
+        if let Ok(api_version) = env_var("AZURE_OPENAI_API_VERSION") {
+            config.api_version = api_version;
+        }
+        Self::new(config)
+    }
+
+    fn endpoint(&self) -> String {
+        endpoint_url(&self.config)
+    }
+
+    fn request_config(&self) -> OpenAiModelConfig {
+        let mut config = OpenAiModelConfig::new(&self.config.api_key, &self.config.deployment);
+        config.temperature = self.config.temperature;
+        config.top_p = self.config.top_p;
+        config.max_tokens = self.config.max_tokens;
+        config
+    }
+}
+
+#[async_trait]
+impl ChatModel for AzureOpenAiModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.deployment)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        describe_chat_request(&build_request(
+            &messages,
+            &[],
+            ModelToolChoice::Auto,
+            &self.request_config(),
+        ))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "azure_openai.invoke",
+            skip_all,
+            fields(model = %self.config.deployment),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.request_config());
+        apply_call_options(&mut request, options);
+
+        let response = self
+            .client
+            .post(self.endpoint())
+            .header("api-key", &self.config.api_key)
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| ProviderError::request(err.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
+        }
+
+        let (payload, raw) =
+            parse_response::<OpenAiChatCompletionResponse>(response, options.capture_raw).await?;
+
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
+    }
+}
+
+fn endpoint_url(config: &AzureOpenAiModelConfig) -> String {
+    format!(
+        "{}/openai/deployments/{}/chat/completions?api-version={}",
+        config.endpoint.trim_end_matches('/'),
+        config.deployment,
+        config.api_version
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn endpoint_url_uses_deployment_path_and_api_version() {
+        let mut config = AzureOpenAiModelConfig::new(
+            "key",
+            "https://my-resource.openai.azure.com/",
+            "gpt-4o-prod",
+        );
+        config.api_version = "2024-06-01".to_string();
+
+        assert_eq!(
+            endpoint_url(&config),
+            "https://my-resource.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions?api-version=2024-06-01"
+        );
+    }
+
+    #[test]
+    fn normalize_response_handles_azure_payload() {
+        let payload = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "model": "gpt-4o-2024-08-06",
+            "prompt_filter_results": [
+                {"prompt_index": 0, "content_filter_results": {"hate": {"filtered": false, "severity": "safe"}}}
+            ],
+            "choices": [{
+                "index": 0,
+                "finish_reason": "tool_calls",
+                "content_filter_results": {},
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "lookup", "arguments": "{\"query\":\"rust\"}"}
+                    }]
+                }
+            }],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17}
+        });
+        let response = serde_json::from_value::<OpenAiChatCompletionResponse>(payload)
+            .expect("azure payload deserializes");
+
+        let completion = normalize_response(response).expect("normalizes");
+
+        assert_eq!(completion.text, None);
+        assert_eq!(completion.tool_calls[0].name, "lookup");
+        assert_eq!(completion.tool_calls[0].arguments, json!({"query": "rust"}));
+        let usage = completion.usage.expect("usage");
+        assert_eq!((usage.input_tokens, usage.output_tokens), (12, 5));
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches these variables.
+        unsafe {
+            std::env::set_var("AGENT_SDK_TEST_AZURE_KEY", "tenant-key");
+            std::env::set_var("AZURE_OPENAI_ENDPOINT", "https://tenant.openai.azure.com");
+        }
+
+        let model = AzureOpenAiModel::from_env_with_key("AGENT_SDK_TEST_AZURE_KEY", "gpt-4o-prod")
+            .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+        assert_eq!(
+            model.endpoint(),
+            "https://tenant.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions?api-version=2024-10-21"
+        );
+    }
+
+    #[test]
+    fn describe_request_keeps_each_system_message() {
+        let model = AzureOpenAiModel::new(AzureOpenAiModelConfig::new(
+            "key",
+            "https://my-resource.openai.azure.com",
+            "gpt-4o",
+        ))
+        .expect("model builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief".to_string(), "answer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
+}
@@ -0,0 +1,860 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use aws_credential_types::Credentials;
+use aws_sigv4::http_request::{SignableBody, SignableRequest, SigningSettings, sign};
+use aws_sigv4::sign::v4;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::error::ProviderError;
+use crate::llm::{
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage, RequestDescription,
+    http_client, joined_len, parse_response, with_prefill_instruction,
+};
+
+const SIGNING_SERVICE: &str = "bedrock";
+
+#[derive(Debug, Clone)]
+/// Runtime configuration for [`BedrockModel`].
+pub struct BedrockModelConfig {
+    /// AWS region hosting the model (for example `us-east-1`).
+    pub region: String,
+    /// Bedrock model id or inference profile (for example `anthropic.claude-sonnet-4-5-20250929-v1:0`).
+    pub model: String,
+    /// AWS access key id.
+    pub access_key_id: String,
+    /// AWS secret access key.
+    pub secret_access_key: String,
+    /// Optional session token for temporary credentials.
+    pub session_token: Option<String>,
+    /// Optional endpoint override (defaults to `https://bedrock-runtime.{region}.amazonaws.com`).
+    pub endpoint_url: Option<String>,
+    /// Optional sampling temperature.
+    pub temperature: Option<f32>,
+    /// Optional nucleus sampling parameter.
+    pub top_p: Option<f32>,
+    /// Optional max output tokens.
+    pub max_tokens: Option<u32>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
+}
+
+impl BedrockModelConfig {
+    /// Creates a config with sensible defaults.
+    pub fn new(
+        region: impl Into<String>,
+        model: impl Into<String>,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+    ) -> Self {
+        Self {
+            region: region.into(),
+            model: model.into(),
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+            endpoint_url: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: Some(4096),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// AWS Bedrock provider adapter (Converse API) implementing [`ChatModel`].
+pub struct BedrockModel {
+    client: Client,
+    config: BedrockModelConfig,
+}
+
+impl BedrockModel {
+    /// Creates a model adapter from explicit config.
+    pub fn new(config: BedrockModelConfig) -> Result<Self, ProviderError> {
+        let client = http_client(
+            None,
+            &HashMap::new(),
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Creates a model adapter using the standard AWS environment variables.
+    ///
+    /// Reads `AWS_REGION` (or `AWS_DEFAULT_REGION`), `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY`, and optionally `AWS_SESSION_TOKEN`.
+    pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .map_err(|_| ProviderError::request("AWS_REGION (or AWS_DEFAULT_REGION) is not set"))?;
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| ProviderError::request("AWS_ACCESS_KEY_ID is not set"))?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+            .map_err(|_| ProviderError::request("AWS_SECRET_ACCESS_KEY is not set"))?;
+
+        let mut config = BedrockModelConfig::new(region, model, access_key_id, secret_access_key);
+        config.session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+        Self::new(config)
+    }
+
+    fn endpoint(&self) -> String {
+        let base = match self.config.endpoint_url.as_deref() {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!(
+                "https://bedrock-runtime.{}.amazonaws.com",
+                self.config.region
+            ),
+        };
+        format!(
+            "{base}/model/{}/converse",
+            encode_path_segment(&self.config.model)
+        )
+    }
+
+    /// Returns the SigV4 headers (`authorization`, `x-amz-date`, ...) for one request.
+    fn signing_headers(
+        &self,
+        url: &str,
+        body: &[u8],
+    ) -> Result<Vec<(String, String)>, ProviderError> {
+        let identity = Credentials::new(
+            self.config.access_key_id.clone(),
+            self.config.secret_access_key.clone(),
+            self.config.session_token.clone(),
+            None,
+            "agent-sdk-rs",
+        )
+        .into();
+        let params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&self.config.region)
+            .name(SIGNING_SERVICE)
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()
+            .map_err(|err| ProviderError::request(format!("bedrock signing failed: {err}")))?
+            .into();
+
+        let signable = SignableRequest::new(
+            "POST",
+            url,
+            [("content-type", "application/json")].into_iter(),
+            SignableBody::Bytes(body),
+        )
+        .map_err(|err| ProviderError::request(format!("bedrock signing failed: {err}")))?;
+        let (instructions, _signature) = sign(signable, &params)
+            .map_err(|err| ProviderError::request(format!("bedrock signing failed: {err}")))?
+            .into_parts();
+
+        Ok(instructions
+            .headers()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl ChatModel for BedrockModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &self.config);
+        RequestDescription {
+            system: request.system.into_iter().map(|block| block.text).collect(),
+            message_count: request.messages.len(),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "bedrock.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.config);
+        apply_call_options(&mut request, options);
+        let body = serde_json::to_vec(&request)
+            .map_err(|err| ProviderError::request(format!("bedrock request encoding: {err}")))?;
+
+        let url = self.endpoint();
+        let mut builder = self
+            .client
+            .post(&url)
+            .header("content-type", "application/json");
+        for (name, value) in self.signing_headers(&url, &body)? {
+            builder = builder.header(name, value);
+        }
+
+        let response = builder
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| ProviderError::request(err.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
+        }
+
+        let (payload, raw) =
+            parse_response::<BedrockConverseResponse>(response, options.capture_raw).await?;
+
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockConverseRequest {
+    messages: Vec<BedrockMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<BedrockSystemBlock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inference_config: Option<BedrockInferenceConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<BedrockToolConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BedrockMessage {
+    role: String,
+    content: Vec<BedrockContentBlock>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockContentBlock {
+    Text(String),
+    ToolUse(BedrockToolUse),
+    ToolResult(BedrockToolResult),
+    ReasoningContent(BedrockReasoningContent),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockToolUse {
+    tool_use_id: String,
+    name: String,
+    input: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockToolResult {
+    tool_use_id: String,
+    content: Vec<BedrockToolResultContent>,
+    status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockToolResultContent {
+    Text(String),
+    Image(Value),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockReasoningContent {
+    #[serde(default)]
+    reasoning_text: Option<BedrockReasoningText>,
+    #[serde(default)]
+    redacted_content: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BedrockReasoningText {
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BedrockSystemBlock {
+    text: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockInferenceConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockToolConfig {
+    tools: Vec<BedrockTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockTool {
+    tool_spec: BedrockToolSpec,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockToolSpec {
+    name: String,
+    description: String,
+    input_schema: BedrockInputSchema,
+}
+
+#[derive(Debug, Serialize)]
+struct BedrockInputSchema {
+    json: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockConverseResponse {
+    output: Option<BedrockOutput>,
+    usage: Option<BedrockUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BedrockOutput {
+    message: Option<BedrockMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BedrockErrorBody {
+    message: Option<String>,
+}
+
+fn build_request(
+    messages: &[ModelMessage],
+    tools: &[ModelToolDefinition],
+    tool_choice: ModelToolChoice,
+    config: &BedrockModelConfig,
+) -> BedrockConverseRequest {
+    let (messages, system) = to_bedrock_messages(messages);
+
+    let tool_config = if tools.is_empty() {
+        None
+    } else {
+        Some(BedrockToolConfig {
+            tools: tools
+                .iter()
+                .map(|tool| BedrockTool {
+                    tool_spec: BedrockToolSpec {
+                        name: tool.name.clone(),
+                        description: tool.description.clone(),
+                        input_schema: BedrockInputSchema {
+                            json: tool.parameters.clone(),
+                        },
+                    },
+                })
+                .collect(),
+            // Converse has no "none" choice; omitting it leaves the model on auto.
+            tool_choice: match tool_choice {
+                ModelToolChoice::Auto => Some(json!({"auto": {}})),
+                ModelToolChoice::Required => Some(json!({"any": {}})),
+                ModelToolChoice::None => None,
+                ModelToolChoice::Tool(name) => Some(json!({"tool": {"name": name}})),
+            },
+        })
+    };
+
+    let inference_config =
+        if config.max_tokens.is_none() && config.temperature.is_none() && config.top_p.is_none() {
+            None
+        } else {
+            Some(BedrockInferenceConfig {
+                max_tokens: config.max_tokens,
+                temperature: config.temperature,
+                top_p: config.top_p,
+                stop_sequences: Vec::new(),
+            })
+        };
+
+    BedrockConverseRequest {
+        messages,
+        system,
+        inference_config,
+        tool_config,
+    }
+}
+
+/// Maps history to Converse messages, merging adjacent same-role turns as the API requires.
+fn to_bedrock_messages(
+    messages: &[ModelMessage],
+) -> (Vec<BedrockMessage>, Vec<BedrockSystemBlock>) {
+    let mut system = Vec::new();
+    let mut bedrock_messages: Vec<BedrockMessage> = Vec::new();
+
+    for message in messages {
+        let (role, blocks) = match message {
+            ModelMessage::System(content) => {
+                if !content.is_empty() {
+                    system.push(BedrockSystemBlock {
+                        text: content.clone(),
+                    });
+                }
+                continue;
+            }
+            ModelMessage::User(content) => {
+                if content.is_empty() {
+                    continue;
+                }
+                ("user", vec![BedrockContentBlock::Text(content.clone())])
+            }
+            ModelMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let mut blocks = Vec::new();
+                if let Some(content) = content
+                    && !content.is_empty()
+                {
+                    blocks.push(BedrockContentBlock::Text(content.clone()));
+                }
+                for call in tool_calls {
+                    blocks.push(BedrockContentBlock::ToolUse(BedrockToolUse {
+                        tool_use_id: call.id.clone(),
+                        name: call.name.clone(),
+                        input: call.arguments.clone(),
+                    }));
+                }
+                if blocks.is_empty() {
+                    continue;
+                }
+                ("assistant", blocks)
+            }
+            ModelMessage::ToolResult {
+                tool_call_id,
+                tool_name: _,
+                content,
+                is_error,
+                images,
+            } => {
+                let mut result_content = vec![BedrockToolResultContent::Text(content.clone())];
+                result_content.extend(images.iter().map(|image| {
+                    BedrockToolResultContent::Image(json!({
+                        "format": image
+                            .media_type
+                            .strip_prefix("image/")
+                            .unwrap_or(&image.media_type),
+                        "source": {"bytes": image.data},
+                    }))
+                }));
+                (
+                    "user",
+                    vec![BedrockContentBlock::ToolResult(BedrockToolResult {
+                        tool_use_id: tool_call_id.clone(),
+                        content: result_content,
+                        status: if *is_error { "error" } else { "success" }.to_string(),
+                    })],
+                )
+            }
+        };
+
+        match bedrock_messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => bedrock_messages.push(BedrockMessage {
+                role: role.to_string(),
+                content: blocks,
+            }),
+        }
+    }
+
+    (bedrock_messages, system)
+}
+
+fn apply_call_options(request: &mut BedrockConverseRequest, options: &ModelCallOptions) {
+    if options.is_empty() {
+        return;
+    }
+    let inference_config = request
+        .inference_config
+        .get_or_insert_with(Default::default);
+    if let Some(max_tokens) = options.max_tokens {
+        inference_config.max_tokens = Some(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        inference_config.temperature = Some(temperature);
+    }
+    if !options.stop_sequences.is_empty() {
+        inference_config.stop_sequences = options.stop_sequences.clone();
+    }
+}
+
+fn normalize_response(response: BedrockConverseResponse) -> Result<ModelCompletion, ProviderError> {
+    let message = response
+        .output
+        .and_then(|output| output.message)
+        .ok_or_else(|| {
+            ProviderError::response("bedrock response missing output message".to_string())
+        })?;
+
+    let mut text_parts = Vec::new();
+    let mut thinking = Vec::new();
+    let mut thinking_offsets = Vec::new();
+    let mut tool_calls = Vec::new();
+
+    for block in message.content {
+        match block {
+            BedrockContentBlock::Text(text) => text_parts.push(text),
+            BedrockContentBlock::ToolUse(tool_use) => tool_calls.push(ModelToolCall {
+                id: tool_use.tool_use_id,
+                name: tool_use.name,
+                arguments: tool_use.input,
+            }),
+            BedrockContentBlock::ReasoningContent(reasoning) => {
+                if let Some(reasoning_text) = reasoning.reasoning_text {
+                    thinking.push(reasoning_text.text);
+                } else if let Some(redacted) = reasoning.redacted_content {
+                    thinking.push(format!("[redacted:{} bytes]", redacted.len()));
+                } else {
+                    continue;
+                }
+                thinking_offsets.push(joined_len(&text_parts, "\n"));
+            }
+            BedrockContentBlock::ToolResult(_) => {}
+        }
+    }
+
+    let text = if text_parts.is_empty() {
+        None
+    } else {
+        Some(text_parts.join("\n"))
+    };
+
+    Ok(ModelCompletion {
+        text,
+        thinking,
+        thinking_offsets,
+        tool_calls,
+        usage: response.usage.map(|usage| ModelUsage {
+            input_tokens: usage.input_tokens.unwrap_or(0),
+            output_tokens: usage.output_tokens.unwrap_or(0),
+        }),
+        raw: None,
+    })
+}
+
+/// Percent-encodes a model id for use as one URL path segment (ids contain `:`).
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+async fn extract_api_error(response: reqwest::Response) -> ProviderError {
+    let status = response.status();
+    let error_type = response
+        .headers()
+        .get("x-amzn-errortype")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(':').next().unwrap_or(value).to_string());
+    let body = response.text().await.unwrap_or_default();
+
+    if let Ok(parsed) = serde_json::from_str::<BedrockErrorBody>(&body)
+        && let Some(message) = parsed.message
+    {
+        let error_type = error_type.unwrap_or_else(|| status.to_string().to_uppercase());
+        return ProviderError::api(
+            status.as_u16(),
+            format!(
+                "bedrock api error {} {error_type}: {message}",
+                status.as_u16()
+            ),
+            Some(message),
+        );
+    }
+
+    ProviderError::http(
+        status.as_u16(),
+        if body.is_empty() {
+            format!("bedrock api request failed ({status})")
+        } else {
+            format!("bedrock api request failed ({status}): {body}")
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tool_definition() -> ModelToolDefinition {
+        ModelToolDefinition {
+            name: "lookup".to_string(),
+            description: "Look up something".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string"}
+                },
+                "required": ["query"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn config() -> BedrockModelConfig {
+        BedrockModelConfig::new(
+            "us-east-1",
+            "anthropic.claude-sonnet-4-5-20250929-v1:0",
+            "AKIDEXAMPLE",
+            "secret",
+        )
+    }
+
+    #[test]
+    fn build_request_maps_system_tools_and_tool_results() {
+        let messages = vec![
+            ModelMessage::System("You are helpful".to_string()),
+            ModelMessage::User("Find docs".to_string()),
+            ModelMessage::Assistant {
+                content: Some("Calling tools".to_string()),
+                tool_calls: vec![
+                    ModelToolCall {
+                        id: "call_1".to_string(),
+                        name: "lookup".to_string(),
+                        arguments: json!({"query": "rust"}),
+                    },
+                    ModelToolCall {
+                        id: "call_2".to_string(),
+                        name: "lookup".to_string(),
+                        arguments: json!({"query": "tokio"}),
+                    },
+                ],
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_1".to_string(),
+                tool_name: "lookup".to_string(),
+                content: "found".to_string(),
+                is_error: false,
+                images: Vec::new(),
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_2".to_string(),
+                tool_name: "lookup".to_string(),
+                content: "missing".to_string(),
+                is_error: true,
+                images: Vec::new(),
+            },
+        ];
+
+        let request = build_request(
+            &messages,
+            &[tool_definition()],
+            ModelToolChoice::Tool("lookup".to_string()),
+            &config(),
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["system"], json!([{"text": "You are helpful"}]));
+        assert_eq!(value["messages"].as_array().map(Vec::len), Some(3));
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["messages"][0]["content"][0]["text"], "Find docs");
+        assert_eq!(value["messages"][1]["role"], "assistant");
+        assert_eq!(
+            value["messages"][1]["content"][1]["toolUse"],
+            json!({"toolUseId": "call_1", "name": "lookup", "input": {"query": "rust"}})
+        );
+        assert_eq!(value["messages"][2]["role"], "user");
+        assert_eq!(
+            value["messages"][2]["content"],
+            json!([
+                {"toolResult": {"toolUseId": "call_1", "content": [{"text": "found"}], "status": "success"}},
+                {"toolResult": {"toolUseId": "call_2", "content": [{"text": "missing"}], "status": "error"}}
+            ])
+        );
+        assert_eq!(
+            value["toolConfig"]["tools"][0]["toolSpec"]["inputSchema"]["json"]["required"],
+            json!(["query"])
+        );
+        assert_eq!(
+            value["toolConfig"]["toolChoice"],
+            json!({"tool": {"name": "lookup"}})
+        );
+        assert_eq!(value["inferenceConfig"]["maxTokens"], 4096);
+    }
+
+    #[test]
+    fn build_request_translates_tool_choice_modes() {
+        let messages = vec![ModelMessage::User("hi".to_string())];
+        let choice = |tool_choice| {
+            let request = build_request(&messages, &[tool_definition()], tool_choice, &config());
+            serde_json::to_value(request).expect("serializes")["toolConfig"]["toolChoice"].clone()
+        };
+
+        assert_eq!(choice(ModelToolChoice::Auto), json!({"auto": {}}));
+        assert_eq!(choice(ModelToolChoice::Required), json!({"any": {}}));
+        assert_eq!(choice(ModelToolChoice::None), Value::Null);
+
+        let request = build_request(&messages, &[], ModelToolChoice::Required, &config());
+        let value = serde_json::to_value(request).expect("serializes");
+        assert!(value.get("toolConfig").is_none());
+        assert!(value.get("system").is_none());
+    }
+
+    #[test]
+    fn normalize_response_extracts_text_reasoning_tool_calls_and_usage() {
+        let response: BedrockConverseResponse = serde_json::from_value(json!({
+            "output": {
+                "message": {
+                    "role": "assistant",
+                    "content": [
+                        {"reasoningContent": {"reasoningText": {"text": "plan", "signature": "sig"}}},
+                        {"text": "Looking it up"},
+                        {"toolUse": {"toolUseId": "tooluse_1", "name": "lookup", "input": {"query": "rust"}}}
+                    ]
+                }
+            },
+            "stopReason": "tool_use",
+            "usage": {"inputTokens": 12, "outputTokens": 5, "totalTokens": 17}
+        }))
+        .expect("response parses");
+
+        let completion = normalize_response(response).expect("response normalizes");
+
+        assert_eq!(completion.text.as_deref(), Some("Looking it up"));
+        assert_eq!(completion.thinking, vec!["plan".to_string()]);
+        assert_eq!(
+            completion.tool_calls,
+            vec![ModelToolCall {
+                id: "tooluse_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: json!({"query": "rust"}),
+            }]
+        );
+        assert_eq!(
+            completion.usage,
+            Some(ModelUsage {
+                input_tokens: 12,
+                output_tokens: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn normalize_response_requires_output_message() {
+        let response: BedrockConverseResponse =
+            serde_json::from_value(json!({"usage": {"inputTokens": 1, "outputTokens": 0}}))
+                .expect("response parses");
+
+        let err = normalize_response(response).expect_err("should fail");
+        assert!(
+            matches!(err, ProviderError::Response { message, .. } if message.contains("missing output"))
+        );
+    }
+
+    #[test]
+    fn endpoint_encodes_model_id_and_signing_adds_auth_headers() {
+        let mut config = config();
+        config.session_token = Some("token".to_string());
+        let model = BedrockModel::new(config).expect("model builds");
+
+        let url = model.endpoint();
+        assert_eq!(
+            url,
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/anthropic.claude-sonnet-4-5-20250929-v1%3A0/converse"
+        );
+
+        let headers = model.signing_headers(&url, b"{}").expect("signs");
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let authorization = header("authorization").expect("authorization header");
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(authorization.contains("/us-east-1/bedrock/aws4_request"));
+        assert!(header("x-amz-date").is_some());
+        assert_eq!(header("x-amz-security-token"), Some("token"));
+    }
+
+    #[test]
+    fn describe_request_lists_one_system_block_per_message() {
+        let model = BedrockModel::new(BedrockModelConfig::new(
+            "us-east-1",
+            "anthropic.claude-3-haiku",
+            "AKID",
+            "SECRET",
+        ))
+        .expect("model builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief".to_string(), "answer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
+}
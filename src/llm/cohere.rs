@@ -0,0 +1,925 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::error::ProviderError;
+use crate::llm::{
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage, RequestDescription, env_var,
+    http_client, omitted_images_note, parse_response, with_prefill_instruction,
+};
+
+const DEFAULT_API_BASE_URL: &str = "https://api.cohere.com/v2";
+const EMPTY_USER_CONTENT_FALLBACK: &str = " ";
+
+#[derive(Debug, Clone)]
+/// Runtime configuration for [`CohereModel`].
+pub struct CohereModelConfig {
+    /// Cohere API key.
+    pub api_key: String,
+    /// Model id (for example `command-a-03-2025`).
+    pub model: String,
+    /// Optional base URL override.
+    pub api_base_url: Option<String>,
+    /// Optional sampling temperature.
+    pub temperature: Option<f32>,
+    /// Optional nucleus sampling parameter (sent as `p`).
+    pub top_p: Option<f32>,
+    /// Optional max output tokens.
+    pub max_tokens: Option<u32>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
+}
+
+impl CohereModelConfig {
+    /// Creates a config with sensible defaults.
+    pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: model.into(),
+            api_base_url: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: Some(4096),
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Cohere Command provider adapter implementing [`ChatModel`] over the v2 Chat API.
+pub struct CohereModel {
+    client: Client,
+    config: CohereModelConfig,
+}
+
+impl CohereModel {
+    /// Creates a model adapter from explicit config.
+    pub fn new(config: CohereModelConfig) -> Result<Self, ProviderError> {
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Creates a model adapter using `COHERE_API_KEY`.
+    pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
+        Self::from_env_with_key("COHERE_API_KEY", model)
+    }
+
+    /// Creates a model adapter reading the API key from the `var_name` environment variable.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        Self::new(CohereModelConfig::new(env_var(var_name)?, model))
+    }
+
+    fn endpoint(&self) -> String {
+        let base = self
+            .config
+            .api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_BASE_URL)
+            .trim_end_matches('/');
+        format!("{base}/chat")
+    }
+}
+
+#[async_trait]
+impl ChatModel for CohereModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &self.config);
+        let mut description = RequestDescription::default();
+        for message in request.messages {
+            match message {
+                CohereRequestMessage::System { content } => description.system.push(content),
+                _ => description.message_count += 1,
+            }
+        }
+        description
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "cohere.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.config);
+        apply_call_options(&mut request, options);
+
+        let response = self
+            .client
+            .post(self.endpoint())
+            .header("authorization", format!("Bearer {}", self.config.api_key))
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| ProviderError::request(err.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
+        }
+
+        let (payload, raw) =
+            parse_response::<CohereChatResponse>(response, options.capture_raw).await?;
+
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CohereChatRequest {
+    model: String,
+    messages: Vec<CohereRequestMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<CohereToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+enum CohereRequestMessage {
+    System {
+        content: String,
+    },
+    User {
+        content: String,
+    },
+    Assistant {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        /// Cohere expects the reasoning that precedes tool calls here rather than in `content`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_plan: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_calls: Option<Vec<CohereToolCall>>,
+    },
+    Tool {
+        tool_call_id: String,
+        content: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct CohereToolDefinition {
+    #[serde(rename = "type")]
+    type_: String,
+    function: CohereToolFunctionDefinition,
+}
+
+#[derive(Debug, Serialize)]
+struct CohereToolFunctionDefinition {
+    name: String,
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CohereToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    type_: String,
+    function: CohereToolCallFunction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CohereToolCallFunction {
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereChatResponse {
+    message: Option<CohereAssistantMessage>,
+    usage: Option<CohereUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereAssistantMessage {
+    #[serde(default)]
+    content: Vec<CohereContentBlock>,
+    #[serde(default)]
+    tool_plan: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<CohereToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum CohereContentBlock {
+    Text {
+        text: String,
+    },
+    Thinking {
+        thinking: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereUsage {
+    billed_units: Option<CohereBilledUnits>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereBilledUnits {
+    input_tokens: Option<f64>,
+    output_tokens: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CohereApiError {
+    message: Option<String>,
+}
+
+fn build_request(
+    messages: &[ModelMessage],
+    tools: &[ModelToolDefinition],
+    tool_choice: ModelToolChoice,
+    config: &CohereModelConfig,
+) -> CohereChatRequest {
+    let request_messages = ensure_valid_messages(to_cohere_messages(messages));
+
+    // Cohere cannot force one named tool, so offer only that tool and require a call.
+    let (offered_tools, tool_choice_payload) = match tool_choice {
+        ModelToolChoice::Auto => (tools.iter().collect::<Vec<_>>(), None),
+        ModelToolChoice::Required => (tools.iter().collect(), Some("REQUIRED")),
+        ModelToolChoice::None => (tools.iter().collect(), Some("NONE")),
+        ModelToolChoice::Tool(name) => (
+            tools.iter().filter(|tool| tool.name == name).collect(),
+            Some("REQUIRED"),
+        ),
+    };
+
+    let tools_payload = if offered_tools.is_empty() {
+        None
+    } else {
+        Some(
+            offered_tools
+                .into_iter()
+                .map(|tool| CohereToolDefinition {
+                    type_: "function".to_string(),
+                    function: CohereToolFunctionDefinition {
+                        name: tool.name.clone(),
+                        description: tool.description.clone(),
+                        parameters: tool.parameters.clone(),
+                    },
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    CohereChatRequest {
+        model: config.model.clone(),
+        messages: request_messages,
+        tool_choice: tools_payload
+            .as_ref()
+            .and(tool_choice_payload)
+            .map(str::to_string),
+        tools: tools_payload,
+        temperature: config.temperature,
+        p: config.top_p,
+        max_tokens: config.max_tokens,
+        stop_sequences: Vec::new(),
+    }
+}
+
+fn to_cohere_messages(messages: &[ModelMessage]) -> Vec<CohereRequestMessage> {
+    let mut request_messages = Vec::new();
+
+    for message in messages {
+        match message {
+            ModelMessage::System(content) => {
+                if content.is_empty() {
+                    continue;
+                }
+                request_messages.push(CohereRequestMessage::System {
+                    content: content.clone(),
+                });
+            }
+            ModelMessage::User(content) => {
+                if content.is_empty() {
+                    continue;
+                }
+                request_messages.push(CohereRequestMessage::User {
+                    content: content.clone(),
+                });
+            }
+            ModelMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let assistant_content = content.as_ref().filter(|text| !text.is_empty()).cloned();
+                if tool_calls.is_empty() {
+                    if let Some(content) = assistant_content {
+                        request_messages.push(CohereRequestMessage::Assistant {
+                            content: Some(content),
+                            tool_plan: None,
+                            tool_calls: None,
+                        });
+                    }
+                    continue;
+                }
+
+                request_messages.push(CohereRequestMessage::Assistant {
+                    content: None,
+                    tool_plan: assistant_content,
+                    tool_calls: Some(
+                        tool_calls
+                            .iter()
+                            .map(|tool_call| CohereToolCall {
+                                id: tool_call.id.clone(),
+                                type_: "function".to_string(),
+                                function: CohereToolCallFunction {
+                                    name: tool_call.name.clone(),
+                                    arguments: tool_call.arguments.to_string(),
+                                },
+                            })
+                            .collect(),
+                    ),
+                });
+            }
+            ModelMessage::ToolResult {
+                tool_call_id,
+                tool_name: _,
+                content,
+                is_error,
+                images,
+            } => {
+                let mut rendered = if *is_error {
+                    format!("Error: {content}")
+                } else {
+                    content.clone()
+                };
+                if let Some(note) = omitted_images_note(images) {
+                    rendered.push('\n');
+                    rendered.push_str(&note);
+                }
+
+                request_messages.push(CohereRequestMessage::Tool {
+                    tool_call_id: tool_call_id.clone(),
+                    content: rendered,
+                });
+            }
+        }
+    }
+
+    request_messages
+}
+
+/// Drops tool results without a preceding call and makes sure the chat opens with a user turn.
+fn ensure_valid_messages(messages: Vec<CohereRequestMessage>) -> Vec<CohereRequestMessage> {
+    let mut normalized = Vec::with_capacity(messages.len().saturating_add(1));
+    let mut pending_tool_call_ids = Vec::<String>::new();
+
+    for message in messages {
+        match &message {
+            CohereRequestMessage::System { .. } | CohereRequestMessage::User { .. } => {
+                pending_tool_call_ids.clear();
+            }
+            CohereRequestMessage::Assistant { tool_calls, .. } => {
+                pending_tool_call_ids.clear();
+                pending_tool_call_ids.extend(
+                    tool_calls
+                        .iter()
+                        .flatten()
+                        .map(|tool_call| tool_call.id.clone()),
+                );
+            }
+            CohereRequestMessage::Tool { tool_call_id, .. } => {
+                let Some(position) = pending_tool_call_ids
+                    .iter()
+                    .position(|id| id == tool_call_id)
+                else {
+                    continue;
+                };
+                pending_tool_call_ids.remove(position);
+            }
+        }
+        normalized.push(message);
+    }
+
+    let first_non_system = normalized
+        .iter()
+        .position(|message| !matches!(message, CohereRequestMessage::System { .. }));
+    let starts_with_user = first_non_system
+        .is_some_and(|index| matches!(normalized[index], CohereRequestMessage::User { .. }));
+    if !starts_with_user {
+        normalized.insert(
+            first_non_system.unwrap_or(normalized.len()),
+            CohereRequestMessage::User {
+                content: EMPTY_USER_CONTENT_FALLBACK.to_string(),
+            },
+        );
+    }
+
+    normalized
+}
+
+fn apply_call_options(request: &mut CohereChatRequest, options: &ModelCallOptions) {
+    if let Some(max_tokens) = options.max_tokens {
+        request.max_tokens = Some(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        request.temperature = Some(temperature);
+    }
+    if !options.stop_sequences.is_empty() {
+        request.stop_sequences = options.stop_sequences.clone();
+    }
+}
+
+fn normalize_response(response: CohereChatResponse) -> Result<ModelCompletion, ProviderError> {
+    let message = response
+        .message
+        .ok_or_else(|| ProviderError::response("cohere response missing message".to_string()))?;
+
+    let mut text = String::new();
+    let mut thinking = Vec::new();
+    let mut thinking_offsets = Vec::new();
+    for block in message.content {
+        match block {
+            CohereContentBlock::Text { text: block_text } => text.push_str(&block_text),
+            CohereContentBlock::Thinking { thinking: block } if !block.is_empty() => {
+                thinking.push(block);
+                thinking_offsets.push(text.len());
+            }
+            CohereContentBlock::Thinking { .. } | CohereContentBlock::Other => {}
+        }
+    }
+    if let Some(plan) = message.tool_plan.filter(|plan| !plan.is_empty()) {
+        thinking.push(plan);
+        thinking_offsets.push(text.len());
+    }
+
+    let mut tool_calls = Vec::new();
+    for tool_call in message.tool_calls {
+        let arguments = if tool_call.function.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str::<Value>(&tool_call.function.arguments).map_err(|err| {
+                ProviderError::response(format!(
+                    "cohere tool call arguments for '{}' are not valid JSON: {err}",
+                    tool_call.function.name
+                ))
+            })?
+        };
+
+        tool_calls.push(ModelToolCall {
+            id: tool_call.id,
+            name: tool_call.function.name,
+            arguments,
+        });
+    }
+
+    let usage = response
+        .usage
+        .and_then(|usage| usage.billed_units)
+        .map(|units| ModelUsage {
+            input_tokens: units.input_tokens.unwrap_or(0.0) as u32,
+            output_tokens: units.output_tokens.unwrap_or(0.0) as u32,
+        });
+
+    Ok(ModelCompletion {
+        text: (!text.is_empty()).then_some(text),
+        thinking,
+        thinking_offsets,
+        tool_calls,
+        usage,
+        raw: None,
+    })
+}
+
+async fn extract_api_error(response: reqwest::Response) -> ProviderError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+
+    if let Ok(CohereApiError {
+        message: Some(message),
+    }) = serde_json::from_str::<CohereApiError>(&body)
+    {
+        return ProviderError::api(
+            status.as_u16(),
+            format!("cohere api error ({status}): {message}"),
+            Some(message),
+        );
+    }
+
+    ProviderError::http(
+        status.as_u16(),
+        if body.is_empty() {
+            format!("cohere api request failed ({status})")
+        } else {
+            format!("cohere api request failed ({status}): {body}")
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tool_definition(name: &str) -> ModelToolDefinition {
+        ModelToolDefinition {
+            name: name.to_string(),
+            description: "Look up something".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string"}
+                },
+                "required": ["query"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    fn tool_exchange() -> Vec<ModelMessage> {
+        vec![
+            ModelMessage::System("You are helpful".to_string()),
+            ModelMessage::User("Find docs".to_string()),
+            ModelMessage::Assistant {
+                content: Some("I will search the docs".to_string()),
+                tool_calls: vec![ModelToolCall {
+                    id: "call_1".to_string(),
+                    name: "lookup".to_string(),
+                    arguments: json!({"query": "rust"}),
+                }],
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_1".to_string(),
+                tool_name: "lookup".to_string(),
+                content: "not found".to_string(),
+                is_error: true,
+                images: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn build_request_serializes_messages_tools_and_sampling() {
+        let mut config = CohereModelConfig::new("key", "command-a-03-2025");
+        config.temperature = Some(0.2);
+        config.top_p = Some(0.9);
+        config.max_tokens = Some(512);
+
+        let request = build_request(
+            &tool_exchange(),
+            &[tool_definition("lookup")],
+            ModelToolChoice::Auto,
+            &config,
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["model"], "command-a-03-2025");
+        assert_eq!(value["messages"][0]["role"], "system");
+        assert_eq!(value["messages"][1]["role"], "user");
+        assert_eq!(value["messages"][2]["role"], "assistant");
+        assert_eq!(value["messages"][2]["tool_plan"], "I will search the docs");
+        assert!(value["messages"][2].get("content").is_none());
+        assert_eq!(value["messages"][2]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            value["messages"][2]["tool_calls"][0]["function"]["arguments"],
+            "{\"query\":\"rust\"}"
+        );
+        assert_eq!(value["messages"][3]["role"], "tool");
+        assert_eq!(value["messages"][3]["tool_call_id"], "call_1");
+        assert_eq!(value["messages"][3]["content"], "Error: not found");
+        assert_eq!(value["tools"][0]["type"], "function");
+        assert_eq!(value["tools"][0]["function"]["name"], "lookup");
+        assert!(value.get("tool_choice").is_none());
+        assert!((value["p"].as_f64().unwrap_or_default() - 0.9).abs() < 1e-6);
+        assert_eq!(value["max_tokens"], 512);
+    }
+
+    #[test]
+    fn build_request_maps_tool_choice_modes() {
+        let config = CohereModelConfig::new("key", "command-a-03-2025");
+        let tools = [tool_definition("lookup"), tool_definition("search")];
+        let messages = vec![ModelMessage::User("hi".to_string())];
+
+        let required = serde_json::to_value(build_request(
+            &messages,
+            &tools,
+            ModelToolChoice::Required,
+            &config,
+        ))
+        .expect("serializes");
+        assert_eq!(required["tool_choice"], "REQUIRED");
+        assert_eq!(required["tools"].as_array().map(Vec::len), Some(2));
+
+        let none = serde_json::to_value(build_request(
+            &messages,
+            &tools,
+            ModelToolChoice::None,
+            &config,
+        ))
+        .expect("serializes");
+        assert_eq!(none["tool_choice"], "NONE");
+
+        let forced = serde_json::to_value(build_request(
+            &messages,
+            &tools,
+            ModelToolChoice::Tool("search".to_string()),
+            &config,
+        ))
+        .expect("serializes");
+        assert_eq!(forced["tool_choice"], "REQUIRED");
+        assert_eq!(forced["tools"].as_array().map(Vec::len), Some(1));
+        assert_eq!(forced["tools"][0]["function"]["name"], "search");
+    }
+
+    #[test]
+    fn build_request_omits_tool_choice_without_tools() {
+        let config = CohereModelConfig::new("key", "command-a-03-2025");
+        let messages = vec![ModelMessage::User("hi".to_string())];
+
+        let request = build_request(&messages, &[], ModelToolChoice::Required, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert!(value.get("tools").is_none());
+        assert!(value.get("tool_choice").is_none());
+    }
+
+    #[test]
+    fn build_request_drops_orphan_tool_results_and_inserts_user_fallback() {
+        let messages = vec![
+            ModelMessage::System("You are helpful".to_string()),
+            ModelMessage::ToolResult {
+                tool_call_id: "call_missing".to_string(),
+                tool_name: "lookup".to_string(),
+                content: "result".to_string(),
+                is_error: false,
+                images: Vec::new(),
+            },
+        ];
+        let config = CohereModelConfig::new("key", "command-a-03-2025");
+
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["messages"].as_array().map(Vec::len), Some(2));
+        assert_eq!(value["messages"][0]["role"], "system");
+        assert_eq!(value["messages"][1]["role"], "user");
+        assert_eq!(value["messages"][1]["content"], " ");
+    }
+
+    #[test]
+    fn apply_call_options_overrides_config() {
+        let config = CohereModelConfig::new("key", "command-a-03-2025");
+        let messages = vec![ModelMessage::User("hi".to_string())];
+        let mut request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+
+        apply_call_options(
+            &mut request,
+            &ModelCallOptions {
+                max_tokens: Some(64),
+                temperature: Some(0.0),
+                stop_sequences: vec!["END".to_string()],
+                ..ModelCallOptions::default()
+            },
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["max_tokens"], 64);
+        assert_eq!(value["temperature"], 0.0);
+        assert_eq!(value["stop_sequences"], json!(["END"]));
+    }
+
+    #[test]
+    fn normalize_response_extracts_text_tool_calls_and_billed_usage() {
+        let response: CohereChatResponse = serde_json::from_value(json!({
+            "id": "resp_1",
+            "finish_reason": "TOOL_CALL",
+            "message": {
+                "role": "assistant",
+                "tool_plan": "I should look this up",
+                "content": [
+                    {"type": "thinking", "thinking": "user wants docs"},
+                    {"type": "text", "text": "Searching "},
+                    {"type": "text", "text": "now"}
+                ],
+                "tool_calls": [{
+                    "id": "call_x",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": "{\"query\":\"rust\"}"}
+                }]
+            },
+            "usage": {
+                "billed_units": {"input_tokens": 11, "output_tokens": 7},
+                "tokens": {"input_tokens": 140, "output_tokens": 20}
+            }
+        }))
+        .expect("response parses");
+
+        let completion = normalize_response(response).expect("response normalizes");
+
+        assert_eq!(completion.text.as_deref(), Some("Searching now"));
+        assert_eq!(
+            completion.thinking,
+            vec![
+                "user wants docs".to_string(),
+                "I should look this up".to_string()
+            ]
+        );
+        assert_eq!(completion.tool_calls.len(), 1);
+        assert_eq!(completion.tool_calls[0].id, "call_x");
+        assert_eq!(completion.tool_calls[0].name, "lookup");
+        assert_eq!(completion.tool_calls[0].arguments, json!({"query": "rust"}));
+        assert_eq!(
+            completion.usage,
+            Some(ModelUsage {
+                input_tokens: 11,
+                output_tokens: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn normalize_response_requires_message() {
+        let err = normalize_response(CohereChatResponse {
+            message: None,
+            usage: None,
+        })
+        .expect_err("should fail");
+
+        match err {
+            ProviderError::Response { message, .. } => {
+                assert!(message.contains("missing message"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[test]
+    fn normalize_response_fails_on_invalid_tool_arguments() {
+        let response: CohereChatResponse = serde_json::from_value(json!({
+            "message": {
+                "role": "assistant",
+                "tool_calls": [{
+                    "id": "call_x",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": "{not json}"}
+                }]
+            }
+        }))
+        .expect("response parses");
+
+        let err = normalize_response(response).expect_err("should fail");
+
+        match err {
+            ProviderError::Response { message, .. } => {
+                assert!(message.contains("not valid JSON"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_response_exposes_status_and_provider_message() {
+        let (url, server) = crate::llm::test_server::serve_once(
+            429,
+            r#"{"id":"abc","message":"trial key rate limit exceeded"}"#,
+        );
+        let mut config = CohereModelConfig::new("key", "command-a-03-2025");
+        config.api_base_url = Some(url);
+        let model = CohereModel::new(config).expect("model builds");
+
+        let err = model
+            .invoke(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+            )
+            .await
+            .expect_err("rate limited");
+        server.join().expect("server thread");
+
+        assert_eq!(err.status(), Some(429));
+        assert_eq!(
+            err.provider_message(),
+            Some("trial key rate limit exceeded")
+        );
+        assert!(err.to_string().contains("trial key rate limit exceeded"));
+    }
+
+    #[tokio::test]
+    async fn unparseable_success_response_keeps_its_status() {
+        let (url, server) = crate::llm::test_server::serve_once(200, "not json");
+        let mut config = CohereModelConfig::new("key", "command-a-03-2025");
+        config.api_base_url = Some(url);
+        let model = CohereModel::new(config).expect("model builds");
+
+        let err = model
+            .invoke(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+            )
+            .await
+            .expect_err("invalid body");
+        server.join().expect("server thread");
+
+        assert!(matches!(err, ProviderError::Response { .. }));
+        assert_eq!(err.status(), Some(200));
+        assert_eq!(err.provider_message(), None);
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_COHERE_KEY", "tenant-key") };
+
+        let model =
+            CohereModel::from_env_with_key("AGENT_SDK_TEST_COHERE_KEY", "command-a-03-2025")
+                .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+    }
+
+    #[test]
+    fn describe_request_keeps_each_system_message() {
+        let model =
+            CohereModel::new(CohereModelConfig::new("key", "command-a-03-2025")).expect("builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief".to_string(), "answer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
+}
@@ -0,0 +1,360 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::error::ProviderError;
+use crate::llm::openai::{
+    OpenAiChatCompletionRequest, OpenAiChatCompletionResponse, OpenAiModelConfig,
+    apply_call_options, build_request, describe_chat_request, extract_api_error,
+    normalize_response,
+};
+use crate::llm::{
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolChoice, ModelToolDefinition, RequestDescription, env_var, http_client, parse_response,
+    with_prefill_instruction,
+};
+
+const DEFAULT_API_BASE_URL: &str = "https://api.deepseek.com/v1";
+const REASONER_MODEL_PREFIX: &str = "deepseek-reasoner";
+
+#[derive(Debug, Clone)]
+/// Runtime configuration for [`DeepSeekModel`].
+pub struct DeepSeekModelConfig {
+    /// DeepSeek API key.
+    pub api_key: String,
+    /// Model id (for example `deepseek-chat` or `deepseek-reasoner`).
+    pub model: String,
+    /// Optional base URL override.
+    pub api_base_url: Option<String>,
+    /// Optional sampling temperature; not sent to `deepseek-reasoner`.
+    pub temperature: Option<f32>,
+    /// Optional nucleus sampling parameter; not sent to `deepseek-reasoner`.
+    pub top_p: Option<f32>,
+    /// Optional max output tokens.
+    pub max_tokens: Option<u32>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
+}
+
+impl DeepSeekModelConfig {
+    /// Creates a config with sensible defaults.
+    pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: model.into(),
+            api_base_url: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: Some(4096),
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// DeepSeek chat completions adapter implementing [`ChatModel`].
+///
+/// Shares request and response mapping with [`crate::OpenAiModel`]; `reasoning_content` from
+/// `deepseek-reasoner` becomes [`ModelCompletion::thinking`].
+pub struct DeepSeekModel {
+    client: Client,
+    config: DeepSeekModelConfig,
+}
+
+impl DeepSeekModel {
+    /// Creates a model adapter from explicit config.
+    pub fn new(config: DeepSeekModelConfig) -> Result<Self, ProviderError> {
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Creates a model adapter using `DEEPSEEK_API_KEY`.
+    pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
+        Self::from_env_with_key("DEEPSEEK_API_KEY", model)
+    }
+
+    /// Creates a model adapter reading the API key from the `var_name` environment variable.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        Self::new(DeepSeekModelConfig::new(env_var(var_name)?, model))
+    }
+
+    fn endpoint(&self) -> String {
+        let base = self
+            .config
+            .api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_BASE_URL)
+            .trim_end_matches('/');
+        format!("{base}/chat/completions")
+    }
+}
+
+#[async_trait]
+impl ChatModel for DeepSeekModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        describe_chat_request(&deepseek_request(
+            &messages,
+            &[],
+            ModelToolChoice::Auto,
+            &self.config,
+            options,
+        ))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "deepseek.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let request = deepseek_request(&messages, tools, tool_choice, &self.config, options);
+
+        let response = self
+            .client
+            .post(self.endpoint())
+            .bearer_auth(&self.config.api_key)
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| ProviderError::request(err.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
+        }
+
+        let (payload, raw) =
+            parse_response::<OpenAiChatCompletionResponse>(response, options.capture_raw).await?;
+
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
+    }
+}
+
+/// `deepseek-reasoner` rejects sampling parameters, so they are dropped for it.
+fn is_reasoner(model: &str) -> bool {
+    model.starts_with(REASONER_MODEL_PREFIX)
+}
+
+fn deepseek_request(
+    messages: &[ModelMessage],
+    tools: &[ModelToolDefinition],
+    tool_choice: ModelToolChoice,
+    config: &DeepSeekModelConfig,
+    options: &ModelCallOptions,
+) -> OpenAiChatCompletionRequest {
+    let reasoner = is_reasoner(&config.model);
+
+    let mut request_config = OpenAiModelConfig::new(&config.api_key, &config.model);
+    request_config.max_tokens = config.max_tokens;
+    if !reasoner {
+        request_config.temperature = config.temperature;
+        request_config.top_p = config.top_p;
+    }
+
+    let options = if reasoner && options.temperature.is_some() {
+        Cow::Owned(ModelCallOptions {
+            temperature: None,
+            ..options.clone()
+        })
+    } else {
+        Cow::Borrowed(options)
+    };
+
+    let mut request = build_request(messages, tools, tool_choice, &request_config);
+    apply_call_options(&mut request, &options);
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn tool_definition() -> ModelToolDefinition {
+        ModelToolDefinition {
+            name: "lookup".to_string(),
+            description: "Look up a term".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {"query": {"type": "string"}},
+                "required": ["query"]
+            }),
+        }
+    }
+
+    fn request_json(config: &DeepSeekModelConfig, options: &ModelCallOptions) -> Value {
+        let request = deepseek_request(
+            &[ModelMessage::User("hello".to_string())],
+            &[tool_definition()],
+            ModelToolChoice::Required,
+            config,
+            options,
+        );
+        serde_json::to_value(request).expect("request serializes")
+    }
+
+    #[test]
+    fn deepseek_request_sends_model_tools_and_sampling_params() {
+        let mut config = DeepSeekModelConfig::new("key", "deepseek-chat");
+        config.temperature = Some(0.3);
+        config.top_p = Some(0.9);
+
+        let value = request_json(&config, &ModelCallOptions::default());
+
+        assert_eq!(value["model"], "deepseek-chat");
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["tools"][0]["function"]["name"], "lookup");
+        assert_eq!(value["tool_choice"], "required");
+        assert!((value["temperature"].as_f64().unwrap_or_default() - 0.3).abs() < 1e-6);
+        assert!((value["top_p"].as_f64().unwrap_or_default() - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn deepseek_request_omits_sampling_params_for_reasoner() {
+        let mut config = DeepSeekModelConfig::new("key", "deepseek-reasoner");
+        config.temperature = Some(0.3);
+        config.top_p = Some(0.9);
+        let options = ModelCallOptions {
+            temperature: Some(0.0),
+            max_tokens: Some(64),
+            ..ModelCallOptions::default()
+        };
+
+        let value = request_json(&config, &options);
+
+        assert_eq!(value["model"], "deepseek-reasoner");
+        assert_eq!(value["max_tokens"], 64);
+        assert!(value.get("temperature").is_none());
+        assert!(value.get("top_p").is_none());
+    }
+
+    #[test]
+    fn normalize_response_extracts_reasoning_content() {
+        let payload = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "model": "deepseek-reasoner",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "stop",
+                "message": {
+                    "role": "assistant",
+                    "content": "9.11 is smaller.",
+                    "reasoning_content": "Compare the tenths digit first."
+                }
+            }],
+            "usage": {"prompt_tokens": 18, "completion_tokens": 42, "total_tokens": 60}
+        });
+        let response = serde_json::from_value::<OpenAiChatCompletionResponse>(payload)
+            .expect("deepseek payload deserializes");
+
+        let completion = normalize_response(response).expect("normalizes");
+
+        assert_eq!(completion.text.as_deref(), Some("9.11 is smaller."));
+        assert_eq!(
+            completion.thinking,
+            vec!["Compare the tenths digit first.".to_string()]
+        );
+        let usage = completion.usage.expect("usage");
+        assert_eq!((usage.input_tokens, usage.output_tokens), (18, 42));
+    }
+
+    #[test]
+    fn endpoint_defaults_to_deepseek_api() {
+        let model =
+            DeepSeekModel::new(DeepSeekModelConfig::new("key", "deepseek-chat")).expect("builds");
+
+        assert_eq!(
+            model.endpoint(),
+            "https://api.deepseek.com/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_DEEPSEEK_KEY", "tenant-key") };
+
+        let model =
+            DeepSeekModel::from_env_with_key("AGENT_SDK_TEST_DEEPSEEK_KEY", "deepseek-chat")
+                .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+    }
+
+    #[test]
+    fn describe_request_keeps_each_system_message() {
+        let model =
+            DeepSeekModel::new(DeepSeekModelConfig::new("key", "deepseek-chat")).expect("builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief".to_string(), "answer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
+}
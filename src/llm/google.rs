@@ -2,12 +2,15 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value, json};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 use crate::error::ProviderError;
 use crate::llm::{
-    ChatModel, ModelCompletion, ModelMessage, ModelToolCall, ModelToolChoice, ModelToolDefinition,
-    ModelUsage,
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage, RequestDescription,
+    ResponseFormat, ensure_unique_tool_call_ids, env_var, http_client, joined_len, parse_response,
+    with_prefill_instruction,
 };
 
 const DEFAULT_API_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
@@ -32,6 +35,16 @@ pub struct GoogleModelConfig {
     pub thinking_budget_tokens: Option<u32>,
     /// Whether to include thought parts in responses when supported.
     pub include_thoughts: Option<bool>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
 }
 
 impl GoogleModelConfig {
@@ -46,6 +59,10 @@ impl GoogleModelConfig {
             max_output_tokens: Some(4096),
             thinking_budget_tokens: None,
             include_thoughts: None,
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
         }
     }
 }
@@ -60,9 +77,12 @@ pub struct GoogleModel {
 impl GoogleModel {
     /// Creates a model adapter from explicit config.
     pub fn new(config: GoogleModelConfig) -> Result<Self, ProviderError> {
-        let client = Client::builder()
-            .build()
-            .map_err(|err| ProviderError::Request(err.to_string()))?;
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
 
         Ok(Self { client, config })
     }
@@ -71,13 +91,19 @@ impl GoogleModel {
     pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
         let api_key = std::env::var("GOOGLE_API_KEY")
             .or_else(|_| std::env::var("GEMINI_API_KEY"))
-            .map_err(|_| {
-                ProviderError::Request("GOOGLE_API_KEY (or GEMINI_API_KEY) is not set".to_string())
-            })?;
+            .map_err(|_| ProviderError::request("GOOGLE_API_KEY (or GEMINI_API_KEY) is not set"))?;
 
         Self::new(GoogleModelConfig::new(api_key, model))
     }
 
+    /// Creates a model adapter reading the API key from the `var_name` environment variable.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        Self::new(GoogleModelConfig::new(env_var(var_name)?, model))
+    }
+
     fn endpoint(&self) -> String {
         let base = self
             .config
@@ -97,7 +123,56 @@ impl ChatModel for GoogleModel {
         tools: &[ModelToolDefinition],
         tool_choice: ModelToolChoice,
     ) -> Result<ModelCompletion, ProviderError> {
-        let request = build_request(messages, tools, tool_choice, &self.config);
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &self.config);
+        let system = request
+            .system_instruction
+            .map(|instruction| {
+                instruction
+                    .parts
+                    .into_iter()
+                    .filter_map(|part| part.text)
+                    .collect()
+            })
+            .unwrap_or_default();
+        RequestDescription {
+            system,
+            message_count: request.contents.len(),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "google.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.config);
+        apply_call_options(&mut request, options);
 
         let response = self
             .client
@@ -107,18 +182,19 @@ impl ChatModel for GoogleModel {
             .json(&request)
             .send()
             .await
-            .map_err(|err| ProviderError::Request(err.to_string()))?;
+            .map_err(|err| ProviderError::request(err.to_string()))?;
 
-        if !response.status().is_success() {
-            return Err(ProviderError::Request(extract_api_error(response).await));
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
         }
 
-        let payload = response
-            .json::<GenerateContentResponse>()
-            .await
-            .map_err(|err| ProviderError::Response(err.to_string()))?;
+        let (payload, raw) =
+            parse_response::<GenerateContentResponse>(response, options.capture_raw).await?;
 
-        normalize_response(payload)
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
     }
 }
 
@@ -186,8 +262,14 @@ struct GoogleGenerationConfig {
     top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     thinking_config: Option<GoogleThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -209,6 +291,15 @@ struct GooglePart {
     function_call: Option<GoogleFunctionCall>,
     #[serde(skip_serializing_if = "Option::is_none")]
     function_response: Option<GoogleFunctionResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_data: Option<GoogleInlineData>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct GoogleInlineData {
+    mime_type: String,
+    data: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -329,7 +420,10 @@ fn build_request(
         temperature: config.temperature,
         top_p: config.top_p,
         max_output_tokens: config.max_output_tokens,
+        stop_sequences: Vec::new(),
         thinking_config,
+        response_mime_type: None,
+        response_schema: None,
     };
 
     GenerateContentRequest {
@@ -340,6 +434,7 @@ fn build_request(
                 thought: None,
                 function_call: None,
                 function_response: None,
+                inline_data: None,
             }],
         }),
         tools: tools_payload,
@@ -357,6 +452,7 @@ fn ensure_non_empty_contents(mut contents: Vec<GoogleContent>) -> Vec<GoogleCont
                 thought: None,
                 function_call: None,
                 function_response: None,
+                inline_data: None,
             }],
         });
     }
@@ -385,6 +481,7 @@ fn to_google_contents(messages: &[ModelMessage]) -> (Vec<GoogleContent>, Option<
                         thought: None,
                         function_call: None,
                         function_response: None,
+                        inline_data: None,
                     }],
                 });
             }
@@ -402,6 +499,7 @@ fn to_google_contents(messages: &[ModelMessage]) -> (Vec<GoogleContent>, Option<
                         thought: None,
                         function_call: None,
                         function_response: None,
+                        inline_data: None,
                     });
                 }
 
@@ -415,6 +513,7 @@ fn to_google_contents(messages: &[ModelMessage]) -> (Vec<GoogleContent>, Option<
                             args: Some(call.arguments.clone()),
                         }),
                         function_response: None,
+                        inline_data: None,
                     });
                 }
 
@@ -430,9 +529,9 @@ fn to_google_contents(messages: &[ModelMessage]) -> (Vec<GoogleContent>, Option<
                 tool_name,
                 content,
                 is_error,
-            } => contents.push(GoogleContent {
-                role: "user".to_string(),
-                parts: vec![GooglePart {
+                images,
+            } => {
+                let mut parts = vec![GooglePart {
                     text: None,
                     thought: None,
                     function_call: None,
@@ -440,8 +539,23 @@ fn to_google_contents(messages: &[ModelMessage]) -> (Vec<GoogleContent>, Option<
                         name: tool_name.clone(),
                         response: tool_result_payload(content, *is_error),
                     }),
-                }],
-            }),
+                    inline_data: None,
+                }];
+                parts.extend(images.iter().map(|image| GooglePart {
+                    text: None,
+                    thought: None,
+                    function_call: None,
+                    function_response: None,
+                    inline_data: Some(GoogleInlineData {
+                        mime_type: image.media_type.clone(),
+                        data: image.data.clone(),
+                    }),
+                }));
+                contents.push(GoogleContent {
+                    role: "user".to_string(),
+                    parts,
+                });
+            }
         }
     }
 
@@ -454,6 +568,27 @@ fn to_google_contents(messages: &[ModelMessage]) -> (Vec<GoogleContent>, Option<
     (contents, system)
 }
 
+fn apply_call_options(request: &mut GenerateContentRequest, options: &ModelCallOptions) {
+    let Some(generation_config) = request.generation_config.as_mut() else {
+        return;
+    };
+    if let Some(max_tokens) = options.max_tokens {
+        generation_config.max_output_tokens = Some(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        generation_config.temperature = Some(temperature);
+    }
+    if !options.stop_sequences.is_empty() {
+        generation_config.stop_sequences = options.stop_sequences.clone();
+    }
+    if let Some(format) = &options.response_format {
+        generation_config.response_mime_type = Some("application/json".to_string());
+        if let ResponseFormat::JsonSchema(schema) = format {
+            generation_config.response_schema = Some(clean_gemini_schema(schema.clone()));
+        }
+    }
+}
+
 fn tool_result_payload(content: &str, is_error: bool) -> Value {
     if is_error {
         return json!({"error": content});
@@ -468,13 +603,14 @@ fn tool_result_payload(content: &str, is_error: bool) -> Value {
 
 fn normalize_response(response: GenerateContentResponse) -> Result<ModelCompletion, ProviderError> {
     let Some(candidate) = response.candidates.into_iter().next() else {
-        return Err(ProviderError::Response(
+        return Err(ProviderError::response(
             "google response missing candidates".to_string(),
         ));
     };
 
     let mut text_parts = Vec::new();
     let mut thinking_parts = Vec::new();
+    let mut thinking_offsets = Vec::new();
     let mut tool_calls = Vec::new();
 
     if let Some(content) = candidate.content {
@@ -482,6 +618,7 @@ fn normalize_response(response: GenerateContentResponse) -> Result<ModelCompleti
             if let Some(text) = part.text {
                 if part.thought.unwrap_or(false) {
                     thinking_parts.push(text);
+                    thinking_offsets.push(joined_len(&text_parts, "\n"));
                 } else {
                     text_parts.push(text);
                 }
@@ -489,7 +626,7 @@ fn normalize_response(response: GenerateContentResponse) -> Result<ModelCompleti
 
             if let Some(function_call) = part.function_call {
                 let Some(name) = function_call.name else {
-                    return Err(ProviderError::Response(
+                    return Err(ProviderError::response(
                         "google functionCall missing name".to_string(),
                     ));
                 };
@@ -513,27 +650,25 @@ fn normalize_response(response: GenerateContentResponse) -> Result<ModelCompleti
             .saturating_add(usage.thoughts_token_count.unwrap_or(0)),
     });
 
+    ensure_unique_tool_call_ids(&mut tool_calls);
+
     let text = if text_parts.is_empty() {
         None
     } else {
         Some(text_parts.join("\n"))
     };
 
-    let thinking = if thinking_parts.is_empty() {
-        None
-    } else {
-        Some(thinking_parts.join("\n"))
-    };
-
     Ok(ModelCompletion {
         text,
-        thinking,
+        thinking: thinking_parts,
+        thinking_offsets,
         tool_calls,
         usage,
+        raw: None,
     })
 }
 
-async fn extract_api_error(response: reqwest::Response) -> String {
+async fn extract_api_error(response: reqwest::Response) -> ProviderError {
     let status = response.status();
     let body = response.text().await.unwrap_or_default();
 
@@ -543,18 +678,25 @@ async fn extract_api_error(response: reqwest::Response) -> String {
             .error
             .status
             .unwrap_or_else(|| status.to_string().to_uppercase());
-        let message = parsed
-            .error
-            .message
-            .unwrap_or_else(|| "unknown google api error".to_string());
-        return format!("google api error {code} {status_name}: {message}");
+        let provider_message = parsed.error.message;
+        let message = provider_message
+            .as_deref()
+            .unwrap_or("unknown google api error");
+        return ProviderError::api(
+            status.as_u16(),
+            format!("google api error {code} {status_name}: {message}"),
+            provider_message,
+        );
     }
 
-    if body.is_empty() {
-        format!("google api request failed ({status})")
-    } else {
-        format!("google api request failed ({status}): {body}")
-    }
+    ProviderError::http(
+        status.as_u16(),
+        if body.is_empty() {
+            format!("google api request failed ({status})")
+        } else {
+            format!("google api request failed ({status}): {body}")
+        },
+    )
 }
 
 fn clean_gemini_schema(schema: Value) -> Value {
@@ -687,6 +829,7 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::llm::ImageData;
 
     fn tool_definition() -> ModelToolDefinition {
         ModelToolDefinition {
@@ -722,6 +865,7 @@ mod tests {
                 tool_name: "lookup".to_string(),
                 content: "{\"result\":\"ok\"}".to_string(),
                 is_error: false,
+                images: Vec::new(),
             },
         ];
 
@@ -766,6 +910,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn apply_call_options_requests_json_with_schema() {
+        let messages = vec![ModelMessage::User("classify".to_string())];
+        let config = GoogleModelConfig::new("key", "gemini-2.5-flash");
+        let mut request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+
+        apply_call_options(
+            &mut request,
+            &ModelCallOptions {
+                response_format: Some(ResponseFormat::JsonSchema(json!({
+                    "type": "object",
+                    "properties": {"label": {"type": "string"}},
+                    "additionalProperties": false
+                }))),
+                ..ModelCallOptions::default()
+            },
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        let generation_config = &value["generationConfig"];
+        assert_eq!(generation_config["responseMimeType"], "application/json");
+        assert_eq!(
+            generation_config["responseSchema"]["properties"]["label"]["type"],
+            "string"
+        );
+        assert!(
+            generation_config["responseSchema"]
+                .get("additionalProperties")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn build_request_sends_tool_result_images_as_inline_data() {
+        let messages = vec![ModelMessage::ToolResult {
+            tool_call_id: "call_1".to_string(),
+            tool_name: "screenshot".to_string(),
+            content: "captured".to_string(),
+            is_error: false,
+            images: vec![ImageData::new("image/png", "aGVsbG8=")],
+        }];
+        let config = GoogleModelConfig::new("key", "gemini-2.5-flash");
+
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        let parts = &value["contents"][0]["parts"];
+        assert_eq!(parts[0]["functionResponse"]["name"], "screenshot");
+        assert_eq!(parts[1]["inlineData"]["mimeType"], "image/png");
+        assert_eq!(parts[1]["inlineData"]["data"], "aGVsbG8=");
+    }
+
     #[test]
     fn build_request_adds_fallback_content_for_empty_user_message() {
         let messages = vec![ModelMessage::User(String::new())];
@@ -791,12 +987,14 @@ mod tests {
                             thought: None,
                             function_call: None,
                             function_response: None,
+                            inline_data: None,
                         },
                         GooglePart {
                             text: Some("reasoning".to_string()),
                             thought: Some(true),
                             function_call: None,
                             function_response: None,
+                            inline_data: None,
                         },
                         GooglePart {
                             text: None,
@@ -807,6 +1005,7 @@ mod tests {
                                 args: Some(json!({"q": "rust"})),
                             }),
                             function_response: None,
+                            inline_data: None,
                         },
                     ],
                 }),
@@ -821,7 +1020,7 @@ mod tests {
         let completion = normalize_response(response).expect("response normalizes");
 
         assert_eq!(completion.text.as_deref(), Some("answer"));
-        assert_eq!(completion.thinking.as_deref(), Some("reasoning"));
+        assert_eq!(completion.thinking, vec!["reasoning".to_string()]);
         assert_eq!(completion.tool_calls.len(), 1);
         assert_eq!(completion.tool_calls[0].name, "lookup");
         assert_eq!(completion.tool_calls[0].id, "call_x");
@@ -834,6 +1033,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn normalize_response_gives_function_calls_distinct_ids() {
+        let function_call_part = |id: Option<&str>| GooglePart {
+            text: None,
+            thought: None,
+            function_call: Some(GoogleFunctionCall {
+                id: id.map(ToString::to_string),
+                name: Some("lookup".to_string()),
+                args: Some(json!({"q": "rust"})),
+            }),
+            function_response: None,
+            inline_data: None,
+        };
+        let response = GenerateContentResponse {
+            candidates: vec![GoogleCandidate {
+                content: Some(GoogleContent {
+                    role: "model".to_string(),
+                    parts: vec![
+                        function_call_part(Some("call_2")),
+                        function_call_part(None),
+                        function_call_part(None),
+                    ],
+                }),
+            }],
+            usage_metadata: None,
+        };
+
+        let completion = normalize_response(response).expect("response normalizes");
+
+        let ids = completion
+            .tool_calls
+            .iter()
+            .map(|call| call.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["call_2", "call_2_2", "call_3"]);
+    }
+
     #[test]
     fn normalize_response_requires_candidates() {
         let err = normalize_response(GenerateContentResponse {
@@ -843,7 +1079,7 @@ mod tests {
         .expect_err("should fail");
 
         match err {
-            ProviderError::Response(message) => {
+            ProviderError::Response { message, .. } => {
                 assert!(message.contains("missing candidates"));
             }
             other => panic!("unexpected error: {other}"),
@@ -925,4 +1161,34 @@ mod tests {
         assert!(cleaned["properties"]["root"].get("$ref").is_none());
         assert_eq!(cleaned["properties"]["root"]["properties"]["next"]["type"], "string");
     }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_GOOGLE_KEY", "tenant-key") };
+
+        let model = GoogleModel::from_env_with_key("AGENT_SDK_TEST_GOOGLE_KEY", "gemini-2.5-flash")
+            .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+    }
+
+    #[test]
+    fn describe_request_hoists_system_messages_into_one_instruction() {
+        let model =
+            GoogleModel::new(GoogleModelConfig::new("key", "gemini-2.5-flash")).expect("builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief\n\nanswer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
 }
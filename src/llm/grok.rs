@@ -1,12 +1,18 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
 use crate::error::ProviderError;
+use crate::llm::openai::response_format_payload;
 use crate::llm::{
-    ChatModel, ModelCompletion, ModelMessage, ModelToolCall, ModelToolChoice, ModelToolDefinition,
-    ModelUsage,
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage, RequestDescription, env_var,
+    http_client, omitted_images_note, parse_response, validate_reasoning_effort,
+    with_prefill_instruction,
 };
 
 const DEFAULT_API_BASE_URL: &str = "https://api.x.ai/v1";
@@ -27,6 +33,19 @@ pub struct GrokModelConfig {
     pub top_p: Option<f32>,
     /// Optional max output tokens.
     pub max_tokens: Option<u32>,
+    /// Optional `reasoning_effort` (`low`, `medium`, or `high`) for reasoning models that accept it,
+    /// such as `grok-3-mini`; checked by [`GrokModel::new`].
+    pub reasoning_effort: Option<String>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
 }
 
 impl GrokModelConfig {
@@ -39,6 +58,11 @@ impl GrokModelConfig {
             temperature: None,
             top_p: None,
             max_tokens: Some(4096),
+            reasoning_effort: None,
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
         }
     }
 }
@@ -53,9 +77,13 @@ pub struct GrokModel {
 impl GrokModel {
     /// Creates a model adapter from explicit config.
     pub fn new(config: GrokModelConfig) -> Result<Self, ProviderError> {
-        let client = Client::builder()
-            .build()
-            .map_err(|err| ProviderError::Request(err.to_string()))?;
+        validate_reasoning_effort(config.reasoning_effort.as_deref())?;
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
 
         Ok(Self { client, config })
     }
@@ -64,13 +92,19 @@ impl GrokModel {
     pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
         let api_key = std::env::var("XAI_API_KEY")
             .or_else(|_| std::env::var("GROK_API_KEY"))
-            .map_err(|_| {
-                ProviderError::Request("XAI_API_KEY (or GROK_API_KEY) is not set".to_string())
-            })?;
+            .map_err(|_| ProviderError::request("XAI_API_KEY (or GROK_API_KEY) is not set"))?;
 
         Self::new(GrokModelConfig::new(api_key, model))
     }
 
+    /// Creates a model adapter reading the API key from the `var_name` environment variable.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        Self::new(GrokModelConfig::new(env_var(var_name)?, model))
+    }
+
     fn endpoint(&self) -> String {
         let base = self
             .config
@@ -90,7 +124,50 @@ impl ChatModel for GrokModel {
         tools: &[ModelToolDefinition],
         tool_choice: ModelToolChoice,
     ) -> Result<ModelCompletion, ProviderError> {
-        let request = build_request(messages, tools, tool_choice, &self.config);
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &self.config);
+        let mut description = RequestDescription::default();
+        for message in request.messages {
+            match message {
+                GrokRequestMessage::System { content } => description.system.push(content),
+                _ => description.message_count += 1,
+            }
+        }
+        description
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "grok.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.config);
+        apply_call_options(&mut request, options);
 
         let response = self
             .client
@@ -100,18 +177,19 @@ impl ChatModel for GrokModel {
             .json(&request)
             .send()
             .await
-            .map_err(|err| ProviderError::Request(err.to_string()))?;
+            .map_err(|err| ProviderError::request(err.to_string()))?;
 
-        if !response.status().is_success() {
-            return Err(ProviderError::Request(extract_api_error(response).await));
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
         }
 
-        let payload = response
-            .json::<GrokChatCompletionResponse>()
-            .await
-            .map_err(|err| ProviderError::Response(err.to_string()))?;
+        let (payload, raw) =
+            parse_response::<GrokChatCompletionResponse>(response, options.capture_raw).await?;
 
-        normalize_response(payload)
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
     }
 }
 
@@ -129,6 +207,12 @@ struct GrokChatCompletionRequest {
     top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -291,6 +375,9 @@ fn build_request(
         temperature: config.temperature,
         top_p: config.top_p,
         max_tokens: config.max_tokens,
+        reasoning_effort: config.reasoning_effort.clone(),
+        stop: Vec::new(),
+        response_format: None,
     }
 }
 
@@ -350,12 +437,17 @@ fn to_grok_messages(messages: &[ModelMessage]) -> Vec<GrokRequestMessage> {
                 tool_name: _,
                 content,
                 is_error,
+                images,
             } => {
-                let rendered = if *is_error {
+                let mut rendered = if *is_error {
                     format!("Error: {content}")
                 } else {
                     content.clone()
                 };
+                if let Some(note) = omitted_images_note(images) {
+                    rendered.push('\n');
+                    rendered.push_str(&note);
+                }
 
                 request_messages.push(GrokRequestMessage::Tool {
                     tool_call_id: tool_call_id.clone(),
@@ -436,6 +528,21 @@ fn ensure_non_empty_messages(mut messages: Vec<GrokRequestMessage>) -> Vec<GrokR
     normalized
 }
 
+fn apply_call_options(request: &mut GrokChatCompletionRequest, options: &ModelCallOptions) {
+    if let Some(max_tokens) = options.max_tokens {
+        request.max_tokens = Some(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        request.temperature = Some(temperature);
+    }
+    if !options.stop_sequences.is_empty() {
+        request.stop = options.stop_sequences.clone();
+    }
+    if let Some(format) = &options.response_format {
+        request.response_format = Some(response_format_payload(format));
+    }
+}
+
 fn normalize_response(
     response: GrokChatCompletionResponse,
 ) -> Result<ModelCompletion, ProviderError> {
@@ -443,10 +550,10 @@ fn normalize_response(
         .choices
         .into_iter()
         .next()
-        .ok_or_else(|| ProviderError::Response("grok response missing choices".to_string()))?;
+        .ok_or_else(|| ProviderError::response("grok response missing choices".to_string()))?;
 
     let message = choice.message.ok_or_else(|| {
-        ProviderError::Response("grok response missing choice message".to_string())
+        ProviderError::response("grok response missing choice message".to_string())
     })?;
 
     let mut tool_calls = Vec::new();
@@ -455,7 +562,7 @@ fn normalize_response(
             json!({})
         } else {
             serde_json::from_str::<Value>(&tool_call.function.arguments).map_err(|err| {
-                ProviderError::Response(format!(
+                ProviderError::response(format!(
                     "grok tool call arguments for '{}' are not valid JSON: {err}",
                     tool_call.function.name
                 ))
@@ -485,13 +592,19 @@ fn normalize_response(
 
     Ok(ModelCompletion {
         text: message.content.filter(|text| !text.is_empty()),
-        thinking: message.reasoning_content.filter(|text| !text.is_empty()),
+        thinking: message
+            .reasoning_content
+            .filter(|text| !text.is_empty())
+            .into_iter()
+            .collect(),
         tool_calls,
+        thinking_offsets: Vec::new(),
         usage,
+        raw: None,
     })
 }
 
-async fn extract_api_error(response: reqwest::Response) -> String {
+async fn extract_api_error(response: reqwest::Response) -> ProviderError {
     let status = response.status();
     let body = response.text().await.unwrap_or_default();
 
@@ -508,19 +621,26 @@ async fn extract_api_error(response: reqwest::Response) -> String {
             .error
             .type_
             .unwrap_or_else(|| status.to_string().to_uppercase());
-        let message = parsed
-            .error
-            .message
-            .unwrap_or_else(|| "unknown xai api error".to_string());
+        let provider_message = parsed.error.message;
+        let message = provider_message
+            .as_deref()
+            .unwrap_or("unknown xai api error");
 
-        return format!("xai api error {code} {error_type}: {message}");
+        return ProviderError::api(
+            status.as_u16(),
+            format!("xai api error {code} {error_type}: {message}"),
+            provider_message,
+        );
     }
 
-    if body.is_empty() {
-        format!("xai api request failed ({status})")
-    } else {
-        format!("xai api request failed ({status}): {body}")
-    }
+    ProviderError::http(
+        status.as_u16(),
+        if body.is_empty() {
+            format!("xai api request failed ({status})")
+        } else {
+            format!("xai api request failed ({status}): {body}")
+        },
+    )
 }
 
 #[cfg(test)]
@@ -562,6 +682,7 @@ mod tests {
                 tool_name: "lookup".to_string(),
                 content: "{\"result\":\"ok\"}".to_string(),
                 is_error: false,
+                images: Vec::new(),
             },
         ];
 
@@ -595,6 +716,36 @@ mod tests {
         assert_eq!(value["tool_choice"]["function"]["name"], "lookup");
         assert!((value["temperature"].as_f64().unwrap_or_default() - 0.2).abs() < 1e-6);
         assert_eq!(value["max_tokens"], 512);
+        assert!(value.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn build_request_sends_reasoning_effort() {
+        let mut config = GrokModelConfig::new("key", "grok-3-mini");
+        config.reasoning_effort = Some("high".to_string());
+
+        let request = build_request(
+            &[ModelMessage::User("think".to_string())],
+            &[],
+            ModelToolChoice::Auto,
+            &config,
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["reasoning_effort"], "high");
+    }
+
+    #[test]
+    fn new_rejects_unknown_reasoning_effort() {
+        let mut config = GrokModelConfig::new("key", "grok-3-mini");
+        config.reasoning_effort = Some("extreme".to_string());
+
+        let err = GrokModel::new(config).expect_err("should fail");
+
+        assert!(
+            err.to_string()
+                .contains("invalid reasoning_effort 'extreme': expected one of low, medium, high")
+        );
     }
 
     #[test]
@@ -622,6 +773,7 @@ mod tests {
             tool_name: "lookup".to_string(),
             content: "result".to_string(),
             is_error: false,
+            images: Vec::new(),
         }];
         let config = GrokModelConfig::new("key", "grok-4-1-fast-reasoning");
 
@@ -653,6 +805,7 @@ mod tests {
                 tool_name: "lookup".to_string(),
                 content: "{\"result\":\"ok\"}".to_string(),
                 is_error: false,
+                images: Vec::new(),
             },
         ];
         let config = GrokModelConfig::new("key", "grok-4-1-fast-reasoning");
@@ -697,7 +850,7 @@ mod tests {
         let completion = normalize_response(response).expect("response normalizes");
 
         assert_eq!(completion.text.as_deref(), Some("answer"));
-        assert_eq!(completion.thinking.as_deref(), Some("reasoning"));
+        assert_eq!(completion.thinking, vec!["reasoning".to_string()]);
         assert_eq!(completion.tool_calls.len(), 1);
         assert_eq!(completion.tool_calls[0].name, "lookup");
         assert_eq!(completion.tool_calls[0].id, "call_x");
@@ -750,7 +903,7 @@ mod tests {
         .expect_err("should fail");
 
         match err {
-            ProviderError::Response(message) => {
+            ProviderError::Response { message, .. } => {
                 assert!(message.contains("missing choices"));
             }
             other => panic!("unexpected error: {other}"),
@@ -779,10 +932,87 @@ mod tests {
         .expect_err("should fail");
 
         match err {
-            ProviderError::Response(message) => {
+            ProviderError::Response { message, .. } => {
                 assert!(message.contains("not valid JSON"));
             }
             other => panic!("unexpected error: {other}"),
         }
     }
+
+    #[test]
+    fn new_accepts_explicit_proxy() {
+        let mut config = GrokModelConfig::new("key", "grok-4-1-fast-reasoning");
+        config.proxy = Some("http://127.0.0.1:3128".to_string());
+
+        GrokModel::new(config).expect("model builds with proxy");
+    }
+
+    #[test]
+    fn new_rejects_malformed_proxy_url() {
+        let mut config = GrokModelConfig::new("key", "grok-4-1-fast-reasoning");
+        config.proxy = Some("http://[::1".to_string());
+
+        let err = GrokModel::new(config).expect_err("should fail");
+
+        match err {
+            ProviderError::Request { message, .. } => {
+                assert!(message.contains("invalid proxy url"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limited_response_exposes_status_and_provider_message() {
+        let (url, server) = crate::llm::test_server::serve_once(
+            429,
+            r#"{"error":{"message":"Too many requests","type":"rate_limit","code":429}}"#,
+        );
+        let mut config = GrokModelConfig::new("key", "grok-4-1-fast-reasoning");
+        config.api_base_url = Some(url);
+        let model = GrokModel::new(config).expect("model builds");
+
+        let err = model
+            .invoke(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+            )
+            .await
+            .expect_err("rate limited");
+        server.join().expect("server thread");
+
+        assert_eq!(err.status(), Some(429));
+        assert_eq!(err.provider_message(), Some("Too many requests"));
+        assert!(err.to_string().contains("Too many requests"));
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_GROK_KEY", "tenant-key") };
+
+        let model = GrokModel::from_env_with_key("AGENT_SDK_TEST_GROK_KEY", "grok-4")
+            .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+    }
+
+    #[test]
+    fn describe_request_keeps_each_system_message() {
+        let model = GrokModel::new(GrokModelConfig::new("key", "grok-4")).expect("builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief".to_string(), "answer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
 }
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::error::ProviderError;
+use crate::llm::openai::{
+    OpenAiChatCompletionResponse, OpenAiModelConfig, apply_call_options, build_request,
+    describe_chat_request, extract_api_error, normalize_response,
+};
+use crate::llm::{
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelToolChoice, ModelToolDefinition, RequestDescription, env_var, http_client, parse_response,
+    with_prefill_instruction,
+};
+
+const DEFAULT_API_BASE_URL: &str = "https://api.mistral.ai/v1";
+
+#[derive(Debug, Clone)]
+/// Runtime configuration for [`MistralModel`].
+pub struct MistralModelConfig {
+    /// Mistral API key.
+    pub api_key: String,
+    /// Model id (for example `mistral-large-latest`).
+    pub model: String,
+    /// Optional base URL override.
+    pub api_base_url: Option<String>,
+    /// Optional sampling temperature.
+    pub temperature: Option<f32>,
+    /// Optional nucleus sampling parameter.
+    pub top_p: Option<f32>,
+    /// Optional max output tokens.
+    pub max_tokens: Option<u32>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
+}
+
+impl MistralModelConfig {
+    /// Creates a config with sensible defaults.
+    pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: model.into(),
+            api_base_url: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: Some(4096),
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Mistral (La Plateforme) chat completions adapter implementing [`ChatModel`].
+///
+/// Shares request and response mapping with [`crate::OpenAiModel`]. Responses are first
+/// brought into the OpenAI shape: chunked `content` arrays are flattened and tool-call
+/// arguments are always JSON strings.
+pub struct MistralModel {
+    client: Client,
+    config: MistralModelConfig,
+}
+
+impl MistralModel {
+    /// Creates a model adapter from explicit config.
+    pub fn new(config: MistralModelConfig) -> Result<Self, ProviderError> {
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Creates a model adapter using `MISTRAL_API_KEY`.
+    pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
+        Self::from_env_with_key("MISTRAL_API_KEY", model)
+    }
+
+    /// Creates a model adapter reading the API key from the `var_name` environment variable.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        Self::new(MistralModelConfig::new(env_var(var_name)?, model))
+    }
+
+    fn endpoint(&self) -> String {
+        let base = self
+            .config
+            .api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_BASE_URL)
+            .trim_end_matches('/');
+        format!("{base}/chat/completions")
+    }
+
+    fn request_config(&self) -> OpenAiModelConfig {
+        let mut config = OpenAiModelConfig::new(&self.config.api_key, &self.config.model);
+        config.temperature = self.config.temperature;
+        config.top_p = self.config.top_p;
+        config.max_tokens = self.config.max_tokens;
+        config
+    }
+}
+
+#[async_trait]
+impl ChatModel for MistralModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        describe_chat_request(&build_request(
+            &messages,
+            &[],
+            ModelToolChoice::Auto,
+            &self.request_config(),
+        ))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "mistral.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.request_config());
+        apply_call_options(&mut request, options);
+
+        let response = self
+            .client
+            .post(self.endpoint())
+            .bearer_auth(&self.config.api_key)
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| ProviderError::request(err.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
+        }
+
+        let (payload, _) = parse_response::<Value>(response, false).await?;
+        let raw = options.capture_raw.then(|| payload.clone());
+
+        let mut completion = normalize_response(to_openai_response(payload)?)?;
+        completion.raw = raw;
+        Ok(completion)
+    }
+}
+
+/// Rewrites a Mistral response into the OpenAI chat completion shape.
+fn to_openai_response(mut payload: Value) -> Result<OpenAiChatCompletionResponse, ProviderError> {
+    let messages = payload
+        .get_mut("choices")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|choice| choice.get_mut("message"));
+
+    for message in messages {
+        if let Some(chunks) = message.get("content").and_then(Value::as_array) {
+            let (text, thinking) = flatten_content_chunks(chunks);
+            message["content"] = text.map_or(Value::Null, Value::String);
+            if let Some(thinking) = thinking {
+                message["reasoning_content"] = Value::String(thinking);
+            }
+        }
+
+        let tool_calls = message
+            .get_mut("tool_calls")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten();
+        for tool_call in tool_calls {
+            if !tool_call.get("type").is_some_and(Value::is_string) {
+                tool_call["type"] = Value::String("function".to_string());
+            }
+            let Some(function) = tool_call.get_mut("function") else {
+                continue;
+            };
+            match function.get("arguments") {
+                Some(Value::String(_)) => {}
+                Some(arguments) => {
+                    function["arguments"] = Value::String(arguments.to_string());
+                }
+                None => function["arguments"] = Value::String("{}".to_string()),
+            }
+        }
+    }
+
+    serde_json::from_value(payload).map_err(|err| ProviderError::response(err.to_string()))
+}
+
+/// Splits `content` chunks into joined text and thinking, skipping unknown chunk types.
+fn flatten_content_chunks(chunks: &[Value]) -> (Option<String>, Option<String>) {
+    let mut text = String::new();
+    let mut thinking = String::new();
+    for chunk in chunks {
+        match chunk.get("type").and_then(Value::as_str) {
+            Some("text") => text.push_str(chunk.get("text").and_then(Value::as_str).unwrap_or("")),
+            Some("thinking") => {
+                let parts = chunk
+                    .get("thinking")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|part| part.get("text").and_then(Value::as_str));
+                for part in parts {
+                    thinking.push_str(part);
+                }
+            }
+            _ => {}
+        }
+    }
+    (
+        (!text.is_empty()).then_some(text),
+        (!thinking.is_empty()).then_some(thinking),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::llm::ModelToolCall;
+
+    #[test]
+    fn build_request_serializes_tools_and_string_arguments() {
+        let model = MistralModel::new(MistralModelConfig::new("key", "mistral-large-latest"))
+            .expect("builds");
+        let messages = vec![
+            ModelMessage::User("weather in Paris?".to_string()),
+            ModelMessage::Assistant {
+                content: None,
+                tool_calls: vec![ModelToolCall {
+                    id: "D681PevKs".to_string(),
+                    name: "weather".to_string(),
+                    arguments: json!({"city": "Paris"}),
+                }],
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "D681PevKs".to_string(),
+                tool_name: "weather".to_string(),
+                content: "sunny".to_string(),
+                is_error: false,
+                images: Vec::new(),
+            },
+        ];
+        let tools = vec![ModelToolDefinition {
+            name: "weather".to_string(),
+            description: "Look up weather".to_string(),
+            parameters: json!({"type": "object", "properties": {"city": {"type": "string"}}}),
+        }];
+
+        let request = build_request(
+            &messages,
+            &tools,
+            ModelToolChoice::Required,
+            &model.request_config(),
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(
+            model.endpoint(),
+            "https://api.mistral.ai/v1/chat/completions"
+        );
+        assert_eq!(value["model"], "mistral-large-latest");
+        assert_eq!(value["tools"][0]["function"]["name"], "weather");
+        assert_eq!(value["tool_choice"], "required");
+        let arguments = &value["messages"][1]["tool_calls"][0]["function"]["arguments"];
+        assert_eq!(arguments, "{\"city\":\"Paris\"}");
+        assert_eq!(value["messages"][2]["role"], "tool");
+        assert_eq!(value["messages"][2]["tool_call_id"], "D681PevKs");
+    }
+
+    #[test]
+    fn normalize_parses_string_arguments_and_fills_missing_type() {
+        let payload = json!({
+            "id": "cmpl-1",
+            "object": "chat.completion",
+            "model": "mistral-large-latest",
+            "choices": [{
+                "index": 0,
+                "finish_reason": "tool_calls",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [
+                        {"id": "D681PevKs", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}},
+                        {"id": "x3Kq9LmPz", "type": "function", "function": {"name": "time", "arguments": {"zone": "CET"}}}
+                    ]
+                }
+            }],
+            "usage": {"prompt_tokens": 30, "completion_tokens": 12, "total_tokens": 42}
+        });
+
+        let completion =
+            normalize_response(to_openai_response(payload).expect("converts")).expect("normalizes");
+
+        assert_eq!(completion.tool_calls.len(), 2);
+        assert_eq!(completion.tool_calls[0].id, "D681PevKs");
+        assert_eq!(completion.tool_calls[0].arguments, json!({"city": "Paris"}));
+        assert_eq!(completion.tool_calls[1].arguments, json!({"zone": "CET"}));
+        let usage = completion.usage.expect("usage");
+        assert_eq!((usage.input_tokens, usage.output_tokens), (30, 12));
+    }
+
+    #[test]
+    fn normalize_flattens_text_and_thinking_chunks() {
+        let payload = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": [
+                        {"type": "thinking", "thinking": [{"type": "text", "text": "Add the numbers."}]},
+                        {"type": "text", "text": "2 + 2 = "},
+                        {"type": "text", "text": "4"}
+                    ]
+                }
+            }]
+        });
+
+        let completion =
+            normalize_response(to_openai_response(payload).expect("converts")).expect("normalizes");
+
+        assert_eq!(completion.text.as_deref(), Some("2 + 2 = 4"));
+        assert_eq!(completion.thinking, vec!["Add the numbers.".to_string()]);
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_MISTRAL_KEY", "tenant-key") };
+
+        let model = MistralModel::from_env_with_key("AGENT_SDK_TEST_MISTRAL_KEY", "mistral-small")
+            .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+    }
+
+    #[test]
+    fn describe_request_keeps_each_system_message() {
+        let model = MistralModel::new(MistralModelConfig::new("key", "mistral-large-latest"))
+            .expect("builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+
+        let description = model.describe_request(&messages, &ModelCallOptions::default());
+
+        assert_eq!(
+            description.system,
+            vec!["be brief".to_string(), "answer in French".to_string()]
+        );
+        assert_eq!(description.message_count, 1);
+    }
+}
@@ -1,19 +1,55 @@
 mod anthropic;
+mod azure;
+#[cfg(feature = "aws")]
+mod bedrock;
+mod cohere;
+mod deepseek;
 mod google;
 mod grok;
+mod mistral;
+mod openai;
+mod sse;
+#[cfg(test)]
+pub(crate) mod test_server;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
-use serde_json::Value;
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, NoProxy, Proxy, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
 
 use crate::error::ProviderError;
 
 pub use anthropic::{AnthropicModel, AnthropicModelConfig};
+pub use azure::{AzureOpenAiModel, AzureOpenAiModelConfig};
+#[cfg(feature = "aws")]
+pub use bedrock::{BedrockModel, BedrockModelConfig};
+pub use cohere::{CohereModel, CohereModelConfig};
+pub use deepseek::{DeepSeekModel, DeepSeekModelConfig};
 pub use google::{GoogleModel, GoogleModelConfig};
 pub use grok::{GrokModel, GrokModelConfig};
+pub use mistral::{MistralModel, MistralModelConfig};
+pub use openai::{OpenAiModel, OpenAiModelConfig};
 
-#[derive(Clone, Debug, PartialEq)]
-#[doc(hidden)]
+/// One message in a provider-neutral conversation.
+///
+/// Build example turns with [`ModelMessage::user`], [`ModelMessage::assistant`],
+/// [`ModelMessage::assistant_tool_call`], and [`ModelMessage::tool_result`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ModelMessage {
+    /// System instruction; may also appear mid-conversation as a steering note.
+    ///
+    /// Anthropic, Google and Bedrock fold every system message into the system block; OpenAI
+    /// and Grok keep them inline in conversation order.
     System(String),
     User(String),
     Assistant {
@@ -25,10 +61,99 @@ pub enum ModelMessage {
         tool_name: String,
         content: String,
         is_error: bool,
+        /// Images returned alongside `content`, for vision-capable models.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        images: Vec<ImageData>,
     },
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl ModelMessage {
+    /// System instruction.
+    pub fn system(text: impl Into<String>) -> Self {
+        Self::System(text.into())
+    }
+
+    /// User message.
+    pub fn user(text: impl Into<String>) -> Self {
+        Self::User(text.into())
+    }
+
+    /// Assistant reply without tool calls.
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self::Assistant {
+            content: Some(text.into()),
+            tool_calls: Vec::new(),
+        }
+    }
+
+    /// Assistant turn that makes one tool call.
+    pub fn assistant_tool_call(
+        id: impl Into<String>,
+        name: impl Into<String>,
+        arguments: Value,
+    ) -> Self {
+        Self::Assistant {
+            content: None,
+            tool_calls: vec![ModelToolCall {
+                id: id.into(),
+                name: name.into(),
+                arguments,
+            }],
+        }
+    }
+
+    /// Successful result for the tool call with `tool_call_id`.
+    pub fn tool_result(
+        tool_call_id: impl Into<String>,
+        tool_name: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Self {
+        Self::ToolResult {
+            tool_call_id: tool_call_id.into(),
+            tool_name: tool_name.into(),
+            content: content.into(),
+            is_error: false,
+            images: Vec::new(),
+        }
+    }
+}
+
+/// Base64-encoded image attached to a tool result.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageData {
+    /// MIME type such as `image/png` or `image/jpeg`.
+    pub media_type: String,
+    /// Base64-encoded image bytes (standard alphabet, no data-URL prefix).
+    pub data: String,
+}
+
+impl ImageData {
+    /// Creates image data from a MIME type and base64 payload.
+    pub fn new(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self {
+            media_type: media_type.into(),
+            data: data.into(),
+        }
+    }
+}
+
+/// Text note used by adapters that cannot send tool-result images.
+pub(crate) fn omitted_images_note(images: &[ImageData]) -> Option<String> {
+    if images.is_empty() {
+        return None;
+    }
+    let media_types = images
+        .iter()
+        .map(|image| image.media_type.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(format!(
+        "[{} image(s) omitted; this provider does not accept tool-result images: {media_types}]",
+        images.len()
+    ))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[doc(hidden)]
 pub struct ModelToolCall {
     pub id: String,
@@ -57,18 +182,302 @@ pub enum ModelToolChoice {
 #[doc(hidden)]
 pub struct ModelCompletion {
     pub text: Option<String>,
-    pub thinking: Option<String>,
+    /// Reasoning blocks in the order the model produced them.
+    pub thinking: Vec<String>,
+    /// Byte offset in `text` at which each `thinking` block was produced, parallel to
+    /// `thinking`; blocks without an entry are treated as coming before the text.
+    pub thinking_offsets: Vec<usize>,
     pub tool_calls: Vec<ModelToolCall>,
     pub usage: Option<ModelUsage>,
+    /// Provider response body as received; only set when [`ModelCallOptions::capture_raw`] is on.
+    pub raw: Option<Value>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[doc(hidden)]
 pub struct ModelUsage {
     pub input_tokens: u32,
     pub output_tokens: u32,
 }
 
+/// Rough token count for a message list (about four characters per token).
+///
+/// This is the default estimator used by the agent; swap it with
+/// `AgentBuilder::token_estimator` for a model-specific tokenizer.
+pub fn estimate_tokens(messages: &[ModelMessage]) -> usize {
+    let chars = messages
+        .iter()
+        .map(|message| match message {
+            ModelMessage::System(content) | ModelMessage::User(content) => content.len(),
+            ModelMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                content.as_ref().map_or(0, String::len)
+                    + tool_calls
+                        .iter()
+                        .map(|call| call.name.len() + call.arguments.to_string().len())
+                        .sum::<usize>()
+            }
+            ModelMessage::ToolResult { content, .. } => content.len(),
+        })
+        .sum::<usize>();
+    chars.div_ceil(4)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[doc(hidden)]
+pub enum ModelStreamChunk {
+    TextDelta(String),
+    /// Reasoning text; `index` keeps separate thinking blocks apart.
+    ThinkingDelta {
+        index: usize,
+        content: String,
+    },
+    ToolCallStart {
+        index: usize,
+        id: String,
+        name: String,
+    },
+    ToolCallInputDelta {
+        index: usize,
+        partial_json: String,
+    },
+    Usage(ModelUsage),
+    Completion(ModelCompletion),
+}
+
+/// Stream of incremental completion chunks returned by [`ChatModel::invoke_stream`].
+pub type ModelStream<'a> = BoxStream<'a, Result<ModelStreamChunk, ProviderError>>;
+
+/// Per-call overrides layered on top of an adapter's configuration.
+///
+/// Unset fields keep the adapter's configured values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelCallOptions {
+    /// Max output tokens for this call.
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature for this call.
+    pub temperature: Option<f32>,
+    /// Sequences that stop generation.
+    pub stop_sequences: Vec<String>,
+    /// Text the assistant turn should start with.
+    ///
+    /// Anthropic sends it as a trailing assistant message and returns it as part of the text.
+    /// Other adapters have no prefill, so they add a system instruction asking the model to
+    /// begin its reply with this text; adherence is best-effort.
+    pub assistant_prefill: Option<String>,
+    /// Keep the provider's response body in [`ModelCompletion::raw`] for debugging.
+    ///
+    /// Streaming OpenAI-compatible and Anthropic calls capture their stream events as a JSON
+    /// array. The Anthropic adapter re-serializes the SDK's parsed response or events rather
+    /// than the bytes on the wire.
+    pub capture_raw: bool,
+    /// Constrains the reply to JSON.
+    ///
+    /// OpenAI-compatible adapters send `response_format` and Google sends `responseMimeType`
+    /// with `responseSchema`. Anthropic, Cohere and Bedrock ignore it; the agent still parses
+    /// and validates the final reply when it comes from [`crate::QueryOptions`].
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// JSON output constraint for [`ModelCallOptions::response_format`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResponseFormat {
+    /// Any JSON object.
+    JsonObject,
+    /// JSON matching this schema.
+    JsonSchema(Value),
+}
+
+impl ModelCallOptions {
+    /// True when no override is set.
+    pub fn is_empty(&self) -> bool {
+        self.max_tokens.is_none()
+            && self.temperature.is_none()
+            && self.stop_sequences.is_empty()
+            && self.assistant_prefill.is_none()
+            && !self.capture_raw
+            && self.response_format.is_none()
+    }
+}
+
+/// How an adapter would lay out a request, returned by [`ChatModel::describe_request`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestDescription {
+    /// System text as the provider receives it, one entry per system block.
+    ///
+    /// Anthropic and Gemini get a single joined string; Bedrock gets one block per system
+    /// message; OpenAI-compatible, Grok, and Cohere adapters keep each system message as its
+    /// own `system` role message in place.
+    pub system: Vec<String>,
+    /// Non-system messages sent, after the adapter drops, merges, or adds messages.
+    pub message_count: usize,
+}
+
+impl RequestDescription {
+    /// System blocks joined with blank lines, or `None` when no system text is sent.
+    pub fn system_text(&self) -> Option<String> {
+        if self.system.is_empty() {
+            None
+        } else {
+            Some(self.system.join("\n\n"))
+        }
+    }
+}
+
+/// Makes tool-call ids unique within one completion so each result maps to exactly one call.
+///
+/// Empty ids become `call_{n}` and repeated ids get a `_2`, `_3`, ... suffix.
+pub(crate) fn ensure_unique_tool_call_ids(tool_calls: &mut [ModelToolCall]) {
+    let mut seen = HashSet::new();
+    for (position, call) in tool_calls.iter_mut().enumerate() {
+        if call.id.is_empty() {
+            call.id = format!("call_{}", position + 1);
+        }
+        if seen.contains(&call.id) {
+            let unique = (2..)
+                .map(|suffix| format!("{}_{suffix}", call.id))
+                .find(|candidate| !seen.contains(candidate))
+                .expect("unbounded suffixes");
+            call.id = unique;
+        }
+        seen.insert(call.id.clone());
+    }
+}
+
+/// Length of `parts` joined with `separator`: the offset in the completion text of a block the
+/// model produced right after them.
+pub(crate) fn joined_len(parts: &[String], separator: &str) -> usize {
+    parts.iter().map(String::len).sum::<usize>() + separator.len() * parts.len().saturating_sub(1)
+}
+
+/// Prepends a system instruction emulating `assistant_prefill` for adapters without prefill.
+pub(crate) fn with_prefill_instruction<'a>(
+    messages: &'a [ModelMessage],
+    options: &ModelCallOptions,
+) -> Cow<'a, [ModelMessage]> {
+    match options.assistant_prefill.as_deref() {
+        Some(prefill) if !prefill.is_empty() => {
+            let mut messages = messages.to_vec();
+            messages.insert(
+                0,
+                ModelMessage::System(format!(
+                    "Begin your reply with exactly the following text, then continue from it:\n{prefill}"
+                )),
+            );
+            Cow::Owned(messages)
+        }
+        _ => Cow::Borrowed(messages),
+    }
+}
+
+/// Default per-request timeout for provider HTTP clients.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Reads an API key from `name`, treating a blank value as unset.
+pub(crate) fn env_var(name: &str) -> Result<String, ProviderError> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| ProviderError::request(format!("{name} is not set")))
+}
+
+/// `reasoning_effort` values accepted by the OpenAI-compatible adapters.
+const REASONING_EFFORTS: &[&str] = &["low", "medium", "high"];
+
+/// Rejects a configured `reasoning_effort` outside `low`, `medium`, and `high`.
+pub(crate) fn validate_reasoning_effort(effort: Option<&str>) -> Result<(), ProviderError> {
+    match effort {
+        Some(effort) if !REASONING_EFFORTS.contains(&effort) => {
+            Err(ProviderError::request(format!(
+                "invalid reasoning_effort '{effort}': expected one of {}",
+                REASONING_EFFORTS.join(", ")
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Builds the HTTP client shared by the `reqwest`-based adapters.
+///
+/// `extra_headers` become default headers on every request, so invalid names or values are
+/// reported here instead of on the first call. An explicit `proxy` replaces the environment
+/// proxy settings but still honors `NO_PROXY`.
+pub(crate) fn http_client(
+    user_agent: Option<&str>,
+    extra_headers: &HashMap<String, String>,
+    proxy: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<Client, ProviderError> {
+    let mut headers = HeaderMap::with_capacity(extra_headers.len());
+    for (name, value) in extra_headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| {
+            ProviderError::request(format!("invalid header name '{name}': {err}"))
+        })?;
+        let value = HeaderValue::from_str(value).map_err(|err| {
+            ProviderError::request(format!("invalid value for header '{name}': {err}"))
+        })?;
+        headers.insert(name, value);
+    }
+
+    let mut builder = Client::builder().default_headers(headers);
+    if let Some(user_agent) = user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(proxy) = proxy {
+        let proxy = Proxy::all(proxy)
+            .map_err(|err| ProviderError::request(format!("invalid proxy url '{proxy}': {err}")))?
+            .no_proxy(NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+    if let Some(timeout) = timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder
+        .build()
+        .map_err(|err| ProviderError::request(err.to_string()))
+}
+
+/// Maps a failure while reading a success response; timeouts stay retryable request errors.
+pub(crate) fn response_body_error(err: reqwest::Error, status: u16) -> ProviderError {
+    if err.is_timeout() {
+        ProviderError::request(err.to_string())
+    } else {
+        invalid_body(err.to_string(), status)
+    }
+}
+
+fn invalid_body(message: String, status: u16) -> ProviderError {
+    ProviderError::Response {
+        message,
+        status: Some(status),
+        provider_message: None,
+    }
+}
+
+/// Parses a success response body, also returning it as JSON when `capture_raw` is set.
+pub(crate) async fn parse_response<T: DeserializeOwned>(
+    response: Response,
+    capture_raw: bool,
+) -> Result<(T, Option<Value>), ProviderError> {
+    let status = response.status().as_u16();
+    if !capture_raw {
+        let payload = response
+            .json::<T>()
+            .await
+            .map_err(|err| response_body_error(err, status))?;
+        return Ok((payload, None));
+    }
+
+    let raw = response
+        .json::<Value>()
+        .await
+        .map_err(|err| response_body_error(err, status))?;
+    let payload = T::deserialize(&raw).map_err(|err| invalid_body(err.to_string(), status))?;
+    Ok((payload, Some(raw)))
+}
+
 #[async_trait]
 /// Provider abstraction used by [`crate::Agent`].
 pub trait ChatModel: Send + Sync {
@@ -79,4 +488,405 @@ pub trait ChatModel: Send + Sync {
         tools: &[ModelToolDefinition],
         tool_choice: ModelToolChoice,
     ) -> Result<ModelCompletion, ProviderError>;
+
+    /// Invokes one model completion step and streams chunks as they arrive.
+    ///
+    /// The default implementation calls [`ChatModel::invoke`] and emits a single
+    /// [`ModelStreamChunk::Completion`] chunk.
+    fn invoke_stream<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> ModelStream<'a> {
+        Box::pin(stream::once(async move {
+            self.invoke(messages, tools, tool_choice)
+                .await
+                .map(ModelStreamChunk::Completion)
+        }))
+    }
+
+    /// Model identifier used in diagnostics such as `tracing` spans.
+    fn model_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Describes the request this adapter would send for `messages`, without sending it.
+    ///
+    /// Useful for seeing how each provider resolves system messages. The default
+    /// implementation ignores `options`, treats each non-empty system message as one block,
+    /// and counts every other message.
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let _ = options;
+        let mut description = RequestDescription::default();
+        for message in messages {
+            match message {
+                ModelMessage::System(content) if content.is_empty() => {}
+                ModelMessage::System(content) => description.system.push(content.clone()),
+                _ => description.message_count += 1,
+            }
+        }
+        description
+    }
+
+    /// Like [`ChatModel::invoke`], with per-call overrides.
+    ///
+    /// The default implementation ignores `options`; the built-in adapters honor them.
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let _ = options;
+        self.invoke(messages, tools, tool_choice).await
+    }
+
+    /// Like [`ChatModel::invoke_stream`], with per-call overrides.
+    ///
+    /// Empty options use [`ChatModel::invoke_stream`]; otherwise the default implementation
+    /// calls [`ChatModel::invoke_with_options`] and emits a single completion chunk.
+    fn invoke_stream_with_options<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &'a ModelCallOptions,
+    ) -> ModelStream<'a> {
+        if options.is_empty() {
+            return self.invoke_stream(messages, tools, tool_choice);
+        }
+        Box::pin(stream::once(async move {
+            self.invoke_with_options(messages, tools, tool_choice, options)
+                .await
+                .map(ModelStreamChunk::Completion)
+        }))
+    }
+}
+
+#[derive(Debug, Default)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    input_json: String,
+}
+
+/// Assembles streamed chunks back into one [`ModelCompletion`].
+#[derive(Debug, Default)]
+pub(crate) struct ModelStreamAccumulator {
+    text: String,
+    thinking: BTreeMap<usize, String>,
+    tool_calls: BTreeMap<usize, PartialToolCall>,
+    usage: Option<ModelUsage>,
+    completion: Option<ModelCompletion>,
+}
+
+impl ModelStreamAccumulator {
+    pub(crate) fn push(&mut self, chunk: ModelStreamChunk) {
+        match chunk {
+            ModelStreamChunk::TextDelta(delta) => self.text.push_str(&delta),
+            ModelStreamChunk::ThinkingDelta { index, content } => {
+                self.thinking.entry(index).or_default().push_str(&content)
+            }
+            ModelStreamChunk::ToolCallStart { index, id, name } => {
+                let call = self.tool_calls.entry(index).or_default();
+                call.id = id;
+                call.name = name;
+            }
+            ModelStreamChunk::ToolCallInputDelta {
+                index,
+                partial_json,
+            } => self
+                .tool_calls
+                .entry(index)
+                .or_default()
+                .input_json
+                .push_str(&partial_json),
+            ModelStreamChunk::Usage(usage) => self.usage = Some(usage),
+            ModelStreamChunk::Completion(completion) => self.completion = Some(completion),
+        }
+    }
+
+    /// Returns the assembled completion and, keyed by tool-call id, the parse errors of
+    /// streamed tool calls whose input was not valid JSON; those calls get `{}` as arguments.
+    pub(crate) fn finish(self) -> (ModelCompletion, HashMap<String, String>) {
+        let mut completion = self.completion.unwrap_or_default();
+
+        completion.thinking.extend(
+            self.thinking
+                .into_values()
+                .filter(|block| !block.is_empty()),
+        );
+
+        if !self.text.is_empty() {
+            completion.text = Some(match completion.text {
+                Some(existing) => format!("{existing}{}", self.text),
+                None => self.text,
+            });
+        }
+
+        let mut malformed = Vec::new();
+        for call in self.tool_calls.into_values() {
+            let arguments = if call.input_json.trim().is_empty() {
+                json!({})
+            } else {
+                serde_json::from_str::<Value>(&call.input_json).unwrap_or_else(|err| {
+                    malformed.push((
+                        completion.tool_calls.len(),
+                        format!(
+                            "streamed arguments for tool '{}' are not valid JSON ({err}): {}",
+                            call.name, call.input_json
+                        ),
+                    ));
+                    json!({})
+                })
+            };
+
+            completion.tool_calls.push(ModelToolCall {
+                id: call.id,
+                name: call.name,
+                arguments,
+            });
+        }
+
+        if self.usage.is_some() {
+            completion.usage = self.usage;
+        }
+        ensure_unique_tool_call_ids(&mut completion.tool_calls);
+
+        let malformed = malformed
+            .into_iter()
+            .map(|(position, error)| (completion.tool_calls[position].id.clone(), error))
+            .collect();
+        (completion, malformed)
+    }
+}
+
+/// One model invocation observed by an `AgentBuilder::on_model_io` hook.
+pub struct ModelIo<'a> {
+    /// Messages sent to the provider.
+    pub messages: &'a [ModelMessage],
+    /// Tool definitions sent to the provider.
+    pub tools: &'a [ModelToolDefinition],
+    /// Tool choice sent to the provider.
+    pub tool_choice: &'a ModelToolChoice,
+    /// Assembled completion, or the provider error.
+    pub result: Result<&'a ModelCompletion, &'a ProviderError>,
+}
+
+pub(crate) type ModelIoHook = dyn Fn(&ModelIo<'_>) + Send + Sync;
+
+/// Decorator that reports every invocation of the wrapped model to a hook.
+pub(crate) struct ObservedModel {
+    inner: Arc<dyn ChatModel>,
+    hook: Arc<ModelIoHook>,
+}
+
+impl ObservedModel {
+    pub(crate) fn new(inner: Arc<dyn ChatModel>, hook: Arc<ModelIoHook>) -> Self {
+        Self { inner, hook }
+    }
+
+    fn report(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: &ModelToolChoice,
+        result: Result<&ModelCompletion, &ProviderError>,
+    ) {
+        (self.hook)(&ModelIo {
+            messages,
+            tools,
+            tool_choice,
+            result,
+        });
+    }
+
+    /// Passes chunks through unchanged and reports the assembled completion once the stream ends.
+    fn observe_stream<'a>(
+        &'a self,
+        mut inner: ModelStream<'a>,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> ModelStream<'a> {
+        Box::pin(async_stream::stream! {
+            let mut accumulator = ModelStreamAccumulator::default();
+            while let Some(chunk) = inner.next().await {
+                match &chunk {
+                    Ok(chunk) => accumulator.push(chunk.clone()),
+                    Err(err) => {
+                        self.report(messages, tools, &tool_choice, Err(err));
+                        yield chunk;
+                        return;
+                    }
+                }
+                yield chunk;
+            }
+
+            let (completion, _) = accumulator.finish();
+            self.report(messages, tools, &tool_choice, Ok(&completion));
+        })
+    }
+}
+
+#[async_trait]
+impl ChatModel for ObservedModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let result = self
+            .inner
+            .invoke(messages, tools, tool_choice.clone())
+            .await;
+        self.report(messages, tools, &tool_choice, result.as_ref());
+        result
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> ModelStream<'a> {
+        let inner = self
+            .inner
+            .invoke_stream(messages, tools, tool_choice.clone());
+        self.observe_stream(inner, messages, tools, tool_choice)
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        self.inner.model_id()
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        self.inner.describe_request(messages, options)
+    }
+
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let result = self
+            .inner
+            .invoke_with_options(messages, tools, tool_choice.clone(), options)
+            .await;
+        self.report(messages, tools, &tool_choice, result.as_ref());
+        result
+    }
+
+    fn invoke_stream_with_options<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &'a ModelCallOptions,
+    ) -> ModelStream<'a> {
+        let inner =
+            self.inner
+                .invoke_stream_with_options(messages, tools, tool_choice.clone(), options);
+        self.observe_stream(inner, messages, tools, tool_choice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn accumulator_assembles_deltas_and_tool_calls() {
+        let mut accumulator = ModelStreamAccumulator::default();
+        accumulator.push(ModelStreamChunk::ThinkingDelta {
+            index: 0,
+            content: "plan".to_string(),
+        });
+        accumulator.push(ModelStreamChunk::TextDelta("hel".to_string()));
+        accumulator.push(ModelStreamChunk::TextDelta("lo".to_string()));
+        accumulator.push(ModelStreamChunk::ToolCallStart {
+            index: 1,
+            id: "call_1".to_string(),
+            name: "lookup".to_string(),
+        });
+        accumulator.push(ModelStreamChunk::ToolCallInputDelta {
+            index: 1,
+            partial_json: "{\"q\":".to_string(),
+        });
+        accumulator.push(ModelStreamChunk::ToolCallInputDelta {
+            index: 1,
+            partial_json: "\"rust\"}".to_string(),
+        });
+        accumulator.push(ModelStreamChunk::Usage(ModelUsage {
+            input_tokens: 3,
+            output_tokens: 4,
+        }));
+
+        let (completion, _) = accumulator.finish();
+        assert_eq!(completion.text.as_deref(), Some("hello"));
+        assert_eq!(completion.thinking, vec!["plan".to_string()]);
+        assert_eq!(completion.tool_calls.len(), 1);
+        assert_eq!(completion.tool_calls[0].id, "call_1");
+        assert_eq!(completion.tool_calls[0].arguments, json!({"q": "rust"}));
+        assert_eq!(
+            completion.usage,
+            Some(ModelUsage {
+                input_tokens: 3,
+                output_tokens: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn accumulator_reports_invalid_tool_json_per_call() {
+        let mut accumulator = ModelStreamAccumulator::default();
+        accumulator.push(ModelStreamChunk::ToolCallStart {
+            index: 0,
+            id: "call_1".to_string(),
+            name: "lookup".to_string(),
+        });
+        accumulator.push(ModelStreamChunk::ToolCallInputDelta {
+            index: 0,
+            partial_json: "{\"q\":".to_string(),
+        });
+
+        let (completion, malformed) = accumulator.finish();
+        assert_eq!(completion.tool_calls[0].arguments, json!({}));
+        assert!(malformed["call_1"].contains("not valid JSON"));
+    }
+
+    #[test]
+    fn accumulator_suffixes_duplicate_tool_call_ids() {
+        let mut accumulator = ModelStreamAccumulator::default();
+        for (index, id) in ["call_1", "call_1", ""].into_iter().enumerate() {
+            accumulator.push(ModelStreamChunk::ToolCallStart {
+                index,
+                id: id.to_string(),
+                name: "lookup".to_string(),
+            });
+        }
+
+        let (completion, _) = accumulator.finish();
+        let ids = completion
+            .tool_calls
+            .iter()
+            .map(|call| call.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["call_1", "call_1_2", "call_3"]);
+    }
 }
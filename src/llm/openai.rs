@@ -0,0 +1,1444 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::error::ProviderError;
+use crate::llm::sse::sse_events;
+use crate::llm::{
+    ChatModel, DEFAULT_REQUEST_TIMEOUT, ModelCallOptions, ModelCompletion, ModelMessage,
+    ModelStream, ModelStreamChunk, ModelToolCall, ModelToolChoice, ModelToolDefinition, ModelUsage,
+    RequestDescription, ResponseFormat, env_var, http_client, omitted_images_note, parse_response,
+    validate_reasoning_effort, with_prefill_instruction,
+};
+
+const DEFAULT_API_BASE_URL: &str = "https://api.openai.com/v1";
+const EMPTY_USER_CONTENT_FALLBACK: &str = " ";
+
+#[derive(Debug, Clone)]
+/// Runtime configuration for [`OpenAiModel`].
+pub struct OpenAiModelConfig {
+    /// OpenAI (or compatible provider) API key.
+    pub api_key: String,
+    /// Model id (for example `gpt-4.1`).
+    pub model: String,
+    /// Optional base URL override for vLLM, Together, or other compatible endpoints.
+    pub api_base_url: Option<String>,
+    /// Optional sampling temperature.
+    pub temperature: Option<f32>,
+    /// Optional nucleus sampling parameter.
+    pub top_p: Option<f32>,
+    /// Optional max output tokens.
+    pub max_tokens: Option<u32>,
+    /// Optional `reasoning_effort` (`low`, `medium`, or `high`) for reasoning models such as o-series;
+    /// checked by [`OpenAiModel::new`].
+    pub reasoning_effort: Option<String>,
+    /// Optional `user-agent` header value; reqwest's default is used when unset.
+    pub user_agent: Option<String>,
+    /// Extra headers sent on every request, for example API gateway tags.
+    pub extra_headers: HashMap<String, String>,
+    /// Optional proxy URL for every request; when unset, `HTTPS_PROXY`, `HTTP_PROXY`, and
+    /// `NO_PROXY` from the environment apply.
+    pub proxy: Option<String>,
+    /// Optional limit on one whole HTTP request, including reading the response body.
+    /// Defaults to [`DEFAULT_REQUEST_TIMEOUT`](crate::llm::DEFAULT_REQUEST_TIMEOUT).
+    pub request_timeout: Option<Duration>,
+}
+
+impl OpenAiModelConfig {
+    /// Creates a config with sensible defaults.
+    pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            model: model.into(),
+            api_base_url: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: Some(4096),
+            reasoning_effort: None,
+            user_agent: None,
+            extra_headers: HashMap::new(),
+            proxy: None,
+            request_timeout: Some(DEFAULT_REQUEST_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// OpenAI-compatible chat completions adapter implementing [`ChatModel`].
+///
+/// [`ChatModel::invoke_stream`] requests `"stream": true` and forwards text, reasoning, and
+/// tool-call argument fragments as they arrive.
+pub struct OpenAiModel {
+    client: Client,
+    config: OpenAiModelConfig,
+}
+
+impl OpenAiModel {
+    /// Creates a model adapter from explicit config.
+    pub fn new(config: OpenAiModelConfig) -> Result<Self, ProviderError> {
+        validate_reasoning_effort(config.reasoning_effort.as_deref())?;
+        let client = http_client(
+            config.user_agent.as_deref(),
+            &config.extra_headers,
+            config.proxy.as_deref(),
+            config.request_timeout,
+        )?;
+
+        Ok(Self { client, config })
+    }
+
+    /// Creates a model adapter using `OPENAI_API_KEY` and optional `OPENAI_BASE_URL`.
+    pub fn from_env(model: impl Into<String>) -> Result<Self, ProviderError> {
+        Self::from_env_with_key("OPENAI_API_KEY", model)
+    }
+
+    /// Like [`OpenAiModel::from_env`], reading the API key from the `var_name` environment
+    /// variable instead.
+    pub fn from_env_with_key(
+        var_name: &str,
+        model: impl Into<String>,
+    ) -> Result<Self, ProviderError> {
+        let mut config = OpenAiModelConfig::new(env_var(var_name)?, model);
+        config.api_base_url = std::env::var("OPENAI_BASE_URL")
+            .ok()
+            .filter(|url| !url.trim().is_empty());
+        Self::new(config)
+    }
+
+    fn endpoint(&self) -> String {
+        let base = self
+            .config
+            .api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_BASE_URL)
+            .trim_end_matches('/');
+        format!("{base}/chat/completions")
+    }
+
+    /// Sends a `"stream": true` request and maps its server-sent events to stream chunks.
+    ///
+    /// With `capture_raw`, the decoded event payloads are attached as a JSON array once the
+    /// stream ends.
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> ModelStream<'a> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.config);
+        apply_call_options(&mut request, options);
+        request.stream = true;
+        request.stream_options = Some(json!({"include_usage": true}));
+        let capture_raw = options.capture_raw;
+
+        Box::pin(async_stream::try_stream! {
+            let response = self
+                .client
+                .post(self.endpoint())
+                .header("authorization", format!("Bearer {}", self.config.api_key))
+                .header("content-type", "application/json")
+                .json(&request)
+                .send()
+                .await
+                .map_err(|err| ProviderError::request(err.to_string()))?;
+            if !response.status().is_success() {
+                Err(extract_api_error(response).await)?;
+                return;
+            }
+
+            let mut raw_events = Vec::new();
+            let events = sse_events(response);
+            futures_util::pin_mut!(events);
+            while let Some(event) = events.next().await {
+                let event = event?;
+                if event.data == "[DONE]" {
+                    break;
+                }
+                let value = serde_json::from_str::<Value>(&event.data).map_err(|err| {
+                    ProviderError::response(format!(
+                        "openai stream event is not valid JSON ({err}): {}",
+                        event.data
+                    ))
+                })?;
+                for chunk in stream_chunks(&value)? {
+                    yield chunk;
+                }
+                if capture_raw {
+                    raw_events.push(value);
+                }
+            }
+
+            if capture_raw {
+                yield ModelStreamChunk::Completion(ModelCompletion {
+                    raw: Some(Value::Array(raw_events)),
+                    ..ModelCompletion::default()
+                });
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl ChatModel for OpenAiModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.invoke_with_options(messages, tools, tool_choice, &ModelCallOptions::default())
+            .await
+    }
+
+    fn model_id(&self) -> Option<&str> {
+        Some(&self.config.model)
+    }
+
+    fn describe_request(
+        &self,
+        messages: &[ModelMessage],
+        options: &ModelCallOptions,
+    ) -> RequestDescription {
+        let messages = with_prefill_instruction(messages, options);
+        describe_chat_request(&build_request(
+            &messages,
+            &[],
+            ModelToolChoice::Auto,
+            &self.config,
+        ))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "openai.invoke",
+            skip_all,
+            fields(model = %self.config.model),
+            err(level = "debug")
+        )
+    )]
+    async fn invoke_with_options(
+        &self,
+        messages: &[ModelMessage],
+        tools: &[ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &ModelCallOptions,
+    ) -> Result<ModelCompletion, ProviderError> {
+        let messages = with_prefill_instruction(messages, options);
+        let mut request = build_request(&messages, tools, tool_choice, &self.config);
+        apply_call_options(&mut request, options);
+
+        let response = self
+            .client
+            .post(self.endpoint())
+            .header("authorization", format!("Bearer {}", self.config.api_key))
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| ProviderError::request(err.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(extract_api_error(response).await);
+        }
+
+        let (payload, raw) =
+            parse_response::<OpenAiChatCompletionResponse>(response, options.capture_raw).await?;
+
+        let mut completion = normalize_response(payload)?;
+        completion.raw = raw;
+        Ok(completion)
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+    ) -> ModelStream<'a> {
+        self.stream_completion(messages, tools, tool_choice, &ModelCallOptions::default())
+    }
+
+    fn invoke_stream_with_options<'a>(
+        &'a self,
+        messages: &'a [ModelMessage],
+        tools: &'a [ModelToolDefinition],
+        tool_choice: ModelToolChoice,
+        options: &'a ModelCallOptions,
+    ) -> ModelStream<'a> {
+        self.stream_completion(messages, tools, tool_choice, options)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct OpenAiChatCompletionRequest {
+    model: String,
+    messages: Vec<OpenAiRequestMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAiToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<OpenAiToolChoicePayload>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+enum OpenAiRequestMessage {
+    System {
+        content: String,
+    },
+    User {
+        content: String,
+    },
+    Assistant {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tool_calls: Option<Vec<OpenAiToolCall>>,
+    },
+    Tool {
+        tool_call_id: String,
+        content: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiToolDefinition {
+    #[serde(rename = "type")]
+    type_: String,
+    function: OpenAiToolFunctionDefinition,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiToolFunctionDefinition {
+    name: String,
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum OpenAiToolChoicePayload {
+    Mode(String),
+    Specific {
+        #[serde(rename = "type")]
+        type_: String,
+        function: OpenAiToolChoiceFunction,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiToolChoiceFunction {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct OpenAiToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    type_: String,
+    function: OpenAiToolCallFunction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct OpenAiToolCallFunction {
+    name: String,
+    arguments: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct OpenAiChatCompletionResponse {
+    #[serde(default)]
+    choices: Vec<OpenAiChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiChoice {
+    message: Option<OpenAiAssistantMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiAssistantMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCall>,
+    #[serde(default)]
+    reasoning_content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamEvent {
+    #[serde(default)]
+    choices: Vec<OpenAiStreamChoice>,
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamChoice {
+    delta: Option<OpenAiStreamDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamDelta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<OpenAiToolCallDelta>,
+    #[serde(default)]
+    reasoning_content: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiToolCallDelta {
+    index: usize,
+    id: Option<String>,
+    function: Option<OpenAiToolCallFunctionDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiToolCallFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiErrorEnvelope {
+    error: OpenAiApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiApiError {
+    message: Option<String>,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    code: Option<Value>,
+}
+
+pub(super) fn build_request(
+    messages: &[ModelMessage],
+    tools: &[ModelToolDefinition],
+    tool_choice: ModelToolChoice,
+    config: &OpenAiModelConfig,
+) -> OpenAiChatCompletionRequest {
+    let request_messages = ensure_non_empty_messages(to_openai_messages(messages));
+
+    let tools_payload = if tools.is_empty() {
+        None
+    } else {
+        Some(
+            tools
+                .iter()
+                .map(|tool| OpenAiToolDefinition {
+                    type_: "function".to_string(),
+                    function: OpenAiToolFunctionDefinition {
+                        name: tool.name.clone(),
+                        description: tool.description.clone(),
+                        parameters: tool.parameters.clone(),
+                    },
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let tool_choice_payload = if tools.is_empty() {
+        None
+    } else {
+        Some(match tool_choice {
+            ModelToolChoice::Auto => OpenAiToolChoicePayload::Mode("auto".to_string()),
+            ModelToolChoice::Required => OpenAiToolChoicePayload::Mode("required".to_string()),
+            ModelToolChoice::None => OpenAiToolChoicePayload::Mode("none".to_string()),
+            ModelToolChoice::Tool(name) => OpenAiToolChoicePayload::Specific {
+                type_: "function".to_string(),
+                function: OpenAiToolChoiceFunction { name },
+            },
+        })
+    };
+
+    OpenAiChatCompletionRequest {
+        model: config.model.clone(),
+        messages: request_messages,
+        tools: tools_payload,
+        tool_choice: tool_choice_payload,
+        temperature: config.temperature,
+        top_p: config.top_p,
+        max_tokens: config.max_tokens,
+        reasoning_effort: config.reasoning_effort.clone(),
+        stop: Vec::new(),
+        response_format: None,
+        stream: false,
+        stream_options: None,
+    }
+}
+
+fn to_openai_messages(messages: &[ModelMessage]) -> Vec<OpenAiRequestMessage> {
+    let mut request_messages = Vec::new();
+
+    for message in messages {
+        match message {
+            ModelMessage::System(content) => {
+                if content.is_empty() {
+                    continue;
+                }
+                request_messages.push(OpenAiRequestMessage::System {
+                    content: content.clone(),
+                });
+            }
+            ModelMessage::User(content) => {
+                if content.is_empty() {
+                    continue;
+                }
+                request_messages.push(OpenAiRequestMessage::User {
+                    content: content.clone(),
+                });
+            }
+            ModelMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let serialized_tool_calls = tool_calls
+                    .iter()
+                    .map(|tool_call| OpenAiToolCall {
+                        id: tool_call.id.clone(),
+                        type_: "function".to_string(),
+                        function: OpenAiToolCallFunction {
+                            name: tool_call.name.clone(),
+                            arguments: tool_call.arguments.to_string(),
+                        },
+                    })
+                    .collect::<Vec<_>>();
+
+                let assistant_content = content.as_ref().filter(|text| !text.is_empty()).cloned();
+                if assistant_content.is_none() && serialized_tool_calls.is_empty() {
+                    continue;
+                }
+
+                request_messages.push(OpenAiRequestMessage::Assistant {
+                    content: assistant_content,
+                    tool_calls: if serialized_tool_calls.is_empty() {
+                        None
+                    } else {
+                        Some(serialized_tool_calls)
+                    },
+                });
+            }
+            ModelMessage::ToolResult {
+                tool_call_id,
+                tool_name: _,
+                content,
+                is_error,
+                images,
+            } => {
+                let mut rendered = if *is_error {
+                    format!("Error: {content}")
+                } else {
+                    content.clone()
+                };
+                if let Some(note) = omitted_images_note(images) {
+                    rendered.push('\n');
+                    rendered.push_str(&note);
+                }
+
+                request_messages.push(OpenAiRequestMessage::Tool {
+                    tool_call_id: tool_call_id.clone(),
+                    content: rendered,
+                });
+            }
+        }
+    }
+
+    request_messages
+}
+
+fn ensure_non_empty_messages(mut messages: Vec<OpenAiRequestMessage>) -> Vec<OpenAiRequestMessage> {
+    let mut normalized = Vec::with_capacity(messages.len().saturating_add(1));
+    let mut pending_tool_call_ids = Vec::<String>::new();
+
+    for message in messages.drain(..) {
+        match message {
+            OpenAiRequestMessage::System { content } => {
+                pending_tool_call_ids.clear();
+                normalized.push(OpenAiRequestMessage::System { content });
+            }
+            OpenAiRequestMessage::User { content } => {
+                pending_tool_call_ids.clear();
+                normalized.push(OpenAiRequestMessage::User { content });
+            }
+            OpenAiRequestMessage::Assistant {
+                content,
+                tool_calls,
+            } => {
+                pending_tool_call_ids.clear();
+                if let Some(calls) = &tool_calls {
+                    pending_tool_call_ids.extend(calls.iter().map(|call| call.id.clone()));
+                }
+                normalized.push(OpenAiRequestMessage::Assistant {
+                    content,
+                    tool_calls,
+                });
+            }
+            OpenAiRequestMessage::Tool {
+                tool_call_id,
+                content,
+            } => {
+                if let Some(position) = pending_tool_call_ids
+                    .iter()
+                    .position(|id| id == &tool_call_id)
+                {
+                    pending_tool_call_ids.remove(position);
+                    normalized.push(OpenAiRequestMessage::Tool {
+                        tool_call_id,
+                        content,
+                    });
+                }
+            }
+        }
+    }
+
+    if normalized.is_empty() {
+        normalized.push(OpenAiRequestMessage::User {
+            content: EMPTY_USER_CONTENT_FALLBACK.to_string(),
+        });
+        return normalized;
+    }
+
+    let starts_with_valid_role = matches!(
+        normalized.first(),
+        Some(OpenAiRequestMessage::System { .. } | OpenAiRequestMessage::User { .. })
+    );
+    if !starts_with_valid_role {
+        normalized.insert(
+            0,
+            OpenAiRequestMessage::User {
+                content: EMPTY_USER_CONTENT_FALLBACK.to_string(),
+            },
+        );
+    }
+
+    normalized
+}
+
+/// Splits a chat-completions request into `system` role messages and everything else.
+pub(super) fn describe_chat_request(request: &OpenAiChatCompletionRequest) -> RequestDescription {
+    let mut description = RequestDescription::default();
+    for message in &request.messages {
+        match message {
+            OpenAiRequestMessage::System { content } => description.system.push(content.clone()),
+            _ => description.message_count += 1,
+        }
+    }
+    description
+}
+
+pub(super) fn apply_call_options(
+    request: &mut OpenAiChatCompletionRequest,
+    options: &ModelCallOptions,
+) {
+    if let Some(max_tokens) = options.max_tokens {
+        request.max_tokens = Some(max_tokens);
+    }
+    if let Some(temperature) = options.temperature {
+        request.temperature = Some(temperature);
+    }
+    if !options.stop_sequences.is_empty() {
+        request.stop = options.stop_sequences.clone();
+    }
+    if let Some(format) = &options.response_format {
+        request.response_format = Some(response_format_payload(format));
+    }
+}
+
+/// Builds the `response_format` object shared by OpenAI-compatible APIs.
+pub(super) fn response_format_payload(format: &ResponseFormat) -> Value {
+    match format {
+        ResponseFormat::JsonObject => json!({"type": "json_object"}),
+        ResponseFormat::JsonSchema(schema) => json!({
+            "type": "json_schema",
+            "json_schema": {"name": "response", "schema": schema}
+        }),
+    }
+}
+
+pub(super) fn normalize_response(
+    response: OpenAiChatCompletionResponse,
+) -> Result<ModelCompletion, ProviderError> {
+    let choice =
+        response.choices.into_iter().next().ok_or_else(|| {
+            ProviderError::response("openai response missing choices".to_string())
+        })?;
+
+    let message = choice.message.ok_or_else(|| {
+        ProviderError::response("openai response missing choice message".to_string())
+    })?;
+
+    let mut tool_calls = Vec::new();
+    for tool_call in message.tool_calls {
+        let arguments = if tool_call.function.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str::<Value>(&tool_call.function.arguments).map_err(|err| {
+                ProviderError::response(format!(
+                    "openai tool call arguments for '{}' are not valid JSON: {err}",
+                    tool_call.function.name
+                ))
+            })?
+        };
+
+        tool_calls.push(ModelToolCall {
+            id: tool_call.id,
+            name: tool_call.function.name,
+            arguments,
+        });
+    }
+
+    // OpenAI already counts reasoning tokens inside `completion_tokens`.
+    let usage = response.usage.map(|usage| ModelUsage {
+        input_tokens: usage.prompt_tokens.unwrap_or(0),
+        output_tokens: usage.completion_tokens.unwrap_or(0),
+    });
+
+    Ok(ModelCompletion {
+        text: message.content.filter(|text| !text.is_empty()),
+        thinking: message
+            .reasoning_content
+            .filter(|text| !text.is_empty())
+            .into_iter()
+            .collect(),
+        tool_calls,
+        thinking_offsets: Vec::new(),
+        usage,
+        raw: None,
+    })
+}
+
+/// Maps one decoded `chat.completion.chunk` event to stream chunks.
+///
+/// A tool call starts on the fragment that carries its id and name; later fragments for the
+/// same `index` only append argument text.
+fn stream_chunks(value: &Value) -> Result<Vec<ModelStreamChunk>, ProviderError> {
+    if let Some(error) = value.get("error") {
+        return Err(match error.get("message").and_then(Value::as_str) {
+            Some(message) => {
+                ProviderError::stream(format!("openai stream error: {message}"), message)
+            }
+            None => ProviderError::response("openai stream error: unknown openai stream error"),
+        });
+    }
+    let event = OpenAiStreamEvent::deserialize(value)
+        .map_err(|err| ProviderError::response(format!("invalid openai stream event: {err}")))?;
+
+    let mut chunks = Vec::new();
+    if let Some(delta) = event
+        .choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.delta)
+    {
+        if let Some(thinking) = delta.reasoning_content.filter(|text| !text.is_empty()) {
+            chunks.push(ModelStreamChunk::ThinkingDelta {
+                index: 0,
+                content: thinking,
+            });
+        }
+        if let Some(text) = delta.content.filter(|text| !text.is_empty()) {
+            chunks.push(ModelStreamChunk::TextDelta(text));
+        }
+        for call in delta.tool_calls {
+            let function = call.function.unwrap_or(OpenAiToolCallFunctionDelta {
+                name: None,
+                arguments: None,
+            });
+            if let Some(id) = call.id.filter(|id| !id.is_empty())
+                && let Some(name) = function.name.filter(|name| !name.is_empty())
+            {
+                chunks.push(ModelStreamChunk::ToolCallStart {
+                    index: call.index,
+                    id,
+                    name,
+                });
+            }
+            if let Some(arguments) = function.arguments.filter(|text| !text.is_empty()) {
+                chunks.push(ModelStreamChunk::ToolCallInputDelta {
+                    index: call.index,
+                    partial_json: arguments,
+                });
+            }
+        }
+    }
+    if let Some(usage) = event.usage {
+        chunks.push(ModelStreamChunk::Usage(ModelUsage {
+            input_tokens: usage.prompt_tokens.unwrap_or(0),
+            output_tokens: usage.completion_tokens.unwrap_or(0),
+        }));
+    }
+    Ok(chunks)
+}
+
+pub(super) async fn extract_api_error(response: reqwest::Response) -> ProviderError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+
+    if let Ok(parsed) = serde_json::from_str::<OpenAiErrorEnvelope>(&body) {
+        let code = parsed
+            .error
+            .code
+            .map(|value| match value {
+                Value::String(value) => value,
+                other => other.to_string(),
+            })
+            .unwrap_or_else(|| status.as_u16().to_string());
+        let error_type = parsed
+            .error
+            .type_
+            .unwrap_or_else(|| status.to_string().to_uppercase());
+        let provider_message = parsed.error.message;
+        let message = provider_message
+            .as_deref()
+            .unwrap_or("unknown openai api error");
+
+        return ProviderError::api(
+            status.as_u16(),
+            format!("openai api error {code} {error_type}: {message}"),
+            provider_message,
+        );
+    }
+
+    ProviderError::http(
+        status.as_u16(),
+        if body.is_empty() {
+            format!("openai api request failed ({status})")
+        } else {
+            format!("openai api request failed ({status}): {body}")
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::llm::ImageData;
+
+    fn tool_definition() -> ModelToolDefinition {
+        ModelToolDefinition {
+            name: "lookup".to_string(),
+            description: "Look up something".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string"}
+                },
+                "required": ["query"],
+                "additionalProperties": false
+            }),
+        }
+    }
+
+    #[test]
+    fn build_request_serializes_messages_tools_and_tool_choice() {
+        let messages = vec![
+            ModelMessage::System("You are helpful".to_string()),
+            ModelMessage::User("Find docs".to_string()),
+            ModelMessage::Assistant {
+                content: Some("Calling tool".to_string()),
+                tool_calls: vec![ModelToolCall {
+                    id: "call_1".to_string(),
+                    name: "lookup".to_string(),
+                    arguments: json!({"query": "rust"}),
+                }],
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_1".to_string(),
+                tool_name: "lookup".to_string(),
+                content: "{\"result\":\"ok\"}".to_string(),
+                is_error: false,
+                images: Vec::new(),
+            },
+        ];
+
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.temperature = Some(0.2);
+        config.max_tokens = Some(512);
+
+        let request = build_request(
+            &messages,
+            &[tool_definition()],
+            ModelToolChoice::Tool("lookup".to_string()),
+            &config,
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["messages"][0]["role"], "system");
+        assert_eq!(value["messages"][0]["content"], "You are helpful");
+        assert_eq!(value["messages"][2]["role"], "assistant");
+        assert_eq!(
+            value["messages"][2]["tool_calls"][0]["function"]["name"],
+            "lookup"
+        );
+        assert_eq!(
+            value["messages"][2]["tool_calls"][0]["function"]["arguments"],
+            "{\"query\":\"rust\"}"
+        );
+        assert_eq!(value["messages"][3]["role"], "tool");
+        assert_eq!(value["messages"][3]["tool_call_id"], "call_1");
+        assert_eq!(value["tools"][0]["function"]["name"], "lookup");
+        assert_eq!(value["tool_choice"]["type"], "function");
+        assert_eq!(value["tool_choice"]["function"]["name"], "lookup");
+        assert!((value["temperature"].as_f64().unwrap_or_default() - 0.2).abs() < 1e-6);
+        assert_eq!(value["max_tokens"], 512);
+    }
+
+    #[test]
+    fn apply_call_options_overrides_config_values() {
+        let messages = vec![ModelMessage::User("classify".to_string())];
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.temperature = Some(0.7);
+
+        let mut request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        apply_call_options(
+            &mut request,
+            &ModelCallOptions {
+                max_tokens: Some(8),
+                temperature: None,
+                stop_sequences: vec!["\n".to_string()],
+                assistant_prefill: None,
+                capture_raw: false,
+                response_format: None,
+            },
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["max_tokens"], 8);
+        assert!((value["temperature"].as_f64().unwrap_or_default() - 0.7).abs() < 1e-6);
+        assert_eq!(value["stop"], json!(["\n"]));
+    }
+
+    #[test]
+    fn apply_call_options_sets_response_format() {
+        let messages = vec![ModelMessage::User("classify".to_string())];
+        let config = OpenAiModelConfig::new("key", "gpt-4.1");
+        let schema = json!({"type": "object", "properties": {"label": {"type": "string"}}});
+
+        let mut request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        apply_call_options(
+            &mut request,
+            &ModelCallOptions {
+                response_format: Some(ResponseFormat::JsonSchema(schema.clone())),
+                ..ModelCallOptions::default()
+            },
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["response_format"]["type"], "json_schema");
+        assert_eq!(value["response_format"]["json_schema"]["schema"], schema);
+    }
+
+    #[test]
+    fn assistant_prefill_becomes_leading_system_instruction() {
+        let messages = vec![
+            ModelMessage::System("be terse".to_string()),
+            ModelMessage::User("reply in json".to_string()),
+        ];
+        let options = ModelCallOptions {
+            assistant_prefill: Some("{".to_string()),
+            ..ModelCallOptions::default()
+        };
+
+        let messages = with_prefill_instruction(&messages, &options);
+        let request = build_request(
+            &messages,
+            &[],
+            ModelToolChoice::Auto,
+            &OpenAiModelConfig::new("key", "gpt-4.1"),
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["messages"][0]["role"], "system");
+        assert!(
+            value["messages"][0]["content"]
+                .as_str()
+                .is_some_and(|content| content.ends_with("\n{"))
+        );
+        assert_eq!(value["messages"][1]["content"], "be terse");
+        assert_eq!(value["messages"][2]["role"], "user");
+    }
+
+    #[test]
+    fn build_request_notes_omitted_tool_result_images() {
+        let messages = vec![
+            ModelMessage::User("Take a screenshot".to_string()),
+            ModelMessage::Assistant {
+                content: None,
+                tool_calls: vec![ModelToolCall {
+                    id: "call_1".to_string(),
+                    name: "screenshot".to_string(),
+                    arguments: json!({}),
+                }],
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_1".to_string(),
+                tool_name: "screenshot".to_string(),
+                content: "captured".to_string(),
+                is_error: false,
+                images: vec![ImageData::new("image/png", "aGVsbG8=")],
+            },
+        ];
+        let config = OpenAiModelConfig::new("key", "gpt-4.1");
+
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(
+            value["messages"][2]["content"],
+            "captured\n[1 image(s) omitted; this provider does not accept tool-result images: image/png]"
+        );
+    }
+
+    #[test]
+    fn build_request_adds_fallback_content_for_empty_user_message() {
+        let messages = vec![ModelMessage::User(String::new())];
+        let config = OpenAiModelConfig::new("key", "gpt-4.1");
+
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(
+            value["messages"].as_array().map(|values| values.len()),
+            Some(1)
+        );
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["messages"][0]["content"], " ");
+        assert!(value.get("tools").is_none());
+        assert!(value.get("tool_choice").is_none());
+    }
+
+    #[test]
+    fn build_request_inserts_fallback_and_drops_orphan_tool_messages() {
+        let messages = vec![ModelMessage::ToolResult {
+            tool_call_id: "call_1".to_string(),
+            tool_name: "lookup".to_string(),
+            content: "result".to_string(),
+            is_error: false,
+            images: Vec::new(),
+        }];
+        let config = OpenAiModelConfig::new("key", "gpt-4.1");
+
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(
+            value["messages"].as_array().map(|values| values.len()),
+            Some(1)
+        );
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["messages"][0]["content"], " ");
+    }
+
+    #[test]
+    fn build_request_inserts_fallback_when_first_message_is_assistant() {
+        let messages = vec![
+            ModelMessage::User(String::new()),
+            ModelMessage::Assistant {
+                content: Some("Calling tool".to_string()),
+                tool_calls: vec![ModelToolCall {
+                    id: "call_1".to_string(),
+                    name: "lookup".to_string(),
+                    arguments: json!({"query": "rust"}),
+                }],
+            },
+            ModelMessage::ToolResult {
+                tool_call_id: "call_1".to_string(),
+                tool_name: "lookup".to_string(),
+                content: "{\"result\":\"ok\"}".to_string(),
+                is_error: false,
+                images: Vec::new(),
+            },
+        ];
+        let config = OpenAiModelConfig::new("key", "gpt-4.1");
+
+        let request = build_request(&messages, &[], ModelToolChoice::Auto, &config);
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["messages"][0]["role"], "user");
+        assert_eq!(value["messages"][0]["content"], " ");
+        assert_eq!(value["messages"][1]["role"], "assistant");
+        assert_eq!(value["messages"][2]["role"], "tool");
+        assert_eq!(value["messages"][2]["tool_call_id"], "call_1");
+    }
+
+    #[test]
+    fn normalize_response_extracts_text_thinking_tool_calls_and_usage() {
+        let response = OpenAiChatCompletionResponse {
+            choices: vec![OpenAiChoice {
+                message: Some(OpenAiAssistantMessage {
+                    content: Some("answer".to_string()),
+                    tool_calls: vec![OpenAiToolCall {
+                        id: "call_x".to_string(),
+                        type_: "function".to_string(),
+                        function: OpenAiToolCallFunction {
+                            name: "lookup".to_string(),
+                            arguments: "{\"q\":\"rust\"}".to_string(),
+                        },
+                    }],
+                    reasoning_content: Some("reasoning".to_string()),
+                }),
+            }],
+            usage: Some(OpenAiUsage {
+                prompt_tokens: Some(11),
+                completion_tokens: Some(7),
+            }),
+        };
+
+        let completion = normalize_response(response).expect("response normalizes");
+
+        assert_eq!(completion.text.as_deref(), Some("answer"));
+        assert_eq!(completion.thinking, vec!["reasoning".to_string()]);
+        assert_eq!(completion.tool_calls.len(), 1);
+        assert_eq!(completion.tool_calls[0].name, "lookup");
+        assert_eq!(completion.tool_calls[0].id, "call_x");
+        assert_eq!(completion.tool_calls[0].arguments, json!({"q": "rust"}));
+        assert_eq!(
+            completion.usage,
+            Some(ModelUsage {
+                input_tokens: 11,
+                output_tokens: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn normalize_response_treats_empty_arguments_as_empty_object() {
+        let response = OpenAiChatCompletionResponse {
+            choices: vec![OpenAiChoice {
+                message: Some(OpenAiAssistantMessage {
+                    content: Some(String::new()),
+                    tool_calls: vec![OpenAiToolCall {
+                        id: "call_y".to_string(),
+                        type_: "function".to_string(),
+                        function: OpenAiToolCallFunction {
+                            name: "list".to_string(),
+                            arguments: String::new(),
+                        },
+                    }],
+                    reasoning_content: None,
+                }),
+            }],
+            usage: None,
+        };
+
+        let completion = normalize_response(response).expect("response normalizes");
+
+        assert!(completion.text.is_none());
+        assert_eq!(completion.tool_calls[0].arguments, json!({}));
+        assert!(completion.usage.is_none());
+    }
+
+    #[test]
+    fn normalize_response_requires_choices() {
+        let err = normalize_response(OpenAiChatCompletionResponse {
+            choices: Vec::new(),
+            usage: None,
+        })
+        .expect_err("should fail");
+
+        match err {
+            ProviderError::Response { message, .. } => {
+                assert!(message.contains("missing choices"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[test]
+    fn normalize_response_fails_on_invalid_tool_arguments() {
+        let err = normalize_response(OpenAiChatCompletionResponse {
+            choices: vec![OpenAiChoice {
+                message: Some(OpenAiAssistantMessage {
+                    content: None,
+                    tool_calls: vec![OpenAiToolCall {
+                        id: "call_x".to_string(),
+                        type_: "function".to_string(),
+                        function: OpenAiToolCallFunction {
+                            name: "lookup".to_string(),
+                            arguments: "{not json}".to_string(),
+                        },
+                    }],
+                    reasoning_content: None,
+                }),
+            }],
+            usage: None,
+        })
+        .expect_err("should fail");
+
+        match err {
+            ProviderError::Response { message, .. } => {
+                assert!(message.contains("not valid JSON"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn invoke_sends_user_agent_and_extra_headers() {
+        let (url, server) = crate::llm::test_server::serve_once(
+            200,
+            r#"{"choices":[{"message":{"content":"hi"}}]}"#,
+        );
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.api_base_url = Some(url);
+        config.user_agent = Some("gateway-client/1.0".to_string());
+        config
+            .extra_headers
+            .insert("X-Gateway-Tag".to_string(), "team-a".to_string());
+        let model = OpenAiModel::new(config).expect("model builds");
+
+        let completion = model
+            .invoke(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+            )
+            .await
+            .expect("invoke succeeds");
+
+        let head = server.join().expect("server thread");
+        assert_eq!(completion.text.as_deref(), Some("hi"));
+        assert!(head.contains("x-gateway-tag: team-a"));
+        assert!(head.contains("user-agent: gateway-client/1.0"));
+    }
+
+    #[tokio::test]
+    async fn invoke_stream_reassembles_sse_text_and_tool_call_fragments() {
+        let body = concat!(
+            ": keep-alive\n\n",
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Look\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"ing up\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"lookup\",\"arguments\":\"\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"query\\\":\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\" \\\"rust\\\"}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":7}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (url, server) = crate::llm::test_server::serve_once(200, body);
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.api_base_url = Some(url);
+        let model = OpenAiModel::new(config).expect("model builds");
+        let messages = [ModelMessage::User("find rust".to_string())];
+        let tools = [tool_definition()];
+
+        let chunks = model
+            .invoke_stream(&messages, &tools, ModelToolChoice::Auto)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("stream succeeds");
+        server.join().expect("server thread");
+
+        let mut accumulator = crate::llm::ModelStreamAccumulator::default();
+        for chunk in chunks {
+            accumulator.push(chunk);
+        }
+        let (completion, malformed) = accumulator.finish();
+        assert!(malformed.is_empty());
+        assert_eq!(completion.text.as_deref(), Some("Looking up"));
+        assert_eq!(
+            completion.tool_calls,
+            vec![ModelToolCall {
+                id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: json!({"query": "rust"}),
+            }]
+        );
+        assert_eq!(
+            completion.usage,
+            Some(ModelUsage {
+                input_tokens: 12,
+                output_tokens: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn build_request_sends_reasoning_effort() {
+        let mut config = OpenAiModelConfig::new("key", "o4-mini");
+        config.reasoning_effort = Some("low".to_string());
+
+        let request = build_request(
+            &[ModelMessage::User("think".to_string())],
+            &[],
+            ModelToolChoice::Auto,
+            &config,
+        );
+        let value = serde_json::to_value(request).expect("serializes");
+
+        assert_eq!(value["reasoning_effort"], "low");
+    }
+
+    #[test]
+    fn new_rejects_unknown_reasoning_effort() {
+        let mut config = OpenAiModelConfig::new("key", "o4-mini");
+        config.reasoning_effort = Some("max".to_string());
+
+        let err = OpenAiModel::new(config).expect_err("should fail");
+
+        assert!(err.to_string().contains("invalid reasoning_effort 'max'"));
+    }
+
+    #[test]
+    fn new_rejects_invalid_extra_header_name() {
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config
+            .extra_headers
+            .insert("bad header".to_string(), "value".to_string());
+
+        let err = OpenAiModel::new(config).expect_err("should fail");
+
+        assert!(err.to_string().contains("invalid header name 'bad header'"));
+    }
+
+    #[tokio::test]
+    async fn invoke_times_out_against_unresponsive_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind listener");
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.api_base_url = Some(format!(
+            "http://{}",
+            listener.local_addr().expect("local addr")
+        ));
+        config.request_timeout = Some(std::time::Duration::from_millis(200));
+        let model = OpenAiModel::new(config).expect("model builds");
+
+        let started = std::time::Instant::now();
+        let err = model
+            .invoke(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+            )
+            .await
+            .expect_err("request times out");
+
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(matches!(err, ProviderError::Request { status: None, .. }));
+    }
+
+    #[tokio::test]
+    async fn invoke_captures_raw_response_when_requested() {
+        let body = r#"{"choices":[{"message":{"content":"hi","refusal":null}}],"system_fingerprint":"fp_1"}"#;
+        let (url, server) = crate::llm::test_server::serve_once(200, body);
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.api_base_url = Some(url);
+        let model = OpenAiModel::new(config).expect("model builds");
+
+        let completion = model
+            .invoke_with_options(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+                &ModelCallOptions {
+                    capture_raw: true,
+                    ..ModelCallOptions::default()
+                },
+            )
+            .await
+            .expect("invoke succeeds");
+        server.join().expect("server thread");
+
+        assert_eq!(completion.text.as_deref(), Some("hi"));
+        assert_eq!(
+            completion.raw,
+            Some(serde_json::from_str::<Value>(body).expect("valid json"))
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_response_exposes_status_and_provider_message() {
+        let (url, server) = crate::llm::test_server::serve_once(
+            429,
+            r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#,
+        );
+        let mut config = OpenAiModelConfig::new("key", "gpt-4.1");
+        config.api_base_url = Some(url);
+        let model = OpenAiModel::new(config).expect("model builds");
+
+        let err = model
+            .invoke(
+                &[ModelMessage::User("hello".to_string())],
+                &[],
+                ModelToolChoice::Auto,
+            )
+            .await
+            .expect_err("rate limited");
+        server.join().expect("server thread");
+
+        assert_eq!(err.status(), Some(429));
+        assert_eq!(err.provider_message(), Some("Rate limit reached"));
+        assert!(err.to_string().contains("Rate limit reached"));
+    }
+
+    #[test]
+    fn from_env_with_key_reads_named_variable() {
+        // SAFETY: no other test touches this variable.
+        unsafe { std::env::set_var("AGENT_SDK_TEST_OPENAI_KEY", "tenant-key") };
+
+        let model = OpenAiModel::from_env_with_key("AGENT_SDK_TEST_OPENAI_KEY", "gpt-4.1")
+            .expect("model builds");
+
+        assert_eq!(model.config.api_key, "tenant-key");
+    }
+
+    #[test]
+    fn describe_request_keeps_system_messages_and_adds_prefill_instruction() {
+        let model = OpenAiModel::new(OpenAiModelConfig::new("key", "gpt-4.1")).expect("builds");
+        let messages = [
+            ModelMessage::System("be brief".to_string()),
+            ModelMessage::User("hi".to_string()),
+            ModelMessage::System("answer in French".to_string()),
+        ];
+        let options = ModelCallOptions {
+            assistant_prefill: Some("Bonjour".to_string()),
+            ..ModelCallOptions::default()
+        };
+
+        let description = model.describe_request(&messages, &options);
+
+        assert_eq!(
+            description.system_text().as_deref(),
+            Some(
+                "Begin your reply with exactly the following text, then continue from it:\nBonjour\n\nbe brief\n\nanswer in French"
+            )
+        );
+        assert_eq!(description.system.len(), 3);
+        assert_eq!(description.message_count, 1);
+    }
+}
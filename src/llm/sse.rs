@@ -0,0 +1,134 @@
+//! Server-sent events decoding for streaming adapters.
+
+use futures_util::Stream;
+use reqwest::Response;
+
+use crate::error::ProviderError;
+
+/// One dispatched server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SseEvent {
+    /// Value of the `event:` field, if the server sent one.
+    pub(crate) event: Option<String>,
+    /// `data:` lines joined with `\n`.
+    pub(crate) data: String,
+}
+
+/// Incremental decoder that turns raw body bytes into [`SseEvent`]s.
+///
+/// Lines may be split across chunks; incomplete lines are buffered until their newline
+/// arrives. Comment lines (keep-alives such as `: ping`) and unknown fields are ignored.
+#[derive(Debug, Default)]
+pub(crate) struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feeds one body chunk and returns the events it completed.
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(newline) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line = self.buffer.drain(..=newline).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            events.extend(self.process_line(line));
+        }
+        events
+    }
+
+    /// Flushes an event left open when the body ended without a trailing blank line.
+    pub(crate) fn finish(mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.buffer.clear();
+            if let Some(event) = self.process_line(line.trim_end_matches('\r')) {
+                return Some(event);
+            }
+        }
+        self.process_line("")
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            if self.data.is_empty() {
+                self.event = None;
+                return None;
+            }
+            return Some(SseEvent {
+                event: self.event.take(),
+                data: std::mem::take(&mut self.data).join("\n"),
+            });
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Reads a streaming response body and yields its server-sent events as they complete.
+pub(crate) fn sse_events(
+    response: Response,
+) -> impl Stream<Item = Result<SseEvent, ProviderError>> {
+    async_stream::try_stream! {
+        let mut response = response;
+        let mut decoder = SseDecoder::default();
+        while let Some(bytes) = response
+            .chunk()
+            .await
+            .map_err(|err| ProviderError::request(format!("failed to read event stream: {err}")))?
+        {
+            for event in decoder.push(&bytes) {
+                yield event;
+            }
+        }
+        if let Some(event) = decoder.finish() {
+            yield event;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoder_joins_lines_split_across_chunks_and_skips_comments() {
+        let mut decoder = SseDecoder::default();
+
+        let mut events = decoder.push(b": keep-alive\n\ndata: {\"a\"");
+        assert!(events.is_empty());
+        events.extend(decoder.push(b":1}\r\n\r\nevent: ping\ndata: x\ndata: y\n\n"));
+        events.extend(decoder.push(b"data: [DONE]"));
+        events.extend(decoder.finish());
+
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: None,
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    event: Some("ping".to_string()),
+                    data: "x\ny".to_string(),
+                },
+                SseEvent {
+                    event: None,
+                    data: "[DONE]".to_string(),
+                },
+            ]
+        );
+    }
+}
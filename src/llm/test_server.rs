@@ -0,0 +1,47 @@
+//! Minimal one-shot HTTP server for adapter tests.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+
+/// Serves one request with `status` and a JSON `body`, returning the base URL and a handle
+/// that yields the raw request head (request line plus headers).
+pub(crate) fn serve_once(status: u16, body: &str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind test server");
+    let url = format!("http://{}", listener.local_addr().expect("local addr"));
+    let body = body.to_string();
+
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept connection");
+        let mut reader = BufReader::new(stream);
+        let mut head = String::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("read request line");
+            if line == "\r\n" || line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+            head.push_str(&line);
+        }
+        let mut request_body = vec![0; content_length];
+        reader
+            .read_exact(&mut request_body)
+            .expect("read request body");
+
+        let response = format!(
+            "HTTP/1.1 {status} Test\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        reader
+            .get_mut()
+            .write_all(response.as_bytes())
+            .expect("write response");
+        head
+    });
+
+    (url, handle)
+}
@@ -0,0 +1,209 @@
+//! Helpers for testing tools and agents without a real provider.
+//!
+//! ```rust
+//! use agent_sdk_rs::testing::{ScriptedModel, collect_events, completion_text, completion_tool_call};
+//! use agent_sdk_rs::{Agent, AgentEvent, ToolOutcome, ToolSpec};
+//! use serde_json::json;
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let model = ScriptedModel::new(vec![
+//!     Ok(completion_tool_call("call_1", "echo", json!({"text": "hi"}))),
+//!     Ok(completion_text("echoed hi")),
+//! ]);
+//! let echo = ToolSpec::new("echo", "Echo text").with_handler(|args, _deps| async move {
+//!     Ok(ToolOutcome::Text(args["text"].as_str().unwrap_or_default().to_string()))
+//! });
+//!
+//! let mut agent = Agent::builder().model(model.clone()).tool(echo).build()?;
+//! let events = collect_events(&mut agent, "echo hi").await?;
+//!
+//! assert!(events.iter().any(|event| matches!(
+//!     event,
+//!     AgentEvent::ToolResult { result_text, .. } if result_text == "hi"
+//! )));
+//! assert!(matches!(
+//!     events.last(),
+//!     Some(AgentEvent::FinalResponse { content, .. }) if content == "echoed hi"
+//! ));
+//! assert_eq!(model.invocations(), 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde_json::Value;
+
+use crate::agent::{Agent, AgentEvent, Clock};
+use crate::error::{AgentError, ProviderError};
+use crate::llm::{
+    ChatModel, ModelCompletion, ModelMessage, ModelToolCall, ModelToolChoice, ModelToolDefinition,
+};
+
+/// [`ChatModel`] that replays a fixed list of responses, one per invocation.
+///
+/// Clones share the same script and recorded calls, so keep a clone to inspect the model after
+/// handing it to an agent. Once the script runs out every call returns
+/// [`ProviderError::Response`].
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedModel {
+    responses: Arc<Mutex<VecDeque<Result<ModelCompletion, ProviderError>>>>,
+    seen_messages: Arc<Mutex<Vec<Vec<ModelMessage>>>>,
+}
+
+impl ScriptedModel {
+    /// Creates a model that returns `responses` in order.
+    pub fn new(responses: Vec<Result<ModelCompletion, ProviderError>>) -> Self {
+        Self {
+            responses: Arc::new(Mutex::new(VecDeque::from(responses))),
+            seen_messages: Arc::default(),
+        }
+    }
+
+    /// Number of invocations so far.
+    pub fn invocations(&self) -> usize {
+        self.seen_messages
+            .lock()
+            .expect("scripted model lock")
+            .len()
+    }
+
+    /// Message history passed to each invocation, in call order.
+    pub fn seen_messages(&self) -> Vec<Vec<ModelMessage>> {
+        self.seen_messages
+            .lock()
+            .expect("scripted model lock")
+            .clone()
+    }
+
+    /// Number of scripted responses not yet returned.
+    pub fn remaining(&self) -> usize {
+        self.responses.lock().expect("scripted model lock").len()
+    }
+}
+
+#[async_trait]
+impl ChatModel for ScriptedModel {
+    async fn invoke(
+        &self,
+        messages: &[ModelMessage],
+        _tools: &[ModelToolDefinition],
+        _tool_choice: ModelToolChoice,
+    ) -> Result<ModelCompletion, ProviderError> {
+        self.seen_messages
+            .lock()
+            .expect("scripted model lock")
+            .push(messages.to_vec());
+        self.responses
+            .lock()
+            .expect("scripted model lock")
+            .pop_front()
+            .unwrap_or_else(|| {
+                Err(ProviderError::response(
+                    "scripted model exhausted responses".to_string(),
+                ))
+            })
+    }
+}
+
+/// Manual [`Clock`] for deterministic tests.
+///
+/// `sleep` returns immediately after advancing the clock by the requested duration and
+/// recording it, so backoff and pacing can be asserted without real delays. Clones share state.
+#[derive(Clone, Debug)]
+pub struct TestClock {
+    start: Instant,
+    state: Arc<Mutex<TestClockState>>,
+}
+
+#[derive(Debug, Default)]
+struct TestClockState {
+    elapsed: Duration,
+    sleeps: Vec<Duration>,
+}
+
+impl TestClock {
+    /// Creates a clock starting at the current instant.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            state: Arc::default(),
+        }
+    }
+
+    /// Moves the clock forward without recording a sleep.
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().expect("test clock lock").elapsed += duration;
+    }
+
+    /// Total time the clock has advanced.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().expect("test clock lock").elapsed
+    }
+
+    /// Every duration passed to `sleep`, in call order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.lock().expect("test clock lock").sleeps.clone()
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let mut state = self.state.lock().expect("test clock lock");
+        state.elapsed += duration;
+        state.sleeps.push(duration);
+    }
+}
+
+/// Completion with plain text and no tool calls.
+pub fn completion_text(text: &str) -> ModelCompletion {
+    ModelCompletion {
+        text: Some(text.to_string()),
+        ..ModelCompletion::default()
+    }
+}
+
+/// Completion with a single tool call and no text.
+pub fn completion_tool_call(id: &str, name: &str, arguments: Value) -> ModelCompletion {
+    ModelCompletion {
+        tool_calls: vec![ModelToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments,
+        }],
+        ..ModelCompletion::default()
+    }
+}
+
+/// Runs one query and collects every event it emits.
+///
+/// Returns the first error instead, so a failing run fails the test with that error.
+pub async fn collect_events(
+    agent: &mut Agent,
+    user_message: impl Into<String>,
+) -> Result<Vec<AgentEvent>, AgentError> {
+    let stream = agent.query_stream(user_message);
+    futures_util::pin_mut!(stream);
+
+    let mut events = Vec::new();
+    while let Some(event) = stream.next().await {
+        events.push(event?);
+    }
+    Ok(events)
+}
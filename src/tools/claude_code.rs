@@ -1,12 +1,18 @@
+use std::collections::HashMap;
 use std::fs;
+use std::io::{Read, Write};
+use std::ops::Deref;
 use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use glob::Pattern;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{Value, json};
 use tokio::process::Command;
 use tokio::time::{Duration, timeout};
 use walkdir::WalkDir;
@@ -17,11 +23,58 @@ use crate::tools::{DependencyMap, ToolOutcome, ToolSpec};
 #[derive(Debug, Clone)]
 pub struct SandboxContext {
     root_dir: PathBuf,
-    working_dir: PathBuf,
+    working_dir: Arc<Mutex<PathBuf>>,
     session_id: String,
     todos: Arc<Mutex<Vec<TodoItem>>>,
+    max_read_bytes: usize,
+    command_policy: CommandPolicy,
+    env: Option<HashMap<String, String>>,
 }
 
+/// Guard returned by [`SandboxContext::create_ephemeral`] that deletes the sandbox on drop.
+#[derive(Debug)]
+pub struct EphemeralSandbox {
+    ctx: SandboxContext,
+}
+
+impl EphemeralSandbox {
+    /// Clone of the wrapped context, e.g. for registering as an agent dependency.
+    pub fn context(&self) -> SandboxContext {
+        self.ctx.clone()
+    }
+}
+
+impl Deref for EphemeralSandbox {
+    type Target = SandboxContext;
+
+    fn deref(&self) -> &SandboxContext {
+        &self.ctx
+    }
+}
+
+impl Drop for EphemeralSandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(self.ctx.root_dir());
+    }
+}
+
+/// Restricts which programs the `bash` tool may launch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CommandPolicy {
+    /// Any command may run.
+    #[default]
+    Unrestricted,
+    /// Only the listed programs may run; shell metacharacters are rejected.
+    Allow(Vec<String>),
+    /// The listed programs may not run anywhere in the command line.
+    Deny(Vec<String>),
+}
+
+const SHELL_METACHARACTERS: &[&str] = &[";", "|", "&", "`", "$(", ">", "<", "\n"];
+
+/// Default cap on bytes returned by the `read` tool.
+pub const DEFAULT_MAX_READ_BYTES: usize = 256 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct TodoItem {
     pub content: String,
@@ -43,19 +96,139 @@ impl SandboxContext {
         let root_dir = root.canonicalize()?;
 
         Ok(Self {
-            working_dir: root_dir.clone(),
+            working_dir: Arc::new(Mutex::new(root_dir.clone())),
             root_dir,
             session_id,
             todos: Arc::new(Mutex::new(Vec::new())),
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+            command_policy: CommandPolicy::Unrestricted,
+            env: None,
         })
     }
 
+    /// Creates a sandbox in a fresh directory under the system temp dir that is removed when
+    /// the returned guard drops.
+    ///
+    /// Clones taken from the guard share the same root, so they stop being usable once it drops.
+    pub fn create_ephemeral() -> Result<EphemeralSandbox, std::io::Error> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!(
+            "agent_sdk_rs_sandbox_{}_{}_{id}",
+            std::process::id(),
+            short_session_id()
+        ));
+        Ok(EphemeralSandbox {
+            ctx: Self::create(Some(root))?,
+        })
+    }
+
+    /// Removes the sandbox root directory and everything in it.
+    pub fn cleanup(self) -> Result<(), std::io::Error> {
+        fs::remove_dir_all(&self.root_dir)
+    }
+
+    /// Runs `bash` commands with exactly these environment variables instead of the host's.
+    ///
+    /// `PWD` is always set to the sandbox working directory.
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    pub fn env(&self) -> Option<&HashMap<String, String>> {
+        self.env.as_ref()
+    }
+
+    /// Only allows `bash` commands whose program is in `programs`.
+    pub fn with_command_allowlist(mut self, programs: Vec<String>) -> Self {
+        self.command_policy = CommandPolicy::Allow(programs);
+        self
+    }
+
+    /// Rejects `bash` commands that invoke any program in `programs`.
+    pub fn with_command_denylist(mut self, programs: Vec<String>) -> Self {
+        self.command_policy = CommandPolicy::Deny(programs);
+        self
+    }
+
+    pub fn command_policy(&self) -> &CommandPolicy {
+        &self.command_policy
+    }
+
+    /// Checks a shell command against the configured [`CommandPolicy`].
+    pub fn check_command(&self, command: &str) -> Result<(), String> {
+        match &self.command_policy {
+            CommandPolicy::Unrestricted => Ok(()),
+            CommandPolicy::Allow(allowed) => {
+                if let Some(meta) = SHELL_METACHARACTERS
+                    .iter()
+                    .find(|meta| command.contains(**meta))
+                {
+                    return Err(format!(
+                        "Command not permitted: shell metacharacter '{}' is not allowed with a command allowlist",
+                        meta.escape_default()
+                    ));
+                }
+                let program = leading_program(command).unwrap_or_default();
+                if allowed.iter().any(|entry| entry == program) {
+                    Ok(())
+                } else {
+                    Err(format!("Command not permitted: {program}"))
+                }
+            }
+            CommandPolicy::Deny(denied) => {
+                let segments = SHELL_METACHARACTERS.iter().fold(
+                    vec![command.to_string()],
+                    |segments, meta| {
+                        segments
+                            .iter()
+                            .flat_map(|segment| segment.split(meta))
+                            .map(ToString::to_string)
+                            .collect()
+                    },
+                );
+                for segment in &segments {
+                    if let Some(program) = leading_program(segment)
+                        && denied.iter().any(|entry| entry == program)
+                    {
+                        return Err(format!("Command not permitted: {program}"));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets the maximum number of bytes the `read` tool returns from one file.
+    pub fn with_max_read_bytes(mut self, max_read_bytes: usize) -> Self {
+        self.max_read_bytes = max_read_bytes;
+        self
+    }
+
+    pub fn max_read_bytes(&self) -> usize {
+        self.max_read_bytes
+    }
+
     pub fn root_dir(&self) -> &Path {
         &self.root_dir
     }
 
-    pub fn working_dir(&self) -> &Path {
-        &self.working_dir
+    pub fn working_dir(&self) -> PathBuf {
+        self.working_dir
+            .lock()
+            .expect("working dir lock poisoned")
+            .clone()
+    }
+
+    /// Changes the directory relative paths resolve against; it must stay inside the sandbox.
+    pub fn set_working_dir(&self, path: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let resolved = self.resolve_path(path)?;
+        if !resolved.is_dir() {
+            return Err(format!("Not a directory: {}", resolved.display()));
+        }
+        *self.working_dir.lock().expect("working dir lock poisoned") = resolved.clone();
+        Ok(resolved)
     }
 
     pub fn session_id(&self) -> &str {
@@ -67,7 +240,7 @@ impl SandboxContext {
         let unresolved = if candidate.is_absolute() {
             candidate.to_path_buf()
         } else {
-            self.working_dir.join(candidate)
+            self.working_dir().join(candidate)
         };
         let resolved = normalize_absolute_path(&unresolved);
 
@@ -91,6 +264,12 @@ impl SandboxContext {
     }
 }
 
+/// Returns the program name of a command, without any leading directory.
+fn leading_program(command: &str) -> Option<&str> {
+    let token = command.split_whitespace().next()?;
+    Some(token.rsplit('/').next().unwrap_or(token))
+}
+
 fn normalize_absolute_path(path: &Path) -> PathBuf {
     let mut normalized = PathBuf::new();
     for component in path.components() {
@@ -110,9 +289,16 @@ fn normalize_absolute_path(path: &Path) -> PathBuf {
 pub fn all_tools() -> Vec<ToolSpec> {
     vec![
         bash_tool(),
+        cd_tool(),
         read_tool(),
+        read_bytes_tool(),
         write_tool(),
         edit_tool(),
+        multi_edit_tool(),
+        apply_patch_tool(),
+        move_tool(),
+        delete_tool(),
+        ls_tool(),
         glob_search_tool(),
         grep_tool(),
         todo_read_tool(),
@@ -121,6 +307,13 @@ pub fn all_tools() -> Vec<ToolSpec> {
     ]
 }
 
+/// Same as [`all_tools`], plus tools that reach the network (currently `web_fetch`).
+pub fn all_tools_with_network() -> Vec<ToolSpec> {
+    let mut tools = all_tools();
+    tools.push(web_fetch_tool());
+    tools
+}
+
 pub fn bash_tool() -> ToolSpec {
     ToolSpec::new("bash", "Execute a shell command and return output")
         .with_schema(json!({
@@ -148,15 +341,19 @@ pub fn bash_tool() -> ToolSpec {
                     Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
                 };
 
-                let output = timeout(
-                    Duration::from_secs(timeout_secs),
-                    Command::new("sh")
-                        .arg("-lc")
-                        .arg(command)
-                        .current_dir(ctx.working_dir())
-                        .output(),
-                )
-                .await;
+                if let Err(err) = ctx.check_command(&command) {
+                    return Ok(ToolOutcome::Text(err));
+                }
+
+                let working_dir = ctx.working_dir();
+                let mut process = Command::new("sh");
+                process.arg("-lc").arg(command).current_dir(&working_dir);
+                if let Some(env) = ctx.env() {
+                    process.env_clear().envs(env);
+                }
+                process.env("PWD", &working_dir);
+
+                let output = timeout(Duration::from_secs(timeout_secs), process.output()).await;
 
                 match output {
                     Ok(Ok(out)) => {
@@ -177,12 +374,51 @@ pub fn bash_tool() -> ToolSpec {
         })
 }
 
+pub fn cd_tool() -> ToolSpec {
+    ToolSpec::new("cd", "Change the working directory for later tool calls")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"}
+            },
+            "required": ["path"],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(|args, deps| {
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let ctx = get_ctx(deps);
+
+            async move {
+                let ctx = match ctx {
+                    Ok(ctx) => ctx,
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+                };
+
+                match ctx.set_working_dir(&path) {
+                    Ok(dir) => Ok(ToolOutcome::Text(format!(
+                        "Working directory: {}",
+                        dir.display()
+                    ))),
+                    Err(err) => Ok(ToolOutcome::Text(err)),
+                }
+            }
+        })
+}
+
 pub fn read_tool() -> ToolSpec {
     ToolSpec::new("read", "Read contents of a file")
         .with_schema(json!({
             "type": "object",
             "properties": {
-                "file_path": {"type": "string"}
+                "file_path": {"type": "string"},
+                "offset": {"type": "integer", "minimum": 1},
+                "limit": {"type": "integer", "minimum": 1},
+                "show_line_numbers": {"type": "boolean"}
             },
             "required": ["file_path"],
             "additionalProperties": false
@@ -194,6 +430,18 @@ pub fn read_tool() -> ToolSpec {
                 .and_then(|v| v.as_str())
                 .unwrap_or_default()
                 .to_string();
+            let offset = args
+                .get("offset")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let limit = args
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let show_line_numbers = args
+                .get("show_line_numbers")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
             let ctx = get_ctx(deps);
 
             async move {
@@ -216,20 +464,211 @@ pub fn read_tool() -> ToolSpec {
                     )));
                 }
 
-                match fs::read_to_string(path) {
-                    Ok(content) => {
-                        let numbered = content
-                            .lines()
-                            .enumerate()
-                            .map(|(idx, line)| format!("{:4}  {}", idx + 1, line))
-                            .collect::<Vec<_>>()
-                            .join("\n");
-                        Ok(ToolOutcome::Text(numbered))
+                let (content, truncated_bytes) = match read_capped(&path, ctx.max_read_bytes()) {
+                    Ok(CappedRead::Text {
+                        content,
+                        truncated_bytes,
+                    }) => (content, truncated_bytes),
+                    Ok(CappedRead::Binary) => {
+                        return Ok(ToolOutcome::Text(format!(
+                            "Cannot read binary file: {file_path}"
+                        )));
                     }
-                    Err(err) => Ok(ToolOutcome::Text(format!("Error reading file: {err}"))),
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Error reading file: {err}"))),
+                };
+
+                let mut rendered = render_lines(&content, offset, limit, show_line_numbers);
+                if truncated_bytes > 0 {
+                    rendered.push_str(&format!("\n[truncated {truncated_bytes} bytes]"));
                 }
+                Ok(ToolOutcome::Text(rendered))
+            }
+        })
+}
+
+enum CappedRead {
+    Text {
+        content: String,
+        truncated_bytes: u64,
+    },
+    Binary,
+}
+
+/// Reads at most `max_bytes` of a file, flagging content with NUL bytes as binary.
+fn read_capped(path: &Path, max_bytes: usize) -> std::io::Result<CappedRead> {
+    let file = fs::File::open(path)?;
+    let total_bytes = file.metadata()?.len();
+
+    let mut bytes = Vec::new();
+    file.take(max_bytes as u64).read_to_end(&mut bytes)?;
+    if bytes.contains(&0) {
+        return Ok(CappedRead::Binary);
+    }
+
+    let truncated_bytes = total_bytes.saturating_sub(bytes.len() as u64);
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        // The cap may split a multi-byte character; drop the partial tail.
+        Err(err) if truncated_bytes > 0 && err.utf8_error().error_len().is_none() => {
+            let valid_up_to = err.utf8_error().valid_up_to();
+            let mut bytes = err.into_bytes();
+            bytes.truncate(valid_up_to);
+            String::from_utf8(bytes).expect("prefix is valid utf-8")
+        }
+        Err(err) => {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err));
+        }
+    };
+
+    Ok(CappedRead::Text {
+        content,
+        truncated_bytes,
+    })
+}
+
+/// Renders `content` windowed by `offset`/`limit`, with 1-based line numbers in a gutter
+/// sized to the file's line count (at least four columns) unless `line_numbers` is off.
+fn render_lines(
+    content: &str,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    line_numbers: bool,
+) -> String {
+    let total_lines = content.lines().count();
+    let start = offset.unwrap_or(1).max(1);
+
+    if offset.is_some() && start > total_lines {
+        return format!("(offset {start} is past the end of the file; it has {total_lines} lines)");
+    }
+
+    let width = total_lines.to_string().len().max(4);
+    let rendered = content
+        .lines()
+        .enumerate()
+        .skip(start - 1)
+        .take(limit.unwrap_or(usize::MAX))
+        .map(|(idx, line)| {
+            if line_numbers {
+                format!("{:width$}  {}", idx + 1, line)
+            } else {
+                line.to_string()
             }
         })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    match limit.map(|limit| (start - 1).saturating_add(limit)) {
+        Some(end) if end > total_lines => format!(
+            "{rendered}\n(requested lines {start}-{end} but the file has {total_lines} lines)"
+        ),
+        _ => rendered,
+    }
+}
+
+const DEFAULT_READ_BYTES_LIMIT: usize = 64 * 1024;
+
+/// Reads a file as base64, for binaries the text-only `read` tool rejects.
+pub fn read_bytes_tool() -> ToolSpec {
+    ToolSpec::new(
+        "read_bytes",
+        "Read a file as base64-encoded bytes (for binary files such as images)",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "file_path": {"type": "string"},
+            "max_bytes": {"type": "integer", "minimum": 1}
+        },
+        "required": ["file_path"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, deps| {
+        let file_path = args
+            .get("file_path")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let max_bytes = args
+            .get("max_bytes")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_READ_BYTES_LIMIT, |v| v as usize);
+        let ctx = get_ctx(deps);
+
+        async move {
+            let ctx = match ctx {
+                Ok(ctx) => ctx,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            let path = match ctx.resolve_path(&file_path) {
+                Ok(path) => path,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
+            };
+
+            if !path.exists() {
+                return Ok(ToolOutcome::Text(format!("File not found: {file_path}")));
+            }
+            if path.is_dir() {
+                return Ok(ToolOutcome::Text(format!(
+                    "Path is a directory: {file_path}"
+                )));
+            }
+
+            let read = fs::File::open(&path).and_then(|file| {
+                let total_bytes = file.metadata()?.len();
+                let mut bytes = Vec::new();
+                file.take(max_bytes as u64).read_to_end(&mut bytes)?;
+                Ok((bytes, total_bytes))
+            });
+            let (bytes, total_bytes) = match read {
+                Ok(read) => read,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error reading file: {err}"))),
+            };
+
+            let mut header = format!(
+                "{file_path} ({}, {total_bytes} bytes)",
+                mime_type_for_path(&path)
+            );
+            if (bytes.len() as u64) < total_bytes {
+                header.push_str(&format!(" [truncated to first {} bytes]", bytes.len()));
+            }
+            Ok(ToolOutcome::Text(format!(
+                "{header}\nbase64:\n{}",
+                BASE64.encode(&bytes)
+            )))
+        }
+    })
+}
+
+/// Best-effort MIME type from the file extension.
+fn mime_type_for_path(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "wasm" => "application/wasm",
+        "json" => "application/json",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
 }
 
 pub fn write_tool() -> ToolSpec {
@@ -268,10 +707,10 @@ pub fn write_tool() -> ToolSpec {
                     Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
                 };
 
-                if let Some(parent) = path.parent() {
-                    if let Err(err) = fs::create_dir_all(parent) {
-                        return Ok(ToolOutcome::Text(format!("Error writing file: {err}")));
-                    }
+                if let Some(parent) = path.parent()
+                    && let Err(err) = fs::create_dir_all(parent)
+                {
+                    return Ok(ToolOutcome::Text(format!("Error writing file: {err}")));
                 }
 
                 match fs::write(path, content.as_bytes()) {
@@ -292,7 +731,8 @@ pub fn edit_tool() -> ToolSpec {
             "properties": {
                 "file_path": {"type": "string"},
                 "old_string": {"type": "string"},
-                "new_string": {"type": "string"}
+                "new_string": {"type": "string"},
+                "replace_all": {"type": "boolean"}
             },
             "required": ["file_path", "old_string", "new_string"],
             "additionalProperties": false
@@ -314,6 +754,10 @@ pub fn edit_tool() -> ToolSpec {
                 .and_then(|v| v.as_str())
                 .unwrap_or_default()
                 .to_string();
+            let replace_all = args
+                .get("replace_all")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
             let ctx = get_ctx(deps);
 
             async move {
@@ -343,6 +787,11 @@ pub fn edit_tool() -> ToolSpec {
                 }
 
                 let count = content.matches(&old_string).count();
+                if count > 1 && !replace_all {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Error: old_string is not unique ({count} matches); provide more context or set replace_all"
+                    )));
+                }
                 let updated = content.replace(&old_string, &new_string);
                 match fs::write(&path, updated.as_bytes()) {
                     Ok(_) => Ok(ToolOutcome::Text(format!(
@@ -354,117 +803,719 @@ pub fn edit_tool() -> ToolSpec {
         })
 }
 
-pub fn glob_search_tool() -> ToolSpec {
-    ToolSpec::new("glob_search", "Find files matching a glob pattern")
-        .with_schema(json!({
-            "type": "object",
-            "properties": {
-                "pattern": {"type": "string"},
-                "path": {"type": "string"}
-            },
-            "required": ["pattern"],
-            "additionalProperties": false
-        }))
-        .expect("valid schema")
-        .with_handler(|args, deps| {
-            let pattern = args
-                .get("pattern")
-                .and_then(|v| v.as_str())
-                .unwrap_or_default()
-                .to_string();
-            let path = args
-                .get("path")
-                .and_then(|v| v.as_str())
-                .map(ToString::to_string);
-            let ctx = get_ctx(deps);
-
-            async move {
-                let ctx = match ctx {
-                    Ok(ctx) => ctx,
-                    Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
-                };
+#[derive(Debug, Deserialize)]
+struct EditOperation {
+    old_string: String,
+    new_string: String,
+}
 
-                let search_dir = match path {
-                    Some(p) => match ctx.resolve_path(p) {
-                        Ok(path) => path,
-                        Err(err) => {
-                            return Ok(ToolOutcome::Text(format!("Security error: {err}")));
-                        }
+pub fn multi_edit_tool() -> ToolSpec {
+    ToolSpec::new(
+        "multi_edit",
+        "Apply several text replacements to one file; nothing is written unless all succeed",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "file_path": {"type": "string"},
+            "edits": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "old_string": {"type": "string"},
+                        "new_string": {"type": "string"}
                     },
-                    None => ctx.working_dir().to_path_buf(),
-                };
+                    "required": ["old_string", "new_string"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["file_path", "edits"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, deps| {
+        let file_path = args
+            .get("file_path")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let edits_value = args.get("edits").cloned().unwrap_or(json!([]));
+        let ctx = get_ctx(deps);
+
+        async move {
+            let ctx = match ctx {
+                Ok(ctx) => ctx,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            let edits: Vec<EditOperation> = match serde_json::from_value(edits_value) {
+                Ok(edits) => edits,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Invalid edits payload: {err}"))),
+            };
+            if edits.is_empty() {
+                return Ok(ToolOutcome::Text("No edits provided".to_string()));
+            }
 
-                let pattern = match Pattern::new(&pattern) {
-                    Ok(pattern) => pattern,
-                    Err(err) => {
-                        return Ok(ToolOutcome::Text(format!("Invalid glob pattern: {err}")));
-                    }
+            let path = match ctx.resolve_path(&file_path) {
+                Ok(path) => path,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
+            };
+
+            if !path.exists() {
+                return Ok(ToolOutcome::Text(format!("File not found: {file_path}")));
+            }
+
+            let mut content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error editing file: {err}"))),
+            };
+
+            let mut counts = Vec::with_capacity(edits.len());
+            for (index, edit) in edits.iter().enumerate() {
+                let count = if edit.old_string.is_empty() {
+                    0
+                } else {
+                    content.matches(&edit.old_string).count()
                 };
+                if count == 0 {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Edit {} failed: string not found in {file_path}; no changes written",
+                        index + 1
+                    )));
+                }
+                content = content.replace(&edit.old_string, &edit.new_string);
+                counts.push(count);
+            }
 
-                let mut files = Vec::new();
-                for entry in WalkDir::new(&search_dir).into_iter().flatten() {
-                    if !entry.file_type().is_file() {
-                        continue;
-                    }
+            if let Err(err) = fs::write(&path, content.as_bytes()) {
+                return Ok(ToolOutcome::Text(format!("Error editing file: {err}")));
+            }
 
-                    let rel_from_search = match entry.path().strip_prefix(&search_dir) {
-                        Ok(path) => path,
-                        Err(_) => continue,
-                    };
-                    if !pattern.matches_path(rel_from_search) {
-                        continue;
-                    }
+            let summary = counts
+                .iter()
+                .enumerate()
+                .map(|(index, count)| format!("edit {}: {count} occurrence(s)", index + 1))
+                .collect::<Vec<_>>()
+                .join("\n");
+            Ok(ToolOutcome::Text(format!(
+                "Applied {} edit(s) to {file_path}\n{summary}",
+                counts.len()
+            )))
+        }
+    })
+}
 
-                    let shown = entry
-                        .path()
-                        .strip_prefix(ctx.root_dir())
-                        .unwrap_or(entry.path())
-                        .display()
-                        .to_string();
-                    files.push(shown);
+pub fn apply_patch_tool() -> ToolSpec {
+    ToolSpec::new(
+        "apply_patch",
+        "Apply a unified diff to one file; nothing is written unless every hunk applies",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "file_path": {"type": "string"},
+            "patch": {"type": "string"}
+        },
+        "required": ["file_path", "patch"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, deps| {
+        let file_path = args
+            .get("file_path")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let patch = args
+            .get("patch")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let ctx = get_ctx(deps);
+
+        async move {
+            let ctx = match ctx {
+                Ok(ctx) => ctx,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            let path = match ctx.resolve_path(&file_path) {
+                Ok(path) => path,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
+            };
+
+            if !path.exists() {
+                return Ok(ToolOutcome::Text(format!("File not found: {file_path}")));
+            }
 
-                    if files.len() >= 50 {
-                        break;
-                    }
-                }
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error patching file: {err}"))),
+            };
 
-                if files.is_empty() {
-                    Ok(ToolOutcome::Text(format!(
-                        "No files match pattern: {pattern}"
-                    )))
-                } else {
-                    Ok(ToolOutcome::Text(format!(
-                        "Found {} file(s):\n{}",
-                        files.len(),
-                        files.join("\n")
-                    )))
+            let (updated, hunks) = match apply_unified_diff(&content, &patch) {
+                Ok(applied) => applied,
+                Err(err) => {
+                    return Ok(ToolOutcome::Text(format!(
+                        "{err}; no changes written to {file_path}"
+                    )));
                 }
+            };
+
+            match fs::write(&path, updated.as_bytes()) {
+                Ok(_) => Ok(ToolOutcome::Text(format!(
+                    "Applied {hunks} hunk(s) to {file_path}"
+                ))),
+                Err(err) => Ok(ToolOutcome::Text(format!("Error patching file: {err}"))),
             }
-        })
+        }
+    })
 }
 
-pub fn grep_tool() -> ToolSpec {
-    ToolSpec::new("grep", "Search file contents with regex")
+#[derive(Debug, Default)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+}
+
+/// Parses the hunks of a unified diff; file headers and `\ No newline` markers are skipped.
+fn parse_hunks(patch: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in patch.lines() {
+        if let Some(rest) = line.strip_prefix("@@") {
+            let old_range = rest
+                .split_whitespace()
+                .find_map(|part| part.strip_prefix('-'))
+                .ok_or_else(|| format!("Malformed hunk header: {line}"))?;
+            let old_start = old_range
+                .split(',')
+                .next()
+                .and_then(|start| start.parse::<usize>().ok())
+                .ok_or_else(|| format!("Malformed hunk header: {line}"))?;
+            hunks.push(Hunk {
+                header: line.to_string(),
+                old_start,
+                ..Hunk::default()
+            });
+            continue;
+        }
+
+        let Some(hunk) = hunks.last_mut() else {
+            continue;
+        };
+        if let Some(text) = line.strip_prefix('+') {
+            hunk.new_lines.push(text.to_string());
+        } else if let Some(text) = line.strip_prefix('-') {
+            hunk.old_lines.push(text.to_string());
+        } else if let Some(text) = line.strip_prefix(' ') {
+            hunk.old_lines.push(text.to_string());
+            hunk.new_lines.push(text.to_string());
+        } else if line.is_empty() {
+            hunk.old_lines.push(String::new());
+            hunk.new_lines.push(String::new());
+        } else if !line.starts_with('\\') {
+            return Err(format!("Unexpected line in {}: {line}", hunk.header));
+        }
+    }
+
+    if hunks.is_empty() {
+        return Err("Patch contains no hunks".to_string());
+    }
+    Ok(hunks)
+}
+
+/// Applies every hunk in order, preferring the match nearest each hunk's stated line.
+///
+/// Returns the patched content and hunk count, or a message naming the first hunk that failed.
+fn apply_unified_diff(content: &str, patch: &str) -> Result<(String, usize), String> {
+    let hunks = parse_hunks(patch)?;
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut search_from = 0;
+    let mut offset: isize = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+        let position = if hunk.old_lines.is_empty() {
+            Some(expected.clamp(search_from, lines.len()))
+        } else {
+            (search_from..=lines.len().saturating_sub(hunk.old_lines.len()))
+                .filter(|&start| {
+                    lines.get(start..start + hunk.old_lines.len()) == Some(&hunk.old_lines[..])
+                })
+                .min_by_key(|&start| start.abs_diff(expected))
+        };
+        let Some(position) = position else {
+            let expected_lines = hunk
+                .old_lines
+                .iter()
+                .map(|line| format!("  {line}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            return Err(format!(
+                "Hunk {} ({}) does not apply; expected lines not found:\n{expected_lines}",
+                index + 1,
+                hunk.header
+            ));
+        };
+
+        lines.splice(
+            position..position + hunk.old_lines.len(),
+            hunk.new_lines.iter().cloned(),
+        );
+        search_from = position + hunk.new_lines.len();
+        offset += hunk.new_lines.len() as isize - hunk.old_lines.len() as isize;
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') || (content.is_empty() && !updated.is_empty()) {
+        updated.push('\n');
+    }
+    Ok((updated, hunks.len()))
+}
+
+/// Moves or renames a file or directory; both ends must stay inside the sandbox.
+pub fn move_tool() -> ToolSpec {
+    ToolSpec::new("move", "Move or rename a file or directory")
         .with_schema(json!({
             "type": "object",
             "properties": {
-                "pattern": {"type": "string"},
-                "path": {"type": "string"}
+                "from": {"type": "string"},
+                "to": {"type": "string"},
+                "overwrite": {"type": "boolean"}
             },
-            "required": ["pattern"],
+            "required": ["from", "to"],
             "additionalProperties": false
         }))
         .expect("valid schema")
         .with_handler(|args, deps| {
-            let pattern = args
-                .get("pattern")
+            let from = args
+                .get("from")
                 .and_then(|v| v.as_str())
                 .unwrap_or_default()
                 .to_string();
-            let path = args
-                .get("path")
+            let to = args
+                .get("to")
                 .and_then(|v| v.as_str())
-                .map(ToString::to_string);
+                .unwrap_or_default()
+                .to_string();
+            let overwrite = args
+                .get("overwrite")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let ctx = get_ctx(deps);
+
+            async move {
+                let ctx = match ctx {
+                    Ok(ctx) => ctx,
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+                };
+
+                let source = match ctx.resolve_path(&from) {
+                    Ok(path) => path,
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
+                };
+                let destination = match ctx.resolve_path(&to) {
+                    Ok(path) => path,
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
+                };
+
+                if !source.exists() {
+                    return Ok(ToolOutcome::Text(format!("File not found: {from}")));
+                }
+                if source == ctx.root_dir() {
+                    return Ok(ToolOutcome::Text(
+                        "Error: cannot move the sandbox root".to_string(),
+                    ));
+                }
+                if destination.starts_with(&source) {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Error: cannot move {from} into itself"
+                    )));
+                }
+                if destination.exists() && !overwrite {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Destination already exists: {to} (pass overwrite: true to replace it)"
+                    )));
+                }
+
+                if let Some(parent) = destination.parent()
+                    && let Err(err) = fs::create_dir_all(parent)
+                {
+                    return Ok(ToolOutcome::Text(format!("Error moving file: {err}")));
+                }
+
+                match move_path(&source, &destination) {
+                    Ok(()) => Ok(ToolOutcome::Text(format!(
+                        "Moved {} -> {}",
+                        source.display(),
+                        destination.display()
+                    ))),
+                    Err(err) => Ok(ToolOutcome::Text(format!("Error moving file: {err}"))),
+                }
+            }
+        })
+}
+
+/// Renames `from` to `to`, falling back to copy and remove when they sit on different
+/// filesystems.
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            if from.is_dir() {
+                copy_dir_all(from, to)?;
+                fs::remove_dir_all(from)
+            } else {
+                fs::copy(from, to)?;
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry.map_err(std::io::Error::other)?;
+        let relative = entry
+            .path()
+            .strip_prefix(from)
+            .map_err(std::io::Error::other)?;
+        let target = to.join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Directory under the sandbox root that deleted paths are moved into.
+const TRASH_DIR: &str = ".trash";
+const TRASH_MANIFEST: &str = "manifest.jsonl";
+
+/// Deletes a file or directory by moving it into `.trash/{session_id}/` under the sandbox root.
+///
+/// Each deletion appends `{"original", "trashed"}` to the session's `manifest.jsonl` so it can
+/// be undone later.
+pub fn delete_tool() -> ToolSpec {
+    ToolSpec::new(
+        "delete",
+        "Delete a file or directory (moved to a recoverable trash directory)",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "path": {"type": "string"}
+        },
+        "required": ["path"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, deps| {
+        let path = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let ctx = get_ctx(deps);
+
+        async move {
+            let ctx = match ctx {
+                Ok(ctx) => ctx,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            let target = match ctx.resolve_path(&path) {
+                Ok(path) => path,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Security error: {err}"))),
+            };
+
+            if !target.exists() {
+                return Ok(ToolOutcome::Text(format!("File not found: {path}")));
+            }
+            let trash_root = ctx.root_dir().join(TRASH_DIR);
+            if target == ctx.root_dir() || target.starts_with(&trash_root) {
+                return Ok(ToolOutcome::Text(format!("Error: cannot delete {path}")));
+            }
+
+            match move_to_trash(&ctx, &target) {
+                Ok(trashed) => Ok(ToolOutcome::Text(format!(
+                    "Deleted {} (recoverable from {})",
+                    target.display(),
+                    trashed.display()
+                ))),
+                Err(err) => Ok(ToolOutcome::Text(format!("Error deleting file: {err}"))),
+            }
+        }
+    })
+}
+
+/// Moves `target` into the session trash, keeping its sandbox-relative layout, and records
+/// the original location in the trash manifest.
+fn move_to_trash(ctx: &SandboxContext, target: &Path) -> std::io::Result<PathBuf> {
+    let session_trash = ctx.root_dir().join(TRASH_DIR).join(ctx.session_id());
+    let relative = target
+        .strip_prefix(ctx.root_dir())
+        .map_err(std::io::Error::other)?;
+
+    let mut trashed = session_trash.join(relative);
+    let mut suffix = 1;
+    while trashed.exists() {
+        let mut name = relative.as_os_str().to_os_string();
+        name.push(format!(".{suffix}"));
+        trashed = session_trash.join(name);
+        suffix += 1;
+    }
+
+    if let Some(parent) = trashed.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(target, &trashed)?;
+
+    let record = json!({
+        "original": target.display().to_string(),
+        "trashed": trashed.display().to_string(),
+    });
+    let mut manifest = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(session_trash.join(TRASH_MANIFEST))?;
+    writeln!(manifest, "{record}")?;
+
+    Ok(trashed)
+}
+
+/// Default cap on entries returned by the `ls` tool.
+const DEFAULT_LS_MAX_ENTRIES: usize = 200;
+
+pub fn ls_tool() -> ToolSpec {
+    ToolSpec::new("ls", "List directory contents")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "recursive": {"type": "boolean"},
+                "max_entries": {"type": "integer", "minimum": 1}
+            },
+            "required": [],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(|args, deps| {
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .map(ToString::to_string);
+            let recursive = args
+                .get("recursive")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let max_entries = args
+                .get("max_entries")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+                .unwrap_or(DEFAULT_LS_MAX_ENTRIES);
+            let ctx = get_ctx(deps);
+
+            async move {
+                let ctx = match ctx {
+                    Ok(ctx) => ctx,
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+                };
+
+                let dir = match path {
+                    Some(p) => match ctx.resolve_path(p) {
+                        Ok(path) => path,
+                        Err(err) => {
+                            return Ok(ToolOutcome::Text(format!("Security error: {err}")));
+                        }
+                    },
+                    None => ctx.working_dir(),
+                };
+                if !dir.is_dir() {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Not a directory: {}",
+                        dir.display()
+                    )));
+                }
+
+                let walker = WalkDir::new(&dir)
+                    .min_depth(1)
+                    .max_depth(if recursive { usize::MAX } else { 1 })
+                    .follow_links(false)
+                    .sort_by_file_name();
+
+                let mut entries = Vec::new();
+                let mut omitted = 0usize;
+                for entry in walker.into_iter().flatten() {
+                    if entries.len() >= max_entries {
+                        omitted += 1;
+                        continue;
+                    }
+                    let shown = entry
+                        .path()
+                        .strip_prefix(ctx.root_dir())
+                        .unwrap_or(entry.path())
+                        .display()
+                        .to_string();
+                    if entry.file_type().is_dir() {
+                        entries.push(format!("{shown}/"));
+                    } else {
+                        entries.push(shown);
+                    }
+                }
+
+                if entries.is_empty() {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Directory is empty: {}",
+                        dir.display()
+                    )));
+                }
+
+                let mut rendered = entries.join("\n");
+                if omitted > 0 {
+                    rendered.push_str(&format!("\n... ({omitted} more entries not shown)"));
+                }
+                Ok(ToolOutcome::Text(rendered))
+            }
+        })
+}
+
+pub fn glob_search_tool() -> ToolSpec {
+    ToolSpec::new("glob_search", "Find files matching a glob pattern")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {
+                "pattern": {"type": "string"},
+                "path": {"type": "string"}
+            },
+            "required": ["pattern"],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(|args, deps| {
+            let pattern = args
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .map(ToString::to_string);
+            let ctx = get_ctx(deps);
+
+            async move {
+                let ctx = match ctx {
+                    Ok(ctx) => ctx,
+                    Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+                };
+
+                let search_dir = match path {
+                    Some(p) => match ctx.resolve_path(p) {
+                        Ok(path) => path,
+                        Err(err) => {
+                            return Ok(ToolOutcome::Text(format!("Security error: {err}")));
+                        }
+                    },
+                    None => ctx.working_dir(),
+                };
+
+                let pattern = match Pattern::new(&pattern) {
+                    Ok(pattern) => pattern,
+                    Err(err) => {
+                        return Ok(ToolOutcome::Text(format!("Invalid glob pattern: {err}")));
+                    }
+                };
+
+                let mut files = Vec::new();
+                for entry in WalkDir::new(&search_dir).into_iter().flatten() {
+                    if !entry.file_type().is_file() {
+                        continue;
+                    }
+
+                    let rel_from_search = match entry.path().strip_prefix(&search_dir) {
+                        Ok(path) => path,
+                        Err(_) => continue,
+                    };
+                    if !pattern.matches_path(rel_from_search) {
+                        continue;
+                    }
+
+                    let shown = entry
+                        .path()
+                        .strip_prefix(ctx.root_dir())
+                        .unwrap_or(entry.path())
+                        .display()
+                        .to_string();
+                    files.push(shown);
+
+                    if files.len() >= 50 {
+                        break;
+                    }
+                }
+
+                if files.is_empty() {
+                    Ok(ToolOutcome::Text(format!(
+                        "No files match pattern: {pattern}"
+                    )))
+                } else {
+                    Ok(ToolOutcome::Text(format!(
+                        "Found {} file(s):\n{}",
+                        files.len(),
+                        files.join("\n")
+                    )))
+                }
+            }
+        })
+}
+
+pub fn grep_tool() -> ToolSpec {
+    ToolSpec::new("grep", "Search file contents with regex")
+        .with_schema(json!({
+            "type": "object",
+            "properties": {
+                "pattern": {"type": "string"},
+                "path": {"type": "string"},
+                "glob": {"type": "string"},
+                "ignore_case": {"type": "boolean"},
+                "fixed_strings": {"type": "boolean"},
+                "before": {"type": "integer", "minimum": 0},
+                "after": {"type": "integer", "minimum": 0}
+            },
+            "required": ["pattern"],
+            "additionalProperties": false
+        }))
+        .expect("valid schema")
+        .with_handler(|args, deps| {
+            let pattern = args
+                .get("pattern")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .map(ToString::to_string);
+            let glob = args
+                .get("glob")
+                .and_then(|v| v.as_str())
+                .map(ToString::to_string);
+            let ignore_case = args
+                .get("ignore_case")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let fixed_strings = args
+                .get("fixed_strings")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let before = args.get("before").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+            let after = args.get("after").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
             let ctx = get_ctx(deps);
 
             async move {
@@ -480,43 +1531,87 @@ pub fn grep_tool() -> ToolSpec {
                             return Ok(ToolOutcome::Text(format!("Security error: {err}")));
                         }
                     },
-                    None => ctx.working_dir().to_path_buf(),
+                    None => ctx.working_dir(),
                 };
 
-                let regex = match Regex::new(&pattern) {
+                let mut source = if fixed_strings {
+                    regex::escape(&pattern)
+                } else {
+                    pattern.clone()
+                };
+                if ignore_case {
+                    source = format!("(?i){source}");
+                }
+                let regex = match Regex::new(&source) {
                     Ok(regex) => regex,
                     Err(err) => return Ok(ToolOutcome::Text(format!("Invalid regex: {err}"))),
                 };
 
+                let glob = match glob.as_deref().map(Pattern::new).transpose() {
+                    Ok(glob) => glob,
+                    Err(err) => {
+                        return Ok(ToolOutcome::Text(format!("Invalid glob pattern: {err}")));
+                    }
+                };
+
+                let with_context = before > 0 || after > 0;
                 let mut results = Vec::new();
+                let mut match_count = 0;
                 for entry in WalkDir::new(&search_dir).into_iter().flatten() {
                     if !entry.file_type().is_file() {
                         continue;
                     }
+                    if let Some(glob) = &glob {
+                        let rel_from_search = entry
+                            .path()
+                            .strip_prefix(&search_dir)
+                            .unwrap_or(entry.path());
+                        if !glob.matches_path(rel_from_search) {
+                            continue;
+                        }
+                    }
 
                     let content = match fs::read_to_string(entry.path()) {
                         Ok(content) => content,
                         Err(_) => continue,
                     };
+                    let rel = entry
+                        .path()
+                        .strip_prefix(ctx.root_dir())
+                        .unwrap_or(entry.path())
+                        .display()
+                        .to_string();
 
-                    for (index, line) in content.lines().enumerate() {
-                        if regex.is_match(line) {
-                            let rel = entry
-                                .path()
-                                .strip_prefix(ctx.root_dir())
-                                .unwrap_or(entry.path())
-                                .display();
-                            let line_preview = if line.chars().count() > 100 {
-                                let truncated = line.chars().take(100).collect::<String>();
-                                format!("{truncated}...")
-                            } else {
-                                line.to_string()
-                            };
-                            results.push(format!("{rel}:{}: {line_preview}", index + 1));
-                            if results.len() >= 50 {
-                                results.push("... (truncated)".to_string());
-                                return Ok(ToolOutcome::Text(results.join("\n")));
-                            }
+                    let lines = content.lines().collect::<Vec<_>>();
+                    // Index one past the last line already printed for this file.
+                    let mut printed_until = 0;
+                    for (index, line) in lines.iter().enumerate() {
+                        if !regex.is_match(line) {
+                            continue;
+                        }
+                        match_count += 1;
+
+                        let start = index.saturating_sub(before).max(printed_until);
+                        let end = (index + after + 1).min(lines.len());
+                        if with_context
+                            && start < end
+                            && !results.is_empty()
+                            && (printed_until == 0 || start > printed_until)
+                        {
+                            results.push("--".to_string());
+                        }
+                        for (number, text) in (start + 1..).zip(&lines[start.min(end)..end]) {
+                            let separator = if regex.is_match(text) { ':' } else { '-' };
+                            results.push(format!(
+                                "{rel}{separator}{number}{separator} {}",
+                                grep_line_preview(text)
+                            ));
+                        }
+                        printed_until = printed_until.max(end);
+
+                        if match_count >= 50 {
+                            results.push("... (truncated)".to_string());
+                            return Ok(ToolOutcome::Text(results.join("\n")));
                         }
                     }
                 }
@@ -530,6 +1625,294 @@ pub fn grep_tool() -> ToolSpec {
         })
 }
 
+/// Replaces a literal string or regex in every matching file under the working directory.
+///
+/// Not part of [`all_tools`]; register it explicitly. `glob` is matched against paths relative to
+/// the working directory, `.git` directories and the `delete` tool's `.trash` are skipped, and
+/// `dry_run` reports per-file match counts without writing. In regex mode the replacement may use
+/// `$1`-style capture references.
+pub fn replace_in_files_tool() -> ToolSpec {
+    ToolSpec::new(
+        "replace_in_files",
+        "Replace text in all files under the working directory, optionally limited by glob; use dry_run to preview",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "pattern": {"type": "string"},
+            "replacement": {"type": "string"},
+            "glob": {"type": "string"},
+            "regex": {"type": "boolean"},
+            "dry_run": {"type": "boolean"}
+        },
+        "required": ["pattern", "replacement"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, deps| {
+        let pattern = args
+            .get("pattern")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let replacement = args
+            .get("replacement")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let glob = args
+            .get("glob")
+            .and_then(|v| v.as_str())
+            .map(ToString::to_string);
+        let use_regex = args.get("regex").and_then(|v| v.as_bool()).unwrap_or(false);
+        let dry_run = args
+            .get("dry_run")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let ctx = get_ctx(deps);
+
+        async move {
+            let ctx = match ctx {
+                Ok(ctx) => ctx,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            if pattern.is_empty() {
+                return Ok(ToolOutcome::Text("Error: pattern must not be empty".to_string()));
+            }
+            let source = if use_regex {
+                pattern.clone()
+            } else {
+                regex::escape(&pattern)
+            };
+            let regex = match Regex::new(&source) {
+                Ok(regex) => regex,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Invalid regex: {err}"))),
+            };
+            let glob = match glob.as_deref().map(Pattern::new).transpose() {
+                Ok(glob) => glob,
+                Err(err) => {
+                    return Ok(ToolOutcome::Text(format!("Invalid glob pattern: {err}")));
+                }
+            };
+
+            let search_dir = ctx.working_dir();
+            let trash_dir = ctx.root_dir().join(TRASH_DIR);
+            let mut changes = Vec::new();
+            let walker = WalkDir::new(&search_dir)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|entry| entry.file_name() != ".git" && entry.path() != trash_dir);
+            for entry in walker.flatten() {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                if let Some(glob) = &glob {
+                    let rel_from_search = entry
+                        .path()
+                        .strip_prefix(&search_dir)
+                        .unwrap_or(entry.path());
+                    if !glob.matches_path(rel_from_search) {
+                        continue;
+                    }
+                }
+
+                let content = match fs::read_to_string(entry.path()) {
+                    Ok(content) => content,
+                    Err(_) => continue,
+                };
+                let count = regex.find_iter(&content).count();
+                if count == 0 {
+                    continue;
+                }
+                let updated = if use_regex {
+                    regex.replace_all(&content, replacement.as_str())
+                } else {
+                    regex.replace_all(&content, regex::NoExpand(&replacement))
+                };
+                let rel = entry
+                    .path()
+                    .strip_prefix(ctx.root_dir())
+                    .unwrap_or(entry.path())
+                    .display()
+                    .to_string();
+                changes.push((entry.path().to_path_buf(), rel, count, updated.into_owned()));
+            }
+
+            if changes.is_empty() {
+                return Ok(ToolOutcome::Text(format!("No matches for: {pattern}")));
+            }
+
+            let total = changes.iter().map(|(_, _, count, _)| count).sum::<usize>();
+            let mut lines = Vec::with_capacity(changes.len());
+            for (path, rel, count, updated) in &changes {
+                if !dry_run && let Err(err) = fs::write(path, updated.as_bytes()) {
+                    lines.push(format!("{rel}: error writing file: {err}"));
+                    continue;
+                }
+                lines.push(format!("{rel}: {count} match(es)"));
+            }
+            let header = if dry_run {
+                format!(
+                    "Dry run: {total} match(es) in {} file(s) would change; nothing written",
+                    changes.len()
+                )
+            } else {
+                format!("Replaced {total} match(es) in {} file(s)", changes.len())
+            };
+            Ok(ToolOutcome::Text(format!("{header}\n{}", lines.join("\n"))))
+        }
+    })
+}
+
+fn grep_line_preview(line: &str) -> String {
+    if line.chars().count() > 100 {
+        let truncated = line.chars().take(100).collect::<String>();
+        format!("{truncated}...")
+    } else {
+        line.to_string()
+    }
+}
+
+const WEB_FETCH_DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+const WEB_FETCH_TIMEOUT_SECS: u64 = 30;
+
+pub fn web_fetch_tool() -> ToolSpec {
+    ToolSpec::new(
+        "web_fetch",
+        "Fetch an http(s) URL and return its text content",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "url": {"type": "string"},
+            "max_bytes": {"type": "integer", "minimum": 1}
+        },
+        "required": ["url"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, _deps| {
+        let url = args
+            .get("url")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let max_bytes = args
+            .get("max_bytes")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .unwrap_or(WEB_FETCH_DEFAULT_MAX_BYTES);
+
+        async move {
+            let parsed = match reqwest::Url::parse(&url) {
+                Ok(parsed) => parsed,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Invalid URL: {err}"))),
+            };
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Ok(ToolOutcome::Text(format!(
+                    "Unsupported URL scheme: {} (only http and https are allowed)",
+                    parsed.scheme()
+                )));
+            }
+
+            let client = match reqwest::Client::builder()
+                .timeout(Duration::from_secs(WEB_FETCH_TIMEOUT_SECS))
+                .build()
+            {
+                Ok(client) => client,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            let mut response = match client.get(parsed).send().await {
+                Ok(response) => response,
+                Err(err) if err.is_timeout() => {
+                    return Ok(ToolOutcome::Text(format!(
+                        "Request timed out after {WEB_FETCH_TIMEOUT_SECS}s"
+                    )));
+                }
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error fetching {url}: {err}"))),
+            };
+
+            let status = response.status();
+            if !status.is_success() {
+                return Ok(ToolOutcome::Text(format!("HTTP {status} fetching {url}")));
+            }
+
+            let is_html = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.contains("html"));
+
+            let mut body = Vec::new();
+            let mut truncated = false;
+            loop {
+                match response.chunk().await {
+                    Ok(Some(chunk)) => {
+                        // A chunk that exactly fills the cap keeps the loop reading, so any
+                        // further byte marks the body as truncated.
+                        let remaining = max_bytes - body.len();
+                        if chunk.len() > remaining {
+                            body.extend_from_slice(&chunk[..remaining]);
+                            truncated = true;
+                            break;
+                        }
+                        body.extend_from_slice(&chunk);
+                    }
+                    Ok(None) => break,
+                    Err(err) if err.is_timeout() => {
+                        return Ok(ToolOutcome::Text(format!(
+                            "Request timed out after {WEB_FETCH_TIMEOUT_SECS}s"
+                        )));
+                    }
+                    Err(err) => {
+                        return Ok(ToolOutcome::Text(format!("Error reading {url}: {err}")));
+                    }
+                }
+            }
+
+            let raw = String::from_utf8_lossy(&body);
+            let mut text = if is_html {
+                html_to_text(&raw)
+            } else {
+                raw.trim().to_string()
+            };
+            if text.is_empty() {
+                text = "(empty response)".to_string();
+            }
+            if truncated {
+                text.push_str(&format!("\n... (truncated at {max_bytes} bytes)"));
+            }
+            Ok(ToolOutcome::Text(text))
+        }
+    })
+}
+
+fn html_to_text(html: &str) -> String {
+    let without_blocks =
+        Regex::new(r"(?is)<(script|style|noscript)\b.*?</(script|style|noscript)>")
+            .expect("valid regex")
+            .replace_all(html, " ");
+    let without_tags = Regex::new(r"(?s)<[^>]*>")
+        .expect("valid regex")
+        .replace_all(&without_blocks, " ");
+    let decoded = without_tags
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    decoded
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub fn todo_read_tool() -> ToolSpec {
     ToolSpec::new("todo_read", "Read current todo list")
         .with_schema(json!({
@@ -657,6 +2040,33 @@ pub fn done_tool() -> ToolSpec {
         })
 }
 
+/// Done tool that also returns structured `data` to the caller via `FinalResponse`.
+pub fn done_structured_tool() -> ToolSpec {
+    ToolSpec::new(
+        "done",
+        "Signal that the task is complete and return structured result data",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "message": {"type": "string"},
+            "data": {}
+        },
+        "required": ["message", "data"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, _deps| {
+        let message = args
+            .get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("task complete")
+            .to_string();
+        let data = args.get("data").cloned().unwrap_or(Value::Null);
+        async move { Ok(ToolOutcome::DoneWithData { message, data }) }
+    })
+}
+
 fn get_ctx(deps: &DependencyMap) -> Result<Arc<SandboxContext>, ToolError> {
     deps.get::<SandboxContext>()
         .ok_or(ToolError::MissingDependency("SandboxContext"))
@@ -674,26 +2084,341 @@ fn short_session_id() -> String {
 mod tests {
     use std::fs;
 
-    use serde_json::json;
+    use serde_json::json;
+
+    use super::*;
+
+    fn test_context() -> SandboxContext {
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let root =
+            std::env::temp_dir().join(format!("agent_sdk_rs_tools_{}_{id}", short_session_id()));
+        SandboxContext::create(Some(root)).expect("sandbox create")
+    }
+
+    fn deps_with_ctx(ctx: SandboxContext) -> DependencyMap {
+        let deps = DependencyMap::new();
+        deps.insert(ctx);
+        deps
+    }
+
+    #[test]
+    fn ephemeral_sandbox_removes_root_on_drop() {
+        let sandbox = SandboxContext::create_ephemeral().expect("ephemeral sandbox");
+        let root = sandbox.root_dir().to_path_buf();
+        fs::create_dir_all(root.join("nested")).expect("create nested");
+        fs::write(root.join("nested/file.txt"), "data").expect("write sample");
+        assert!(root.starts_with(std::env::temp_dir().canonicalize().expect("temp dir")));
+
+        drop(sandbox);
+
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn ephemeral_sandboxes_get_distinct_roots() {
+        let first = SandboxContext::create_ephemeral().expect("ephemeral sandbox");
+        let second = SandboxContext::create_ephemeral().expect("ephemeral sandbox");
+        assert_ne!(first.root_dir(), second.root_dir());
+
+        let second_root = second.root_dir().to_path_buf();
+        drop(first);
+        assert!(second_root.exists());
+    }
+
+    #[test]
+    fn cleanup_removes_persistent_sandbox() {
+        let ctx = test_context();
+        let root = ctx.root_dir().to_path_buf();
+        fs::write(root.join("file.txt"), "data").expect("write sample");
+
+        ctx.cleanup().expect("cleanup");
+
+        assert!(!root.exists());
+    }
+
+    #[tokio::test]
+    async fn path_resolution_blocks_escape() {
+        let ctx = test_context();
+        let result = ctx.resolve_path("../../etc/passwd");
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn bash_allowlist_permits_listed_programs_only() {
+        let ctx = test_context().with_command_allowlist(vec!["echo".to_string()]);
+        let deps = deps_with_ctx(ctx.clone());
+
+        let allowed = bash_tool()
+            .execute(json!({"command": "echo hello"}), &deps)
+            .await
+            .expect("bash ok");
+        assert_eq!(allowed, ToolOutcome::Text("hello".to_string()));
+
+        let denied = bash_tool()
+            .execute(json!({"command": "/bin/rm -rf ."}), &deps)
+            .await
+            .expect("bash ok");
+        assert_eq!(
+            denied,
+            ToolOutcome::Text("Command not permitted: rm".to_string())
+        );
+
+        let piped = bash_tool()
+            .execute(json!({"command": "echo hi | sh"}), &deps)
+            .await
+            .expect("bash ok");
+        assert!(matches!(piped, ToolOutcome::Text(ref t) if t.contains("metacharacter '|'")));
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn cd_changes_where_relative_paths_resolve() {
+        let ctx = test_context();
+        fs::create_dir_all(ctx.root_dir().join("src")).expect("mkdir");
+        fs::write(ctx.root_dir().join("src/lib.rs"), "pub fn nested() {}\n").expect("write");
+        let deps = deps_with_ctx(ctx.clone());
+
+        let output = cd_tool()
+            .execute(json!({"path": "src"}), &deps)
+            .await
+            .expect("cd ok");
+        let expected_dir = ctx.root_dir().join("src");
+        assert_eq!(
+            output,
+            ToolOutcome::Text(format!("Working directory: {}", expected_dir.display()))
+        );
+        assert_eq!(ctx.working_dir(), expected_dir);
+
+        let output = read_tool()
+            .execute(json!({"file_path": "lib.rs"}), &deps)
+            .await
+            .expect("read ok");
+        let ToolOutcome::Text(text) = output else {
+            panic!("expected text");
+        };
+        assert!(text.contains("pub fn nested() {}"));
+
+        let escaped = cd_tool()
+            .execute(json!({"path": "../.."}), &deps)
+            .await
+            .expect("cd ok");
+        assert!(
+            matches!(escaped, ToolOutcome::Text(text) if text.starts_with("Path escapes sandbox"))
+        );
+        assert_eq!(ctx.working_dir(), expected_dir);
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn move_renames_into_new_directory() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(ctx.root_dir().join("old.rs"), "fn old() {}").expect("write sample");
+
+        let result = move_tool()
+            .execute(json!({"from": "old.rs", "to": "src/new.rs"}), &deps)
+            .await
+            .expect("move ok");
+
+        let destination = ctx.root_dir().join("src/new.rs");
+        assert_eq!(
+            result,
+            ToolOutcome::Text(format!(
+                "Moved {} -> {}",
+                ctx.root_dir().join("old.rs").display(),
+                destination.display()
+            ))
+        );
+        assert!(!ctx.root_dir().join("old.rs").exists());
+        assert_eq!(
+            fs::read_to_string(destination).expect("read moved"),
+            "fn old() {}"
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn move_rejects_destination_outside_sandbox() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(ctx.root_dir().join("keep.txt"), "data").expect("write sample");
+
+        let result = move_tool()
+            .execute(json!({"from": "keep.txt", "to": "../escaped.txt"}), &deps)
+            .await
+            .expect("move returns");
+
+        assert!(matches!(result, ToolOutcome::Text(ref t) if t.starts_with("Security error")));
+        assert!(ctx.root_dir().join("keep.txt").exists());
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn move_refuses_to_overwrite_unless_asked() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(ctx.root_dir().join("a.txt"), "new").expect("write sample");
+        fs::write(ctx.root_dir().join("b.txt"), "old").expect("write sample");
+
+        let refused = move_tool()
+            .execute(json!({"from": "a.txt", "to": "b.txt"}), &deps)
+            .await
+            .expect("move returns");
+        assert!(matches!(refused, ToolOutcome::Text(ref t) if t.contains("already exists")));
+        assert_eq!(
+            fs::read_to_string(ctx.root_dir().join("b.txt")).expect("read"),
+            "old"
+        );
+
+        move_tool()
+            .execute(
+                json!({"from": "a.txt", "to": "b.txt", "overwrite": true}),
+                &deps,
+            )
+            .await
+            .expect("move ok");
+        assert!(!ctx.root_dir().join("a.txt").exists());
+        assert_eq!(
+            fs::read_to_string(ctx.root_dir().join("b.txt")).expect("read"),
+            "new"
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn delete_moves_file_into_session_trash() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::create_dir_all(ctx.root_dir().join("src")).expect("create src");
+        fs::write(ctx.root_dir().join("src/old.rs"), "fn old() {}").expect("write sample");
+
+        let result = delete_tool()
+            .execute(json!({"path": "src/old.rs"}), &deps)
+            .await
+            .expect("delete ok");
+
+        let session_trash = ctx.root_dir().join(".trash").join(ctx.session_id());
+        let trashed = session_trash.join("src/old.rs");
+        assert!(matches!(result, ToolOutcome::Text(ref t) if t.contains("recoverable")));
+        assert!(!ctx.root_dir().join("src/old.rs").exists());
+        assert_eq!(
+            fs::read_to_string(&trashed).expect("read trashed"),
+            "fn old() {}"
+        );
+
+        let manifest =
+            fs::read_to_string(session_trash.join("manifest.jsonl")).expect("read manifest");
+        let record: Value = serde_json::from_str(manifest.trim()).expect("manifest json");
+        assert_eq!(
+            record["original"],
+            ctx.root_dir().join("src/old.rs").display().to_string()
+        );
+        assert_eq!(record["trashed"], trashed.display().to_string());
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn delete_rejects_path_outside_sandbox() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+
+        let result = delete_tool()
+            .execute(json!({"path": "../outside.txt"}), &deps)
+            .await
+            .expect("delete returns");
+
+        assert!(matches!(result, ToolOutcome::Text(ref t) if t.starts_with("Security error")));
+        assert!(!ctx.root_dir().join(".trash").exists());
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn ls_lists_sorted_entries_and_caps_output() {
+        let ctx = test_context();
+        fs::create_dir_all(ctx.root_dir().join("src/nested")).expect("mkdir");
+        fs::write(ctx.root_dir().join("src/nested/deep.rs"), "").expect("write");
+        fs::write(ctx.root_dir().join("src/lib.rs"), "").expect("write");
+        fs::write(ctx.root_dir().join("README.md"), "").expect("write");
+        let deps = deps_with_ctx(ctx.clone());
+
+        let output = ls_tool().execute(json!({}), &deps).await.expect("ls ok");
+        assert_eq!(output, ToolOutcome::Text("README.md\nsrc/".to_string()));
+
+        let output = ls_tool()
+            .execute(json!({"recursive": true}), &deps)
+            .await
+            .expect("ls ok");
+        assert_eq!(
+            output,
+            ToolOutcome::Text(
+                "README.md\nsrc/\nsrc/lib.rs\nsrc/nested/\nsrc/nested/deep.rs".to_string()
+            )
+        );
+
+        let output = ls_tool()
+            .execute(
+                json!({"path": "src", "recursive": true, "max_entries": 2}),
+                &deps,
+            )
+            .await
+            .expect("ls ok");
+        assert_eq!(
+            output,
+            ToolOutcome::Text(
+                "src/lib.rs\nsrc/nested/\n... (1 more entries not shown)".to_string()
+            )
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
 
-    use super::*;
+    #[tokio::test]
+    async fn bash_env_replaces_host_environment() {
+        let ctx = test_context().with_env(HashMap::from([
+            ("PATH".to_string(), "/usr/bin:/bin".to_string()),
+            ("AGENT_GREETING".to_string(), "hi".to_string()),
+        ]));
+        let deps = deps_with_ctx(ctx.clone());
+        assert!(std::env::var_os("CARGO_MANIFEST_DIR").is_some());
 
-    fn test_context() -> SandboxContext {
-        let root = std::env::temp_dir().join(format!("agent_sdk_rs_tools_{}", short_session_id()));
-        SandboxContext::create(Some(root)).expect("sandbox create")
-    }
+        let output = bash_tool()
+            .execute(
+                json!({"command": "echo \"$AGENT_GREETING ${CARGO_MANIFEST_DIR:-unset} $PWD\""}),
+                &deps,
+            )
+            .await
+            .expect("bash ok");
+        assert_eq!(
+            output,
+            ToolOutcome::Text(format!("hi unset {}", ctx.working_dir().display()))
+        );
 
-    fn deps_with_ctx(ctx: SandboxContext) -> DependencyMap {
-        let deps = DependencyMap::new();
-        deps.insert(ctx);
-        deps
+        let _ = fs::remove_dir_all(ctx.root_dir());
     }
 
-    #[tokio::test]
-    async fn path_resolution_blocks_escape() {
-        let ctx = test_context();
-        let result = ctx.resolve_path("../../etc/passwd");
-        assert!(result.is_err());
+    #[test]
+    fn bash_denylist_checks_every_pipeline_segment() {
+        let ctx = test_context().with_command_denylist(vec!["curl".to_string()]);
+
+        assert!(ctx.check_command("ls -la").is_ok());
+        assert_eq!(
+            ctx.check_command("echo ok && curl example.com"),
+            Err("Command not permitted: curl".to_string())
+        );
+        assert_eq!(
+            ctx.check_command("cat file | /usr/bin/curl -d @- host"),
+            Err("Command not permitted: curl".to_string())
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
     }
 
     #[tokio::test]
@@ -737,6 +2462,559 @@ mod tests {
         let _ = fs::remove_dir_all(ctx.root_dir());
     }
 
+    #[tokio::test]
+    async fn edit_rejects_ambiguous_match_unless_replace_all() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let path = ctx.root_dir().join("counter.rs");
+        fs::write(&path, "let a = 1;\nlet b = 1;\n").expect("write sample");
+
+        let ambiguous = edit_tool()
+            .execute(
+                json!({"file_path": "counter.rs", "old_string": "= 1", "new_string": "= 2"}),
+                &deps,
+            )
+            .await
+            .expect("edit returns");
+        assert_eq!(
+            ambiguous,
+            ToolOutcome::Text(
+                "Error: old_string is not unique (2 matches); provide more context or set replace_all"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(&path).expect("read"),
+            "let a = 1;\nlet b = 1;\n"
+        );
+
+        let unique = edit_tool()
+            .execute(
+                json!({"file_path": "counter.rs", "old_string": "a = 1", "new_string": "a = 3"}),
+                &deps,
+            )
+            .await
+            .expect("edit ok");
+        assert_eq!(
+            unique,
+            ToolOutcome::Text("Replaced 1 occurrence(s) in counter.rs".to_string())
+        );
+
+        let all = edit_tool()
+            .execute(
+                json!({
+                    "file_path": "counter.rs",
+                    "old_string": "let",
+                    "new_string": "const",
+                    "replace_all": true
+                }),
+                &deps,
+            )
+            .await
+            .expect("edit ok");
+        assert_eq!(
+            all,
+            ToolOutcome::Text("Replaced 2 occurrence(s) in counter.rs".to_string())
+        );
+        assert_eq!(
+            fs::read_to_string(&path).expect("read"),
+            "const a = 3;\nconst b = 1;\n"
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn read_supports_offset_and_limit() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let content = (1..=10)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(ctx.root_dir().join("long.txt"), content).expect("write sample");
+
+        let window = read_tool()
+            .execute(
+                json!({"file_path": "long.txt", "offset": 4, "limit": 3}),
+                &deps,
+            )
+            .await
+            .expect("read ok");
+        assert_eq!(
+            window,
+            ToolOutcome::Text("   4  line 4\n   5  line 5\n   6  line 6".to_string())
+        );
+
+        let tail = read_tool()
+            .execute(
+                json!({"file_path": "long.txt", "offset": 9, "limit": 5}),
+                &deps,
+            )
+            .await
+            .expect("read ok");
+        assert_eq!(
+            tail,
+            ToolOutcome::Text(
+                "   9  line 9\n  10  line 10\n(requested lines 9-13 but the file has 10 lines)"
+                    .to_string()
+            )
+        );
+
+        let unbounded = read_tool()
+            .execute(
+                json!({"file_path": "long.txt", "offset": 10, "limit": u64::MAX}),
+                &deps,
+            )
+            .await
+            .expect("read ok");
+        assert!(matches!(
+            unbounded,
+            ToolOutcome::Text(ref t) if t.starts_with("  10  line 10\n(requested lines 10-")
+        ));
+
+        let past_end = read_tool()
+            .execute(json!({"file_path": "long.txt", "offset": 20}), &deps)
+            .await
+            .expect("read ok");
+        assert!(matches!(past_end, ToolOutcome::Text(ref t) if t.contains("past the end")));
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn read_widens_gutter_for_long_files_and_can_omit_numbers() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let content = (1..=10_050)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(ctx.root_dir().join("huge.txt"), content).expect("write sample");
+
+        let window = read_tool()
+            .execute(
+                json!({"file_path": "huge.txt", "offset": 9_999, "limit": 3}),
+                &deps,
+            )
+            .await
+            .expect("read ok");
+        assert_eq!(
+            window,
+            ToolOutcome::Text(" 9999  line 9999\n10000  line 10000\n10001  line 10001".to_string())
+        );
+
+        fs::write(
+            ctx.root_dir().join("short.txt"),
+            "fn main() {\n    run();\n}\n",
+        )
+        .expect("write sample");
+        let raw = read_tool()
+            .execute(
+                json!({"file_path": "short.txt", "show_line_numbers": false}),
+                &deps,
+            )
+            .await
+            .expect("read ok");
+        assert_eq!(
+            raw,
+            ToolOutcome::Text("fn main() {\n    run();\n}".to_string())
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn read_truncates_large_files_and_rejects_binary() {
+        let ctx = test_context().with_max_read_bytes(16);
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(ctx.root_dir().join("big.txt"), "a".repeat(40)).expect("write sample");
+        fs::write(
+            ctx.root_dir().join("image.png"),
+            [0x89, b'P', b'N', b'G', 0, 1, 2],
+        )
+        .expect("write binary");
+
+        let truncated = read_tool()
+            .execute(json!({"file_path": "big.txt"}), &deps)
+            .await
+            .expect("read ok");
+        assert_eq!(
+            truncated,
+            ToolOutcome::Text(format!("   1  {}\n[truncated 24 bytes]", "a".repeat(16)))
+        );
+
+        let binary = read_tool()
+            .execute(json!({"file_path": "image.png"}), &deps)
+            .await
+            .expect("read ok");
+        assert_eq!(
+            binary,
+            ToolOutcome::Text("Cannot read binary file: image.png".to_string())
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn apply_patch_applies_clean_hunks() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let file = ctx.root_dir().join("main.rs");
+        fs::write(
+            &file,
+            "fn main() {\n    let a = 1;\n    println!(\"{a}\");\n}\n\nfn helper() {}\n",
+        )
+        .expect("write sample");
+
+        let patch = "--- a/main.rs\n+++ b/main.rs\n@@ -1,4 +1,5 @@\n fn main() {\n-    let a = 1;\n+    let a = 2;\n+    let b = a * 2;\n     println!(\"{a}\");\n }\n@@ -6,1 +7,1 @@\n-fn helper() {}\n+fn helper() -> u8 { 0 }\n";
+        let result = apply_patch_tool()
+            .execute(json!({"file_path": "main.rs", "patch": patch}), &deps)
+            .await
+            .expect("patch applies");
+
+        assert_eq!(
+            result,
+            ToolOutcome::Text("Applied 2 hunk(s) to main.rs".to_string())
+        );
+        assert_eq!(
+            fs::read_to_string(&file).expect("read sample"),
+            "fn main() {\n    let a = 2;\n    let b = a * 2;\n    println!(\"{a}\");\n}\n\nfn helper() -> u8 { 0 }\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_patch_rejects_mismatched_hunk_without_writing() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let file = ctx.root_dir().join("notes.txt");
+        let original = "alpha\nbeta\ngamma\n";
+        fs::write(&file, original).expect("write sample");
+
+        let patch = "@@ -1,1 +1,1 @@\n-alpha\n+ALPHA\n@@ -3,1 +3,1 @@\n-delta\n+DELTA\n";
+        let result = apply_patch_tool()
+            .execute(json!({"file_path": "notes.txt", "patch": patch}), &deps)
+            .await
+            .expect("tool returns text");
+
+        let ToolOutcome::Text(text) = result else {
+            panic!("expected text outcome");
+        };
+        assert!(text.starts_with("Hunk 2 (@@ -3,1 +3,1 @@) does not apply"));
+        assert!(text.contains("  delta"));
+        assert!(text.ends_with("no changes written to notes.txt"));
+        assert_eq!(fs::read_to_string(&file).expect("read sample"), original);
+    }
+
+    #[tokio::test]
+    async fn grep_includes_context_lines_between_separators() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(
+            ctx.root_dir().join("log.txt"),
+            "one\ntwo\nERROR a\nthree\nfour\nfive\nsix\nERROR b\nseven\n",
+        )
+        .expect("write sample");
+
+        let result = grep_tool()
+            .execute(json!({"pattern": "ERROR", "before": 1, "after": 1}), &deps)
+            .await
+            .expect("grep ok");
+
+        assert_eq!(
+            result,
+            ToolOutcome::Text(
+                [
+                    "log.txt-2- two",
+                    "log.txt:3: ERROR a",
+                    "log.txt-4- three",
+                    "--",
+                    "log.txt-7- six",
+                    "log.txt:8: ERROR b",
+                    "log.txt-9- seven",
+                ]
+                .join("\n")
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn grep_glob_limits_scanned_files() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::create_dir_all(ctx.root_dir().join("src")).expect("mkdirs");
+        fs::write(ctx.root_dir().join("src").join("lib.rs"), "// TODO: rust\n").expect("write");
+        fs::write(ctx.root_dir().join("notes.md"), "TODO: docs\n").expect("write");
+
+        let result = grep_tool()
+            .execute(json!({"pattern": "TODO", "glob": "**/*.rs"}), &deps)
+            .await
+            .expect("grep ok");
+
+        assert_eq!(
+            result,
+            ToolOutcome::Text("src/lib.rs:1: // TODO: rust".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn grep_ignore_case_and_fixed_strings() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(
+            ctx.root_dir().join("calc.txt"),
+            "Total = a+b\ntotal = aab\nsum(x)\n",
+        )
+        .expect("write sample");
+
+        let ignore_case = grep_tool()
+            .execute(json!({"pattern": "TOTAL", "ignore_case": true}), &deps)
+            .await
+            .expect("grep ok");
+        assert_eq!(
+            ignore_case,
+            ToolOutcome::Text("calc.txt:1: Total = a+b\ncalc.txt:2: total = aab".to_string())
+        );
+
+        let fixed = grep_tool()
+            .execute(json!({"pattern": "a+b", "fixed_strings": true}), &deps)
+            .await
+            .expect("grep ok");
+        assert_eq!(
+            fixed,
+            ToolOutcome::Text("calc.txt:1: Total = a+b".to_string())
+        );
+
+        let both = grep_tool()
+            .execute(
+                json!({"pattern": "SUM(X)", "fixed_strings": true, "ignore_case": true}),
+                &deps,
+            )
+            .await
+            .expect("grep ok");
+        assert_eq!(both, ToolOutcome::Text("calc.txt:3: sum(x)".to_string()));
+
+        let invalid = grep_tool()
+            .execute(json!({"pattern": "sum(", "ignore_case": true}), &deps)
+            .await
+            .expect("grep returns text");
+        assert!(matches!(invalid, ToolOutcome::Text(ref t) if t.starts_with("Invalid regex")));
+    }
+
+    #[tokio::test]
+    async fn replace_in_files_dry_run_reports_without_writing() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::create_dir_all(ctx.root_dir().join("src")).expect("mkdirs");
+        fs::write(ctx.root_dir().join("src/a.rs"), "old_name(); old_name();\n").expect("write");
+        fs::write(ctx.root_dir().join("notes.md"), "old_name\n").expect("write");
+
+        let result = replace_in_files_tool()
+            .execute(
+                json!({
+                    "pattern": "old_name",
+                    "replacement": "new_name",
+                    "glob": "**/*.rs",
+                    "dry_run": true
+                }),
+                &deps,
+            )
+            .await
+            .expect("replace ok");
+
+        assert_eq!(
+            result,
+            ToolOutcome::Text(
+                "Dry run: 2 match(es) in 1 file(s) would change; nothing written\nsrc/a.rs: 2 match(es)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(ctx.root_dir().join("src/a.rs")).expect("read"),
+            "old_name(); old_name();\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn replace_in_files_applies_regex_across_files() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(ctx.root_dir().join("a.txt"), "v1.2 and v3.4\n").expect("write");
+        fs::write(ctx.root_dir().join("b.txt"), "release v5.6\n").expect("write");
+        fs::write(ctx.root_dir().join("c.txt"), "no versions\n").expect("write");
+
+        let result = replace_in_files_tool()
+            .execute(
+                json!({
+                    "pattern": r"v(\d+)\.(\d+)",
+                    "replacement": "version $1-$2",
+                    "regex": true
+                }),
+                &deps,
+            )
+            .await
+            .expect("replace ok");
+
+        assert_eq!(
+            result,
+            ToolOutcome::Text(
+                "Replaced 3 match(es) in 2 file(s)\na.txt: 2 match(es)\nb.txt: 1 match(es)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(ctx.root_dir().join("a.txt")).expect("read"),
+            "version 1-2 and version 3-4\n"
+        );
+        assert_eq!(
+            fs::read_to_string(ctx.root_dir().join("b.txt")).expect("read"),
+            "release version 5-6\n"
+        );
+        assert_eq!(
+            fs::read_to_string(ctx.root_dir().join("c.txt")).expect("read"),
+            "no versions\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn replace_in_files_leaves_trash_untouched() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        fs::write(ctx.root_dir().join("old_name.txt"), "old_name\n").expect("write");
+        fs::write(ctx.root_dir().join("live.txt"), "old_name\n").expect("write");
+        delete_tool()
+            .execute(json!({"path": "old_name.txt"}), &deps)
+            .await
+            .expect("delete ok");
+        let session_trash = ctx.root_dir().join(TRASH_DIR).join(ctx.session_id());
+        let manifest_path = session_trash.join(TRASH_MANIFEST);
+        let manifest = fs::read_to_string(&manifest_path).expect("read manifest");
+
+        let result = replace_in_files_tool()
+            .execute(
+                json!({"pattern": "old_name", "replacement": "new_name"}),
+                &deps,
+            )
+            .await
+            .expect("replace ok");
+
+        assert_eq!(
+            result,
+            ToolOutcome::Text(
+                "Replaced 1 match(es) in 1 file(s)\nlive.txt: 1 match(es)".to_string()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(session_trash.join("old_name.txt")).expect("read trashed"),
+            "old_name\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&manifest_path).expect("read manifest"),
+            manifest
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
+    #[tokio::test]
+    async fn read_bytes_returns_base64_and_honors_cap() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let payload = [0x89_u8, b'P', b'N', b'G', 0x00, 0xff, 0x10, 0x20];
+        fs::write(ctx.root_dir().join("pixel.png"), payload).expect("write binary");
+
+        let decode = |outcome: ToolOutcome| {
+            let ToolOutcome::Text(text) = outcome else {
+                panic!("expected text outcome");
+            };
+            let (header, encoded) = text.split_once("\nbase64:\n").expect("base64 section");
+            (
+                header.to_string(),
+                BASE64.decode(encoded).expect("valid base64"),
+            )
+        };
+
+        let full = read_bytes_tool()
+            .execute(json!({"file_path": "pixel.png"}), &deps)
+            .await
+            .expect("read_bytes executes");
+        let (header, bytes) = decode(full);
+        assert_eq!(header, "pixel.png (image/png, 8 bytes)");
+        assert_eq!(bytes, payload);
+
+        let capped = read_bytes_tool()
+            .execute(json!({"file_path": "pixel.png", "max_bytes": 3}), &deps)
+            .await
+            .expect("read_bytes executes");
+        let (header, bytes) = decode(capped);
+        assert_eq!(
+            header,
+            "pixel.png (image/png, 8 bytes) [truncated to first 3 bytes]"
+        );
+        assert_eq!(bytes, payload[..3]);
+
+        let escaped = read_bytes_tool()
+            .execute(json!({"file_path": "../../etc/passwd"}), &deps)
+            .await
+            .expect("read_bytes executes");
+        assert!(matches!(escaped, ToolOutcome::Text(text) if text.starts_with("Security error")));
+    }
+
+    #[tokio::test]
+    async fn multi_edit_is_all_or_nothing() {
+        let ctx = test_context();
+        let deps = deps_with_ctx(ctx.clone());
+        let file = ctx.root_dir().join("lib.rs");
+        fs::write(&file, "let a = 1;\nlet b = a + a;\n").expect("write sample");
+
+        let failed = multi_edit_tool()
+            .execute(
+                json!({
+                    "file_path": "lib.rs",
+                    "edits": [
+                        {"old_string": "let a", "new_string": "let x"},
+                        {"old_string": "missing", "new_string": "anything"},
+                        {"old_string": "a + a", "new_string": "x + x"}
+                    ]
+                }),
+                &deps,
+            )
+            .await
+            .expect("multi edit returns text");
+        assert!(matches!(failed, ToolOutcome::Text(ref t) if t.starts_with("Edit 2 failed")));
+        assert_eq!(
+            fs::read_to_string(&file).expect("read sample"),
+            "let a = 1;\nlet b = a + a;\n"
+        );
+
+        let applied = multi_edit_tool()
+            .execute(
+                json!({
+                    "file_path": "lib.rs",
+                    "edits": [
+                        {"old_string": "a + a", "new_string": "x + x"},
+                        {"old_string": "let a", "new_string": "let x"}
+                    ]
+                }),
+                &deps,
+            )
+            .await
+            .expect("multi edit ok");
+        assert_eq!(
+            applied,
+            ToolOutcome::Text(
+                "Applied 2 edit(s) to lib.rs\nedit 1: 1 occurrence(s)\nedit 2: 1 occurrence(s)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(&file).expect("read sample"),
+            "let x = 1;\nlet b = x + x;\n"
+        );
+
+        let _ = fs::remove_dir_all(ctx.root_dir());
+    }
+
     #[tokio::test]
     async fn todo_and_search_tools_work() {
         let ctx = test_context();
@@ -792,4 +3070,84 @@ mod tests {
 
         let _ = fs::remove_dir_all(ctx.root_dir());
     }
+
+    /// Serves one canned HTTP response on a local port and returns its base URL.
+    fn serve_once(status_line: &'static str, content_type: &'static str, body: String) -> String {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind listener");
+        let addr = listener.local_addr().expect("local addr");
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0_u8; 1024];
+                let _ = stream.read(&mut buf);
+                let response = format!(
+                    "HTTP/1.1 {status_line}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{addr}/")
+    }
+
+    #[tokio::test]
+    async fn web_fetch_strips_html_and_caps_size() {
+        let url = serve_once(
+            "200 OK",
+            "text/html; charset=utf-8",
+            "<html><head><style>body{}</style></head><body><h1>Docs</h1><p>Hello &amp; welcome</p></body></html>"
+                .to_string(),
+        );
+        let fetched = web_fetch_tool()
+            .execute(json!({"url": url}), &DependencyMap::new())
+            .await
+            .expect("fetch ok");
+        assert_eq!(
+            fetched,
+            ToolOutcome::Text("Docs Hello & welcome".to_string())
+        );
+
+        let url = serve_once("200 OK", "text/plain", "x".repeat(64));
+        let fetched = web_fetch_tool()
+            .execute(json!({"url": url, "max_bytes": 10}), &DependencyMap::new())
+            .await
+            .expect("fetch ok");
+        assert!(matches!(
+            fetched,
+            ToolOutcome::Text(ref t) if t.starts_with("xxxxxxxxxx\n") && t.contains("truncated at 10 bytes")
+        ));
+
+        let url = serve_once("200 OK", "text/plain", "x".repeat(10));
+        let fetched = web_fetch_tool()
+            .execute(json!({"url": url, "max_bytes": 10}), &DependencyMap::new())
+            .await
+            .expect("fetch ok");
+        assert_eq!(fetched, ToolOutcome::Text("x".repeat(10)));
+    }
+
+    #[tokio::test]
+    async fn web_fetch_reports_errors_as_text() {
+        let url = serve_once("404 Not Found", "text/plain", "missing".to_string());
+        let fetched = web_fetch_tool()
+            .execute(json!({"url": url}), &DependencyMap::new())
+            .await
+            .expect("tool returns text");
+        assert!(matches!(fetched, ToolOutcome::Text(ref t) if t.starts_with("HTTP 404")));
+
+        let fetched = web_fetch_tool()
+            .execute(json!({"url": "file:///etc/passwd"}), &DependencyMap::new())
+            .await
+            .expect("tool returns text");
+        assert!(
+            matches!(fetched, ToolOutcome::Text(ref t) if t.contains("Unsupported URL scheme"))
+        );
+
+        assert!(!all_tools().iter().any(|tool| tool.name() == "web_fetch"));
+        assert!(
+            all_tools_with_network()
+                .iter()
+                .any(|tool| tool.name() == "web_fetch")
+        );
+    }
 }
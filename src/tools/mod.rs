@@ -1,4 +1,5 @@
 pub mod claude_code;
+pub mod rust;
 
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
@@ -6,25 +7,66 @@ use std::future::Future;
 use std::sync::{Arc, RwLock};
 
 use futures_util::future::BoxFuture;
+use regex::Regex;
 use serde_json::Value;
+use tokio::sync::{OnceCell, Semaphore};
 
 use crate::error::{SchemaError, ToolError};
+use crate::llm::ImageData;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ToolOutcome {
     Text(String),
     Done(String),
+    /// Like [`ToolOutcome::Done`], with a machine-readable result for the caller.
+    DoneWithData {
+        message: String,
+        data: Value,
+    },
+    /// Tool result plus a note added as a user message before the next model call.
+    ///
+    /// The note follows every tool result of the same turn and is emitted as
+    /// `AgentEvent::HiddenUserMessage`. It is dropped if the run ends in that turn.
+    TextWithNote {
+        result: String,
+        note: String,
+    },
+    /// Result with image attachments; providers without image support get a text note instead.
+    Multimodal {
+        text: Option<String>,
+        images: Vec<ImageData>,
+    },
+    /// Pause the run until the host answers via `Agent::provide_input`.
+    NeedsInput {
+        prompt: String,
+    },
 }
 
 type DynDependency = Arc<dyn Any + Send + Sync>;
+type DependencyFactory = dyn Fn() -> BoxFuture<'static, DynDependency> + Send + Sync;
 type ToolHandler = dyn Fn(Value, &DependencyMap) -> BoxFuture<'static, Result<ToolOutcome, ToolError>>
     + Send
     + Sync;
 
+/// Factory plus the once-initialized value it produces; shared across merged maps.
+struct LazyDependency {
+    cell: OnceCell<DynDependency>,
+    factory: Box<DependencyFactory>,
+}
+
+impl std::fmt::Debug for LazyDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyDependency")
+            .field("initialized", &self.cell.initialized())
+            .finish()
+    }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct DependencyMap {
     typed: Arc<RwLock<HashMap<TypeId, DynDependency>>>,
     named: Arc<RwLock<HashMap<String, DynDependency>>>,
+    lazy: Arc<RwLock<HashMap<TypeId, Arc<LazyDependency>>>>,
 }
 
 impl DependencyMap {
@@ -41,17 +83,71 @@ impl DependencyMap {
             .write()
             .expect("dependency typed map lock poisoned");
         typed.insert(TypeId::of::<T>(), Arc::new(value));
+        drop(typed);
+        self.remove_lazy(TypeId::of::<T>());
     }
 
+    /// Registers a dependency that is built on first [`DependencyMap::get_or_init`].
+    ///
+    /// The factory runs at most once, even under concurrent access; unused dependencies are
+    /// never built.
+    pub fn insert_lazy<T, F>(&self, factory: F)
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> BoxFuture<'static, T> + Send + Sync + 'static,
+    {
+        let lazy = LazyDependency {
+            cell: OnceCell::new(),
+            factory: Box::new(move || {
+                let future = factory();
+                Box::pin(async move { Arc::new(future.await) as DynDependency })
+            }),
+        };
+        self.lazy
+            .write()
+            .expect("dependency lazy map lock poisoned")
+            .insert(TypeId::of::<T>(), Arc::new(lazy));
+        if let Ok(mut typed) = self.typed.write() {
+            typed.remove(&TypeId::of::<T>());
+        }
+    }
+
+    /// Returns an eager dependency, or a lazy one that has already been initialized.
     pub fn get<T>(&self) -> Option<Arc<T>>
     where
         T: Send + Sync + 'static,
     {
-        let typed = self.typed.read().ok()?;
-        let value = typed.get(&TypeId::of::<T>())?.clone();
+        let value = match self.typed.read().ok()?.get(&TypeId::of::<T>()) {
+            Some(value) => value.clone(),
+            None => self.lazy_entry(TypeId::of::<T>())?.cell.get()?.clone(),
+        };
         Arc::downcast::<T>(value).ok()
     }
 
+    /// Returns the dependency, running its lazy factory first if needed.
+    pub async fn get_or_init<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        if let Some(value) = self.get::<T>() {
+            return Some(value);
+        }
+        let lazy = self.lazy_entry(TypeId::of::<T>())?;
+        let value = lazy.cell.get_or_init(|| (lazy.factory)()).await.clone();
+        Arc::downcast::<T>(value).ok()
+    }
+
+    fn lazy_entry(&self, key: TypeId) -> Option<Arc<LazyDependency>> {
+        self.lazy.read().ok()?.get(&key).cloned()
+    }
+
+    fn remove_lazy(&self, key: TypeId) -> bool {
+        self.lazy
+            .write()
+            .map(|mut lazy| lazy.remove(&key).is_some())
+            .unwrap_or(false)
+    }
+
     pub fn insert_named<T>(&self, key: impl Into<String>, value: T)
     where
         T: Send + Sync + 'static,
@@ -72,6 +168,45 @@ impl DependencyMap {
         Arc::downcast::<T>(value).ok()
     }
 
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Send + Sync + 'static,
+    {
+        self.typed
+            .read()
+            .map(|typed| typed.contains_key(&TypeId::of::<T>()))
+            .unwrap_or(false)
+            || self.lazy_entry(TypeId::of::<T>()).is_some()
+    }
+
+    pub fn contains_named(&self, key: &str) -> bool {
+        self.named
+            .read()
+            .map(|named| named.contains_key(key))
+            .unwrap_or(false)
+    }
+
+    pub fn remove<T>(&self) -> bool
+    where
+        T: Send + Sync + 'static,
+    {
+        let mut typed = self
+            .typed
+            .write()
+            .expect("dependency typed map lock poisoned");
+        let removed = typed.remove(&TypeId::of::<T>()).is_some();
+        drop(typed);
+        self.remove_lazy(TypeId::of::<T>()) || removed
+    }
+
+    pub fn remove_named(&self, key: &str) -> bool {
+        let mut named = self
+            .named
+            .write()
+            .expect("dependency named map lock poisoned");
+        named.remove(key).is_some()
+    }
+
     pub fn merged_with(&self, overrides: &DependencyMap) -> DependencyMap {
         let merged = DependencyMap::new();
 
@@ -90,6 +225,24 @@ impl DependencyMap {
                     dst_typed.insert(*key, value.clone());
                 }
             }
+
+            let mut dst_lazy = merged
+                .lazy
+                .write()
+                .expect("dependency lazy map lock poisoned");
+            if let Ok(src_lazy) = self.lazy.read() {
+                for (key, value) in &*src_lazy {
+                    if !dst_typed.contains_key(key) {
+                        dst_lazy.insert(*key, value.clone());
+                    }
+                }
+            }
+            if let Ok(src_lazy_override) = overrides.lazy.read() {
+                for (key, value) in &*src_lazy_override {
+                    dst_typed.remove(key);
+                    dst_lazy.insert(*key, value.clone());
+                }
+            }
         }
 
         {
@@ -111,6 +264,53 @@ impl DependencyMap {
 
         merged
     }
+
+    /// Copies every typed, lazy, and named entry of `other` into this map.
+    ///
+    /// Entries from `other` replace existing ones with the same type or name, as in
+    /// [`DependencyMap::merged_with`]; lazy dependencies keep their shared initialization state.
+    pub fn extend_from(&self, other: &DependencyMap) {
+        // Snapshot first so extending a map from a clone of itself cannot deadlock.
+        let typed = other
+            .typed
+            .read()
+            .map(|typed| typed.clone())
+            .unwrap_or_default();
+        let lazy = other
+            .lazy
+            .read()
+            .map(|lazy| lazy.clone())
+            .unwrap_or_default();
+        let named = other
+            .named
+            .read()
+            .map(|named| named.clone())
+            .unwrap_or_default();
+
+        {
+            let mut dst_typed = self
+                .typed
+                .write()
+                .expect("dependency typed map lock poisoned");
+            let mut dst_lazy = self
+                .lazy
+                .write()
+                .expect("dependency lazy map lock poisoned");
+            for (key, value) in typed {
+                dst_lazy.remove(&key);
+                dst_typed.insert(key, value);
+            }
+            for (key, value) in lazy {
+                dst_typed.remove(&key);
+                dst_lazy.insert(key, value);
+            }
+        }
+
+        self.named
+            .write()
+            .expect("dependency named map lock poisoned")
+            .extend(named);
+    }
 }
 
 #[derive(Clone)]
@@ -119,6 +319,9 @@ pub struct ToolSpec {
     description: String,
     json_schema: Value,
     handler: Arc<ToolHandler>,
+    concurrency: Option<Arc<Semaphore>>,
+    /// Compiled `pattern` constraints of string properties, keyed by property name.
+    patterns: Arc<HashMap<String, Regex>>,
 }
 
 impl std::fmt::Debug for ToolSpec {
@@ -149,11 +352,48 @@ impl ToolSpec {
                     ))
                 })
             }),
+            concurrency: None,
+            patterns: Arc::default(),
         }
     }
 
+    /// Creates a tool whose handler receives arguments deserialized into `A`.
+    ///
+    /// The JSON schema is derived from `A` via `schemars`; arguments that fail to deserialize
+    /// are reported as [`ToolError::InvalidArguments`].
+    #[cfg(feature = "schemars")]
+    pub fn new_typed<A, F, Fut>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        handler: F,
+    ) -> Result<Self, SchemaError>
+    where
+        A: serde::de::DeserializeOwned + schemars::JsonSchema,
+        F: Fn(A, &DependencyMap) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ToolOutcome, ToolError>> + Send + 'static,
+    {
+        let name = name.into();
+        let schema = schemars::schema_for!(A).to_value();
+        let tool_name = name.clone();
+
+        let tool = Self::new(name, description)
+            .with_schema(schema)?
+            .with_handler(move |args, deps| {
+                let parsed =
+                    serde_json::from_value::<A>(args).map_err(|err| ToolError::InvalidArguments {
+                        tool: tool_name.clone(),
+                        message: err.to_string(),
+                    });
+                let future = parsed.map(|args| handler(args, deps));
+                async move { future?.await }
+            });
+        Ok(tool)
+    }
+
+    /// Sets the argument schema; `pattern` constraints are compiled here, once per spec.
     pub fn with_schema(mut self, schema: Value) -> Result<Self, SchemaError> {
         validate_schema(&schema)?;
+        self.patterns = Arc::new(compile_patterns(&schema)?);
         self.json_schema = schema;
         Ok(self)
     }
@@ -167,6 +407,20 @@ impl ToolSpec {
         self
     }
 
+    /// Caps how many invocations of this tool may run at once; extra calls wait their turn.
+    ///
+    /// The limit is shared by every clone of this spec. A limit of 0 is treated as 1.
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.concurrency = Some(Arc::new(Semaphore::new(max.max(1))));
+        self
+    }
+
+    /// Renames the tool; the new name is what the model sees and calls.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -184,7 +438,16 @@ impl ToolSpec {
         args: Value,
         dependencies: &DependencyMap,
     ) -> Result<ToolOutcome, ToolError> {
-        validate_arguments(self.name(), &self.json_schema, &args)?;
+        validate_arguments(self.name(), &self.json_schema, &self.patterns, &args)?;
+        let _permit = match &self.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire()
+                    .await
+                    .map_err(|err| ToolError::Execution(err.to_string()))?,
+            ),
+            None => None,
+        };
         (self.handler)(args, dependencies).await
     }
 }
@@ -213,7 +476,33 @@ fn validate_schema(schema: &Value) -> Result<(), SchemaError> {
     Ok(())
 }
 
-fn validate_arguments(tool_name: &str, schema: &Value, args: &Value) -> Result<(), ToolError> {
+fn compile_patterns(schema: &Value) -> Result<HashMap<String, Regex>, SchemaError> {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return Ok(HashMap::new());
+    };
+
+    properties
+        .iter()
+        .filter_map(|(key, field_schema)| {
+            let pattern = field_schema.get("pattern")?.as_str()?;
+            Some(
+                Regex::new(pattern)
+                    .map(|regex| (key.clone(), regex))
+                    .map_err(|err| SchemaError::InvalidPattern {
+                        field: key.clone(),
+                        message: err.to_string(),
+                    }),
+            )
+        })
+        .collect()
+}
+
+fn validate_arguments(
+    tool_name: &str,
+    schema: &Value,
+    patterns: &HashMap<String, Regex>,
+    args: &Value,
+) -> Result<(), ToolError> {
     let args_obj = args
         .as_object()
         .ok_or_else(|| ToolError::InvalidArguments {
@@ -264,21 +553,143 @@ fn validate_arguments(tool_name: &str, schema: &Value, args: &Value) -> Result<(
     }
 
     for (key, value) in args_obj {
-        if let Some(field_schema) = properties.get(key) {
-            if let Some(type_name) = field_schema.get("type").and_then(Value::as_str) {
-                if !value_matches_type(value, type_name) {
-                    return Err(ToolError::InvalidArguments {
-                        tool: tool_name.to_string(),
-                        message: format!("field '{key}' must be of type {type_name}"),
-                    });
-                }
-            }
+        let Some(field_schema) = properties.get(key) else {
+            continue;
+        };
+
+        if let Some(type_name) = field_schema.get("type").and_then(Value::as_str)
+            && !value_matches_type(value, type_name)
+        {
+            return Err(ToolError::InvalidArguments {
+                tool: tool_name.to_string(),
+                message: format!("field '{key}' must be of type {type_name}"),
+            });
+        }
+
+        if let Some(allowed) = field_schema.get("enum").and_then(Value::as_array)
+            && !allowed.contains(value)
+        {
+            let allowed_list = allowed
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(ToolError::InvalidArguments {
+                tool: tool_name.to_string(),
+                message: format!("field '{key}' must be one of: {allowed_list}"),
+            });
+        }
+
+        if let (Some(text), Some(pattern)) = (value.as_str(), patterns.get(key))
+            && !pattern.is_match(text)
+        {
+            return Err(ToolError::InvalidArguments {
+                tool: tool_name.to_string(),
+                message: format!("field '{key}' must match pattern {}", pattern.as_str()),
+            });
+        }
+
+        if let Some(number) = value.as_f64()
+            && let Some(message) = numeric_bound_violation(key, number, field_schema)
+        {
+            return Err(ToolError::InvalidArguments {
+                tool: tool_name.to_string(),
+                message,
+            });
+        }
+
+        if let Some(message) = length_bound_violation(key, value, field_schema) {
+            return Err(ToolError::InvalidArguments {
+                tool: tool_name.to_string(),
+                message,
+            });
         }
     }
 
     Ok(())
 }
 
+/// Checks `minLength`/`maxLength` (in characters) on strings and `minItems`/`maxItems` on arrays.
+fn length_bound_violation(key: &str, value: &Value, field_schema: &Value) -> Option<String> {
+    let (length, min_key, max_key, (singular, plural)) = match value {
+        Value::String(text) => (
+            text.chars().count(),
+            "minLength",
+            "maxLength",
+            ("character", "characters"),
+        ),
+        Value::Array(items) => (items.len(), "minItems", "maxItems", ("item", "items")),
+        _ => return None,
+    };
+    let bound = |name: &str| field_schema.get(name).and_then(Value::as_u64);
+    let units = |count: u64| if count == 1 { singular } else { plural };
+
+    if let Some(minimum) = bound(min_key)
+        && (length as u64) < minimum
+    {
+        return Some(format!(
+            "field '{key}' must have at least {minimum} {} (got {length})",
+            units(minimum)
+        ));
+    }
+    if let Some(maximum) = bound(max_key)
+        && (length as u64) > maximum
+    {
+        return Some(format!(
+            "field '{key}' must have at most {maximum} {} (got {length})",
+            units(maximum)
+        ));
+    }
+
+    None
+}
+
+fn numeric_bound_violation(key: &str, number: f64, field_schema: &Value) -> Option<String> {
+    let bound = |name: &str| field_schema.get(name).and_then(Value::as_f64);
+
+    if let Some(minimum) = bound("minimum")
+        && number < minimum
+    {
+        return Some(format!("field '{key}' must be >= {minimum}"));
+    }
+    if let Some(maximum) = bound("maximum")
+        && number > maximum
+    {
+        return Some(format!("field '{key}' must be <= {maximum}"));
+    }
+    if let Some(minimum) = bound("exclusiveMinimum")
+        && number <= minimum
+    {
+        return Some(format!("field '{key}' must be > {minimum}"));
+    }
+    if let Some(maximum) = bound("exclusiveMaximum")
+        && number >= maximum
+    {
+        return Some(format!("field '{key}' must be < {maximum}"));
+    }
+
+    None
+}
+
+/// Checks a JSON value against a schema with the same rules used for tool arguments.
+///
+/// Object schemas get the full argument checks; other schemas only check the root `type`.
+pub(crate) fn validate_value(schema: &Value, value: &Value) -> Result<(), String> {
+    if let Some(type_name) = schema.get("type").and_then(Value::as_str)
+        && !value_matches_type(value, type_name)
+    {
+        return Err(format!("expected a JSON {type_name}"));
+    }
+    if !value.is_object() {
+        return Ok(());
+    }
+    let patterns = compile_patterns(schema).map_err(|err| err.to_string())?;
+    validate_arguments("response", schema, &patterns, value).map_err(|err| match err {
+        ToolError::InvalidArguments { message, .. } => message,
+        err => err.to_string(),
+    })
+}
+
 fn value_matches_type(value: &Value, type_name: &str) -> bool {
     match type_name {
         "string" => value.is_string(),
@@ -339,6 +750,97 @@ mod tests {
         assert_eq!(outcome, ToolOutcome::Text("9".to_string()));
     }
 
+    #[test]
+    fn dependency_remove_and_contains() {
+        let deps = DependencyMap::new();
+        deps.insert::<u32>(7);
+        deps.insert_named("label", "hello".to_string());
+
+        assert!(deps.contains::<u32>());
+        assert!(deps.contains_named("label"));
+
+        assert!(deps.remove::<u32>());
+        assert!(!deps.remove::<u32>());
+        assert!(!deps.contains::<u32>());
+        assert!(deps.get::<u32>().is_none());
+
+        assert!(deps.remove_named("label"));
+        assert!(!deps.remove_named("label"));
+        assert!(!deps.contains_named("label"));
+        assert!(deps.get_named::<String>("label").is_none());
+    }
+
+    #[test]
+    fn extend_from_copies_entries_and_other_wins_conflicts() {
+        let deps = DependencyMap::new();
+        deps.insert::<u32>(1);
+        deps.insert::<u64>(10);
+        deps.insert_named("label", "base".to_string());
+        deps.insert_named("kept", "base".to_string());
+
+        let plugin = DependencyMap::new();
+        plugin.insert::<u32>(2);
+        plugin.insert::<i8>(-1);
+        plugin.insert_named("label", "plugin".to_string());
+        plugin.insert_lazy::<u64, _>(|| Box::pin(async { 20 }));
+
+        deps.extend_from(&plugin);
+        deps.extend_from(&deps.clone());
+
+        assert_eq!(deps.get::<u32>().as_deref(), Some(&2));
+        assert_eq!(deps.get::<i8>().as_deref(), Some(&-1));
+        assert!(deps.contains::<u64>());
+        assert!(deps.get::<u64>().is_none());
+        assert_eq!(
+            deps.get_named::<String>("label").as_deref(),
+            Some(&"plugin".to_string())
+        );
+        assert_eq!(
+            deps.get_named::<String>("kept").as_deref(),
+            Some(&"base".to_string())
+        );
+        assert_eq!(plugin.get::<u32>().as_deref(), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn lazy_dependency_initializes_once_under_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let deps = DependencyMap::new();
+        let factory_runs = runs.clone();
+        deps.insert_lazy::<String, _>(move || {
+            let factory_runs = factory_runs.clone();
+            Box::pin(async move {
+                factory_runs.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                "pool".to_string()
+            })
+        });
+
+        assert!(deps.contains::<String>());
+        assert!(deps.get::<String>().is_none());
+
+        let merged = deps.merged_with(&DependencyMap::new());
+        let values = futures_util::future::join_all((0..8).map(|i| {
+            let deps = if i % 2 == 0 { &deps } else { &merged };
+            deps.get_or_init::<String>()
+        }))
+        .await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(
+            values
+                .iter()
+                .all(|value| value.as_deref().map(String::as_str) == Some("pool"))
+        );
+        assert_eq!(
+            deps.get::<String>().as_deref().map(String::as_str),
+            Some("pool")
+        );
+        assert!(deps.get_or_init::<u32>().await.is_none());
+    }
+
     #[tokio::test]
     async fn argument_validation_reports_missing_required() {
         let tool = ToolSpec::new("req", "required")
@@ -359,4 +861,229 @@ mod tests {
         let message = err.to_string();
         assert!(message.contains("missing required field"));
     }
+
+    #[cfg(feature = "schemars")]
+    #[tokio::test]
+    async fn typed_tool_receives_deserialized_arguments() {
+        #[derive(serde::Deserialize, schemars::JsonSchema)]
+        struct RepeatArgs {
+            text: String,
+            times: u32,
+        }
+
+        let tool = ToolSpec::new_typed("repeat", "repeat text", |args: RepeatArgs, _deps| {
+            let output = args.text.repeat(args.times as usize);
+            async move { Ok(ToolOutcome::Text(output)) }
+        })
+        .expect("derived schema is valid");
+
+        assert_eq!(tool.json_schema()["type"], json!("object"));
+        assert_eq!(
+            tool.json_schema()["properties"]["text"]["type"],
+            json!("string")
+        );
+
+        let outcome = tool
+            .execute(json!({"text": "ab", "times": 3}), &DependencyMap::new())
+            .await
+            .expect("tool executes");
+        assert_eq!(outcome, ToolOutcome::Text("ababab".to_string()));
+
+        let err = tool
+            .execute(json!({"text": "ab"}), &DependencyMap::new())
+            .await
+            .expect_err("missing field should fail");
+        assert!(matches!(err, ToolError::InvalidArguments { .. }));
+
+        let err = tool
+            .execute(json!({"text": "ab", "times": -1}), &DependencyMap::new())
+            .await
+            .expect_err("negative count should fail deserialization");
+        assert!(matches!(err, ToolError::InvalidArguments { .. }));
+    }
+
+    /// Builds a tool with a no-op handler, so only argument validation is exercised.
+    fn validating_tool(schema: Value) -> ToolSpec {
+        ToolSpec::new("validate", "validate arguments")
+            .with_schema(schema)
+            .expect("schema valid")
+            .with_handler(|_args, _deps| async move { Ok(ToolOutcome::Text("ok".into())) })
+    }
+
+    /// Runs `accepted` and `rejected` argument sets against a [`validating_tool`] for `schema`.
+    async fn check_arguments(schema: Value, accepted: &[Value], rejected: &[(Value, &str)]) {
+        let tool = validating_tool(schema);
+        let deps = DependencyMap::new();
+
+        for args in accepted {
+            let outcome = tool
+                .execute(args.clone(), &deps)
+                .await
+                .unwrap_or_else(|err| panic!("{args} should be accepted: {err}"));
+            assert_eq!(outcome, ToolOutcome::Text("ok".to_string()));
+        }
+        for (args, expected) in rejected {
+            let err = tool
+                .execute(args.clone(), &deps)
+                .await
+                .expect_err("should fail");
+            assert!(matches!(err, ToolError::InvalidArguments { .. }));
+            assert!(
+                err.to_string().contains(expected),
+                "{args}: expected {expected:?} in {err}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn argument_validation_checks_enum_members() {
+        check_arguments(
+            json!({
+                "type": "object",
+                "properties": {"mode": {"type": "string", "enum": ["fast", "slow"]}},
+                "required": ["mode"],
+                "additionalProperties": false
+            }),
+            &[json!({"mode": "fast"})],
+            &[
+                (
+                    json!({"mode": "medium"}),
+                    r#"field 'mode' must be one of: "fast", "slow""#,
+                ),
+                // Type mismatches are reported before enum membership.
+                (json!({"mode": 1}), "field 'mode' must be of type string"),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn argument_validation_checks_numeric_bounds() {
+        check_arguments(
+            json!({
+                "type": "object",
+                "properties": {
+                    "timeout": {"type": "integer", "minimum": 1, "maximum": 300},
+                    "ratio": {"type": "number", "exclusiveMinimum": 0, "exclusiveMaximum": 1}
+                },
+                "required": ["timeout"],
+                "additionalProperties": false
+            }),
+            &[json!({"timeout": 1}), json!({"timeout": 300, "ratio": 0.5})],
+            &[
+                (json!({"timeout": 0}), "field 'timeout' must be >= 1"),
+                (json!({"timeout": 600}), "field 'timeout' must be <= 300"),
+                (
+                    json!({"timeout": 5, "ratio": 0}),
+                    "field 'ratio' must be > 0",
+                ),
+                (
+                    json!({"timeout": 5, "ratio": 1}),
+                    "field 'ratio' must be < 1",
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn argument_validation_checks_string_patterns() {
+        check_arguments(
+            json!({
+                "type": "object",
+                "properties": {"version": {"type": "string", "pattern": "^\\d+\\.\\d+\\.\\d+$"}},
+                "required": ["version"],
+                "additionalProperties": false
+            }),
+            &[json!({"version": "1.2.3"})],
+            &[(
+                json!({"version": "v1.2"}),
+                r"field 'version' must match pattern ^\d+\.\d+\.\d+$",
+            )],
+        )
+        .await;
+    }
+
+    #[test]
+    fn schema_with_invalid_pattern_is_rejected() {
+        let err = ToolSpec::new("bad", "bad pattern")
+            .with_schema(json!({
+                "type": "object",
+                "properties": {"name": {"type": "string", "pattern": "([a-z"}}
+            }))
+            .expect_err("invalid pattern");
+        assert!(matches!(err, SchemaError::InvalidPattern { ref field, .. } if field == "name"));
+    }
+
+    #[tokio::test]
+    async fn argument_validation_checks_length_bounds() {
+        check_arguments(
+            json!({
+                "type": "object",
+                "properties": {
+                    "content": {"type": "string", "minLength": 1, "maxLength": 5},
+                    "todos": {"type": "array", "minItems": 1, "maxItems": 3}
+                },
+                "additionalProperties": false
+            }),
+            &[
+                json!({"content": "a"}),
+                json!({"content": "héllo"}),
+                json!({"todos": ["a"]}),
+                json!({"todos": ["a", "b", "c"]}),
+            ],
+            &[
+                (
+                    json!({"content": ""}),
+                    "field 'content' must have at least 1 character (got 0)",
+                ),
+                (
+                    json!({"content": "hello!"}),
+                    "field 'content' must have at most 5 characters (got 6)",
+                ),
+                (
+                    json!({"todos": []}),
+                    "field 'todos' must have at least 1 item (got 0)",
+                ),
+                (
+                    json!({"todos": ["a", "b", "c", "d"]}),
+                    "field 'todos' must have at most 3 items (got 4)",
+                ),
+            ],
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn max_concurrency_serializes_parallel_calls() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (active_in_tool, peak_in_tool) = (active.clone(), peak.clone());
+        let tool = ToolSpec::new("slow", "slow tool")
+            .with_handler(move |_args, _deps| {
+                let active = active_in_tool.clone();
+                let peak = peak_in_tool.clone();
+                async move {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    Ok(ToolOutcome::Text("ok".into()))
+                }
+            })
+            .with_max_concurrency(1);
+
+        let deps = DependencyMap::new();
+        let clone = tool.clone();
+        let (first, second) = tokio::join!(
+            tool.execute(json!({}), &deps),
+            clone.execute(json!({}), &deps)
+        );
+
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
 }
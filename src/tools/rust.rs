@@ -0,0 +1,300 @@
+//! Tools for Rust coding agents.
+//!
+//! These run inside the [`SandboxContext`] working directory, like the claude-code pack.
+
+use std::collections::HashSet;
+use std::process::Stdio;
+
+use serde_json::{Value, json};
+use tokio::process::Command;
+use tokio::time::{Duration, timeout};
+
+use crate::error::ToolError;
+use crate::tools::claude_code::SandboxContext;
+use crate::tools::{ToolOutcome, ToolSpec};
+
+/// Cargo subcommands that accept `--message-format=json`.
+const CARGO_SUBCOMMANDS: &[&str] = &["build", "check", "clippy", "test"];
+const DEFAULT_CARGO_TIMEOUT_SECS: u64 = 300;
+/// Trailing lines of non-diagnostic output kept in the result.
+const MAX_OUTPUT_LINES: usize = 40;
+
+/// Runs `cargo {subcommand}` with `--message-format=json` and summarizes compiler diagnostics.
+///
+/// Errors and warnings are listed as `level[code]: message --> file:line:column`; test output
+/// and, on failures without diagnostics, stderr are appended.
+pub fn cargo_tool() -> ToolSpec {
+    ToolSpec::new(
+        "cargo",
+        "Run a cargo command (build, check, clippy, test) and list compiler errors and warnings",
+    )
+    .with_schema(json!({
+        "type": "object",
+        "properties": {
+            "subcommand": {"type": "string", "enum": CARGO_SUBCOMMANDS},
+            "args": {"type": "array", "items": {"type": "string"}},
+            "timeout": {"type": "integer"}
+        },
+        "required": ["subcommand"],
+        "additionalProperties": false
+    }))
+    .expect("valid schema")
+    .with_handler(|args, deps| {
+        let subcommand = args
+            .get("subcommand")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let extra_args = args
+            .get("args")
+            .and_then(|v| v.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(ToString::to_string))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let timeout_secs = args
+            .get("timeout")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_CARGO_TIMEOUT_SECS);
+        let ctx = deps
+            .get::<SandboxContext>()
+            .ok_or(ToolError::MissingDependency("SandboxContext"));
+
+        async move {
+            let ctx = match ctx {
+                Ok(ctx) => ctx,
+                Err(err) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+            };
+
+            if let Err(err) = ctx.check_command("cargo") {
+                return Ok(ToolOutcome::Text(err));
+            }
+
+            let working_dir = ctx.working_dir();
+            let mut process = Command::new("cargo");
+            process
+                .arg(&subcommand)
+                .arg("--message-format=json")
+                .args(&extra_args)
+                .current_dir(&working_dir)
+                .stdin(Stdio::null())
+                .kill_on_drop(true);
+            if let Some(env) = ctx.env() {
+                process.env_clear().envs(env);
+            }
+            process.env("PWD", &working_dir);
+
+            let output = match timeout(Duration::from_secs(timeout_secs), process.output()).await {
+                Ok(Ok(output)) => output,
+                Ok(Err(err)) => return Ok(ToolOutcome::Text(format!("Error: {err}"))),
+                Err(_) => {
+                    return Ok(ToolOutcome::Text(format!(
+                        "cargo {subcommand} timed out after {timeout_secs}s"
+                    )));
+                }
+            };
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Ok(ToolOutcome::Text(summarize_cargo_output(
+                &subcommand,
+                output.status.success(),
+                &stdout,
+                &stderr,
+            )))
+        }
+    })
+}
+
+#[derive(Debug)]
+struct Diagnostic {
+    level: String,
+    code: Option<String>,
+    message: String,
+    location: Option<String>,
+}
+
+impl Diagnostic {
+    fn render(&self) -> String {
+        let mut rendered = self.level.clone();
+        if let Some(code) = &self.code {
+            rendered.push_str(&format!("[{code}]"));
+        }
+        rendered.push_str(&format!(": {}", self.message));
+        if let Some(location) = &self.location {
+            rendered.push_str(&format!(" --> {location}"));
+        }
+        rendered
+    }
+}
+
+/// Parses one `--message-format=json` line into an error or warning diagnostic.
+fn parse_diagnostic(line: &Value) -> Option<Diagnostic> {
+    if line.get("reason").and_then(Value::as_str) != Some("compiler-message") {
+        return None;
+    }
+    let message = line.get("message")?;
+    let level = message.get("level").and_then(Value::as_str)?;
+    if !matches!(level, "error" | "warning") {
+        return None;
+    }
+    let text = message.get("message").and_then(Value::as_str)?;
+
+    let spans = message
+        .get("spans")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    // Span-less summaries such as "aborting due to 1 previous error" repeat the counts.
+    if spans.is_empty() && (text.starts_with("aborting due to") || text.ends_with("emitted")) {
+        return None;
+    }
+    let location = spans
+        .iter()
+        .find(|span| span.get("is_primary").and_then(Value::as_bool) == Some(true))
+        .or_else(|| spans.first())
+        .and_then(|span| {
+            Some(format!(
+                "{}:{}:{}",
+                span.get("file_name").and_then(Value::as_str)?,
+                span.get("line_start").and_then(Value::as_u64)?,
+                span.get("column_start").and_then(Value::as_u64)?
+            ))
+        });
+
+    Some(Diagnostic {
+        level: level.to_string(),
+        code: message
+            .get("code")
+            .and_then(|code| code.get("code"))
+            .and_then(Value::as_str)
+            .map(ToString::to_string),
+        message: text.to_string(),
+        location,
+    })
+}
+
+fn summarize_cargo_output(subcommand: &str, success: bool, stdout: &str, stderr: &str) -> String {
+    let mut seen = HashSet::new();
+    let mut diagnostics = Vec::new();
+    let mut other_output = Vec::new();
+
+    for line in stdout.lines() {
+        match serde_json::from_str::<Value>(line) {
+            Ok(value) => {
+                if let Some(diagnostic) = parse_diagnostic(&value)
+                    && seen.insert(diagnostic.render())
+                {
+                    diagnostics.push(diagnostic);
+                }
+            }
+            Err(_) if !line.trim().is_empty() => other_output.push(line),
+            Err(_) => {}
+        }
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.level == "error")
+        .count();
+    let warnings = diagnostics.len() - errors;
+    let mut rendered = format!(
+        "cargo {subcommand} {} ({errors} error(s), {warnings} warning(s))",
+        if success { "succeeded" } else { "failed" }
+    );
+    for diagnostic in &diagnostics {
+        rendered.push('\n');
+        rendered.push_str(&diagnostic.render());
+    }
+
+    if !other_output.is_empty() {
+        rendered.push_str("\n\nOutput:\n");
+        rendered.push_str(&tail_lines(&other_output, MAX_OUTPUT_LINES));
+    }
+    if !success && errors == 0 {
+        let stderr_lines = stderr
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>();
+        if !stderr_lines.is_empty() {
+            rendered.push_str("\n\nStderr:\n");
+            rendered.push_str(&tail_lines(&stderr_lines, MAX_OUTPUT_LINES));
+        }
+    }
+    rendered
+}
+
+fn tail_lines(lines: &[&str], max: usize) -> String {
+    lines[lines.len().saturating_sub(max)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::tools::DependencyMap;
+
+    #[test]
+    fn summarize_lists_deduplicated_diagnostics_with_locations() {
+        let error = json!({
+            "reason": "compiler-message",
+            "message": {
+                "level": "error",
+                "message": "mismatched types",
+                "code": {"code": "E0308"},
+                "spans": [{"file_name": "src/lib.rs", "line_start": 2, "column_start": 5, "is_primary": true}]
+            }
+        });
+        let aborting = json!({
+            "reason": "compiler-message",
+            "message": {"level": "error", "message": "aborting due to 1 previous error", "code": null, "spans": []}
+        });
+        let stdout = format!(
+            "{error}\n{error}\n{aborting}\n{}\n",
+            json!({"reason": "build-finished", "success": false})
+        );
+
+        let summary = summarize_cargo_output("check", false, &stdout, "error: could not compile");
+
+        assert_eq!(
+            summary,
+            "cargo check failed (1 error(s), 0 warning(s))\nerror[E0308]: mismatched types --> src/lib.rs:2:5"
+        );
+    }
+
+    #[tokio::test]
+    async fn cargo_check_surfaces_compile_error() {
+        let sandbox = SandboxContext::create_ephemeral().expect("ephemeral sandbox");
+        fs::write(
+            sandbox.root_dir().join("Cargo.toml"),
+            "[package]\nname = \"broken\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+        )
+        .expect("write manifest");
+        fs::create_dir_all(sandbox.root_dir().join("src")).expect("create src");
+        fs::write(
+            sandbox.root_dir().join("src/lib.rs"),
+            "pub fn answer() -> u32 {\n    \"forty-two\"\n}\n",
+        )
+        .expect("write lib");
+        let deps = DependencyMap::new();
+        deps.insert(sandbox.context());
+
+        let result = cargo_tool()
+            .execute(json!({"subcommand": "check", "args": ["--offline"]}), &deps)
+            .await
+            .expect("cargo tool runs");
+
+        let ToolOutcome::Text(text) = result else {
+            panic!("unexpected outcome: {result:?}");
+        };
+        assert!(text.starts_with("cargo check failed (1 error(s)"), "{text}");
+        assert!(
+            text.contains("error[E0308]: mismatched types --> src/lib.rs:2:5"),
+            "{text}"
+        );
+    }
+}